@@ -1,5 +1,6 @@
 use std::{collections::HashMap, path::PathBuf};
 
+use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
 
 use crate::author::LibraryAuthor;
@@ -63,6 +64,17 @@ pub struct LibraryBook {
     /// Canonical Calibre language codes (ISO 639-2/3, e.g. `eng`, `fra`),
     /// ordered. Empty when the book has no language metadata.
     pub language_list: Vec<String>,
+
+    pub publisher: Option<String>,
+    /// Calibre's 0–10 scale (two points per displayed star); `None` when
+    /// unrated.
+    pub rating: Option<i32>,
+    /// Calibre's `pubdate`; `None` when unset.
+    pub publication_date: Option<NaiveDateTime>,
+    /// When the book was added to the library (Calibre's `timestamp`).
+    pub created_at: NaiveDateTime,
+    /// When the book's metadata last changed (Calibre's `last_modified`).
+    pub updated_at: NaiveDateTime,
 }
 
 impl LibraryBook {
@@ -97,6 +109,11 @@ impl LibraryBook {
             series: book.series.clone(),
             series_index: book.series_index,
             language_list: book.language_codes.clone(),
+            publisher: book.publisher.clone(),
+            rating: book.rating,
+            publication_date: book.publication_date,
+            created_at: book.created_at,
+            updated_at: book.updated_at,
             cover_image: book_cover_url(book, library_path, url_builder, cache_bust_cover),
             file_list: book
                 .files
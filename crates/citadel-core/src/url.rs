@@ -174,6 +174,9 @@ mod tests {
             description: None,
             language_codes: vec![],
             identifiers: vec![],
+            publisher: None,
+            rating: None,
+            publication_date: None,
             has_cover,
             is_read: false,
             files,
@@ -28,6 +28,21 @@ use libcalibre::{AuthorId, BookQuery, BookSortOrder, CustomColumnKind, Library};
 
 const PAGE_SIZE: i64 = 100;
 
+/// The text filter as plain LIKE scans, which `query_books` falls back to
+/// when the search index is unavailable or the needle is under three
+/// characters. Binds the `%needle%` pattern three times.
+const LIKE_TEXT_WHERE: &str = "(books.title LIKE ? ESCAPE '\\' \
+     OR EXISTS (SELECT 1 FROM books_authors_link bal \
+                JOIN authors a ON a.id = bal.author \
+                WHERE bal.book = books.id AND a.name LIKE ? ESCAPE '\\') \
+     OR EXISTS (SELECT 1 FROM books_series_link bsl \
+                JOIN series s ON s.id = bsl.series \
+                WHERE bsl.book = books.id AND s.name LIKE ? ESCAPE '\\'))";
+
+/// The text filter through the search index. Binds one FTS5 match query.
+const FTS_TEXT_WHERE: &str = "books.id IN (SELECT rowid FROM citadel_search.metadata_fts \
+     WHERE metadata_fts MATCH ?)";
+
 struct Args {
     library: PathBuf,
     runs: usize,
@@ -144,6 +159,12 @@ struct IdCountRow {
     c: i64,
 }
 
+#[derive(diesel::QueryableByName)]
+struct CountRow {
+    #[diesel(sql_type = BigInt)]
+    total: i64,
+}
+
 #[derive(diesel::QueryableByName)]
 struct ExplainRow {
     #[diesel(sql_type = Integer)]
@@ -216,6 +237,21 @@ fn most_linked(conn: &mut SqliteConnection, link_table: &str, column: &str) -> O
     rows.first().map(|row| (row.id, row.c))
 }
 
+/// Count the books matching `needle` with the LIKE text filter, bypassing the
+/// search index, as a baseline for the indexed `query_books` text search.
+fn like_text_count(conn: &mut SqliteConnection, needle: &str) -> i64 {
+    let pattern = format!("%{needle}%");
+    sql_query(format!(
+        "SELECT COUNT(*) AS total FROM books WHERE 1=1 AND {LIKE_TEXT_WHERE}"
+    ))
+    .bind::<Text, _>(&pattern)
+    .bind::<Text, _>(&pattern)
+    .bind::<Text, _>(&pattern)
+    .get_result::<CountRow>(conn)
+    .map(|row| row.total)
+    .expect("LIKE text count")
+}
+
 fn explain(conn: &mut SqliteConnection, label: &str, sql: &str, text_binds: usize) {
     println!("\n### EXPLAIN QUERY PLAN: {label}\n");
     println!("```sql\n{sql}\n```\n");
@@ -223,6 +259,9 @@ fn explain(conn: &mut SqliteConnection, label: &str, sql: &str, text_binds: usiz
     let explain_sql = format!("EXPLAIN QUERY PLAN {sql}");
     let result: Result<Vec<ExplainRow>, _> = match text_binds {
         0 => sql_query(explain_sql).load(conn),
+        1 => sql_query(explain_sql)
+            .bind::<Text, _>("{title authors series} : \"war\"")
+            .load(conn),
         3 => sql_query(explain_sql)
             .bind::<Text, _>("%war%")
             .bind::<Text, _>("%war%")
@@ -296,6 +335,14 @@ fn run() -> Result<(), String> {
     let db_path = args.library.join("metadata.db");
     let mut raw_conn = establish_connection(db_path.to_str().ok_or("non-UTF-8 db path")?)
         .map_err(|_| "failed to open raw connection".to_string())?;
+    // Opening the library created (or refreshed) the search index; attach it so
+    // the indexed text filter can be explained.
+    let index_path = args.library.join(".citadel").join("search-index.db");
+    let has_search_index = index_path.is_file()
+        && sql_query("ATTACH DATABASE ? AS citadel_search")
+            .bind::<Text, _>(index_path.to_string_lossy())
+            .execute(&mut raw_conn)
+            .is_ok();
 
     // ---- Sanity check + scenario inputs --------------------------------
     let first_page = library
@@ -494,6 +541,25 @@ fn run() -> Result<(), String> {
             format!("{} authors with books", counts.len())
         },
     ));
+    for token in [&common_token, &rare_token] {
+        results.push(bench(
+            &format!("LIKE text count ('{token}') [unindexed baseline, raw SQL]"),
+            runs,
+            warmup,
+            || format!("total {}", like_text_count(&mut raw_conn, token)),
+        ));
+    }
+    results.push(bench(
+        "rebuild_search_index() [full reindex]",
+        runs,
+        warmup,
+        || {
+            library
+                .rebuild_search_index()
+                .expect("rebuild_search_index");
+            "ok".to_string()
+        },
+    ));
 
     // ---- Report ----------------------------------------------------------
     println!("\n## Benchmark results ({runs} runs after {warmup} warmup, ms)\n");
@@ -518,18 +584,13 @@ fn run() -> Result<(), String> {
 
     // ---- EXPLAIN QUERY PLAN ----------------------------------------------
     // These SQL strings mirror what queries/books.rs::filter_where_sql /
-    // order_by_sql generate; keep them in sync when the crate changes.
+    // order_by_sql generate; keep them in sync when the crate changes. The
+    // text filter is shown both ways: through the search index, which
+    // query_books uses for needles of three or more characters, and as the
+    // LIKE fallback.
     let author_sort_expr = "(SELECT a.sort FROM books_authors_link bal \
          JOIN authors a ON a.id = bal.author \
          WHERE bal.book = books.id ORDER BY bal.id LIMIT 1)";
-    let text_where = "(books.title LIKE ? ESCAPE '\\' \
-              OR EXISTS (SELECT 1 FROM books_authors_link bal \
-                         JOIN authors a ON a.id = bal.author \
-                         WHERE bal.book = books.id AND a.name LIKE ? ESCAPE '\\') \
-              OR EXISTS (SELECT 1 FROM books_series_link bsl \
-                         JOIN series s ON s.id = bsl.series \
-                         WHERE bsl.book = books.id AND s.name LIKE ? ESCAPE '\\'))";
-
     println!("\n## Query plans\n");
     explain(
         &mut raw_conn,
@@ -547,19 +608,41 @@ fn run() -> Result<(), String> {
         ),
         0,
     );
+    if has_search_index {
+        explain(
+            &mut raw_conn,
+            "paged ids, indexed text filter, ORDER BY title",
+            &format!(
+                "SELECT books.id FROM books WHERE 1=1 AND {FTS_TEXT_WHERE} \
+                 ORDER BY books.sort ASC, books.id ASC LIMIT 100 OFFSET 0"
+            ),
+            1,
+        );
+        explain(
+            &mut raw_conn,
+            "count, indexed text filter",
+            &format!("SELECT COUNT(*) AS total FROM books WHERE 1=1 AND {FTS_TEXT_WHERE}"),
+            1,
+        );
+    } else {
+        println!(
+            "\n(no search index at {}; skipping indexed text EXPLAIN)",
+            index_path.display()
+        );
+    }
     explain(
         &mut raw_conn,
-        "paged ids, text filter, ORDER BY title",
+        "paged ids, LIKE text filter, ORDER BY title",
         &format!(
-            "SELECT books.id FROM books WHERE 1=1 AND {text_where} \
+            "SELECT books.id FROM books WHERE 1=1 AND {LIKE_TEXT_WHERE} \
              ORDER BY books.sort ASC, books.id ASC LIMIT 100 OFFSET 0"
         ),
         3,
     );
     explain(
         &mut raw_conn,
-        "count, text filter",
-        &format!("SELECT COUNT(*) AS total FROM books WHERE 1=1 AND {text_where}"),
+        "count, LIKE text filter",
+        &format!("SELECT COUNT(*) AS total FROM books WHERE 1=1 AND {LIKE_TEXT_WHERE}"),
         3,
     );
 
@@ -1,4 +1,5 @@
 use crate::CalibreError;
+use sanitise_file_name::sanitise;
 use std::path::Path;
 
 use std::path::PathBuf;
@@ -18,3 +19,25 @@ pub fn asset_path(library_root: &String, book_path: &str, filename: &str) -> Pat
     let library_path = Path::new(library_root);
     library_path.join(book_path).join(filename)
 }
+
+/// A book's folder relative to the library root, in Calibre's layout:
+/// `Author/Title (id)`, named after the book's first author.
+pub fn book_dir_relative(primary_author: &str, title: &str, book_id: i32) -> PathBuf {
+    Path::new(&sanitise(primary_author)).join(sanitise(&format!("{} ({})", title, book_id)))
+}
+
+/// The file name (without extension) Calibre gives every format of a book:
+/// `Title - Author`. Stored in `data.name`.
+pub fn book_file_stem(title: &str, primary_author: &str) -> String {
+    sanitise(&format!("{} - {}", title, primary_author))
+}
+
+/// `stem.ext` for a format file, with the extension lowercased. Formats
+/// without an extension use the bare stem.
+pub fn book_file_name(stem: &str, file_format: &str) -> String {
+    if file_format.is_empty() {
+        stem.to_string()
+    } else {
+        format!("{}.{}", stem, file_format.to_lowercase())
+    }
+}
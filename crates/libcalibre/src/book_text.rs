@@ -0,0 +1,134 @@
+use std::{error::Error, ffi::OsStr, path::Path};
+
+use regex::Regex;
+
+use crate::mime_type::MIMETYPE;
+
+/// The searchable text of a book file: paragraphs separated by newlines,
+/// markup removed. `None` for formats we cannot extract text from.
+pub fn book_text_from_path(path: &Path) -> Result<Option<String>, Box<dyn Error>> {
+    let extension = path
+        .extension()
+        .and_then(OsStr::to_str)
+        .ok_or("Failed to read file extension")?;
+
+    match MIMETYPE::from_file_extension(extension) {
+        Some(MIMETYPE::EPUB) => {
+            let mut doc = epub::doc::EpubDoc::new(path)?;
+            let stripper = HtmlStripper::new();
+            let mut chapters = Vec::with_capacity(doc.get_num_chapters());
+            loop {
+                if let Some((chapter, _mime)) = doc.get_current_str() {
+                    let text = stripper.strip(&chapter);
+                    if !text.is_empty() {
+                        chapters.push(text);
+                    }
+                }
+                if !doc.go_next() {
+                    break;
+                }
+            }
+            Ok(Some(chapters.join("\n")))
+        }
+        Some(MIMETYPE::TXT) => {
+            let bytes = std::fs::read(path)?;
+            let text = String::from_utf8_lossy(&bytes);
+            Ok(Some(normalize_lines(text.trim_start_matches('\u{feff}'))))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Turns (X)HTML chapters into plain text. Regex-based rather than a parser,
+/// because chapters in the wild are often not well-formed XML.
+struct HtmlStripper {
+    invisible: Regex,
+    block_boundary: Regex,
+    tag: Regex,
+    entity: Regex,
+}
+
+impl HtmlStripper {
+    fn new() -> Self {
+        Self {
+            invisible: Regex::new(
+                r"(?is)<!--.*?-->|<head[\s>].*?</head\s*>|<script[\s>].*?</script\s*>|<style[\s>].*?</style\s*>",
+            )
+            .unwrap(),
+            block_boundary: Regex::new(
+                r"(?i)</?(p|div|h[1-6]|li|tr|br|hr|blockquote|section|article|aside|pre|dt|dd|table|ul|ol)\b[^>]*>",
+            )
+            .unwrap(),
+            tag: Regex::new(r"<[^>]*>").unwrap(),
+            entity: Regex::new(r"&(#[0-9]+|#[xX][0-9a-fA-F]+|[a-zA-Z]+);").unwrap(),
+        }
+    }
+
+    fn strip(&self, html: &str) -> String {
+        let text = self.invisible.replace_all(html, "");
+        let text = self.block_boundary.replace_all(&text, "\n");
+        let text = self.tag.replace_all(&text, "");
+        let text = self
+            .entity
+            .replace_all(&text, |captures: &regex::Captures| {
+                decode_entity(&captures[1]).unwrap_or_else(|| captures[0].to_string())
+            });
+        normalize_lines(&text)
+    }
+}
+
+fn decode_entity(entity: &str) -> Option<String> {
+    if let Some(number) = entity.strip_prefix('#') {
+        let code = match number.strip_prefix(['x', 'X']) {
+            Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+            None => number.parse().ok()?,
+        };
+        return char::from_u32(code).map(String::from);
+    }
+    let decoded = match entity {
+        "amp" => "&",
+        "lt" => "<",
+        "gt" => ">",
+        "quot" => "\"",
+        "apos" => "'",
+        "nbsp" => " ",
+        "shy" => "",
+        "ndash" => "\u{2013}",
+        "mdash" => "\u{2014}",
+        "hellip" => "\u{2026}",
+        "lsquo" => "\u{2018}",
+        "rsquo" => "\u{2019}",
+        "ldquo" => "\u{201c}",
+        "rdquo" => "\u{201d}",
+        "copy" => "\u{a9}",
+        _ => return None,
+    };
+    Some(decoded.to_string())
+}
+
+/// Collapse runs of whitespace within lines and drop blank lines.
+fn normalize_lines(text: &str) -> String {
+    text.lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_html() {
+        let html = r#"<?xml version="1.0"?>
+<html><head><title>Chapter</title><style>p { color: red }</style></head>
+<body><h1>One</h1><!-- a comment -->
+<p>It was a <i>dark</i>&nbsp;and stormy&#x2026;</p><p>Fish &amp; chips<br/>&bogus;</p>
+<script>var x = "<p>";</script></body></html>"#;
+        assert_eq!(
+            HtmlStripper::new().strip(html),
+            "One\nIt was a dark and stormy\u{2026}\nFish & chips\n&bogus;"
+        );
+    }
+}
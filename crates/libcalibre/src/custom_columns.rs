@@ -509,6 +509,14 @@ struct TextValueRow {
     value: String,
 }
 
+#[derive(QueryableByName)]
+struct SeriesValueRow {
+    #[diesel(sql_type = SqlText)]
+    value: String,
+    #[diesel(sql_type = diesel::sql_types::Nullable<Double>)]
+    extra: Option<f64>,
+}
+
 #[derive(QueryableByName)]
 struct BookI64Row {
     #[diesel(sql_type = Integer)]
@@ -703,6 +711,73 @@ pub(crate) fn batch_get_values(
     }
 }
 
+/// One book's value for a custom column as Calibre serializes it into
+/// `metadata.opf`: the `#value#` JSON and, for series columns, the series
+/// index (`#extra#`). Unlike [`get_value`] this reads every stored datatype;
+/// composite columns have no stored value and read as `null`.
+pub(crate) fn get_opf_value(
+    conn: &mut SqliteConnection,
+    column: &CustomColumn,
+    book_id: BookId,
+) -> Result<(serde_json::Value, Option<f64>), CalibreError> {
+    let n = column.id;
+    match &column.kind {
+        CustomColumnKind::Series => {
+            let row = sql_query(format!(
+                "SELECT cc.value AS value, l.extra AS extra
+                 FROM books_custom_column_{n}_link l
+                 INNER JOIN custom_column_{n} cc ON cc.id = l.value
+                 WHERE l.book = ?"
+            ))
+            .bind::<Integer, _>(book_id.as_i32())
+            .get_result::<SeriesValueRow>(conn)
+            .optional()
+            .map_err(CalibreError::from)?;
+            Ok(match row {
+                Some(row) => (serde_json::Value::String(row.value), row.extra),
+                None => (serde_json::Value::Null, None),
+            })
+        }
+        CustomColumnKind::Rating => {
+            let rating = sql_query(format!(
+                "SELECT cc.value AS value
+                 FROM books_custom_column_{n}_link l
+                 INNER JOIN custom_column_{n} cc ON cc.id = l.value
+                 WHERE l.book = ?"
+            ))
+            .bind::<Integer, _>(book_id.as_i32())
+            .get_result::<I64ValueRow>(conn)
+            .optional()
+            .map_err(CalibreError::from)?;
+            Ok((
+                rating.map_or(serde_json::Value::Null, |row| row.value.into()),
+                None,
+            ))
+        }
+        CustomColumnKind::Composite | CustomColumnKind::Other(_) => {
+            Ok((serde_json::Value::Null, None))
+        }
+        _ => {
+            let value = match get_value(conn, column, book_id)? {
+                None => serde_json::Value::Null,
+                Some(CustomValue::Bool(value)) => value.into(),
+                Some(CustomValue::Int(value)) => value.into(),
+                Some(CustomValue::Float(value)) => value.into(),
+                Some(CustomValue::Text(value)) | Some(CustomValue::Enumeration(value)) => {
+                    value.into()
+                }
+                Some(CustomValue::TextMultiple(values)) => values.into(),
+                // Calibre's JSON encoding of a datetime (`utils/config.py`).
+                Some(CustomValue::Datetime(value)) => serde_json::json!({
+                    "__class__": "datetime.datetime",
+                    "__value__": value.format("%Y-%m-%dT%H:%M:%S%:z").to_string(),
+                }),
+            };
+            Ok((value, None))
+        }
+    }
+}
+
 fn get_non_normalized_i64(
     conn: &mut SqliteConnection,
     n: i32,
@@ -852,6 +927,75 @@ pub(crate) fn set_value(
     }
 }
 
+/// Store one book's value for a custom column as read from `metadata.opf`:
+/// the inverse of [`get_opf_value`]. `null` leaves the book without a value,
+/// and composite columns, which store nothing, ignore the value.
+pub(crate) fn set_opf_value(
+    conn: &mut SqliteConnection,
+    column: &CustomColumn,
+    book_id: BookId,
+    value: &serde_json::Value,
+    extra: Option<f64>,
+) -> Result<(), CalibreError> {
+    if value.is_null() {
+        return Ok(());
+    }
+    let mismatch = || {
+        CalibreError::InvalidCustomValue(format!(
+            "{value} is not a valid value for column '{}' with datatype '{}'",
+            column.label,
+            column.kind.datatype()
+        ))
+    };
+
+    let n = column.id;
+    let value = match &column.kind {
+        CustomColumnKind::Series => {
+            let series_name = value.as_str().ok_or_else(mismatch)?.to_string();
+            set_normalized_values(conn, n, book_id, &[series_name])?;
+            return sql_query(format!(
+                "UPDATE books_custom_column_{n}_link SET extra = ? WHERE book = ?"
+            ))
+            .bind::<Double, _>(extra.unwrap_or(1.0))
+            .bind::<Integer, _>(book_id.as_i32())
+            .execute(conn)
+            .map(|_| ())
+            .map_err(CalibreError::from);
+        }
+        CustomColumnKind::Rating => {
+            let rating = value.as_f64().ok_or_else(mismatch)? as i64;
+            return set_normalized_values(conn, n, book_id, &[rating.to_string()]);
+        }
+        CustomColumnKind::Composite | CustomColumnKind::Other(_) => return Ok(()),
+        CustomColumnKind::Bool => CustomValue::Bool(value.as_bool().ok_or_else(mismatch)?),
+        CustomColumnKind::Int => CustomValue::Int(value.as_f64().ok_or_else(mismatch)? as i64),
+        CustomColumnKind::Float => CustomValue::Float(value.as_f64().ok_or_else(mismatch)?),
+        CustomColumnKind::Text if column.is_multiple => CustomValue::TextMultiple(
+            value
+                .as_array()
+                .ok_or_else(mismatch)?
+                .iter()
+                .filter_map(|item| item.as_str().map(str::to_string))
+                .collect(),
+        ),
+        CustomColumnKind::Text | CustomColumnKind::Comments => {
+            CustomValue::Text(value.as_str().ok_or_else(mismatch)?.to_string())
+        }
+        CustomColumnKind::Enumeration => {
+            CustomValue::Enumeration(value.as_str().ok_or_else(mismatch)?.to_string())
+        }
+        CustomColumnKind::Datetime => {
+            // Encoded as `{"__class__": "datetime.datetime", "__value__": ...}`.
+            let encoded = value["__value__"].as_str().or(value.as_str());
+            let date_time = encoded
+                .and_then(crate::opf::parse_date)
+                .ok_or_else(mismatch)?;
+            CustomValue::Datetime(Utc.from_utc_datetime(&date_time))
+        }
+    };
+    set_value(conn, column, book_id, Some(value))
+}
+
 fn clear_value(
     conn: &mut SqliteConnection,
     column: &CustomColumn,
@@ -2,5 +2,7 @@ pub mod author;
 pub mod book_file;
 pub mod book_row;
 pub mod language;
+pub mod publisher;
+pub mod rating;
 pub mod series;
 pub mod tag;
@@ -0,0 +1,18 @@
+use diesel::prelude::*;
+
+use crate::schema::publishers;
+
+#[derive(Clone, Debug, Queryable, QueryableByName, Selectable, Identifiable)]
+#[diesel(table_name = publishers)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct Publisher {
+    pub id: i32,
+    pub name: String,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = publishers)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct NewPublisher {
+    pub name: String,
+}
@@ -0,0 +1,19 @@
+use diesel::prelude::*;
+
+use crate::schema::ratings;
+
+#[derive(Clone, Debug, Queryable, QueryableByName, Selectable, Identifiable)]
+#[diesel(table_name = ratings)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct Rating {
+    pub id: i32,
+    /// Calibre's 0–10 scale: two points per displayed star.
+    pub rating: i32,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = ratings)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct NewRating {
+    pub rating: i32,
+}
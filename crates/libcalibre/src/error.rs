@@ -29,9 +29,26 @@ pub enum CalibreError {
     #[error("Book file not found")]
     BookCoverNotFound,
 
+    /// No book with the given ID is in the library's trash.
+    #[error("Book not found in trash: {0}")]
+    TrashEntryNotFound(BookId),
+
     #[error("Author cannot be deleted; they have associated books")]
     AuthorHasAssociatedBooks(Vec<BookId>),
 
+    /// Tag with given ID was not found.
+    #[error("Tag not found: {0}")]
+    TagNotFound(i32),
+
+    /// Series with given ID was not found.
+    #[error("Series not found: {0}")]
+    SeriesNotFound(i32),
+
+    /// A new reading order for a series does not list exactly the books in
+    /// it.
+    #[error("Invalid series order: {0}")]
+    InvalidSeriesOrder(String),
+
     /// Custom column with given ID was not found.
     #[error("Custom column not found: {0}")]
     CustomColumnNotFound(i32),
@@ -51,6 +68,26 @@ pub enum CalibreError {
     #[error("Invalid custom column value: {0}")]
     InvalidCustomValue(String),
 
+    /// The library's database schema is older than any this crate can
+    /// upgrade; opening it in a recent Calibre upgrades it.
+    #[error("Library database schema version {0} is too old; open the library in Calibre to upgrade it first")]
+    UnsupportedSchemaVersion(i32),
+
+    /// A search query is not valid in Calibre's search language (unbalanced
+    /// parentheses, an unknown column, a malformed number or date, ...).
+    #[error("Invalid search: {0}")]
+    InvalidSearch(String),
+
+    /// A new name for a tag, series or other named item is unusable (empty,
+    /// for instance).
+    #[error("Invalid name: {0}")]
+    InvalidName(String),
+
+    /// A value for one of Calibre's well-known preferences has a shape
+    /// Calibre would not load (an empty name, a malformed entry, ...).
+    #[error("Invalid preference: {0}")]
+    InvalidPreference(String),
+
     #[error("Library not initialized")]
     LibraryNotInitialized,
 
@@ -0,0 +1,147 @@
+//! ISBN normalization and equivalence: the single place ISBN-10 and ISBN-13
+//! are converted between, shared by duplicate detection and the metadata
+//! providers.
+
+/// Normalize a raw ISBN string into a compact, validated form (13 digits, or
+/// 10 digits with an optional trailing `X`). Returns `None` if the input is
+/// not a structurally valid ISBN.
+pub fn normalize_isbn(raw: &str) -> Option<String> {
+    let trimmed = raw.trim();
+    if trimmed.is_empty() {
+        return None;
+    }
+    let without_prefix = trimmed
+        .strip_prefix("ISBN:")
+        .or_else(|| trimmed.strip_prefix("isbn:"))
+        .unwrap_or(trimmed)
+        .trim();
+
+    let compact: String = without_prefix
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .collect();
+    if compact.is_empty() {
+        return None;
+    }
+
+    let upper = compact.to_uppercase();
+    let valid = upper.len() == 13 && upper.chars().all(|c| c.is_ascii_digit())
+        || (upper.len() == 10
+            && upper[..9].chars().all(|c| c.is_ascii_digit())
+            && upper[9..].chars().all(|c| c.is_ascii_digit() || c == 'X'));
+    if valid {
+        Some(upper)
+    } else {
+        None
+    }
+}
+
+/// Canonicalize any valid ISBN to its 13-digit form so that the ISBN-10 and
+/// ISBN-13 representations of the same edition compare equal.
+pub fn canonical_isbn13(raw: &str) -> Option<String> {
+    let n = normalize_isbn(raw)?;
+    match n.len() {
+        13 => Some(n),
+        10 => {
+            let core: String = format!("978{}", &n[..9]);
+            let check = ean13_check_digit(&core)?;
+            Some(format!("{core}{check}"))
+        }
+        _ => None,
+    }
+}
+
+/// The ISBN-10 form of a compact ISBN-13, which only `978` ISBNs have.
+pub(crate) fn isbn10_from_13(isbn13: &str) -> Option<String> {
+    let core = isbn13.strip_prefix("978")?.get(..9)?;
+    let mut sum = 0u32;
+    for (i, c) in core.chars().enumerate() {
+        sum += c.to_digit(10)? * (10 - i as u32);
+    }
+    let check = match (11 - sum % 11) % 11 {
+        10 => 'X',
+        digit => char::from_digit(digit, 10)?,
+    };
+    Some(format!("{core}{check}"))
+}
+
+fn ean13_check_digit(twelve: &str) -> Option<char> {
+    if twelve.len() != 12 {
+        return None;
+    }
+    let mut sum = 0u32;
+    for (i, c) in twelve.chars().enumerate() {
+        let d = c.to_digit(10)?;
+        sum += if i % 2 == 0 { d } else { d * 3 };
+    }
+    let check = (10 - (sum % 10)) % 10;
+    char::from_digit(check, 10)
+}
+
+/// True when two ISBN strings denote the same edition, comparing across the
+/// ISBN-10 and ISBN-13 forms. The frontend relies on Rust having verified
+/// equivalence.
+pub fn isbn_equivalent(a: &str, b: &str) -> bool {
+    match (canonical_isbn13(a), canonical_isbn13(b)) {
+        (Some(x), Some(y)) => x == y,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_and_strips_prefixes() {
+        assert_eq!(
+            normalize_isbn("ISBN: 978-0-553-10354-0").as_deref(),
+            Some("9780553103540")
+        );
+        assert_eq!(normalize_isbn("0553103547").as_deref(), Some("0553103547"));
+        assert_eq!(normalize_isbn("notanisbn"), None);
+    }
+
+    #[test]
+    fn isbn_10_and_13_are_equivalent() {
+        // A Game of Thrones, ISBN-10 vs ISBN-13 for the same edition.
+        assert!(isbn_equivalent("0553103547", "9780553103540"));
+        assert!(isbn_equivalent("9780553103540", "0553103547"));
+        // Same form compares equal.
+        assert!(isbn_equivalent("9780553103540", "978-0-553-10354-0"));
+        // Different editions do not.
+        assert!(!isbn_equivalent("9780553103540", "9780374528379"));
+    }
+
+    #[test]
+    fn canonical_form_is_isbn13() {
+        assert_eq!(
+            canonical_isbn13("0-553-10354-7").as_deref(),
+            Some("9780553103540")
+        );
+        assert_eq!(canonical_isbn13("garbage"), None);
+    }
+
+    #[test]
+    fn isbn13_converts_back_to_isbn10() {
+        assert_eq!(
+            isbn10_from_13("9780553103540").as_deref(),
+            Some("0553103547")
+        );
+        assert_eq!(
+            isbn10_from_13("9780306406157").as_deref(),
+            Some("0306406152")
+        );
+        assert_eq!(
+            isbn10_from_13("9780804429573").as_deref(),
+            Some("080442957X")
+        );
+        assert_eq!(isbn10_from_13("9791032305690"), None);
+    }
+
+    #[test]
+    fn equivalence_is_false_for_garbage() {
+        assert!(!isbn_equivalent("", "9780553103540"));
+        assert!(!isbn_equivalent("xyz", "9780553103540"));
+    }
+}
@@ -1,15 +1,22 @@
 mod assets;
+mod book_text;
 mod cover_image;
 mod custom_columns;
 mod entities;
 pub mod error;
+pub mod isbn;
 pub mod library;
 pub mod mime_type;
 pub(crate) mod models;
+mod new_library;
 mod operations;
+mod opf;
 pub mod persistence;
+mod preferences;
 mod queries;
 pub(crate) mod schema;
+mod schema_upgrades;
+mod search;
 pub mod sorting;
 pub mod types;
 pub mod util;
@@ -18,10 +25,16 @@ pub mod util;
 pub use custom_columns::{CustomColumn, CustomColumnKind, CustomColumnSpec, CustomValue};
 pub use error::CalibreError;
 pub use library::{
-    Author as LibraryAuthor, AuthorAdd, AuthorUpdate, Book as LibraryBook, BookAdd, BookFileInfo,
-    BookIdentifier, BookPage, BookQuery, BookSortOrder, BookUpdate, Library, SeriesSummary,
-    TagSummary,
+    Author as LibraryAuthor, AuthorAdd, AuthorSortUpdate, AuthorUpdate, Book as LibraryBook,
+    BookAdd, BookFacets, BookFileInfo, BookIdentifier, BookPage, BookQuery, BookSortOrder,
+    BookUpdate, BulkEdit, BulkSeriesIndex, BulkUpdateFailure, BulkUpdateReport, DateRange,
+    DuplicateAuthor, DuplicateAuthorGroup, DuplicateBookGroup, DuplicateBookMatch, DuplicateReason,
+    FacetCount, FullTextMatch, ImportOutcome, Library, MergeCover, OnDuplicate, RestoreFailure,
+    RestoreReport, SavedSearch, SearchReplace, SearchReplaceField, SeriesNumbering, SeriesSummary,
+    SnippetSpan, TagSummary, TrashEntry, TrashEntryKind, VirtualLibrary,
 };
+pub use preferences::{ColumnDisplay, FieldMetadata, Preferences, UserCategoryItem};
+pub use schema_upgrades::{SchemaStatus, MIN_UPGRADABLE_SCHEMA_VERSION};
 pub use types::{AuthorId, BookFileId, BookId};
 
 // Keep entity exports that are needed internally (for operations/queries)
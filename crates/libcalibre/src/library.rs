@@ -1,25 +1,37 @@
-use std::{collections::HashMap, path::Path, path::PathBuf};
+use std::{collections::HashMap, path::Path, path::PathBuf, time::Duration};
 
 use chrono::{NaiveDate, NaiveDateTime};
 use diesel::{prelude::*, sql_query, RunQueryDsl, SqliteConnection};
-use sanitise_file_name::sanitise;
 
 use crate::{
-    cover_image::cover_image_data_from_path,
     custom_columns::{self, CustomColumn, CustomColumnKind, CustomColumnSpec, CustomValue},
-    entities::book_file::NewBookFile,
-    operations,
+    operations::{
+        self,
+        saved_searches::{self, NamedSearches},
+    },
     persistence::establish_connection,
+    preferences::Preferences,
     queries::{authors as author_queries, book_files, books as book_queries},
-    sorting,
+    search::{
+        fulltext::{self, TextSources},
+        index as search_index,
+    },
     types::{AuthorId, BookId},
     util::ValidDbPath,
-    CalibreError, NewBook, UpdateBookData,
+    CalibreError, SchemaStatus,
 };
 
 pub struct Library {
     db_path: ValidDbPath,
     conn: SqliteConnection,
+    schema_status: SchemaStatus,
+    /// Whether the metadata search index is attached. Without it, text
+    /// queries fall back to scanning with `LIKE`.
+    search_index: bool,
+    /// Whether Calibre's `full-text-search.db` is attached. Calibre creates
+    /// it when full-text search is turned on, and only then are new books'
+    /// texts extracted automatically.
+    calibre_full_text: bool,
 }
 
 #[derive(Clone, Debug)]
@@ -42,6 +54,13 @@ pub struct Book {
     /// no language metadata.
     pub language_codes: Vec<String>,
     pub identifiers: Vec<BookIdentifier>,
+    pub publisher: Option<String>,
+    /// Calibre's 0–10 scale (two points per displayed star); `None` when
+    /// unrated.
+    pub rating: Option<i32>,
+    /// Calibre calls this `pubdate`. `None` when Calibre's "undefined date"
+    /// sentinel (year 101) or no value is stored.
+    pub publication_date: Option<NaiveDateTime>,
     pub has_cover: bool,
     pub is_read: bool,
     pub files: Vec<BookFileInfo>,
@@ -85,6 +104,7 @@ pub struct BookAdd {
     pub file_paths: Vec<PathBuf>,
 }
 
+#[derive(Default)]
 pub struct BookUpdate {
     pub title: Option<String>,
 
@@ -112,10 +132,20 @@ pub struct BookUpdate {
     /// (canonicalized to Calibre's ISO 639-2/3 form, deduped, order preserved).
     /// An empty list clears all language links; `None` leaves them unchanged.
     pub language_codes: Option<Vec<String>>,
+    /// If provided, links the book to the named publisher (created if it does
+    /// not exist), replacing any existing one. An empty (or whitespace) name
+    /// unlinks the publisher. Publishers no book uses any more are deleted.
     pub publisher: Option<String>,
     pub publication_date: Option<NaiveDate>,
+    /// Calibre's 0–10 scale (two points per star). Values are clamped into
+    /// range; `Some(0)` clears the rating. `None` leaves it unchanged.
     pub rating: Option<i32>,
+    /// Alias for `description`: both write Calibre's `comments` table, and an
+    /// empty (or whitespace) value removes the stored text.
+    /// **Do not use both `description` and `comments` at the same time.**
     pub comments: Option<String>,
+    /// If provided, replaces all of the book's identifiers (type -> value).
+    /// Types are lowercased; an empty map clears every identifier.
     pub identifiers: Option<HashMap<String, String>>,
 }
 
@@ -141,7 +171,8 @@ pub struct AuthorUpdate {
 
 /// Sort order for [`Library::query_books`]. Orders by Calibre's precomputed
 /// sort columns (`books.sort` for titles, the primary linked author's
-/// `authors.sort` for authors), with `books.id` as a stable tiebreak.
+/// `authors.sort` for authors), with `books.id` as a stable tiebreak. Books
+/// with no value for the field sort first ascending, last descending.
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
 pub enum BookSortOrder {
     #[default]
@@ -149,6 +180,30 @@ pub enum BookSortOrder {
     TitleDesc,
     AuthorAsc,
     AuthorDesc,
+    /// Date added to the library (`books.timestamp`).
+    AddedAsc,
+    AddedDesc,
+    /// Date the book's metadata last changed (`books.last_modified`).
+    ModifiedAsc,
+    ModifiedDesc,
+    /// Publication date (`books.pubdate`).
+    PublishedAsc,
+    PublishedDesc,
+    /// Series sort name, then position in the series.
+    SeriesAsc,
+    SeriesDesc,
+    RatingAsc,
+    RatingDesc,
+    PublisherAsc,
+    PublisherDesc,
+    /// Size of the book's largest file.
+    SizeAsc,
+    SizeDesc,
+    /// The value of the custom column with this id. Comments and composite
+    /// columns cannot be sorted by
+    /// ([`CalibreError::UnsupportedCustomColumn`]).
+    CustomColumnAsc(i32),
+    CustomColumnDesc(i32),
 }
 
 /// A paged, sorted, filtered book query. All filters compose (AND).
@@ -167,6 +222,44 @@ pub struct BookQuery {
     /// Exclude books marked read (filtered in SQL, so paging and totals stay
     /// correct).
     pub hide_read: bool,
+    /// Only books linked to at least one of these tags. Empty matches all.
+    pub tags_any: Vec<i32>,
+    /// Only books linked to every one of these tags. Empty matches all.
+    pub tags_all: Vec<i32>,
+    /// Only books in at least one of these languages (any ISO 639 form;
+    /// canonicalized like [`BookUpdate::language_codes`]). Empty matches all.
+    pub language_codes: Vec<String>,
+    /// Only books with a file in at least one of these formats (`EPUB`,
+    /// `pdf`, ...; case-insensitive). Empty matches all.
+    pub formats: Vec<String>,
+    /// Only books linked to this publisher.
+    pub publisher_id: Option<i32>,
+    /// Only books rated at least this, on Calibre's 0–10 scale. Unrated
+    /// books never match a rating bound.
+    pub min_rating: Option<i32>,
+    /// Only books rated at most this, on Calibre's 0–10 scale.
+    pub max_rating: Option<i32>,
+    /// Only books with (`Some(true)`) or without (`Some(false)`) a cover.
+    pub has_cover: Option<bool>,
+    /// Only books with an identifier of each of these types (`isbn`,
+    /// `amazon`, ...).
+    pub with_identifiers: Vec<String>,
+    /// Only books with no identifier of any of these types.
+    pub without_identifiers: Vec<String>,
+    /// Only books added to the library within this range.
+    pub added: DateRange,
+    /// Only books published within this range. Books with no publication
+    /// date never match a bounded range.
+    pub published: DateRange,
+    /// Only books in the virtual library with this name (see
+    /// [`Library::virtual_libraries`]). An unknown name is a
+    /// [`CalibreError::InvalidSearch`].
+    pub virtual_library: Option<String>,
+    /// A query in Calibre's search language, e.g.
+    /// `tag:fantasy and not series:"Discworld" rating:>=4`. `None` or
+    /// empty/whitespace matches all books; a query that does not parse is a
+    /// [`CalibreError::InvalidSearch`].
+    pub search: Option<String>,
     pub sort: BookSortOrder,
     /// Maximum number of books to return. `None` returns all matches.
     pub limit: Option<i64>,
@@ -174,6 +267,14 @@ pub struct BookQuery {
     pub offset: i64,
 }
 
+/// An inclusive range of days for [`BookQuery`] date filters. `None` leaves
+/// that end open; the default range matches every book.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DateRange {
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+}
+
 /// One page of results from [`Library::query_books`].
 #[derive(Clone, Debug)]
 pub struct BookPage {
@@ -184,6 +285,56 @@ pub struct BookPage {
     pub total: i64,
 }
 
+/// How many of a query's matching books fall under each author, series,
+/// tag, publisher, language, format and rating: the counts Calibre's tag
+/// browser shows. Returned by [`Library::facets`]. Values no matching book
+/// has are absent.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BookFacets {
+    /// Number of books matching the query.
+    pub total: i64,
+    /// Sorted by author sort.
+    pub authors: Vec<FacetCount<AuthorId>>,
+    pub series: Vec<FacetCount<i32>>,
+    pub tags: Vec<FacetCount<i32>>,
+    pub publishers: Vec<FacetCount<i32>>,
+    /// Keyed by canonical language code, which is also the name.
+    pub languages: Vec<FacetCount<String>>,
+    /// Keyed by uppercase format name, which is also the name.
+    pub formats: Vec<FacetCount<String>>,
+    /// Keyed by rating on Calibre's 0–10 scale, named in stars (`4.5`),
+    /// highest first. Unrated books are not counted.
+    pub ratings: Vec<FacetCount<i32>>,
+}
+
+/// One value of a [`BookFacets`] category. `value` is what the matching
+/// [`BookQuery`] filter takes (`author_id`, `tags_any`, `min_rating`, ...),
+/// so a client can narrow the query by it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FacetCount<V> {
+    pub value: V,
+    pub name: String,
+    pub book_count: i64,
+}
+
+/// A book whose text matched [`Library::search_full_text`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct FullTextMatch {
+    pub book_id: BookId,
+    /// The format whose text matched (`EPUB`, `TXT`, ...).
+    pub format: String,
+    /// The text around the first match, split into highlighted (matching)
+    /// and plain spans. Begins or ends with `…` where the text goes on.
+    pub snippet: Vec<SnippetSpan>,
+}
+
+/// One run of a [`FullTextMatch`] snippet.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SnippetSpan {
+    pub text: String,
+    pub highlighted: bool,
+}
+
 /// One series in the library, with its linked-book count. Returned by
 /// [`Library::list_series`]; series ids are what [`BookQuery::series_id`]
 /// filters on.
@@ -194,6 +345,208 @@ pub struct SeriesSummary {
     pub book_count: i64,
 }
 
+/// How a series is numbered. Returned by [`Library::series_numbering`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct SeriesNumbering {
+    pub series: SeriesSummary,
+    /// Every book with its `series_index`, in reading order.
+    pub books: Vec<(BookId, f32)>,
+    /// Whole numbers from 1 up to the highest index that no book has.
+    /// Fractional indices (a novella at 2.5) neither fill nor open a gap.
+    pub gaps: Vec<i32>,
+    /// Indices shared by more than one book, with those books.
+    pub duplicates: Vec<(f32, Vec<BookId>)>,
+}
+
+/// What [`Library::recompute_author_sorts`] changed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AuthorSortUpdate {
+    /// Authors whose sort name was re-derived to something new.
+    pub authors_updated: usize,
+    /// Books whose author sort changed as a result.
+    pub books_updated: usize,
+}
+
+/// Authors that are probably one person written differently, found by
+/// [`Library::find_duplicate_authors`].
+#[derive(Clone, Debug)]
+pub struct DuplicateAuthorGroup {
+    /// The [`crate::sorting::author_name_key`] the authors share.
+    pub key: String,
+    /// Most books first; the first is the natural one to merge the rest into.
+    pub authors: Vec<DuplicateAuthor>,
+}
+
+#[derive(Clone, Debug)]
+pub struct DuplicateAuthor {
+    pub author: Author,
+    pub book_count: i64,
+}
+
+/// Why two books are taken to be the same.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DuplicateReason {
+    /// They share an ISBN, counting ISBN-10 and ISBN-13 forms as equal.
+    Isbn,
+    /// Their titles match under [`crate::sorting::title_key`] and they share
+    /// an author under [`crate::sorting::author_name_key`].
+    TitleAndAuthor,
+    /// They have a format file with identical content.
+    SameFile,
+}
+
+/// A book already in the library that a new one would duplicate.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DuplicateBookMatch {
+    pub book_id: BookId,
+    pub reasons: Vec<DuplicateReason>,
+}
+
+/// Books in the library that look like one another, found by
+/// [`Library::find_duplicate_books`]. Each book is linked to another in the
+/// group for at least one of the `reasons`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DuplicateBookGroup {
+    pub book_ids: Vec<BookId>,
+    pub reasons: Vec<DuplicateReason>,
+}
+
+/// What [`Library::import_book`] does with a book the library already has.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OnDuplicate {
+    /// Add nothing, and report the duplicates.
+    Skip,
+    /// Add the new book's files to the closest duplicate as extra formats.
+    /// Formats that book already has are left as they are.
+    AddFormats,
+    /// Add it as a separate book regardless.
+    AddAnyway,
+}
+
+/// What [`Library::import_book`] did.
+#[derive(Clone, Debug)]
+pub enum ImportOutcome {
+    Added(Box<Book>),
+    Skipped {
+        duplicates: Vec<DuplicateBookMatch>,
+    },
+    /// The files were added to an existing book; `formats` lists the formats
+    /// it gained, which is empty when it already had them all.
+    AddedFormats {
+        book_id: BookId,
+        formats: Vec<String>,
+    },
+}
+
+/// Which cover [`Library::merge_books`] leaves the merged book with.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MergeCover {
+    /// The target's own cover, or the first source's if it has none.
+    #[default]
+    Target,
+    /// The first source's cover that has one, or the target's if none does.
+    Source,
+    /// The largest cover file of them all; the target's on a tie.
+    Largest,
+}
+
+/// Changes [`Library::bulk_update`] makes to every book it is given. Each
+/// field left `None` (or empty) leaves that metadata as it is.
+#[derive(Clone, Debug, Default)]
+pub struct BulkEdit {
+    /// Tags to add; a tag the book has in any case is not added again.
+    pub add_tags: Vec<String>,
+    /// Tags to remove, in any case.
+    pub remove_tags: Vec<String>,
+    /// Link every book to the named series (created if it does not exist).
+    /// An empty (or whitespace) name unlinks the books from their series.
+    pub series: Option<String>,
+    /// How the books are numbered in `series`. Ignored without it.
+    pub series_index: BulkSeriesIndex,
+    /// An empty (or whitespace) name unlinks the publisher.
+    pub publisher: Option<String>,
+    /// Replaces the books' languages, as [`BookUpdate::language_codes`].
+    pub language_codes: Option<Vec<String>>,
+    /// Calibre's 0–10 scale; `Some(0)` clears the rating.
+    pub rating: Option<i32>,
+    pub is_read: Option<bool>,
+    /// Values to set (or clear, with `None`), keyed by custom column id.
+    pub custom_values: HashMap<i32, Option<CustomValue>>,
+    /// Applied after the other changes, to the values they leave.
+    pub search_replace: Option<SearchReplace>,
+}
+
+/// The series index [`BulkEdit::series`] gives each book.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum BulkSeriesIndex {
+    /// Each book keeps the index it has.
+    #[default]
+    Keep,
+    /// Number the books in the order given, after the highest whole index
+    /// among the series' other books (from 1 in an empty series), as
+    /// Calibre's "automatically number books" does.
+    Next,
+    /// Number the books in the order given, from this index.
+    From(f32),
+}
+
+/// A regular expression replaced throughout one field of each book, as in
+/// Calibre's bulk "Search & replace".
+#[derive(Clone, Debug)]
+pub struct SearchReplace {
+    pub field: SearchReplaceField,
+    /// A regular expression; every match is replaced.
+    pub pattern: String,
+    /// May refer to capture groups as `$1` or `${name}`.
+    pub replacement: String,
+    pub case_sensitive: bool,
+}
+
+/// The fields [`SearchReplace`] can edit. Tags are edited one by one; a tag
+/// or series replaced with nothing is removed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SearchReplaceField {
+    Title,
+    Tags,
+    Series,
+    Publisher,
+    Comments,
+}
+
+/// The outcome of [`Library::bulk_update`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BulkUpdateReport {
+    /// Books the edit was applied to, in the order given.
+    pub updated: Vec<BookId>,
+    /// Books left as they were.
+    pub failed: Vec<BulkUpdateFailure>,
+}
+
+/// A book [`Library::bulk_update`] could not edit, and why.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BulkUpdateFailure {
+    pub book_id: BookId,
+    pub reason: String,
+}
+
+/// A query saved under a name, as Calibre keeps them in its
+/// `saved_searches` preference. Run it with `search:"name"` in
+/// [`BookQuery::search`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SavedSearch {
+    pub name: String,
+    pub query: String,
+}
+
+/// A named view of part of the library, as Calibre keeps them in its
+/// `virtual_libraries` preference. Select it with
+/// [`BookQuery::virtual_library`]; an empty `search` is the whole library.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VirtualLibrary {
+    pub name: String,
+    pub search: String,
+}
+
 /// One tag in the library. Returned by [`Library::list_tags`]; the full
 /// vocabulary feeds tag autocomplete in clients.
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -202,16 +555,155 @@ pub struct TagSummary {
     pub name: String,
 }
 
+/// What a trash entry holds: a whole removed book, or formats removed from a
+/// book that is still in the library.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrashEntryKind {
+    Book,
+    Formats,
+}
+
+/// One entry in the library's trash (`.caltrash`). Returned by
+/// [`Library::list_trash`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct TrashEntry {
+    pub book_id: BookId,
+    pub kind: TrashEntryKind,
+    pub title: String,
+    pub authors: Vec<String>,
+    /// Uppercase format names (`EPUB`, `PDF`, ...) held by the entry.
+    pub formats: Vec<String>,
+    /// When the entry was trashed (UTC), from its folder's modification time.
+    pub trashed_at: NaiveDateTime,
+}
+
+/// The outcome of [`Library::restore_from_opf`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RestoreReport {
+    /// Books recreated, under the ids their `metadata.opf` records.
+    pub restored: Vec<BookId>,
+    /// Book folders that could not be restored.
+    pub failed: Vec<RestoreFailure>,
+}
+
+/// A book folder [`Library::restore_from_opf`] skipped, and why.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RestoreFailure {
+    /// Relative to the library root, e.g. `Author/Title (12)`.
+    pub path: String,
+    pub reason: String,
+}
+
 impl Library {
+    /// Open the library. A database an older Calibre created is left as it
+    /// is: [`Library::schema_status`] reports it, and
+    /// [`Library::upgrade_schema`] upgrades it once the user agrees.
     pub fn new(db_path: ValidDbPath) -> Result<Self, CalibreError> {
-        let conn = establish_connection(&db_path.database_path)?;
+        let mut conn = establish_connection(&db_path.database_path)?;
+        let schema_status = crate::schema_upgrades::detect_schema(&mut conn)?;
+        let search_index = match search_index::attach(&mut conn, &db_path.library_path) {
+            Ok(_) => true,
+            Err(e) => {
+                eprintln!(
+                    "WARNING: Search index unavailable, searching without it: {}",
+                    e
+                );
+                false
+            }
+        };
+
+        let calibre_full_text = fulltext::attach_calibre(&mut conn, &db_path.library_path)
+            .unwrap_or_else(|e| {
+                eprintln!(
+                    "WARNING: Calibre's full-text database unavailable, searching without it: {}",
+                    e
+                );
+                false
+            });
 
-        Ok(Self { db_path, conn })
+        Ok(Self {
+            db_path,
+            conn,
+            schema_status,
+            search_index,
+            calibre_full_text,
+        })
     }
 
-    /// Create a new Calibre library in the provided folder.
-    pub fn create_library_at(_path: &str) -> Result<Library, CalibreError> {
-        todo!()
+    /// The database's schema version, and whether it needs an upgrade, has
+    /// been upgraded, or is newer than this crate understands.
+    pub fn schema_status(&self) -> SchemaStatus {
+        self.schema_status
+    }
+
+    /// Upgrade a database [`Library::schema_status`] reports as needing it,
+    /// after copying it to `metadata_v<from>_backup.db` in the library
+    /// folder. Any other database is left alone and its status returned.
+    pub fn upgrade_schema(&mut self) -> Result<SchemaStatus, CalibreError> {
+        if let SchemaStatus::NeedsUpgrade { from, .. } = self.schema_status {
+            let backup =
+                Path::new(&self.db_path.library_path).join(format!("metadata_v{from}_backup.db"));
+            crate::schema_upgrades::backup_database(&mut self.conn, &backup)?;
+            self.schema_status = crate::schema_upgrades::upgrade_schema(&mut self.conn)?;
+        }
+        Ok(self.schema_status)
+    }
+
+    /// Create a new, empty Calibre library in the provided folder (created if
+    /// missing) and open it. The `metadata.db` matches what Calibre 7.x writes
+    /// for a new library, with a fresh random library UUID. Fails if the
+    /// folder already holds a `metadata.db`.
+    pub fn create_library_at(path: &str) -> Result<Library, CalibreError> {
+        let library_root = Path::new(path);
+        std::fs::create_dir_all(library_root)?;
+        crate::new_library::initialize_database(&library_root.join("metadata.db"))?;
+
+        let db_path = crate::util::get_db_path(path).ok_or_else(|| {
+            CalibreError::FileSystem(format!("Could not open new library at {path}"))
+        })?;
+        Library::new(db_path)
+    }
+
+    /// Rebuild the library's `metadata.db` from the `metadata.opf` in each
+    /// book folder, like Calibre's "Restore database". The database in place
+    /// (if any) is kept as `metadata_pre_restore.db`, and put back should the
+    /// restore itself fail; the restore is refused while an earlier backup of
+    /// that name is still there. Folders that cannot be restored — no or an
+    /// unparseable `metadata.opf`, a book id already taken — are listed in
+    /// the report instead.
+    ///
+    /// No `Library` may have the database open while it is replaced.
+    pub fn restore_from_opf(library_root: &str) -> Result<(Library, RestoreReport), CalibreError> {
+        let root = Path::new(library_root);
+        let db_file = root.join("metadata.db");
+        let had_database = db_file.exists();
+        if had_database {
+            if root.join("metadata_pre_restore.db").exists() {
+                return Err(CalibreError::FileSystem(format!(
+                    "{} already exists; move it away before restoring again",
+                    root.join("metadata_pre_restore.db").display()
+                )));
+            }
+            move_database(root, "metadata.db", "metadata_pre_restore.db")?;
+        }
+
+        let result = (|| {
+            crate::new_library::initialize_database(&db_file)?;
+            let db_path = crate::util::get_db_path(library_root).ok_or_else(|| {
+                CalibreError::FileSystem(format!("Could not open new library at {library_root}"))
+            })?;
+            let mut library = Library::new(db_path)?;
+            let report = operations::restore::restore_library(library_root, &mut library.conn)?;
+            Ok((library, report))
+        })();
+
+        if result.is_err() {
+            let _ = remove_database(root, "metadata.db");
+            if had_database {
+                let _ = move_database(root, "metadata_pre_restore.db", "metadata.db");
+            }
+        }
+        result
     }
 
     pub fn library_path(&self) -> &str {
@@ -226,154 +718,100 @@ impl Library {
     // Books
     // =========================================================================
 
+    /// Add a book with its files to the library. Nothing is left behind if
+    /// any part of the add fails; see [`operations::books::add_book`].
     pub fn add_book(&mut self, book: BookAdd) -> Result<Book, CalibreError> {
-        // 1. Create/find authors
-        let created_authors: Vec<crate::entities::author::Author> = book
-            .author_names
-            .iter()
-            .map(|name| author_queries::create_if_not_exists(&mut self.conn, name))
-            .collect::<Result<Vec<_>, _>>()?;
-
-        // 2. Create book record
-        let new_book = NewBook {
-            title: book.title.clone(),
-            timestamp: None,
-            pubdate: book
-                .publication_date
-                .map(|d| NaiveDateTime::new(d, chrono::NaiveTime::from_hms_opt(0, 0, 0).unwrap())),
-            series_index: book.series_index.unwrap_or(1.0),
-            has_cover: None,
-        };
-        let book_row = book_queries::create(&mut self.conn, new_book)?;
-
-        // Re-fetch to get trigger-generated uuid and sort
-        let book_row = book_queries::get(&mut self.conn, BookId(book_row.id))?
-            .ok_or(CalibreError::BookNotFound(BookId(book_row.id)))?;
-
-        // 3. Set author_sort and link authors
-        let combined_sort = created_authors
-            .iter()
-            .map(|a| sorting::sort_author_name_apa(&a.name))
-            .collect::<Vec<String>>()
-            .join(" & ");
-        let _ = book_queries::update(
-            &mut self.conn,
-            BookId(book_row.id),
-            UpdateBookData {
-                author_sort: Some(combined_sort),
-                ..Default::default()
-            },
-        );
+        let book_id =
+            operations::books::add_book(&self.db_path.library_path, &mut self.conn, book)?;
 
-        for author in &created_authors {
-            author_queries::link_book(&mut self.conn, AuthorId(author.id), BookId(book_row.id))?;
-        }
+        // Generate metadata.opf from the freshly written DB state.
+        let _ = self.regenerate_metadata_opf(book_id);
+        self.index_new_book_text(book_id);
 
-        if let Some(tags) = &book.tags {
-            for tag_name in unique_tag_names(tags) {
-                let tag = crate::queries::tags::create_if_not_exists(&mut self.conn, tag_name)?;
-                crate::queries::tags::link_book(&mut self.conn, tag.id, BookId(book_row.id))?;
-            }
-        }
+        self.get_book(book_id)
+    }
 
-        if let Some(series_name) = &book.series {
-            let series = crate::queries::series::create_if_not_exists(&mut self.conn, series_name)?;
-            crate::queries::series::link_book(&mut self.conn, series.id, BookId(book_row.id))?;
+    /// Add a book unless the library already has it, by ISBN, by title and
+    /// author, or by identical file content; `on_duplicate` decides what
+    /// happens when it does.
+    pub fn import_book(
+        &mut self,
+        book: BookAdd,
+        on_duplicate: OnDuplicate,
+    ) -> Result<ImportOutcome, CalibreError> {
+        if on_duplicate == OnDuplicate::AddAnyway {
+            return self
+                .add_book(book)
+                .map(|book| ImportOutcome::Added(Box::new(book)));
         }
-
-        if let Some(language) = &book.language {
-            crate::queries::languages::set_for_book(
-                &mut self.conn,
-                BookId(book_row.id),
-                std::slice::from_ref(language),
-            )?;
+        let duplicates = self.find_duplicates_of(&book)?;
+        let Some(closest) = duplicates.first() else {
+            return self
+                .add_book(book)
+                .map(|book| ImportOutcome::Added(Box::new(book)));
+        };
+        if on_duplicate == OnDuplicate::Skip {
+            return Ok(ImportOutcome::Skipped { duplicates });
         }
 
-        // 4. Create directories
-        let primary_author = created_authors
-            .first()
-            .map(|a| a.name.clone())
-            .unwrap_or_default();
-        let author_dir_name = primary_author.clone();
-        let book_dir_name = sanitise(&format!("{} ({})", &book.title, book_row.id));
-        let book_dir_relative = Path::new(&author_dir_name).join(&book_dir_name);
-        let library_root = Path::new(&self.db_path.library_path);
-
-        std::fs::create_dir_all(library_root.join(&book_dir_relative))
-            .map_err(|e| CalibreError::FileSystem(e.to_string()))?;
-
-        // Update book with relative path
-        let _ = book_queries::update(
+        let book_id = closest.book_id;
+        let formats = operations::books::add_formats(
+            &self.db_path.library_path,
             &mut self.conn,
-            BookId(book_row.id),
-            UpdateBookData {
-                path: Some(book_dir_relative.to_str().unwrap().to_string()),
-                ..Default::default()
-            },
-        );
-
-        // 5. Copy book files
-        let mut created_files = Vec::new();
-        for file_path in &book.file_paths {
-            let extension = file_path
-                .extension()
-                .and_then(|e| e.to_str())
-                .unwrap_or("")
-                .to_uppercase();
-            let file_stem = sanitise(&format!("{} - {}", &book.title, &primary_author));
-            let file_size = std::fs::metadata(file_path)
-                .map(|m| m.len() as i32)
-                .unwrap_or(0);
-
-            let new_file = NewBookFile {
-                book: book_row.id,
-                format: extension.clone(),
-                uncompressed_size: file_size,
-                name: file_stem.clone(),
-            };
-            book_files::create(&mut self.conn, new_file)?;
-
-            // Copy file to library
-            let dest_filename = if extension.is_empty() {
-                file_stem.clone()
-            } else {
-                format!("{}.{}", file_stem, extension.to_lowercase())
-            };
-            let dest_path = library_root.join(&book_dir_relative).join(&dest_filename);
-            if file_path.exists() {
-                std::fs::copy(file_path, &dest_path)
-                    .map_err(|e| CalibreError::FileSystem(e.to_string()))?;
-            }
-
-            created_files.push(crate::entities::book_file::BookFile {
-                id: 0, // We don't have the ID from create, but it's fine for now
-                book: book_row.id,
-                format: extension,
-                uncompressed_size: file_size,
-                name: file_stem,
-            });
+            book_id,
+            &book.file_paths,
+        )?;
+        if !formats.is_empty() {
+            self.index_new_book_text(book_id);
         }
+        Ok(ImportOutcome::AddedFormats { book_id, formats })
+    }
 
-        // 6. Extract cover image
-        if let Some(primary_file) = book.file_paths.first() {
-            if let Ok(Some(cover_data)) = cover_image_data_from_path(primary_file) {
-                let cover_path = library_root.join(&book_dir_relative).join("cover.jpg");
-                let _ = std::fs::write(&cover_path, &cover_data);
-                let _ = book_queries::update(
-                    &mut self.conn,
-                    BookId(book_row.id),
-                    UpdateBookData {
-                        has_cover: Some(true),
-                        ..Default::default()
-                    },
-                );
-            }
-        }
+    /// Books already in the library that `book` would duplicate, the closest
+    /// (most reasons) first.
+    pub fn find_duplicates_of(
+        &mut self,
+        book: &BookAdd,
+    ) -> Result<Vec<DuplicateBookMatch>, CalibreError> {
+        let library_root = Path::new(&self.db_path.library_path);
+        operations::duplicates::matches(library_root, &mut self.conn, book)
+    }
 
-        // 7. Generate metadata.opf from the freshly written DB state.
-        let _ = self.regenerate_metadata_opf(BookId(book_row.id));
+    /// Scan the whole library for books that look like one another. Reads
+    /// only the format files that share a size with another book's.
+    pub fn find_duplicate_books(&mut self) -> Result<Vec<DuplicateBookGroup>, CalibreError> {
+        let library_root = Path::new(&self.db_path.library_path);
+        operations::duplicates::books(library_root, &mut self.conn)
+    }
 
-        self.get_book(BookId(book_row.id))
+    /// Merge duplicate records into `target`, as Calibre's "Merge books"
+    /// does. The target keeps its metadata and gains what it lacks from the
+    /// sources: single values it has none of, and every tag, language,
+    /// identifier and multi-valued custom value. Formats it lacks move into
+    /// its folder; the rest go to the trash with the sources. If any step
+    /// fails, nothing changes.
+    pub fn merge_books(
+        &mut self,
+        target: BookId,
+        sources: &[BookId],
+        cover: MergeCover,
+    ) -> Result<Book, CalibreError> {
+        for &source in sources {
+            // The trash keeps only what metadata.opf records.
+            let _ = self.regenerate_metadata_opf(source);
+        }
+        operations::merge::merge_books(
+            &self.db_path.library_path,
+            &mut self.conn,
+            target,
+            sources,
+            cover,
+        )?;
+        // Filling in missing authors can rename the folder.
+        self.relocate_book_files(target);
+        self.write_dirty_opfs(&[target]);
+        self.index_new_book_text(target);
+        self.get_book(target)
     }
 
     pub fn books(&mut self) -> Result<Vec<Book>, CalibreError> {
@@ -402,6 +840,9 @@ impl Library {
     ) -> Result<Book, CalibreError> {
         // Handle is_read separately since it uses custom columns
         let is_read_update = update.is_read;
+        // Title and first author determine the book's folder and file names.
+        let renames_files =
+            update.title.is_some() || update.author_names.is_some() || update.author_ids.is_some();
 
         operations::books::update_book(&mut self.conn, book_id, update)?;
 
@@ -409,6 +850,10 @@ impl Library {
             self.set_book_read_state(book_id, is_read)?;
         }
 
+        if renames_files {
+            self.relocate_book_files(book_id);
+        }
+
         // Keep the on-disk metadata.opf faithful to the DB. Best-effort: a
         // metadata write failure must not fail the (already committed) update.
         let _ = self.regenerate_metadata_opf(book_id);
@@ -416,38 +861,83 @@ impl Library {
         self.get_book(book_id)
     }
 
-    /// Rewrite a book's `metadata.opf` from current DB state (title, authors,
-    /// language codes, …). Called after both add and update so the derived
+    /// Apply one edit to many books in a single transaction, writing their
+    /// OPFs once it commits. A book the edit cannot be applied to is left as
+    /// it was and reported, without stopping the rest.
+    pub fn bulk_update(
+        &mut self,
+        book_ids: &[BookId],
+        edit: BulkEdit,
+    ) -> Result<BulkUpdateReport, CalibreError> {
+        let read_column = match edit.is_read {
+            Some(_) => Some(self.get_or_create_read_state_column()?),
+            None => None,
+        };
+        let report =
+            operations::bulk::update(&mut self.conn, book_ids, &edit, read_column.as_ref())?;
+
+        // Of the fields a bulk edit touches, only the title names files.
+        let renames_files = edit
+            .search_replace
+            .as_ref()
+            .is_some_and(|search_replace| search_replace.field == SearchReplaceField::Title);
+        if renames_files {
+            for &book_id in &report.updated {
+                self.relocate_book_files(book_id);
+            }
+        }
+        self.write_dirty_opfs(&report.updated);
+        Ok(report)
+    }
+
+    /// Move the book's folder and files to match its current title and first
+    /// author. Best-effort like OPF writes: the relocation is crash-safe, so a
+    /// failure leaves the old (still consistent) layout in place and must not
+    /// fail the already committed metadata change.
+    fn relocate_book_files(&mut self, book_id: BookId) {
+        if let Err(e) = operations::book_paths::relocate_book(
+            &self.db_path.library_path,
+            &mut self.conn,
+            book_id,
+        ) {
+            eprintln!("WARNING: Failed to move files for {}: {}", book_id, e);
+        }
+    }
+
+    /// Rewrite a book's `metadata.opf` from current DB state (every field
+    /// Calibre records, custom columns included). Called after both add and update so the derived
     /// file never drifts from the database. Missing files/dirs are surfaced as
     /// `Err`, but callers treat OPF writes as best-effort.
     fn regenerate_metadata_opf(&mut self, book_id: BookId) -> Result<(), CalibreError> {
-        let book_row = book_queries::get(&mut self.conn, book_id)?
-            .ok_or(CalibreError::BookNotFound(book_id))?;
-        let author_ids = book_queries::find_authors(&mut self.conn, book_id)?;
-        let authors = author_queries::get_many(&mut self.conn, author_ids)?;
-        let language_codes =
-            crate::queries::languages::find_codes_for_book(&mut self.conn, book_id)?;
-
-        let contents = format_metadata_opf(&book_row, &authors, &language_codes)
-            .map_err(|_| CalibreError::DatabaseIntegrity("Failed to render metadata.opf".into()))?;
+        operations::metadata_opf::write_metadata_opf(
+            &self.db_path.library_path,
+            &mut self.conn,
+            book_id,
+        )
+    }
 
-        let opf_path = Path::new(&self.db_path.library_path)
-            .join(&book_row.path)
-            .join("metadata.opf");
-        std::fs::write(&opf_path, contents.as_bytes())
-            .map_err(|e| CalibreError::FileSystem(e.to_string()))
+    /// Rewrite the OPF of books an operation marked dirty, clearing each mark
+    /// once its file is written. Best-effort: a book whose OPF cannot be
+    /// written stays marked, and Calibre rewrites it the next time it runs.
+    fn write_dirty_opfs(&mut self, book_ids: &[BookId]) {
+        for &book_id in book_ids {
+            if self.regenerate_metadata_opf(book_id).is_ok() {
+                let _ = book_queries::clear_dirty(&mut self.conn, book_id);
+            }
+        }
     }
 
+    /// Move books to the library's trash, from where [`Library::restore_book`]
+    /// can bring them back until the trash is emptied.
     pub fn remove_books(&mut self, book_ids: Vec<BookId>) -> Result<Vec<BookId>, CalibreError> {
         let mut removed = Vec::new();
         for book_id in book_ids {
-            let book = book_queries::get(&mut self.conn, book_id)?
-                .ok_or(CalibreError::BookNotFound(book_id))?;
-            operations::assets::delete_entire_book(
+            // The trash keeps only what metadata.opf records.
+            let _ = self.regenerate_metadata_opf(book_id);
+            operations::trash::move_book_to_trash(
                 &self.db_path.library_path,
                 &mut self.conn,
                 book_id,
-                &book.path,
             )?;
             removed.push(book_id);
         }
@@ -472,6 +962,41 @@ impl Library {
         operations::books::cover_sources_for(&mut self.conn, ids)
     }
 
+    // =========================================================================
+    // Trash
+    // =========================================================================
+
+    /// Everything in the library's trash: removed books first, then removed
+    /// formats, each newest first.
+    pub fn list_trash(&mut self) -> Result<Vec<TrashEntry>, CalibreError> {
+        operations::trash::list_trash(&self.db_path.library_path)
+    }
+
+    /// Bring a removed book back under its original id, with the metadata
+    /// its trashed `metadata.opf` records.
+    pub fn restore_book(&mut self, book_id: BookId) -> Result<Book, CalibreError> {
+        operations::trash::restore_book(&self.db_path.library_path, &mut self.conn, book_id)?;
+        let _ = self.regenerate_metadata_opf(book_id);
+
+        self.get_book(book_id)
+    }
+
+    /// Put a removed format back on its book.
+    pub fn restore_book_file(&mut self, book_id: BookId, format: &str) -> Result<(), CalibreError> {
+        operations::trash::restore_format(
+            &self.db_path.library_path,
+            &mut self.conn,
+            book_id,
+            format,
+        )
+    }
+
+    /// Permanently delete trash entries older than `older_than`, or the whole
+    /// trash when `None`. Returns the number of entries deleted.
+    pub fn empty_trash(&mut self, older_than: Option<Duration>) -> Result<usize, CalibreError> {
+        operations::trash::empty_trash(&self.db_path.library_path, older_than)
+    }
+
     // =========================================================================
     // Assets (covers, book files)
     // =========================================================================
@@ -490,11 +1015,19 @@ impl Library {
             &mut self.conn,
             book_id,
             cover_data,
-        )
+        )?;
+        let _ = self.regenerate_metadata_opf(book_id);
+
+        Ok(())
     }
 
-    pub fn remove_book_cover(&mut self, _book_id: BookId) -> Result<(), CalibreError> {
-        todo!()
+    /// Delete the book's `cover.jpg` and mark it as having no cover. Removing
+    /// the cover of a book that has none is a no-op rather than an error.
+    pub fn remove_book_cover(&mut self, book_id: BookId) -> Result<(), CalibreError> {
+        operations::assets::remove_book_cover(&self.db_path.library_path, &mut self.conn, book_id)?;
+        let _ = self.regenerate_metadata_opf(book_id);
+
+        Ok(())
     }
 
     pub fn get_book_file(
@@ -523,8 +1056,15 @@ impl Library {
         )
     }
 
+    /// Move one format of a book to the trash. Removing a book's last format
+    /// trashes the whole book.
     pub fn remove_book_file(&mut self, book_id: BookId, format: &str) -> Result<(), CalibreError> {
-        operations::assets::remove_book_file(
+        let files = book_files::find_by_book_id(&mut self.conn, book_id)?;
+        let is_last_format = files.len() == 1 && files[0].format.eq_ignore_ascii_case(format);
+        if is_last_format {
+            return self.remove_books(vec![book_id]).map(|_| ());
+        }
+        operations::trash::move_format_to_trash(
             &self.db_path.library_path,
             &mut self.conn,
             book_id,
@@ -544,7 +1084,9 @@ impl Library {
             book_id,
             file_format,
             file_data,
-        )
+        )?;
+        self.index_new_book_text(book_id);
+        Ok(())
     }
 
     pub fn add_book_file_from_path(
@@ -559,7 +1101,9 @@ impl Library {
             book_id,
             file_format,
             file_path,
-        )
+        )?;
+        self.index_new_book_text(book_id);
+        Ok(())
     }
 
     // =========================================================================
@@ -591,7 +1135,18 @@ impl Library {
         author_id: AuthorId,
         update: AuthorUpdate,
     ) -> Result<Author, CalibreError> {
-        operations::authors::update(&mut self.conn, author_id, update)
+        let renames_files = update.name.is_some();
+        let author = operations::authors::update(&mut self.conn, author_id, update)?;
+
+        // Names, sorts and links are all recorded in each book's OPF.
+        for book_id in author_queries::find_books(&mut self.conn, author_id)? {
+            if renames_files {
+                self.relocate_book_files(book_id);
+            }
+            let _ = self.regenerate_metadata_opf(book_id);
+        }
+
+        Ok(author)
     }
 
     pub fn remove_author(&mut self, author_id: AuthorId) -> Result<AuthorId, CalibreError> {
@@ -599,6 +1154,40 @@ impl Library {
         Ok(author_id)
     }
 
+    /// Merge a duplicate author into another ("J.R.R. Tolkien" into
+    /// "J. R. R. Tolkien"): every book of `from` is relinked to `into`, with
+    /// its author sort, folder and OPF updated, and `from` is deleted.
+    /// Returns `into`.
+    pub fn merge_authors(
+        &mut self,
+        from: AuthorId,
+        into: AuthorId,
+    ) -> Result<Author, CalibreError> {
+        let book_ids = operations::authors::merge(&mut self.conn, from, into)?;
+        // Folders are named after the first author.
+        for &book_id in &book_ids {
+            self.relocate_book_files(book_id);
+        }
+        self.write_dirty_opfs(&book_ids);
+        self.get_author(into)
+    }
+
+    /// Re-derive every author's sort name from their name with
+    /// [`crate::sorting::sort_author_name_apa`], and every book's author sort
+    /// from its authors', rewriting the OPFs of books that changed.
+    pub fn recompute_author_sorts(&mut self) -> Result<AuthorSortUpdate, CalibreError> {
+        let (update, book_ids) = operations::authors::recompute_sorts(&mut self.conn)?;
+        self.write_dirty_opfs(&book_ids);
+        Ok(update)
+    }
+
+    /// Groups of authors whose names differ only in spacing, accents, case,
+    /// titles or "Last, First" order, for review before
+    /// [`Library::merge_authors`]. Changes nothing.
+    pub fn find_duplicate_authors(&mut self) -> Result<Vec<DuplicateAuthorGroup>, CalibreError> {
+        operations::duplicates::authors(&mut self.conn)
+    }
+
     // =========================================================================
     // Identifiers
     // =========================================================================
@@ -711,7 +1300,9 @@ impl Library {
         value: Option<CustomValue>,
     ) -> Result<(), CalibreError> {
         let column = custom_columns::get_column(&mut self.conn, column_id)?;
-        custom_columns::set_value(&mut self.conn, &column, book_id, value)
+        custom_columns::set_value(&mut self.conn, &column, book_id, value)?;
+        let _ = self.regenerate_metadata_opf(book_id);
+        Ok(())
     }
 
     /// One column's values for many books at once. Books with no stored
@@ -766,7 +1357,9 @@ impl Library {
             &column,
             book_id,
             Some(CustomValue::Bool(is_read)),
-        )
+        )?;
+        let _ = self.regenerate_metadata_opf(book_id);
+        Ok(())
     }
 
     pub fn batch_get_read_states(
@@ -804,6 +1397,114 @@ impl Library {
     /// Run a paged, sorted, filtered book query. Returns one page of
     /// hydrated books plus the total match count (ignoring limit/offset).
     pub fn query_books(&mut self, query: BookQuery) -> Result<BookPage, CalibreError> {
+        let search = self.compile_search(&query)?;
+        let filters = self.page_filters(&query, search.as_ref())?;
+
+        let total = book_queries::query_count(&mut self.conn, &filters)?;
+        let book_ids = book_queries::query_page(
+            &mut self.conn,
+            &filters,
+            query.sort,
+            query.limit,
+            query.offset,
+        )?;
+        let items = self.get_books_with_read_states(book_ids)?;
+
+        Ok(BookPage { items, total })
+    }
+
+    /// Count the books matching `query` per author, series, tag, publisher,
+    /// language, format and rating, as Calibre's tag browser does for the
+    /// current search. The query's sort, limit and offset are ignored.
+    pub fn facets(&mut self, query: &BookQuery) -> Result<BookFacets, CalibreError> {
+        let search = self.compile_search(query)?;
+        let filters = self.page_filters(query, search.as_ref())?;
+        book_queries::facets(&mut self.conn, &filters)
+    }
+
+    /// The query's search, restricted to its virtual library if it has one.
+    fn compile_search(
+        &mut self,
+        query: &BookQuery,
+    ) -> Result<Option<crate::search::CompiledSearch>, CalibreError> {
+        let virtual_library = query
+            .virtual_library
+            .as_deref()
+            .map(|name| crate::search::compile_virtual_library(&mut self.conn, name))
+            .transpose()?;
+        let search = query
+            .search
+            .as_deref()
+            .map(str::trim)
+            .filter(|search| !search.is_empty())
+            .map(|search| crate::search::compile(&mut self.conn, search))
+            .transpose()?;
+
+        Ok(match (virtual_library, search) {
+            (Some(virtual_library), Some(search)) => Some(virtual_library.and(search)),
+            (virtual_library, search) => virtual_library.or(search),
+        })
+    }
+
+    /// Typed and raw access to the preferences Calibre stores in the
+    /// library's database.
+    pub fn preferences(&mut self) -> Preferences<'_> {
+        Preferences::new(&mut self.conn)
+    }
+
+    /// Every saved search, sorted by name.
+    pub fn saved_searches(&mut self) -> Result<Vec<SavedSearch>, CalibreError> {
+        let entries = saved_searches::list(&mut self.conn, NamedSearches::SavedSearches)?;
+        Ok(entries
+            .into_iter()
+            .map(|(name, query)| SavedSearch { name, query })
+            .collect())
+    }
+
+    /// Save `query` under `name`, replacing any saved search of that name.
+    /// The query must be valid ([`CalibreError::InvalidSearch`] otherwise).
+    pub fn save_search(&mut self, name: &str, query: &str) -> Result<(), CalibreError> {
+        saved_searches::set(&mut self.conn, NamedSearches::SavedSearches, name, query)
+    }
+
+    /// Delete the saved search `name`. Returns whether it existed.
+    pub fn remove_saved_search(&mut self, name: &str) -> Result<bool, CalibreError> {
+        saved_searches::remove(&mut self.conn, NamedSearches::SavedSearches, name)
+    }
+
+    /// Every virtual library, sorted by name.
+    pub fn virtual_libraries(&mut self) -> Result<Vec<VirtualLibrary>, CalibreError> {
+        let entries = saved_searches::list(&mut self.conn, NamedSearches::VirtualLibraries)?;
+        Ok(entries
+            .into_iter()
+            .map(|(name, search)| VirtualLibrary { name, search })
+            .collect())
+    }
+
+    /// Define the virtual library `name` as the books matching `search`,
+    /// replacing any virtual library of that name. An empty search is the
+    /// whole library.
+    pub fn save_virtual_library(&mut self, name: &str, search: &str) -> Result<(), CalibreError> {
+        saved_searches::set(
+            &mut self.conn,
+            NamedSearches::VirtualLibraries,
+            name,
+            search,
+        )
+    }
+
+    /// Delete the virtual library `name`. Returns whether it existed.
+    pub fn remove_virtual_library(&mut self, name: &str) -> Result<bool, CalibreError> {
+        saved_searches::remove(&mut self.conn, NamedSearches::VirtualLibraries, name)
+    }
+
+    /// The SQL filters for `query`, shared by [`Library::query_books`] and
+    /// [`Library::facets`].
+    fn page_filters<'q>(
+        &mut self,
+        query: &'q BookQuery,
+        search: Option<&'q crate::search::CompiledSearch>,
+    ) -> Result<book_queries::BookPageFilters<'q>, CalibreError> {
         // The read state lives in the `read` bool custom column. When the
         // column does not exist yet, no book has been marked read, so there
         // is nothing to hide.
@@ -814,7 +1515,7 @@ impl Library {
             None
         };
 
-        let filters = book_queries::BookPageFilters {
+        Ok(book_queries::BookPageFilters {
             text: query
                 .text
                 .as_deref()
@@ -823,19 +1524,126 @@ impl Library {
             author_id: query.author_id,
             series_id: query.series_id,
             hide_read_column,
-        };
+            tags_any: &query.tags_any,
+            tags_all: &query.tags_all,
+            language_codes: query
+                .language_codes
+                .iter()
+                .map(|code| crate::queries::languages::canonicalize_lang_code(code))
+                .collect(),
+            formats: query
+                .formats
+                .iter()
+                .map(|format| format.trim().to_uppercase())
+                .collect(),
+            publisher_id: query.publisher_id,
+            min_rating: query.min_rating,
+            max_rating: query.max_rating,
+            has_cover: query.has_cover,
+            with_identifiers: &query.with_identifiers,
+            without_identifiers: &query.without_identifiers,
+            added: query.added,
+            published: query.published,
+            search,
+            use_search_index: self.search_index,
+        })
+    }
 
-        let total = book_queries::query_count(&mut self.conn, &filters)?;
-        let book_ids = book_queries::query_page(
+    /// Throw away the metadata search index and index every book again.
+    /// Opening a library already catches up with books changed since it was
+    /// last open, so this is only needed for an index suspected damaged.
+    pub fn rebuild_search_index(&mut self) -> Result<(), CalibreError> {
+        if !self.search_index {
+            search_index::attach(&mut self.conn, &self.db_path.library_path)?;
+            self.search_index = true;
+        }
+        search_index::rebuild(&mut self.conn)
+    }
+
+    /// Search the text of the books themselves, as extracted by Calibre into
+    /// its `full-text-search.db` or by [`Library::index_book_text`]. Every
+    /// word (or `"quoted phrase"`) of the query must occur; matching ignores
+    /// ASCII case. Returns up to `limit` books, one format each, by book id.
+    pub fn search_full_text(
+        &mut self,
+        query: &str,
+        limit: i64,
+    ) -> Result<Vec<FullTextMatch>, CalibreError> {
+        let terms = fulltext::query_terms(query);
+        let sources = self.text_sources();
+        let hits = fulltext::find(&mut self.conn, sources, &terms, limit)?;
+
+        Ok(hits
+            .into_iter()
+            .map(|hit| {
+                let mut snippet: Vec<SnippetSpan> = fulltext::highlight(&hit.excerpt, &terms)
+                    .into_iter()
+                    .map(|(text, highlighted)| SnippetSpan { text, highlighted })
+                    .collect();
+                if hit.truncated_start != 0 {
+                    snippet.insert(
+                        0,
+                        SnippetSpan {
+                            text: "…".to_string(),
+                            highlighted: false,
+                        },
+                    );
+                }
+                if hit.truncated_end != 0 {
+                    snippet.push(SnippetSpan {
+                        text: "…".to_string(),
+                        highlighted: false,
+                    });
+                }
+                FullTextMatch {
+                    book_id: BookId::from(hit.book),
+                    format: hit.format,
+                    snippet,
+                }
+            })
+            .collect())
+    }
+
+    /// Extract the text of the book's EPUB and TXT formats for
+    /// [`Library::search_full_text`], skipping formats unchanged since they
+    /// were last extracted. The text is stored in Citadel's search index, in
+    /// the layout of Calibre's `books_text`, and the formats are queued for
+    /// Calibre's own indexer when the library uses it. Returns the number of
+    /// formats extracted.
+    pub fn index_book_text(&mut self, book_id: BookId) -> Result<usize, CalibreError> {
+        if !self.search_index {
+            search_index::attach(&mut self.conn, &self.db_path.library_path)?;
+            self.search_index = true;
+        }
+        let sources = self.text_sources();
+        operations::book_text::index_book_text(
+            &self.db_path.library_path,
             &mut self.conn,
-            &filters,
-            query.sort,
-            query.limit,
-            query.offset,
-        )?;
-        let items = self.get_books_with_read_states(book_ids)?;
+            sources,
+            book_id,
+        )
+    }
 
-        Ok(BookPage { items, total })
+    fn text_sources(&self) -> TextSources {
+        TextSources {
+            calibre: self.calibre_full_text,
+            citadel: self.search_index,
+        }
+    }
+
+    /// Extract the text of a book just added to, or given a format in, a
+    /// library that has Calibre's full-text search turned on. Best-effort,
+    /// like the OPF: the book is added either way.
+    fn index_new_book_text(&mut self, book_id: BookId) {
+        if self.calibre_full_text {
+            if let Err(e) = self.index_book_text(book_id) {
+                eprintln!(
+                    "WARNING: Failed to extract text of book {}: {}",
+                    book_id.as_i32(),
+                    e
+                );
+            }
+        }
     }
 
     /// List every series in the library with its linked-book count, sorted
@@ -844,12 +1652,84 @@ impl Library {
         crate::queries::series::list_with_book_counts(&mut self.conn)
     }
 
+    /// Rename a series on every book in it. Renaming onto the name of
+    /// another series (in any case) merges the two; the series that remains
+    /// is returned.
+    pub fn rename_series(
+        &mut self,
+        series_id: i32,
+        new_name: &str,
+    ) -> Result<SeriesSummary, CalibreError> {
+        let (series, book_ids) = operations::series::rename(&mut self.conn, series_id, new_name)?;
+        self.write_dirty_opfs(&book_ids);
+        Ok(series)
+    }
+
+    /// Move every book of series `from` into `into`, keeping their indices,
+    /// and delete `from`. Returns `into`; indices the two shared show up as
+    /// duplicates in [`Library::series_numbering`].
+    pub fn merge_series(&mut self, from: i32, into: i32) -> Result<SeriesSummary, CalibreError> {
+        let (series, book_ids) = operations::series::merge(&mut self.conn, from, into)?;
+        self.write_dirty_opfs(&book_ids);
+        Ok(series)
+    }
+
+    /// Renumber a series: the books in `order`, which must be exactly the
+    /// books in the series, get indices `start`, `start + 1`, ...
+    pub fn renumber_series(
+        &mut self,
+        series_id: i32,
+        order: &[BookId],
+        start: f32,
+    ) -> Result<(), CalibreError> {
+        let book_ids = operations::series::renumber(&mut self.conn, series_id, order, start)?;
+        self.write_dirty_opfs(&book_ids);
+        Ok(())
+    }
+
+    /// A series' books in reading order, with the gaps and duplicate
+    /// indices in its numbering.
+    pub fn series_numbering(&mut self, series_id: i32) -> Result<SeriesNumbering, CalibreError> {
+        operations::series::numbering(&mut self.conn, series_id)
+    }
+
     /// List every tag in the library (the whole vocabulary, including tags
     /// no longer linked to any book), sorted case-insensitively by name.
     pub fn list_tags(&mut self) -> Result<Vec<TagSummary>, CalibreError> {
         crate::queries::tags::list_all(&mut self.conn)
     }
 
+    /// Rename a tag on every book that has it. Renaming onto the name of
+    /// another tag (in any case) merges the two, as Calibre does; the tag
+    /// that remains is returned.
+    pub fn rename_tag(&mut self, tag_id: i32, new_name: &str) -> Result<TagSummary, CalibreError> {
+        let (tag, book_ids) = operations::tags::rename(&mut self.conn, tag_id, new_name)?;
+        self.write_dirty_opfs(&book_ids);
+        Ok(tag)
+    }
+
+    /// Give every book tagged `from` the tag `into` instead, and delete
+    /// `from`. Returns `into`.
+    pub fn merge_tags(&mut self, from: i32, into: i32) -> Result<TagSummary, CalibreError> {
+        let (tag, book_ids) = operations::tags::merge(&mut self.conn, from, into)?;
+        self.write_dirty_opfs(&book_ids);
+        Ok(tag)
+    }
+
+    /// Remove a tag from every book and delete it. Returns how many books
+    /// had it.
+    pub fn delete_tag(&mut self, tag_id: i32) -> Result<usize, CalibreError> {
+        let book_ids = operations::tags::delete(&mut self.conn, tag_id)?;
+        self.write_dirty_opfs(&book_ids);
+        Ok(book_ids.len())
+    }
+
+    /// Delete the tags no book uses any more, which [`Library::list_tags`]
+    /// otherwise keeps returning. Returns the deleted tags.
+    pub fn prune_unused_tags(&mut self) -> Result<Vec<TagSummary>, CalibreError> {
+        operations::tags::prune_unused(&mut self.conn)
+    }
+
     pub fn search_books(&mut self, query: &str) -> Result<Vec<Book>, CalibreError> {
         let query = query.trim();
         if query.is_empty() {
@@ -887,88 +1767,25 @@ impl Library {
     }
 }
 
-// =============================================================================
-// Metadata OPF generation (ported from calibre_client.rs)
-// =============================================================================
-
-fn format_metadata_opf(
-    book: &crate::entities::book_row::BookRow,
-    authors: &[crate::entities::author::Author],
-    language_codes: &[String],
-) -> Result<String, ()> {
-    let author_sort = book.author_sort.clone().unwrap_or_else(|| {
-        authors
-            .iter()
-            .map(|a| a.name.clone())
-            .collect::<Vec<String>>()
-            .join(", ")
-    });
-
-    let authors_string: String = authors
-        .iter()
-        .map(|author| {
-            format!(
-                "<dc:creator opf:file-as=\"{sortable}\" opf:role=\"aut\">{author}</dc:creator>",
-                sortable = author_sort,
-                author = author.name
-            )
-        })
-        .collect();
-
-    // Calibre emits one <dc:language> per linked language code, falling back to
-    // "und" (undetermined) when the book has none.
-    let languages_string: String = if language_codes.is_empty() {
-        "<dc:language>und</dc:language>".to_string()
-    } else {
-        language_codes
-            .iter()
-            .map(|code| format!("<dc:language>{code}</dc:language>"))
-            .collect()
-    };
-
-    Ok(format!(
-        r#"<?xml version='1.0' encoding='utf-8'?>
-<package xmlns="http://www.idpf.org/2007/opf" unique-identifier="uuid_id" version="2.0">
-  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:opf="http://www.idpf.org/2007/opf">
-    <dc:identifier opf:scheme="calibre" id="calibre_id">{calibre_id}</dc:identifier>
-    <dc:identifier opf:scheme="uuid" id="uuid_id">{calibre_uuid}</dc:identifier>
-    <dc:title>{book_title}</dc:title>
-    {authors}
-    <dc:contributor opf:file-as="calibre" opf:role="bkp">citadel (1.0.0) [https://github.com/every-day-things/citadel]</dc:contributor>
-    <dc:date>{pub_date}</dc:date>
-    {languages}
-    <meta name="calibre:timestamp" content="{now}"/>
-    <meta name="calibre:title_sort" content="{book_title_sortable}"/>
-  </metadata>
-  <guide>
-    <reference type="cover" title="Cover" href="cover.jpg"/>
-  </guide>
-</package>"#,
-        calibre_id = book.id,
-        calibre_uuid = book.uuid.as_deref().unwrap_or(""),
-        book_title = book.title,
-        authors = authors_string,
-        languages = languages_string,
-        pub_date = book
-            .pubdate
-            .unwrap_or(chrono::DateTime::UNIX_EPOCH.naive_utc())
-            .to_string(),
-        now = chrono::Utc::now().to_string(),
-        book_title_sortable = book.sort.as_deref().unwrap_or("")
-    ))
-}
-
-fn unique_tag_names<'a>(tag_names: &'a [String]) -> Vec<&'a str> {
-    let mut unique = Vec::new();
-
-    for tag_name in tag_names {
-        if !unique
-            .iter()
-            .any(|existing: &&str| existing.eq_ignore_ascii_case(tag_name))
-        {
-            unique.push(tag_name.as_str());
+/// Rename a SQLite database along with its WAL sidecars, replacing any
+/// database already at `to`.
+fn move_database(root: &Path, from: &str, to: &str) -> Result<(), CalibreError> {
+    remove_database(root, to)?;
+    for suffix in ["", "-wal", "-shm"] {
+        let source = root.join(format!("{from}{suffix}"));
+        if source.exists() {
+            std::fs::rename(source, root.join(format!("{to}{suffix}")))?;
         }
     }
+    Ok(())
+}
 
-    unique
+fn remove_database(root: &Path, name: &str) -> Result<(), CalibreError> {
+    for suffix in ["", "-wal", "-shm"] {
+        let file = root.join(format!("{name}{suffix}"));
+        if file.exists() {
+            std::fs::remove_file(file)?;
+        }
+    }
+    Ok(())
 }
@@ -0,0 +1,88 @@
+//! Creating a Calibre library from scratch.
+//!
+//! Writes the same `metadata.db` Calibre 7.x creates for a new library: the
+//! full schema (tables, views, indexes, triggers, annotation FTS tables), the
+//! default preferences, a random `library_id` and the schema `user_version`.
+
+use std::path::Path;
+
+use diesel::connection::SimpleConnection;
+use diesel::{Connection, SqliteConnection};
+
+use crate::CalibreError;
+
+/// Calibre 7.x DDL, verbatim from a freshly created library.
+const SCHEMA_SQL: &str = include_str!("new_library.sql");
+
+/// Schema version of [`SCHEMA_SQL`]; Calibre tracks it in `PRAGMA user_version`.
+pub(crate) const CALIBRE_SCHEMA_VERSION: i32 = 26;
+
+/// Preferences Calibre writes into every new library, as `(key, JSON value)`.
+/// GUI view state (column widths and the like) is left for Calibre to add.
+const DEFAULT_PREFERENCES: &[(&str, &str)] = &[
+    ("bools_are_tristate", "true"),
+    ("user_categories", "{}"),
+    ("saved_searches", "{}"),
+    ("grouped_search_terms", "{}"),
+    ("tag_browser_hidden_categories", "[]"),
+    (
+        "tag_browser_category_order",
+        "[\n  \"authors\",\n  \"languages\",\n  \"series\",\n  \"formats\",\n  \"publisher\",\n  \"rating\",\n  \"news\",\n  \"tags\",\n  \"identifiers\"\n]",
+    ),
+];
+
+/// Create a new Calibre `metadata.db` at `db_path`.
+///
+/// Refuses to touch an existing file. Everything is written in one
+/// transaction, and a partially written file is removed on failure, so the
+/// caller either gets a complete library database or none at all.
+pub(crate) fn initialize_database(db_path: &Path) -> Result<(), CalibreError> {
+    if db_path.exists() {
+        return Err(CalibreError::FileSystem(format!(
+            "A Calibre database already exists at {}",
+            db_path.display()
+        )));
+    }
+
+    let db_path_str = db_path.to_str().ok_or_else(|| {
+        CalibreError::FileSystem(format!("Invalid database path: {}", db_path.display()))
+    })?;
+
+    let result = SqliteConnection::establish(db_path_str)
+        .map_err(CalibreError::database)
+        .and_then(|mut conn| write_schema(&mut conn));
+
+    if result.is_err() {
+        let _ = std::fs::remove_file(db_path);
+    }
+
+    result
+}
+
+fn write_schema(conn: &mut SqliteConnection) -> Result<(), CalibreError> {
+    conn.transaction::<(), CalibreError, _>(|conn| {
+        conn.batch_execute(SCHEMA_SQL)?;
+
+        let mut inserts = String::new();
+        for (key, value) in DEFAULT_PREFERENCES {
+            inserts.push_str(&format!(
+                "INSERT INTO preferences (key, val) VALUES ({}, {});\n",
+                sql_string(key),
+                sql_string(value)
+            ));
+        }
+        inserts.push_str(&format!(
+            "INSERT INTO library_id (uuid) VALUES ({});\n",
+            sql_string(&uuid::Uuid::new_v4().to_string())
+        ));
+        inserts.push_str(&format!("PRAGMA user_version = {CALIBRE_SCHEMA_VERSION};"));
+        conn.batch_execute(&inserts)?;
+
+        Ok(())
+    })
+}
+
+/// Quote `value` as an SQL string literal.
+fn sql_string(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
@@ -2,9 +2,9 @@ use diesel::{Connection, SqliteConnection};
 
 use crate::{
     assets::{self, COVER_FILENAME},
-    queries::{authors, book_descriptions, book_files, book_identifiers, books},
+    queries::{authors, book_files, books},
     types::BookId,
-    CalibreError, UpdateBookData,
+    BookRow, CalibreError, UpdateBookData,
 };
 
 pub fn get_book_cover(
@@ -28,8 +28,8 @@ pub fn get_book_file_path(
     let file = book_files::find_by_book_and_format(conn, book_id, file_format.to_string())?;
 
     match file {
-        Some(_) => {
-            let book_filename = filename(book.path.clone(), file_format.to_string());
+        Some(file) => {
+            let book_filename = assets::book_file_name(&file.name, file_format);
             let file_path = assets::asset_path(library_root, &book.path, &book_filename);
             Ok(file_path)
         }
@@ -61,7 +61,8 @@ pub fn add_book_file_from_bytes(
 ) -> Result<(), CalibreError> {
     let book = books::get(conn, book_id)?.ok_or(CalibreError::BookNotFound(book_id))?;
 
-    let book_filename = filename(book.path.clone(), file_format.clone());
+    let stem = format_file_stem(conn, &book)?;
+    let book_filename = assets::book_file_name(&stem, &file_format);
     let file_path = assets::asset_path(library_root, &book.path, &book_filename);
     assets::write(&file_path, &data)?;
 
@@ -69,7 +70,7 @@ pub fn add_book_file_from_bytes(
         book: book_id.as_i32(),
         format: file_format.to_uppercase(),
         uncompressed_size: data.len() as i32,
-        name: book.path.clone(),
+        name: stem,
     };
 
     match book_files::create(conn, new_file) {
@@ -91,7 +92,8 @@ pub fn add_book_file_from_path(
 ) -> Result<(), CalibreError> {
     let book = books::get(conn, book_id)?.ok_or(CalibreError::BookNotFound(book_id))?;
 
-    let book_filename = filename(book.path.clone(), file_format.clone());
+    let stem = format_file_stem(conn, &book)?;
+    let book_filename = assets::book_file_name(&stem, &file_format);
     let dest_path = assets::asset_path(library_root, &book.path, &book_filename);
     std::fs::copy(file_path, &dest_path).map_err(|e| CalibreError::FileSystem(e.to_string()))?;
 
@@ -103,7 +105,7 @@ pub fn add_book_file_from_path(
         book: book_id.as_i32(),
         format: file_format.to_uppercase(),
         uncompressed_size: metadata.len() as i32,
-        name: book.path.clone(),
+        name: stem,
     };
 
     match book_files::create(conn, new_file) {
@@ -137,83 +139,51 @@ pub fn set_book_cover(
     })
 }
 
-pub fn remove_book_file(
+/// Delete a book's `cover.jpg` and clear `books.has_cover`. The flag is
+/// cleared first, inside the transaction, so a cover file that cannot be
+/// deleted rolls the flag back. A cover file that is already missing is not
+/// an error.
+pub fn remove_book_cover(
     library_root: &String,
     conn: &mut SqliteConnection,
     book_id: BookId,
-    file_format: &str,
 ) -> Result<(), CalibreError> {
     conn.transaction::<(), CalibreError, _>(|conn| {
         let book = books::get(conn, book_id)?.ok_or(CalibreError::BookNotFound(book_id))?;
+        let cover_path = assets::asset_path(library_root, &book.path, COVER_FILENAME);
 
-        let file =
-            book_files::find_by_book_and_format(conn, book_id, file_format.to_string())?.ok_or(
-                CalibreError::BookFileNotFound(book_id, file_format.to_string()),
-            )?;
-
-        let all_files = book_files::find_by_book_id(conn, book_id)?;
-        let is_last_format = all_files.len() == 1;
-
-        if is_last_format {
-            delete_entire_book(library_root, conn, book_id, &book.path)?;
-        } else {
-            book_files::delete(conn, crate::types::BookFileId(file.id))?;
-
-            let book_filename = filename(book.path.clone(), file_format.to_string());
-            let file_path = assets::asset_path(library_root, &book.path, &book_filename);
+        let update = UpdateBookData {
+            has_cover: Some(false),
+            ..Default::default()
+        };
+        books::update(conn, book_id, update)?;
 
-            match std::fs::remove_file(&file_path) {
-                Ok(_) => {}
-                Err(e) => {
-                    eprintln!("WARNING: Failed to delete file {:?}: {}", file_path, e);
-                }
-            }
+        match std::fs::remove_file(&cover_path) {
+            Ok(_) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(CalibreError::FileSystem(e.to_string())),
         }
-
-        Ok(())
     })
 }
 
-pub(crate) fn delete_entire_book(
-    library_root: &String,
+/// The name Calibre gives the book's format files: the one its existing
+/// files share, or "Title - Author" for a book that has none yet.
+pub(crate) fn format_file_stem(
     conn: &mut SqliteConnection,
-    book_id: BookId,
-    book_path: &str,
-) -> Result<(), CalibreError> {
-    book_files::delete_all(conn, book_id)?;
-
-    book_descriptions::delete(conn, book_id)?;
-
-    book_identifiers::delete_all(conn, book_id)?;
-
-    let author_ids = books::find_authors(conn, book_id)?;
-    for author_id in author_ids {
-        authors::unlink_book(conn, author_id, book_id)?;
-    }
-
-    books::delete(conn, book_id)?;
-
-    let book_dir = std::path::Path::new(library_root).join(book_path);
-    match std::fs::remove_dir_all(&book_dir) {
-        Ok(_) => {}
-        Err(e) => {
-            eprintln!(
-                "WARNING: Failed to delete book directory {:?}: {}",
-                book_dir, e
-            );
-        }
-    }
-
-    Ok(())
-}
-
-fn filename(book_path: String, file_format: String) -> String {
-    let ext = file_format.to_lowercase();
-    let base_name = book_path;
-
-    if ext.is_empty() {
-        format!("{}", base_name)
-    } else {
-        format!("{}.{}", base_name, ext)
+    book: &BookRow,
+) -> Result<String, CalibreError> {
+    let book_id = BookId(book.id);
+    if let Some(file) = book_files::find_by_book_id(conn, book_id)?
+        .into_iter()
+        .next()
+    {
+        return Ok(file.name);
     }
+    let primary_author = match books::find_authors(conn, book_id)?.first() {
+        Some(author_id) => authors::get(conn, *author_id)?
+            .map(|author| author.name)
+            .unwrap_or_default(),
+        None => String::new(),
+    };
+    Ok(assets::book_file_stem(&book.title, &primary_author))
 }
@@ -1,10 +1,10 @@
-use diesel::SqliteConnection;
+use diesel::{Connection, SqliteConnection};
 
 use crate::{
     entities::author::{NewAuthor, UpdateAuthorData},
-    library::AuthorAdd,
-    queries::authors,
-    types::AuthorId,
+    library::{AuthorAdd, AuthorSortUpdate},
+    queries::{authors, books},
+    types::{AuthorId, BookId},
     CalibreError,
 };
 
@@ -67,6 +67,118 @@ pub fn remove(conn: &mut SqliteConnection, author_id: AuthorId) -> Result<(), Ca
     authors::delete(conn, author_id)
 }
 
+/// Relink every book of `from` to `into`, then delete `from`. `into` takes
+/// `from`'s place in each book's author order, and each book's author sort
+/// is rewritten. Returns the books that changed, marked dirty.
+pub fn merge(
+    conn: &mut SqliteConnection,
+    from: AuthorId,
+    into: AuthorId,
+) -> Result<Vec<BookId>, CalibreError> {
+    conn.transaction::<_, CalibreError, _>(|conn| {
+        for author_id in [from, into] {
+            authors::get(conn, author_id)?.ok_or(CalibreError::AuthorNotFound(author_id))?;
+        }
+        if from == into {
+            return Ok(vec![]);
+        }
+
+        let book_ids = authors::find_books(conn, from)?;
+        authors::move_links(conn, from, into)?;
+        authors::delete(conn, from)?;
+        for &book_id in &book_ids {
+            refresh_book_author_sort(conn, book_id)?;
+        }
+        books::mark_dirty(conn, &book_ids)?;
+        Ok(book_ids)
+    })
+}
+
+/// Re-derive every author's sort name from their name, and every book's
+/// author sort from its authors'. Returns the counts and the books marked
+/// dirty: those whose author sort changed or whose authors' did.
+pub fn recompute_sorts(
+    conn: &mut SqliteConnection,
+) -> Result<(AuthorSortUpdate, Vec<BookId>), CalibreError> {
+    conn.transaction::<_, CalibreError, _>(|conn| {
+        let mut dirty = Vec::new();
+        let mut authors_updated = 0;
+        for author in authors::all(conn)? {
+            let sort = author.sortable_name();
+            if author.sort.as_deref() == Some(sort.as_str()) {
+                continue;
+            }
+            let author_id = AuthorId(author.id);
+            authors::update(
+                conn,
+                author_id,
+                UpdateAuthorData {
+                    sort: Some(sort),
+                    ..UpdateAuthorData::default()
+                },
+            )?;
+            authors_updated += 1;
+            dirty.extend(authors::find_books(conn, author_id)?);
+        }
+
+        let mut books_updated = 0;
+        for book in books::all(conn)? {
+            let book_id = BookId(book.id);
+            if refresh_book_author_sort(conn, book_id)? {
+                books_updated += 1;
+                dirty.push(book_id);
+            }
+        }
+
+        dirty.sort_by_key(|book_id| book_id.as_i32());
+        dirty.dedup();
+        books::mark_dirty(conn, &dirty)?;
+        Ok((
+            AuthorSortUpdate {
+                authors_updated,
+                books_updated,
+            },
+            dirty,
+        ))
+    })
+}
+
+/// Set the book's author sort to its authors' sort names, in author order,
+/// joined as Calibre joins them. Returns whether it changed.
+fn refresh_book_author_sort(
+    conn: &mut SqliteConnection,
+    book_id: BookId,
+) -> Result<bool, CalibreError> {
+    let book = books::get(conn, book_id)?.ok_or(CalibreError::BookNotFound(book_id))?;
+    let author_ids = books::find_authors(conn, book_id)?;
+    let linked = authors::get_many(conn, author_ids.clone())?;
+    let author_sort = author_ids
+        .iter()
+        .filter_map(|author_id| linked.iter().find(|author| author.id == author_id.as_i32()))
+        .map(|author| {
+            author
+                .sort
+                .clone()
+                .filter(|sort| !sort.is_empty())
+                .unwrap_or_else(|| author.sortable_name())
+        })
+        .collect::<Vec<String>>()
+        .join(" & ");
+
+    if book.author_sort.as_deref() == Some(author_sort.as_str()) {
+        return Ok(false);
+    }
+    books::update(
+        conn,
+        book_id,
+        crate::UpdateBookData {
+            author_sort: Some(author_sort),
+            ..Default::default()
+        },
+    )?;
+    Ok(true)
+}
+
 pub fn add(
     conn: &mut SqliteConnection,
     author: AuthorAdd,
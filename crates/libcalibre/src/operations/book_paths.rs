@@ -0,0 +1,277 @@
+//! Keeping a book's folder and file names in Calibre's layout.
+//!
+//! Calibre names a book's folder `Author/Title (id)` and every format file
+//! `Title - Author.ext`, after the book's title and first author. When either
+//! changes, Calibre moves the files (`db/backend.py`, `update_path`); this is
+//! the same step for libcalibre.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use diesel::{Connection, SqliteConnection};
+
+use crate::{
+    assets,
+    queries::{authors, book_files, books},
+    types::BookId,
+    CalibreError, UpdateBookData,
+};
+
+/// One entry of the book folder and where it goes.
+struct Move {
+    from: PathBuf,
+    to: PathBuf,
+    /// `to` was created as a link or copy of `from`, so `from` is removed once
+    /// the database points at `to`. Otherwise `from` was renamed in place.
+    copied: bool,
+}
+
+/// Move a book's folder and format files to the names Calibre would give them
+/// for the book's current title and first author, and update `books.path` and
+/// `data.name` to match. A no-op when the layout is already current.
+///
+/// Crash-safe: everything is first linked (or copied) to its new name, then
+/// the database is repointed in one transaction, and only then are the old
+/// names removed. An interruption before the commit leaves the database on
+/// the untouched originals; one after it leaves at worst stray old files.
+/// The old author folder is removed once it is empty.
+pub fn relocate_book(
+    library_root: &String,
+    conn: &mut SqliteConnection,
+    book_id: BookId,
+) -> Result<(), CalibreError> {
+    let book = books::get(conn, book_id)?.ok_or(CalibreError::BookNotFound(book_id))?;
+    let primary_author = match books::find_authors(conn, book_id)?.first() {
+        Some(author_id) => authors::get(conn, *author_id)?
+            .map(|author| author.name)
+            .unwrap_or_default(),
+        None => String::new(),
+    };
+
+    let new_dir_relative = assets::book_dir_relative(&primary_author, &book.title, book.id)
+        .to_str()
+        .map(|path| path.to_string())
+        .ok_or_else(|| CalibreError::FileSystem("Book path is not valid UTF-8".to_string()))?;
+    let new_stem = assets::book_file_stem(&book.title, &primary_author);
+    let files = book_files::find_by_book_id(conn, book_id)?;
+
+    if new_dir_relative == book.path && files.iter().all(|file| file.name == new_stem) {
+        return Ok(());
+    }
+
+    let root = Path::new(library_root);
+    let old_dir = root.join(&book.path);
+    let new_dir = root.join(&new_dir_relative);
+
+    let renamed_formats: HashMap<String, String> = files
+        .iter()
+        .map(|file| {
+            (
+                assets::book_file_name(&file.name, &file.format),
+                assets::book_file_name(&new_stem, &file.format),
+            )
+        })
+        .collect();
+
+    fs::create_dir_all(&new_dir)?;
+
+    // Stage: make every entry reachable under its new name.
+    let mut moves: Vec<Move> = Vec::new();
+    let staged = stage_moves(&old_dir, &new_dir, &renamed_formats, &mut moves);
+
+    let committed = staged.and_then(|_| {
+        conn.transaction::<(), CalibreError, _>(|conn| {
+            books::update(
+                conn,
+                book_id,
+                UpdateBookData {
+                    path: Some(new_dir_relative.clone()),
+                    ..Default::default()
+                },
+            )?;
+            book_files::set_name_for_book(conn, book_id, &new_stem)
+        })
+    });
+
+    if let Err(e) = committed {
+        unstage_moves(&moves);
+        if old_dir != new_dir {
+            remove_dir_if_empty(root, &new_dir);
+        }
+        return Err(e);
+    }
+
+    // Clean up: the database now points at the new names only.
+    for entry in moves.iter().filter(|entry| entry.copied) {
+        let removed = if entry.from.is_dir() {
+            fs::remove_dir_all(&entry.from)
+        } else {
+            fs::remove_file(&entry.from)
+        };
+        if let Err(e) = removed {
+            eprintln!("WARNING: Failed to remove old file {:?}: {}", entry.from, e);
+        }
+    }
+    if old_dir != new_dir {
+        remove_dir_if_empty(root, &old_dir);
+    }
+
+    Ok(())
+}
+
+/// Link or copy every entry of `old_dir` to its place in `new_dir`, recording
+/// each in `moves` as it goes so a failure part-way can be undone. Format
+/// files take their new names from `renamed_formats`; everything else (cover,
+/// `metadata.opf`, extra files) keeps its name.
+fn stage_moves(
+    old_dir: &Path,
+    new_dir: &Path,
+    renamed_formats: &HashMap<String, String>,
+    moves: &mut Vec<Move>,
+) -> Result<(), CalibreError> {
+    if !old_dir.is_dir() {
+        return Ok(());
+    }
+
+    for entry in fs::read_dir(old_dir)? {
+        let file_name = entry?.file_name();
+        let new_name = file_name
+            .to_str()
+            .and_then(|name| renamed_formats.get(name))
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(&file_name));
+
+        let from = old_dir.join(&file_name);
+        let to = new_dir.join(new_name);
+        if from == to {
+            continue;
+        }
+
+        if is_same_file(&from, &to) {
+            if is_separate_link(&from, &to) {
+                // A hard link left by an interrupted relocation: already
+                // staged.
+                moves.push(Move {
+                    from,
+                    to,
+                    copied: true,
+                });
+            } else {
+                // A case-only (or, on macOS, normalization-only) rename on a
+                // filesystem that treats both names as one file: linking
+                // would collide with the file itself, so rename in place.
+                fs::rename(&from, &to)?;
+                moves.push(Move {
+                    from,
+                    to,
+                    copied: false,
+                });
+            }
+            continue;
+        }
+
+        // Left over from an interrupted relocation; the database never
+        // pointed at it.
+        if to.is_dir() {
+            fs::remove_dir_all(&to)?;
+        } else if to.exists() {
+            fs::remove_file(&to)?;
+        }
+
+        link_or_copy(&from, &to)?;
+        moves.push(Move {
+            from,
+            to,
+            copied: true,
+        });
+    }
+
+    Ok(())
+}
+
+/// Best-effort undo of [`stage_moves`], newest first.
+fn unstage_moves(moves: &[Move]) {
+    for entry in moves.iter().rev() {
+        let undone = if !entry.copied {
+            fs::rename(&entry.to, &entry.from)
+        } else if entry.to.is_dir() {
+            fs::remove_dir_all(&entry.to)
+        } else {
+            fs::remove_file(&entry.to)
+        };
+        if let Err(e) = undone {
+            eprintln!("WARNING: Failed to undo move of {:?}: {}", entry.from, e);
+        }
+    }
+}
+
+/// Hard-link `from` to `to`, falling back to a copy (e.g. across devices).
+/// Directories are recreated and their contents linked recursively.
+fn link_or_copy(from: &Path, to: &Path) -> Result<(), CalibreError> {
+    if from.is_dir() {
+        fs::create_dir_all(to)?;
+        for entry in fs::read_dir(from)? {
+            let file_name = entry?.file_name();
+            link_or_copy(&from.join(&file_name), &to.join(&file_name))?;
+        }
+        return Ok(());
+    }
+
+    if fs::hard_link(from, to).is_err() {
+        fs::copy(from, to)?;
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (fs::symlink_metadata(a), fs::symlink_metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    b.exists() && a.to_string_lossy().to_lowercase() == b.to_string_lossy().to_lowercase()
+}
+
+/// For two paths to the same file: whether they are two directory entries
+/// (hard links) rather than one entry the filesystem resolves under two
+/// spellings (a case-insensitive or normalizing filesystem).
+fn is_separate_link(from: &Path, to: &Path) -> bool {
+    let (Some(from_dir), Some(to_dir)) = (from.parent(), to.parent()) else {
+        return false;
+    };
+    if !is_same_file(from_dir, to_dir) {
+        return true;
+    }
+    from.file_name() != to.file_name() && has_entry_named(from) && has_entry_named(to)
+}
+
+/// Whether `path`'s folder lists an entry with exactly its name, as opposed
+/// to a differently cased or normalized name the filesystem resolves to it.
+fn has_entry_named(path: &Path) -> bool {
+    let (Some(dir), Some(name)) = (path.parent(), path.file_name()) else {
+        return false;
+    };
+    fs::read_dir(dir)
+        .map(|entries| entries.flatten().any(|entry| entry.file_name() == name))
+        .unwrap_or(false)
+}
+
+/// Remove `dir`, then its parent (the author folder), if they are empty. Never
+/// touches the library root itself.
+pub(crate) fn remove_dir_if_empty(library_root: &Path, dir: &Path) {
+    if dir == library_root || fs::remove_dir(dir).is_err() {
+        return;
+    }
+    if let Some(parent) = dir.parent() {
+        if parent != library_root {
+            let _ = fs::remove_dir(parent);
+        }
+    }
+}
@@ -0,0 +1,68 @@
+//! Extracting the text of a book's formats for full-text search.
+
+use diesel::SqliteConnection;
+
+use crate::{
+    assets,
+    book_text::book_text_from_path,
+    mime_type::MIMETYPE,
+    queries::{book_files, books},
+    search::fulltext::{self, ExtractedText, TextSources},
+    types::BookId,
+    CalibreError,
+};
+
+/// Extract and store the text of every format of the book we can read
+/// (EPUB, TXT). Formats whose file is unchanged since their text was last
+/// extracted are skipped. Returns the number of formats extracted.
+pub fn index_book_text(
+    library_root: &String,
+    conn: &mut SqliteConnection,
+    sources: TextSources,
+    book_id: BookId,
+) -> Result<usize, CalibreError> {
+    let book = books::get(conn, book_id)?.ok_or(CalibreError::BookNotFound(book_id))?;
+    let mut extracted = 0;
+
+    for file in book_files::find_by_book_id(conn, book_id)? {
+        if !matches!(
+            MIMETYPE::from_file_extension(&file.format.to_lowercase()),
+            Some(MIMETYPE::EPUB | MIMETYPE::TXT)
+        ) {
+            continue;
+        }
+        let path = assets::asset_path(
+            library_root,
+            &book.path,
+            &assets::book_file_name(&file.name, &file.format),
+        );
+        let bytes = std::fs::read(&path)?;
+        let format_hash = fulltext::sha1_hex(&bytes);
+        if fulltext::stored_format_hash(conn, book_id.as_i32(), &file.format)?.as_deref()
+            == Some(format_hash.as_str())
+        {
+            continue;
+        }
+
+        // A file we cannot parse is recorded with its error, as Calibre does,
+        // so it is not retried until it changes.
+        let text = match book_text_from_path(&path) {
+            Ok(text) => Ok(text.unwrap_or_default()),
+            Err(e) => Err(e.to_string()),
+        };
+        fulltext::store(
+            conn,
+            sources,
+            ExtractedText {
+                book_id: book_id.as_i32(),
+                format: &file.format,
+                format_size: bytes.len() as i64,
+                format_hash: &format_hash,
+                text,
+            },
+        )?;
+        extracted += 1;
+    }
+
+    Ok(extracted)
+}
@@ -1,17 +1,28 @@
 use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
 
-use chrono::NaiveDateTime;
+use chrono::{Datelike, NaiveDateTime};
 use diesel::{Connection, SqliteConnection};
 
 use crate::{
-    library::{Book, BookFileInfo, BookIdentifier, BookUpdate},
+    assets,
+    cover_image::cover_image_data_from_path,
+    entities::book_file::NewBookFile,
+    library::{Book, BookAdd, BookFileInfo, BookIdentifier, BookUpdate},
     queries::{
-        authors, book_descriptions, book_files, book_identifiers, books, languages, series, tags,
+        authors, book_descriptions, book_files, book_identifiers, books, languages, publishers,
+        ratings, series, tags,
     },
+    sorting,
     types::{AuthorId, BookId},
-    BookRow, CalibreError, UpdateBookData,
+    BookRow, CalibreError, NewBook, UpdateBookData,
 };
 
+/// Year of Calibre's `UNDEFINED_DATE` (`0101-01-01`), its stand-in for
+/// "no date".
+const CALIBRE_UNDEFINED_YEAR: i32 = 101;
+
 pub fn update_book(
     conn: &mut SqliteConnection,
     book_id: BookId,
@@ -23,6 +34,12 @@ pub fn update_book(
         ));
     }
 
+    if update.description.is_some() && update.comments.is_some() {
+        return Err(CalibreError::BannedFunctionInvocation(
+            "Cannot provide both description and comments".to_string(),
+        ));
+    }
+
     conn.transaction::<(), CalibreError, _>(|conn| {
         // Only update the book row if there are actual book-row field changes
         let has_book_row_changes = update.title.is_some()
@@ -58,15 +75,45 @@ pub fn update_book(
             languages::set_for_book(conn, book_id, &codes)?;
         }
 
-        if let Some(description) = update.description {
+        // `description` and `comments` both name Calibre's `comments` table.
+        if let Some(description) = update.description.or(update.comments) {
             let existing = book_descriptions::get(conn, book_id)?;
-            if existing.is_some() {
+            if description.trim().is_empty() {
+                // Calibre stores no row rather than an empty comment.
+                book_descriptions::delete(conn, book_id)?;
+            } else if existing.is_some() {
                 book_descriptions::update(conn, book_id, description)?;
             } else {
                 book_descriptions::create(conn, book_id, description)?;
             }
         }
 
+        if let Some(publisher_name) = update.publisher {
+            let publisher_name = publisher_name.trim();
+            if publisher_name.is_empty() {
+                publishers::unlink_book(conn, book_id)?;
+            } else {
+                let publisher = publishers::create_if_not_exists(conn, publisher_name)?;
+                publishers::set_book_publisher(conn, publisher.id, book_id)?;
+            }
+            publishers::delete_unused(conn)?;
+        }
+
+        if let Some(rating_value) = update.rating {
+            match ratings::normalize(rating_value) {
+                Some(value) => {
+                    let rating = ratings::create_if_not_exists(conn, value)?;
+                    ratings::set_book_rating(conn, rating.id, book_id)?;
+                }
+                None => ratings::unlink_book(conn, book_id)?,
+            }
+            ratings::delete_unused(conn)?;
+        }
+
+        if let Some(identifiers) = update.identifiers {
+            book_identifiers::set_for_book(conn, book_id, &identifiers)?;
+        }
+
         if let Some(author_names) = update.author_names {
             let existing = books::find_authors(conn, book_id)?;
             let existing_authors = authors::get_many(conn, existing)?;
@@ -171,16 +218,280 @@ fn unique_tag_names(tag_names: &[String]) -> Vec<&str> {
     unique
 }
 
+/// Add a book: its database rows, its folder in the library and copies of its
+/// files (plus a cover taken from the first file, when it has one).
+///
+/// All-or-nothing: the rows are written in one transaction, and every folder
+/// and file created along the way is recorded and removed again if any step
+/// fails, so a failed add leaves neither rows nor files behind.
+pub fn add_book(
+    library_root: &String,
+    conn: &mut SqliteConnection,
+    book: BookAdd,
+) -> Result<BookId, CalibreError> {
+    let mut staged = StagedFiles::default();
+
+    let result = conn.transaction::<BookId, CalibreError, _>(|conn| {
+        write_new_book(Path::new(library_root), conn, &book, &mut staged)
+    });
+
+    if result.is_err() {
+        staged.rollback();
+    }
+    result
+}
+
+/// Add the files in `file_paths` to an existing book, skipping any format it
+/// already has (or an earlier path already supplied). Returns the formats
+/// added.
+///
+/// All-or-nothing, like [`add_book`]: the rows are written in one
+/// transaction and every file copied is removed again if any step fails.
+pub fn add_formats(
+    library_root: &str,
+    conn: &mut SqliteConnection,
+    book_id: BookId,
+    file_paths: &[PathBuf],
+) -> Result<Vec<String>, CalibreError> {
+    let mut staged = StagedFiles::default();
+
+    let result = conn.transaction::<Vec<String>, CalibreError, _>(|conn| {
+        let book = books::get(conn, book_id)?.ok_or(CalibreError::BookNotFound(book_id))?;
+        let book_dir = Path::new(library_root).join(&book.path);
+        let stem = crate::operations::assets::format_file_stem(conn, &book)?;
+        let mut has: Vec<String> = book_files::find_by_book_id(conn, book_id)?
+            .into_iter()
+            .map(|file| file.format)
+            .collect();
+
+        let mut added = Vec::new();
+        for file_path in file_paths {
+            let format = file_path
+                .extension()
+                .and_then(|e| e.to_str())
+                .unwrap_or("")
+                .to_uppercase();
+            if has.contains(&format) {
+                continue;
+            }
+            let dest_path = book_dir.join(assets::book_file_name(&stem, &format));
+            let file_size = staged.copy(file_path, &dest_path)?;
+            book_files::create(
+                conn,
+                NewBookFile {
+                    book: book_id.as_i32(),
+                    format: format.clone(),
+                    uncompressed_size: file_size as i32,
+                    name: stem.clone(),
+                },
+            )?;
+            has.push(format.clone());
+            added.push(format);
+        }
+        Ok(added)
+    });
+
+    if result.is_err() {
+        staged.rollback();
+    }
+    result
+}
+
+fn write_new_book(
+    library_root: &Path,
+    conn: &mut SqliteConnection,
+    book: &BookAdd,
+    staged: &mut StagedFiles,
+) -> Result<BookId, CalibreError> {
+    let created_authors = book
+        .author_names
+        .iter()
+        .map(|name| authors::create_if_not_exists(conn, name))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let book_row = books::create(
+        conn,
+        NewBook {
+            title: book.title.clone(),
+            timestamp: None,
+            pubdate: book
+                .publication_date
+                .map(|date| date.and_time(chrono::NaiveTime::MIN)),
+            series_index: book.series_index.unwrap_or(1.0),
+            has_cover: None,
+        },
+    )?;
+    let book_id = BookId(book_row.id);
+
+    for author in &created_authors {
+        authors::link_book(conn, AuthorId(author.id), book_id)?;
+    }
+
+    if let Some(tag_names) = &book.tags {
+        for tag_name in unique_tag_names(tag_names) {
+            let tag = tags::create_if_not_exists(conn, tag_name)?;
+            tags::link_book(conn, tag.id, book_id)?;
+        }
+    }
+
+    if let Some(series_name) = &book.series {
+        let series = series::create_if_not_exists(conn, series_name)?;
+        series::link_book(conn, series.id, book_id)?;
+    }
+
+    if let Some(language) = &book.language {
+        languages::set_for_book(conn, book_id, std::slice::from_ref(language))?;
+    }
+
+    if !book.identifiers.is_empty() {
+        book_identifiers::set_for_book(conn, book_id, &book.identifiers)?;
+    }
+
+    let primary_author = created_authors
+        .first()
+        .map(|author| author.name.clone())
+        .unwrap_or_default();
+    let book_dir_relative = assets::book_dir_relative(&primary_author, &book.title, book_row.id);
+    let book_dir = library_root.join(&book_dir_relative);
+    staged.create_dir_all(&book_dir)?;
+
+    let file_stem = assets::book_file_stem(&book.title, &primary_author);
+    for file_path in &book.file_paths {
+        let extension = file_path
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or("")
+            .to_uppercase();
+        let dest_path = book_dir.join(assets::book_file_name(&file_stem, &extension));
+        let file_size = staged.copy(file_path, &dest_path)?;
+
+        book_files::create(
+            conn,
+            NewBookFile {
+                book: book_row.id,
+                format: extension,
+                uncompressed_size: file_size as i32,
+                name: file_stem.clone(),
+            },
+        )?;
+    }
+
+    let has_cover = match book.file_paths.first() {
+        Some(primary_file) => match cover_image_data_from_path(primary_file) {
+            Ok(Some(cover_data)) => {
+                staged.write(&book_dir.join("cover.jpg"), &cover_data)?;
+                true
+            }
+            // A file without a usable cover is still a valid book.
+            _ => false,
+        },
+        None => false,
+    };
+
+    let author_sort = created_authors
+        .iter()
+        .map(|author| sorting::sort_author_name_apa(&author.name))
+        .collect::<Vec<String>>()
+        .join(" & ");
+    books::update(
+        conn,
+        book_id,
+        UpdateBookData {
+            author_sort: Some(author_sort),
+            path: Some(
+                book_dir_relative
+                    .to_str()
+                    .ok_or_else(|| {
+                        CalibreError::FileSystem("Book path is not valid UTF-8".to_string())
+                    })?
+                    .to_string(),
+            ),
+            has_cover: has_cover.then_some(true),
+            ..Default::default()
+        },
+    )?;
+
+    Ok(book_id)
+}
+
+/// Folders and files created while adding a book or its formats, so they can
+/// be removed if the add fails part-way.
+#[derive(Default)]
+struct StagedFiles {
+    dirs: Vec<PathBuf>,
+    files: Vec<PathBuf>,
+}
+
+impl StagedFiles {
+    /// Like [`fs::create_dir_all`], recording each folder that did not exist.
+    fn create_dir_all(&mut self, dir: &Path) -> Result<(), CalibreError> {
+        let missing: Vec<PathBuf> = dir
+            .ancestors()
+            .take_while(|ancestor| !ancestor.exists())
+            .map(Path::to_path_buf)
+            .collect();
+
+        // Record before creating: a failure part-way may still leave some.
+        self.dirs.extend(missing.into_iter().rev());
+        fs::create_dir_all(dir).map_err(|e| {
+            CalibreError::FileSystem(format!("Failed to create {}: {}", dir.display(), e))
+        })
+    }
+
+    /// Copy `from` to `to`, returning the number of bytes copied.
+    fn copy(&mut self, from: &Path, to: &Path) -> Result<u64, CalibreError> {
+        if !from.is_file() {
+            return Err(CalibreError::FileSystem(format!(
+                "Book file not found: {}",
+                from.display()
+            )));
+        }
+        self.files.push(to.to_path_buf());
+        fs::copy(from, to).map_err(|e| {
+            CalibreError::FileSystem(format!("Failed to copy {}: {}", from.display(), e))
+        })
+    }
+
+    fn write(&mut self, path: &Path, contents: &[u8]) -> Result<(), CalibreError> {
+        self.files.push(path.to_path_buf());
+        fs::write(path, contents).map_err(|e| {
+            CalibreError::FileSystem(format!("Failed to write {}: {}", path.display(), e))
+        })
+    }
+
+    /// Best-effort removal of everything recorded, newest first.
+    fn rollback(self) {
+        for file in self.files.iter().rev() {
+            if let Err(e) = fs::remove_file(file) {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    eprintln!("WARNING: Failed to remove {:?}: {}", file, e);
+                }
+            }
+        }
+        for dir in self.dirs.iter().rev() {
+            let _ = fs::remove_dir(dir);
+        }
+    }
+}
+
 pub fn get_book(conn: &mut SqliteConnection, book_id: BookId) -> Result<Book, CalibreError> {
     let book = books::get(conn, book_id)?.ok_or(CalibreError::BookNotFound(book_id))?;
     let book_desc = book_descriptions::get(conn, book_id)?;
     let author_ids = books::find_authors(conn, book_id)?;
-    let author_models = authors::get_many(conn, author_ids)?;
+    // Loaded by id; put them back in the book's author order.
+    let mut author_models = authors::get_many(conn, author_ids.clone())?;
+    author_models.sort_by_key(|author| {
+        author_ids
+            .iter()
+            .position(|author_id| author_id.as_i32() == author.id)
+    });
     let tags = tags::find_for_book(conn, book_id)?;
     let series_name = series::find_series_name_for_book(conn, book_id)?;
     let language_codes = languages::find_codes_for_book(conn, book_id)?;
     let identifier_models = book_identifiers::get(conn, book_id)?;
     let file_models = book_files::find_by_book_id(conn, book_id)?;
+    let publisher = publishers::find_publisher_name_for_book(conn, book_id)?;
+    let rating = ratings::find_rating_for_book(conn, book_id)?;
 
     let book_authors = to_library_authors(author_models);
 
@@ -217,6 +528,9 @@ pub fn get_book(conn: &mut SqliteConnection, book_id: BookId) -> Result<Book, Ca
         identifiers,
         description: book_desc,
         language_codes,
+        publisher,
+        rating,
+        publication_date: publication_date(book.pubdate),
         has_cover: book.has_cover.unwrap_or(false),
         is_read: false, // Populated by Library via read state queries
         files,
@@ -276,6 +590,8 @@ fn hydrate(
     let tags_map = tags::find_tag_names_by_book_ids(conn, book_ids.clone())?;
     let series_map = series::find_series_names_by_book_ids(conn, book_ids.clone())?;
     let languages_map = languages::find_codes_by_book_ids(conn, book_ids.clone())?;
+    let publishers_map = publishers::find_publisher_names_by_book_ids(conn, book_ids.clone())?;
+    let ratings_map = ratings::find_ratings_by_book_ids(conn, book_ids.clone())?;
     let files_map = book_files::find_many_by_book_ids(conn, book_ids)?;
 
     let unique_author_ids: Vec<AuthorId> = author_ids_by_book
@@ -344,6 +660,9 @@ fn hydrate(
             identifiers: book_identifiers,
             description: book_description,
             language_codes: book_languages,
+            publisher: publishers_map.get(&book_id).cloned(),
+            rating: ratings_map.get(&book_id).copied(),
+            publication_date: publication_date(book_row.pubdate),
             has_cover: book_row.has_cover.unwrap_or(false),
             is_read: false, // Populated by Library via read state queries
             files,
@@ -360,6 +679,12 @@ fn hydrate(
     Ok(book_list)
 }
 
+/// Calibre stores "no publication date" as `0101-01-01` (its `UNDEFINED_DATE`)
+/// rather than NULL; treat anything at or before that year as unset.
+fn publication_date(pubdate: Option<NaiveDateTime>) -> Option<NaiveDateTime> {
+    pubdate.filter(|date| date.year() > CALIBRE_UNDEFINED_YEAR)
+}
+
 fn to_library_authors(author_models: Vec<crate::Author>) -> Vec<crate::library::Author> {
     author_models
         .into_iter()
@@ -0,0 +1,205 @@
+//! Editing many books at once, as Calibre's bulk metadata dialog does.
+//!
+//! Every book is edited inside one transaction, each in a savepoint of its
+//! own: a book that cannot be edited is rolled back and reported, and the
+//! rest go ahead.
+
+use diesel::{Connection, SqliteConnection};
+use regex::{Regex, RegexBuilder};
+
+use crate::{
+    custom_columns::{self, CustomColumn, CustomValue},
+    library::{
+        BookUpdate, BulkEdit, BulkSeriesIndex, BulkUpdateFailure, BulkUpdateReport, SearchReplace,
+        SearchReplaceField,
+    },
+    operations,
+    queries::{books, series},
+    types::BookId,
+    CalibreError,
+};
+
+/// Apply `edit` to each of `book_ids`, marking the books it changed dirty
+/// for the caller to write their OPFs. `read_column` is the column
+/// [`BulkEdit::is_read`] writes, and must be given when it is set.
+///
+/// An invalid search pattern or an unknown custom column fails the whole
+/// edit before any book is changed.
+pub fn update(
+    conn: &mut SqliteConnection,
+    book_ids: &[BookId],
+    edit: &BulkEdit,
+    read_column: Option<&CustomColumn>,
+) -> Result<BulkUpdateReport, CalibreError> {
+    let search_replace = edit
+        .search_replace
+        .as_ref()
+        .map(|search_replace| {
+            RegexBuilder::new(&search_replace.pattern)
+                .case_insensitive(!search_replace.case_sensitive)
+                .build()
+                .map(|regex| (search_replace, regex))
+                .map_err(|e| CalibreError::InvalidSearch(format!("bad regular expression: {e}")))
+        })
+        .transpose()?;
+
+    conn.transaction::<_, CalibreError, _>(|conn| {
+        let mut custom_values = Vec::new();
+        for (&column_id, value) in &edit.custom_values {
+            let column = custom_columns::get_column(conn, column_id)?;
+            custom_values.push((column, value.clone()));
+        }
+        if let (Some(column), Some(is_read)) = (read_column, edit.is_read) {
+            custom_values.push((column.clone(), Some(CustomValue::Bool(is_read))));
+        }
+
+        let mut series_index = first_series_index(conn, book_ids, edit)?;
+        let mut report = BulkUpdateReport::default();
+        for &book_id in book_ids {
+            let edited = conn.transaction(|conn| {
+                edit_book(
+                    conn,
+                    book_id,
+                    edit,
+                    series_index,
+                    search_replace.as_ref(),
+                    &custom_values,
+                )
+            });
+            match edited {
+                Ok(()) => {
+                    report.updated.push(book_id);
+                    series_index = series_index.map(|index| index + 1.0);
+                }
+                Err(e) => report.failed.push(BulkUpdateFailure {
+                    book_id,
+                    reason: e.to_string(),
+                }),
+            }
+        }
+
+        books::mark_dirty(conn, &report.updated)?;
+        Ok(report)
+    })
+}
+
+/// The index of the first book [`BulkEdit::series_index`] numbers, or `None`
+/// when the books keep theirs.
+fn first_series_index(
+    conn: &mut SqliteConnection,
+    book_ids: &[BookId],
+    edit: &BulkEdit,
+) -> Result<Option<f32>, CalibreError> {
+    let Some(name) = edit.series.as_deref().map(str::trim) else {
+        return Ok(None);
+    };
+    if name.is_empty() {
+        return Ok(None);
+    }
+    match edit.series_index {
+        BulkSeriesIndex::Keep => Ok(None),
+        BulkSeriesIndex::From(start) => Ok(Some(start)),
+        BulkSeriesIndex::Next => {
+            let highest = match series::find_by_name_case_insensitive(conn, name)? {
+                Some(existing) => series::find_books_with_index(conn, existing.id)?
+                    .into_iter()
+                    .filter(|(book_id, _)| !book_ids.contains(book_id))
+                    .map(|(_, index)| index.floor())
+                    .fold(0.0, f32::max),
+                None => 0.0,
+            };
+            Ok(Some(highest + 1.0))
+        }
+    }
+}
+
+fn edit_book(
+    conn: &mut SqliteConnection,
+    book_id: BookId,
+    edit: &BulkEdit,
+    series_index: Option<f32>,
+    search_replace: Option<&(&SearchReplace, Regex)>,
+    custom_values: &[(CustomColumn, Option<CustomValue>)],
+) -> Result<(), CalibreError> {
+    let book = operations::books::get_book(conn, book_id)?;
+
+    let tags = if edit.add_tags.is_empty() && edit.remove_tags.is_empty() {
+        None
+    } else {
+        let mut tags: Vec<String> = book
+            .tags
+            .iter()
+            .filter(|tag| !contains_ignoring_case(&edit.remove_tags, tag))
+            .cloned()
+            .collect();
+        for tag in &edit.add_tags {
+            if !contains_ignoring_case(&tags, tag) {
+                tags.push(tag.clone());
+            }
+        }
+        Some(tags)
+    };
+
+    let mut update = BookUpdate {
+        tags,
+        series: edit.series.clone(),
+        series_index,
+        language_codes: edit.language_codes.clone(),
+        publisher: edit.publisher.clone(),
+        rating: edit.rating,
+        ..Default::default()
+    };
+
+    if let Some((search_replace, regex)) = search_replace {
+        let replace = |value: &str| {
+            regex
+                .replace_all(value, search_replace.replacement.as_str())
+                .trim()
+                .to_string()
+        };
+        match search_replace.field {
+            SearchReplaceField::Title => {
+                let title = replace(&book.title);
+                if title.is_empty() {
+                    return Err(CalibreError::InvalidName(format!(
+                        "replacing in '{}' leaves an empty title",
+                        book.title
+                    )));
+                }
+                update.title = Some(title);
+            }
+            SearchReplaceField::Tags => {
+                let mut tags = Vec::new();
+                for tag in update.tags.take().unwrap_or(book.tags) {
+                    let tag = replace(&tag);
+                    if !tag.is_empty() && !contains_ignoring_case(&tags, &tag) {
+                        tags.push(tag);
+                    }
+                }
+                update.tags = Some(tags);
+            }
+            SearchReplaceField::Series => {
+                update.series = update.series.or(book.series).map(|name| replace(&name));
+            }
+            SearchReplaceField::Publisher => {
+                update.publisher = update
+                    .publisher
+                    .or(book.publisher)
+                    .map(|name| replace(&name));
+            }
+            SearchReplaceField::Comments => {
+                update.comments = book.description.map(|text| replace(&text));
+            }
+        }
+    }
+
+    operations::books::update_book(conn, book_id, update)?;
+    for (column, value) in custom_values {
+        custom_columns::set_value(conn, column, book_id, value.clone())?;
+    }
+    Ok(())
+}
+
+fn contains_ignoring_case(names: &[String], name: &str) -> bool {
+    names.iter().any(|have| have.eq_ignore_ascii_case(name))
+}
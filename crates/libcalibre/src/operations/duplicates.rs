@@ -0,0 +1,357 @@
+//! Finding records that are likely the same thing entered twice.
+//!
+//! Nothing here changes the library: each function returns candidates for
+//! the user to review and then merge.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fs::File,
+    path::{Path, PathBuf},
+};
+
+use diesel::SqliteConnection;
+use sha1::{Digest, Sha1};
+
+use crate::{
+    assets,
+    isbn::{canonical_isbn13, isbn10_from_13},
+    library::{
+        Book, BookAdd, DuplicateAuthor, DuplicateAuthorGroup, DuplicateBookGroup,
+        DuplicateBookMatch, DuplicateReason,
+    },
+    operations,
+    queries::{authors, books},
+    sorting::{author_name_key, title_key},
+    types::{AuthorId, BookId},
+    CalibreError,
+};
+
+/// Group authors whose names share an [`author_name_key`], keeping only the
+/// groups with more than one author. Groups are ordered by key; within a
+/// group the author with the most books comes first, as the likely merge
+/// target.
+pub fn authors(conn: &mut SqliteConnection) -> Result<Vec<DuplicateAuthorGroup>, CalibreError> {
+    let book_counts = authors::book_counts(conn)?;
+
+    let mut by_key: BTreeMap<String, Vec<DuplicateAuthor>> = BTreeMap::new();
+    for author in operations::authors::all(conn)? {
+        let key = author_name_key(&author.name);
+        if key.is_empty() {
+            continue;
+        }
+        let book_count = book_counts.get(&author.id).copied().unwrap_or(0);
+        by_key
+            .entry(key)
+            .or_default()
+            .push(DuplicateAuthor { author, book_count });
+    }
+
+    Ok(by_key
+        .into_iter()
+        .filter(|(_, authors)| authors.len() > 1)
+        .map(|(key, mut authors)| {
+            authors.sort_by_key(|dup| (-dup.book_count, dup.author.id.as_i32()));
+            DuplicateAuthorGroup { key, authors }
+        })
+        .collect())
+}
+
+/// What a book, in the library or about to be added, is compared by.
+struct Fingerprint {
+    /// Canonical ISBN-13s.
+    isbns: BTreeSet<String>,
+    title: String,
+    authors: HashSet<String>,
+    /// Format files with their sizes; only files of equal size are hashed.
+    files: Vec<(PathBuf, u64)>,
+}
+
+impl Fingerprint {
+    fn new<'a>(
+        title: &str,
+        author_names: impl Iterator<Item = &'a str>,
+        isbns: impl Iterator<Item = &'a str>,
+        files: Vec<(PathBuf, u64)>,
+    ) -> Self {
+        Fingerprint {
+            isbns: isbns.filter_map(canonical_isbn13).collect(),
+            title: title_key(title),
+            authors: author_names
+                .map(author_name_key)
+                .filter(|key| !key.is_empty())
+                .collect(),
+            files,
+        }
+    }
+
+    fn of_book(library_root: &Path, book: &Book) -> Self {
+        let book_dir = library_root.join(&book.book_dir_path);
+        Fingerprint::new(
+            &book.title,
+            book.authors.iter().map(|author| author.name.as_str()),
+            isbn_values(
+                book.identifiers
+                    .iter()
+                    .map(|id| (id.label.as_str(), id.value.as_str())),
+            ),
+            book.files
+                .iter()
+                .map(|file| {
+                    let path = book_dir.join(assets::book_file_name(&file.name, &file.format));
+                    (path, file.uncompressed_size as u64)
+                })
+                .collect(),
+        )
+    }
+
+    /// Files that cannot be read are left out; the add reports them.
+    fn of_candidate(book: &BookAdd) -> Self {
+        Fingerprint::new(
+            &book.title,
+            book.author_names.iter().map(String::as_str),
+            isbn_values(
+                book.identifiers
+                    .iter()
+                    .map(|(label, value)| (label.as_str(), value.as_str())),
+            ),
+            book.file_paths
+                .iter()
+                .filter_map(|path| Some((path.clone(), std::fs::metadata(path).ok()?.len())))
+                .collect(),
+        )
+    }
+
+    fn shares_isbn(&self, other: &Fingerprint) -> bool {
+        !self.isbns.is_disjoint(&other.isbns)
+    }
+
+    /// Same title, and a shared author unless neither has any.
+    fn same_title_and_author(&self, other: &Fingerprint) -> bool {
+        !self.title.is_empty()
+            && self.title == other.title
+            && (!self.authors.is_disjoint(&other.authors)
+                || (self.authors.is_empty() && other.authors.is_empty()))
+    }
+}
+
+fn isbn_values<'a>(
+    identifiers: impl Iterator<Item = (&'a str, &'a str)>,
+) -> impl Iterator<Item = &'a str> {
+    identifiers
+        .filter(|(label, _)| label.eq_ignore_ascii_case("isbn"))
+        .map(|(_, value)| value)
+}
+
+/// SHA-1 of each file hashed so far; `None` for files that cannot be read.
+#[derive(Default)]
+struct ContentHashes(HashMap<PathBuf, Option<String>>);
+
+impl ContentHashes {
+    fn get(&mut self, path: &Path) -> Option<String> {
+        self.0
+            .entry(path.to_path_buf())
+            .or_insert_with(|| {
+                let mut hasher = Sha1::new();
+                std::io::copy(&mut File::open(path).ok()?, &mut hasher).ok()?;
+                Some(format!("{:x}", hasher.finalize()))
+            })
+            .clone()
+    }
+}
+
+/// Books already in the library that `candidate` would duplicate, most
+/// reasons first. Only the books that share an ISBN, an author or a file
+/// size with `candidate` are loaded and compared.
+pub fn matches(
+    library_root: &Path,
+    conn: &mut SqliteConnection,
+    candidate: &BookAdd,
+) -> Result<Vec<DuplicateBookMatch>, CalibreError> {
+    let new = Fingerprint::of_candidate(candidate);
+    let mut hashes = ContentHashes::default();
+
+    let isbns: Vec<String> = new
+        .isbns
+        .iter()
+        .flat_map(|isbn13| [Some(isbn13.clone()), isbn10_from_13(isbn13)])
+        .flatten()
+        .collect();
+    // A shared title only counts with a shared author, or when neither book
+    // has one: authors whose names have no key count as none.
+    let mut author_ids = Vec::new();
+    let mut nameless = Vec::new();
+    for author in authors::all(conn)? {
+        let key = author_name_key(&author.name);
+        if key.is_empty() {
+            nameless.push(AuthorId(author.id));
+        } else if new.authors.contains(&key) {
+            author_ids.push(AuthorId(author.id));
+        }
+    }
+    let file_sizes: Vec<i64> = new.files.iter().map(|(_, size)| *size as i64).collect();
+    let candidate_ids = books::duplicate_candidates(
+        conn,
+        &isbns,
+        &author_ids,
+        new.authors.is_empty().then_some(nameless.as_slice()),
+        &file_sizes,
+    )?;
+
+    let mut found = Vec::new();
+    for book in operations::books::get_many(conn, candidate_ids)? {
+        let existing = Fingerprint::of_book(library_root, &book);
+        let mut reasons = Vec::new();
+        if new.shares_isbn(&existing) {
+            reasons.push(DuplicateReason::Isbn);
+        }
+        if new.same_title_and_author(&existing) {
+            reasons.push(DuplicateReason::TitleAndAuthor);
+        }
+        let same_file = new.files.iter().any(|(new_path, new_size)| {
+            existing.files.iter().any(|(path, size)| {
+                size == new_size && {
+                    let hash = hashes.get(path);
+                    hash.is_some() && hash == hashes.get(new_path)
+                }
+            })
+        });
+        if same_file {
+            reasons.push(DuplicateReason::SameFile);
+        }
+        if !reasons.is_empty() {
+            found.push(DuplicateBookMatch {
+                book_id: book.id,
+                reasons,
+            });
+        }
+    }
+
+    found.sort_by_key(|found| {
+        (
+            std::cmp::Reverse(found.reasons.len()),
+            found.book_id.as_i32(),
+        )
+    });
+    Ok(found)
+}
+
+/// Every group of books in the library that look like one another, ordered
+/// by their lowest book id.
+pub fn books(
+    library_root: &Path,
+    conn: &mut SqliteConnection,
+) -> Result<Vec<DuplicateBookGroup>, CalibreError> {
+    let all_books = operations::books::all(conn)?;
+    let prints: Vec<Fingerprint> = all_books
+        .iter()
+        .map(|book| Fingerprint::of_book(library_root, book))
+        .collect();
+
+    let mut links: Vec<(usize, usize, DuplicateReason)> = Vec::new();
+
+    let mut by_isbn: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (index, print) in prints.iter().enumerate() {
+        for isbn in &print.isbns {
+            by_isbn.entry(isbn).or_default().push(index);
+        }
+    }
+    for same in by_isbn.values() {
+        for pair in same.windows(2) {
+            links.push((pair[0], pair[1], DuplicateReason::Isbn));
+        }
+    }
+
+    let mut by_title: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (index, print) in prints.iter().enumerate() {
+        by_title.entry(&print.title).or_default().push(index);
+    }
+    for same in by_title.values() {
+        for (position, &a) in same.iter().enumerate() {
+            for &b in &same[position + 1..] {
+                if prints[a].same_title_and_author(&prints[b]) {
+                    links.push((a, b, DuplicateReason::TitleAndAuthor));
+                }
+            }
+        }
+    }
+
+    // Only files that share a size with another book's file are read.
+    let mut by_size: HashMap<u64, Vec<(usize, &Path)>> = HashMap::new();
+    for (index, print) in prints.iter().enumerate() {
+        for (path, size) in &print.files {
+            by_size.entry(*size).or_default().push((index, path));
+        }
+    }
+    let mut hashes = ContentHashes::default();
+    for same_size in by_size.values() {
+        if same_size.iter().all(|(index, _)| *index == same_size[0].0) {
+            continue;
+        }
+        let mut by_hash: HashMap<String, Vec<usize>> = HashMap::new();
+        for (index, path) in same_size {
+            if let Some(hash) = hashes.get(path) {
+                by_hash.entry(hash).or_default().push(*index);
+            }
+        }
+        for same in by_hash.values() {
+            for pair in same.windows(2) {
+                if pair[0] != pair[1] {
+                    links.push((pair[0], pair[1], DuplicateReason::SameFile));
+                }
+            }
+        }
+    }
+
+    let mut groups = DisjointSets::new(prints.len());
+    for &(a, b, _) in &links {
+        groups.join(a, b);
+    }
+    let mut members: BTreeMap<usize, Vec<BookId>> = BTreeMap::new();
+    for (index, book) in all_books.iter().enumerate() {
+        members.entry(groups.root(index)).or_default().push(book.id);
+    }
+    let mut reasons: HashMap<usize, BTreeSet<DuplicateReason>> = HashMap::new();
+    for (a, _, reason) in links {
+        reasons.entry(groups.root(a)).or_default().insert(reason);
+    }
+
+    let mut found: Vec<DuplicateBookGroup> = members
+        .into_iter()
+        .filter(|(_, book_ids)| book_ids.len() > 1)
+        .map(|(root, mut book_ids)| {
+            book_ids.sort_by_key(|book_id| book_id.as_i32());
+            DuplicateBookGroup {
+                book_ids,
+                reasons: reasons
+                    .remove(&root)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .collect(),
+            }
+        })
+        .collect();
+    found.sort_by_key(|group| group.book_ids[0].as_i32());
+    Ok(found)
+}
+
+/// Union-find over book positions, for joining pairwise matches into groups.
+struct DisjointSets(Vec<usize>);
+
+impl DisjointSets {
+    fn new(len: usize) -> Self {
+        DisjointSets((0..len).collect())
+    }
+
+    fn root(&mut self, mut index: usize) -> usize {
+        while self.0[index] != index {
+            self.0[index] = self.0[self.0[index]];
+            index = self.0[index];
+        }
+        index
+    }
+
+    fn join(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.root(a), self.root(b));
+        self.0[a.max(b)] = a.min(b);
+    }
+}
@@ -0,0 +1,324 @@
+//! Merging duplicate book records into one, like Calibre's "Merge books".
+//!
+//! The target keeps its own metadata and gains whatever it lacks from the
+//! sources, takes over the formats it does not have, and the sources go to
+//! the trash. Database changes run in one transaction and every file moved
+//! or overwritten along the way is recorded, so a failure at any step leaves
+//! the library as it was.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use diesel::{Connection, SqliteConnection};
+
+use crate::{
+    assets::{self, COVER_FILENAME},
+    custom_columns::{self, CustomValue},
+    library::{Book, BookUpdate, MergeCover},
+    operations::{self, book_paths, trash},
+    queries::{book_files, books},
+    types::{AuthorId, BookFileId, BookId},
+    CalibreError,
+};
+
+/// File changes made so far, to put back if the merge fails.
+#[derive(Default)]
+struct Undo {
+    /// Renames as `(from, to)`, in the order they were made.
+    moves: Vec<(PathBuf, PathBuf)>,
+    /// The target's cover as it was before being replaced, if it had one.
+    cover: Option<(PathBuf, Option<Vec<u8>>)>,
+    /// Trash folders created for the sources.
+    trash_dirs: Vec<PathBuf>,
+}
+
+impl Undo {
+    fn apply(self) {
+        trash::undo_moves(&self.moves);
+        for dir in &self.trash_dirs {
+            let _ = fs::remove_dir_all(dir);
+        }
+        if let Some((path, previous)) = self.cover {
+            let restored = match previous {
+                Some(data) => fs::write(&path, data),
+                None => fs::remove_file(&path),
+            };
+            if let Err(e) = restored {
+                eprintln!("WARNING: Failed to restore cover {:?}: {}", path, e);
+            }
+        }
+    }
+}
+
+/// Merge `sources` into `target`: fill in the target's missing metadata,
+/// move over the formats it lacks, pick its cover by `cover`, and move the
+/// sources to the trash. A source listed twice, or equal to the target, is
+/// merged once or not at all.
+///
+/// The caller should refresh the sources' `metadata.opf` beforehand, as for
+/// [`trash::move_book_to_trash`], and write the target's afterwards: it is
+/// marked dirty.
+pub fn merge_books(
+    library_root: &String,
+    conn: &mut SqliteConnection,
+    target: BookId,
+    sources: &[BookId],
+    cover: MergeCover,
+) -> Result<(), CalibreError> {
+    let mut seen = HashSet::from([target]);
+    let sources: Vec<BookId> = sources
+        .iter()
+        .copied()
+        .filter(|source| seen.insert(*source))
+        .collect();
+
+    let mut undo = Undo::default();
+    let mut source_dirs = Vec::new();
+    let merged = conn.transaction::<_, CalibreError, _>(|conn| {
+        let target_book = operations::books::get_book(conn, target)?;
+        let source_books = sources
+            .iter()
+            .map(|&source| operations::books::get_book(conn, source))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        operations::books::update_book(conn, target, fill_missing(&target_book, &source_books))?;
+        merge_custom_values(conn, target, &sources)?;
+        merge_cover(
+            library_root,
+            conn,
+            &target_book,
+            &source_books,
+            cover,
+            &mut undo,
+        )?;
+        move_formats(library_root, conn, &target_book, &source_books, &mut undo)?;
+
+        let root = Path::new(library_root);
+        for book in &source_books {
+            let book_dir = root.join(&book.book_dir_path);
+            if book_dir.is_dir() {
+                undo.trash_dirs
+                    .push(trash::book_trash_dir(library_root, book.id));
+            }
+            trash::move_book_dir_to_trash(library_root, book.id, &book_dir, &mut undo.moves)?;
+            trash::delete_book_records(conn, book.id)?;
+            source_dirs.push(book_dir);
+        }
+
+        books::mark_dirty(conn, &[target])
+    });
+
+    if let Err(e) = merged {
+        undo.apply();
+        return Err(e);
+    }
+    for dir in &source_dirs {
+        book_paths::remove_dir_if_empty(Path::new(library_root), dir);
+    }
+    Ok(())
+}
+
+/// An update that gives the target what it lacks: each single value it has
+/// none of from the first source that has one, plus every source's tags,
+/// languages and identifiers. On an identifier type both have, the target's
+/// value stays.
+fn fill_missing(target: &Book, sources: &[Book]) -> BookUpdate {
+    let author_ids = if target.authors.is_empty() {
+        let mut author_ids: Vec<AuthorId> = Vec::new();
+        for author in sources.iter().flat_map(|book| &book.authors) {
+            if !author_ids.contains(&author.id) {
+                author_ids.push(author.id);
+            }
+        }
+        Some(author_ids).filter(|ids| !ids.is_empty())
+    } else {
+        None
+    };
+
+    // Series and index come as a pair from the same source.
+    let series_source = match target.series {
+        Some(_) => None,
+        None => sources.iter().find(|book| book.series.is_some()),
+    };
+
+    let mut tags = target.tags.clone();
+    for tag in sources.iter().flat_map(|book| &book.tags) {
+        if !tags.iter().any(|have| have.eq_ignore_ascii_case(tag)) {
+            tags.push(tag.clone());
+        }
+    }
+
+    let mut language_codes = target.language_codes.clone();
+    for code in sources.iter().flat_map(|book| &book.language_codes) {
+        if !language_codes.contains(code) {
+            language_codes.push(code.clone());
+        }
+    }
+
+    let mut identifiers: HashMap<String, String> = HashMap::new();
+    for identifier in std::iter::once(target)
+        .chain(sources)
+        .flat_map(|book| &book.identifiers)
+    {
+        identifiers
+            .entry(identifier.label.to_lowercase())
+            .or_insert_with(|| identifier.value.clone());
+    }
+
+    BookUpdate {
+        author_ids,
+        description: fill(&target.description, sources, |book| {
+            book.description.clone()
+        }),
+        tags: Some(tags).filter(|tags| tags.len() > target.tags.len()),
+        series: series_source.and_then(|book| book.series.clone()),
+        series_index: series_source.and_then(|book| book.series_index),
+        language_codes: Some(language_codes)
+            .filter(|codes| codes.len() > target.language_codes.len()),
+        publisher: fill(&target.publisher, sources, |book| book.publisher.clone()),
+        publication_date: fill(&target.publication_date, sources, |book| {
+            book.publication_date
+        })
+        .map(|date| date.date()),
+        rating: fill(&target.rating, sources, |book| book.rating),
+        identifiers: Some(identifiers).filter(|ids| ids.len() > target.identifiers.len()),
+        ..Default::default()
+    }
+}
+
+/// The first source's value for a field the target has no value for.
+fn fill<T>(have: &Option<T>, sources: &[Book], value: impl Fn(&Book) -> Option<T>) -> Option<T> {
+    match have {
+        Some(_) => None,
+        None => sources.iter().find_map(value),
+    }
+}
+
+/// Give the target each custom value it has none of from the first source
+/// with one, and the union of every book's values in multi-valued text
+/// columns.
+fn merge_custom_values(
+    conn: &mut SqliteConnection,
+    target: BookId,
+    sources: &[BookId],
+) -> Result<(), CalibreError> {
+    for column in custom_columns::list(conn)? {
+        if !column.kind.supports_value_io() {
+            continue;
+        }
+        let mut merged = custom_columns::get_value(conn, &column, target)?;
+        let before = merged.clone();
+        for &source in sources {
+            let Some(value) = custom_columns::get_value(conn, &column, source)? else {
+                continue;
+            };
+            merged = match (merged, value) {
+                (None, value) => Some(value),
+                (Some(CustomValue::TextMultiple(mut have)), CustomValue::TextMultiple(more)) => {
+                    for item in more {
+                        if !have.iter().any(|h| h.eq_ignore_ascii_case(&item)) {
+                            have.push(item);
+                        }
+                    }
+                    Some(CustomValue::TextMultiple(have))
+                }
+                (have, _) => have,
+            };
+        }
+        if merged != before {
+            custom_columns::set_value(conn, &column, target, merged)?;
+        }
+    }
+    Ok(())
+}
+
+/// Give the target the cover `policy` picks, keeping a copy of the one it
+/// replaces in `undo`.
+fn merge_cover(
+    library_root: &String,
+    conn: &mut SqliteConnection,
+    target: &Book,
+    sources: &[Book],
+    policy: MergeCover,
+    undo: &mut Undo,
+) -> Result<(), CalibreError> {
+    let mut covers: Vec<(BookId, PathBuf)> = std::iter::once(target)
+        .chain(sources)
+        .filter(|book| book.has_cover)
+        .map(|book| {
+            let path = assets::asset_path(library_root, &book.book_dir_path, COVER_FILENAME);
+            (book.id, path)
+        })
+        .filter(|(_, path)| path.is_file())
+        .collect();
+
+    let chosen = match policy {
+        MergeCover::Target => covers.first(),
+        MergeCover::Source => covers
+            .iter()
+            .find(|(book_id, _)| *book_id != target.id)
+            .or(covers.first()),
+        MergeCover::Largest => {
+            // The first of equal sizes wins, so the target on a tie.
+            covers.reverse();
+            covers
+                .iter()
+                .max_by_key(|(_, path)| fs::metadata(path).map(|m| m.len()).unwrap_or(0))
+        }
+    };
+    let Some((book_id, _)) = chosen else {
+        return Ok(());
+    };
+    if *book_id == target.id {
+        return Ok(());
+    }
+
+    let data = operations::assets::get_book_cover(library_root, conn, *book_id)?;
+    let target_cover = assets::asset_path(library_root, &target.book_dir_path, COVER_FILENAME);
+    let previous = target_cover
+        .is_file()
+        .then(|| fs::read(&target_cover))
+        .transpose()?;
+    undo.cover = Some((target_cover, previous));
+    operations::assets::set_book_cover(library_root, conn, target.id, data)
+}
+
+/// Move each format the target lacks out of the first source that has it,
+/// renaming it after the target's files and relinking its `data` row.
+/// Formats the target already has stay with their source and go to the
+/// trash with it.
+fn move_formats(
+    library_root: &String,
+    conn: &mut SqliteConnection,
+    target: &Book,
+    sources: &[Book],
+    undo: &mut Undo,
+) -> Result<(), CalibreError> {
+    let target_row = books::get(conn, target.id)?.ok_or(CalibreError::BookNotFound(target.id))?;
+    let stem = operations::assets::format_file_stem(conn, &target_row)?;
+    let target_dir = Path::new(library_root).join(&target.book_dir_path);
+
+    let mut formats: HashSet<String> = target.files.iter().map(|f| f.format.clone()).collect();
+    for book in sources {
+        for file in &book.files {
+            if !formats.insert(file.format.clone()) {
+                continue;
+            }
+            let from =
+                operations::assets::get_book_file_path(library_root, conn, book.id, &file.format)?;
+            let to = target_dir.join(assets::book_file_name(&stem, &file.format));
+            if to.exists() {
+                return Err(CalibreError::FileSystem(format!(
+                    "{} already exists",
+                    to.display()
+                )));
+            }
+            fs::create_dir_all(&target_dir)?;
+            fs::rename(&from, &to)?;
+            undo.moves.push((from, to));
+            book_files::relink(conn, BookFileId(file.id), target.id, &stem)?;
+        }
+    }
+    Ok(())
+}
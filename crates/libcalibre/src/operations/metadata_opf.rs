@@ -0,0 +1,100 @@
+//! Keeping each book's `metadata.opf` in step with the database.
+//!
+//! Calibre's "Restore database" rebuilds `metadata.db` from nothing but these
+//! files, so every field it stores for a book — custom columns included —
+//! has to be written out, not just the ones citadel edits.
+
+use std::fs;
+use std::path::Path;
+
+use diesel::SqliteConnection;
+
+use crate::{
+    custom_columns,
+    operations::books as book_operations,
+    opf::{self, OpfMetadata, UserMetadataField},
+    queries::books,
+    types::BookId,
+    CalibreError,
+};
+
+const OPF_FILENAME: &str = "metadata.opf";
+
+/// Rewrite `metadata.opf` in the book's folder from current database state.
+pub fn write_metadata_opf(
+    library_root: &str,
+    conn: &mut SqliteConnection,
+    book_id: BookId,
+) -> Result<(), CalibreError> {
+    let metadata = gather(conn, book_id)?;
+    let book_dir = books::get(conn, book_id)?
+        .ok_or(CalibreError::BookNotFound(book_id))?
+        .path;
+
+    fs::write(
+        Path::new(library_root).join(book_dir).join(OPF_FILENAME),
+        opf::write(&metadata),
+    )
+    .map_err(|e| CalibreError::FileSystem(e.to_string()))
+}
+
+/// Everything Calibre serializes for one book.
+pub(crate) fn gather(
+    conn: &mut SqliteConnection,
+    book_id: BookId,
+) -> Result<OpfMetadata, CalibreError> {
+    // The raw row keeps what `Book` normalizes away: the undefined-pubdate
+    // sentinel and the series index of books outside any series.
+    let row = books::get(conn, book_id)?.ok_or(CalibreError::BookNotFound(book_id))?;
+    let book = book_operations::get_book(conn, book_id)?;
+
+    let mut user_metadata = Vec::new();
+    for column in custom_columns::list(conn)? {
+        let (value, extra) = custom_columns::get_opf_value(conn, &column, book_id)?;
+        user_metadata.push(UserMetadataField {
+            label: column.label,
+            name: column.name,
+            datatype: column.kind.datatype().to_string(),
+            is_multiple: column.is_multiple,
+            is_editable: column.editable,
+            colnum: column.id,
+            display: serde_json::from_str(&column.display)
+                .unwrap_or_else(|_| serde_json::json!({})),
+            value,
+            extra,
+        });
+    }
+
+    Ok(OpfMetadata {
+        calibre_id: Some(row.id),
+        uuid: row.uuid,
+        title: Some(row.title),
+        title_sort: row.sort,
+        author_sort: row.author_sort,
+        author_links: book
+            .authors
+            .iter()
+            .filter_map(|author| {
+                let link = author.link.clone().filter(|link| !link.is_empty())?;
+                Some((author.name.clone(), link))
+            })
+            .collect(),
+        authors: book.authors.into_iter().map(|author| author.name).collect(),
+        identifiers: book
+            .identifiers
+            .into_iter()
+            .map(|identifier| (identifier.label.to_lowercase(), identifier.value))
+            .collect(),
+        publisher: book.publisher,
+        comments: book.description,
+        publication_date: row.pubdate,
+        timestamp: row.timestamp,
+        language_codes: book.language_codes,
+        tags: book.tags,
+        series: book.series,
+        series_index: Some(row.series_index),
+        rating: book.rating,
+        user_metadata,
+        has_cover: row.has_cover.unwrap_or(false),
+    })
+}
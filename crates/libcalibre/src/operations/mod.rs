@@ -1,3 +1,14 @@
 pub mod assets;
 pub mod authors;
+pub mod book_paths;
+pub mod book_text;
 pub mod books;
+pub mod bulk;
+pub mod duplicates;
+pub mod merge;
+pub mod metadata_opf;
+pub mod restore;
+pub mod saved_searches;
+pub mod series;
+pub mod tags;
+pub mod trash;
@@ -0,0 +1,288 @@
+//! Rebuilding a library's database from its `metadata.opf` files.
+//!
+//! This is Calibre's "Restore database" (`db/restore.py`): every book folder
+//! (`<author>/<title> (<id>)/`) carries a complete `metadata.opf`, so walking
+//! the tree and reading each one back recreates the books under their
+//! original ids, with their links, formats, covers and custom columns.
+//! Preferences come from `metadata_db_prefs_backup.json`, which Calibre keeps
+//! next to the database for the same purpose.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::Text;
+
+use crate::{
+    assets::COVER_FILENAME,
+    custom_columns::{self, CustomColumn, CustomColumnKind, CustomColumnSpec},
+    entities::{author::UpdateAuthorData, book_file::NewBookFile},
+    library::{RestoreFailure, RestoreReport},
+    operations::trash,
+    opf::{self, OpfMetadata, UserMetadataField},
+    queries::{authors, book_files, books},
+    types::{AuthorId, BookId},
+    CalibreError, UpdateBookData,
+};
+
+const OPF_FILENAME: &str = "metadata.opf";
+const PREFS_BACKUP_FILENAME: &str = "metadata_db_prefs_backup.json";
+
+/// A book folder whose `metadata.opf` parsed.
+struct FoundBook {
+    /// Relative to the library root, as stored in `books.path`.
+    path: String,
+    dir: PathBuf,
+    book_id: BookId,
+    metadata: OpfMetadata,
+}
+
+/// Fill the empty database behind `conn` from the book folders under
+/// `library_root`. Folders that cannot be restored are reported rather than
+/// failing the whole restore; each book is written in its own transaction.
+pub fn restore_library(
+    library_root: &str,
+    conn: &mut SqliteConnection,
+) -> Result<RestoreReport, CalibreError> {
+    let mut report = RestoreReport::default();
+    let mut found: Vec<FoundBook> = Vec::new();
+
+    for (path, dir) in book_dirs(Path::new(library_root))? {
+        let book = read_book_dir(&path, &dir).and_then(|book| {
+            match found.iter().find(|other| other.book_id == book.book_id) {
+                Some(other) => Err(format!(
+                    "Book id {} is already used by {}",
+                    book.book_id, other.path
+                )),
+                None => Ok(book),
+            }
+        });
+        match book {
+            Ok(book) => found.push(book),
+            Err(reason) => report.failed.push(RestoreFailure { path, reason }),
+        }
+    }
+
+    let columns = create_custom_columns(conn, &found)?;
+
+    for book in found {
+        match conn.transaction::<(), CalibreError, _>(|conn| restore_book(conn, &book, &columns)) {
+            Ok(()) => report.restored.push(book.book_id),
+            Err(e) => report.failed.push(RestoreFailure {
+                path: book.path,
+                reason: e.to_string(),
+            }),
+        }
+    }
+
+    restore_preferences(Path::new(library_root), conn)?;
+    Ok(report)
+}
+
+/// `(relative path, folder)` of every book folder: the second level of the
+/// library tree, skipping hidden folders such as `.caltrash`.
+fn book_dirs(library_root: &Path) -> Result<Vec<(String, PathBuf)>, CalibreError> {
+    let mut dirs = Vec::new();
+    for author_dir in visible_subdirs(library_root)? {
+        for book_dir in visible_subdirs(&author_dir)? {
+            let relative = book_dir
+                .strip_prefix(library_root)
+                .ok()
+                .and_then(|relative| relative.to_str())
+                .map(|relative| relative.replace('\\', "/"));
+            match relative {
+                Some(relative) => dirs.push((relative, book_dir)),
+                None => eprintln!(
+                    "WARNING: Skipping book folder with a non UTF-8 path: {}",
+                    book_dir.display()
+                ),
+            }
+        }
+    }
+    Ok(dirs)
+}
+
+fn visible_subdirs(dir: &Path) -> Result<Vec<PathBuf>, CalibreError> {
+    let mut subdirs = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() && !entry.file_name().to_string_lossy().starts_with('.') {
+            subdirs.push(entry.path());
+        }
+    }
+    subdirs.sort();
+    Ok(subdirs)
+}
+
+/// Parse a book folder's `metadata.opf`. The book keeps the id the OPF
+/// records, falling back to the `(<id>)` suffix of the folder name.
+fn read_book_dir(path: &str, dir: &Path) -> Result<FoundBook, String> {
+    let opf_path = dir.join(OPF_FILENAME);
+    if !opf_path.is_file() {
+        return Err("No metadata.opf in folder".to_string());
+    }
+    let contents = fs::read_to_string(&opf_path).map_err(|e| e.to_string())?;
+    let metadata = opf::parse(&contents).map_err(|e| e.to_string())?;
+
+    let book_id = metadata
+        .calibre_id
+        .or_else(|| folder_book_id(dir))
+        .ok_or_else(|| "metadata.opf records no book id".to_string())?;
+
+    Ok(FoundBook {
+        path: path.to_string(),
+        dir: dir.to_path_buf(),
+        book_id: BookId(book_id),
+        metadata,
+    })
+}
+
+/// The id in a `Title (12)` folder name.
+fn folder_book_id(dir: &Path) -> Option<i32> {
+    let name = dir.file_name()?.to_str()?;
+    let (_, id) = name.strip_suffix(')')?.rsplit_once('(')?;
+    id.parse().ok()
+}
+
+/// Create every custom column the books describe, keyed by label. The first
+/// book to mention a column defines it, as in Calibre.
+fn create_custom_columns(
+    conn: &mut SqliteConnection,
+    found: &[FoundBook],
+) -> Result<HashMap<String, CustomColumn>, CalibreError> {
+    let mut fields: Vec<&UserMetadataField> = Vec::new();
+    for field in found.iter().flat_map(|book| &book.metadata.user_metadata) {
+        if !fields.iter().any(|known| known.label == field.label) {
+            fields.push(field);
+        }
+    }
+    // Recreate columns in their original order so they keep their
+    // relative table numbers.
+    fields.sort_by_key(|field| field.colnum);
+
+    let mut columns = HashMap::new();
+    for field in fields {
+        let kind = CustomColumnKind::from_datatype(&field.datatype);
+        let spec = CustomColumnSpec {
+            label: field.label.clone(),
+            name: field.name.clone(),
+            is_multiple: field.is_multiple && kind == CustomColumnKind::Text,
+            kind,
+            enum_values: Vec::new(),
+            display: Some(field.display.to_string()),
+        };
+        let column = match custom_columns::create(conn, &spec) {
+            Ok(column) => column,
+            Err(e) => {
+                eprintln!(
+                    "WARNING: Could not restore custom column #{}: {}",
+                    field.label, e
+                );
+                continue;
+            }
+        };
+        if !field.is_editable {
+            use crate::schema::custom_columns::dsl::*;
+            diesel::update(custom_columns.filter(id.eq(column.id)))
+                .set(editable.eq(false))
+                .execute(conn)
+                .map_err(CalibreError::from)?;
+        }
+        columns.insert(field.label.clone(), column);
+    }
+    Ok(columns)
+}
+
+fn restore_book(
+    conn: &mut SqliteConnection,
+    book: &FoundBook,
+    columns: &HashMap<String, CustomColumn>,
+) -> Result<(), CalibreError> {
+    let book_id = book.book_id;
+    trash::create_book_record(conn, book_id, &book.metadata)?;
+
+    for (format, stem, size) in trash::book_format_files(&book.dir)? {
+        book_files::create(
+            conn,
+            NewBookFile {
+                book: book_id.as_i32(),
+                format,
+                uncompressed_size: size as i32,
+                name: stem,
+            },
+        )?;
+    }
+    books::update(
+        conn,
+        book_id,
+        UpdateBookData {
+            path: Some(book.path.clone()),
+            has_cover: Some(book.dir.join(COVER_FILENAME).is_file()),
+            ..Default::default()
+        },
+    )?;
+
+    for (name, link) in &book.metadata.author_links {
+        if let Some(author) = authors::find_by_name(conn, name)? {
+            authors::update(
+                conn,
+                AuthorId(author.id),
+                UpdateAuthorData {
+                    link: Some(link.clone()),
+                    ..Default::default()
+                },
+            )?;
+        }
+    }
+
+    for field in &book.metadata.user_metadata {
+        let Some(column) = columns.get(&field.label) else {
+            continue;
+        };
+        if let Err(e) =
+            custom_columns::set_opf_value(conn, column, book_id, &field.value, field.extra)
+        {
+            eprintln!(
+                "WARNING: Could not restore #{} for {}: {}",
+                field.label, book.path, e
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Bring back the preferences Calibre backs up to
+/// `metadata_db_prefs_backup.json`, over the defaults of the new database.
+fn restore_preferences(
+    library_root: &Path,
+    conn: &mut SqliteConnection,
+) -> Result<(), CalibreError> {
+    let Ok(contents) = fs::read_to_string(library_root.join(PREFS_BACKUP_FILENAME)) else {
+        return Ok(());
+    };
+    let prefs: serde_json::Map<String, serde_json::Value> = match serde_json::from_str(&contents) {
+        Ok(prefs) => prefs,
+        Err(e) => {
+            eprintln!(
+                "WARNING: Ignoring unreadable {}: {}",
+                PREFS_BACKUP_FILENAME, e
+            );
+            return Ok(());
+        }
+    };
+
+    conn.transaction::<(), CalibreError, _>(|conn| {
+        for (key, value) in prefs {
+            // Calibre stores preference values as indented JSON.
+            let value = serde_json::to_string_pretty(&value).map_err(CalibreError::unknown)?;
+            sql_query("INSERT OR REPLACE INTO preferences (key, val) VALUES (?, ?)")
+                .bind::<Text, _>(key)
+                .bind::<Text, _>(value)
+                .execute(conn)
+                .map_err(CalibreError::from)?;
+        }
+        Ok(())
+    })
+}
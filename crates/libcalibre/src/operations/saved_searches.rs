@@ -0,0 +1,88 @@
+//! Saved searches and virtual libraries.
+//!
+//! Calibre keeps both in its `preferences` table as JSON objects mapping a
+//! name to a query in its search language: `saved_searches` and
+//! `virtual_libraries`. Entries are read and written in place, so a library
+//! shared with Calibre keeps one set of curated views.
+
+use diesel::{Connection, SqliteConnection};
+
+use crate::{
+    queries::preferences,
+    search::{self, SAVED_SEARCHES_PREF, VIRTUAL_LIBRARIES_PREF},
+    CalibreError,
+};
+
+/// Which of the two named-query preferences to work on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NamedSearches {
+    SavedSearches,
+    VirtualLibraries,
+}
+
+impl NamedSearches {
+    fn pref_key(self) -> &'static str {
+        match self {
+            NamedSearches::SavedSearches => SAVED_SEARCHES_PREF,
+            NamedSearches::VirtualLibraries => VIRTUAL_LIBRARIES_PREF,
+        }
+    }
+}
+
+/// Every `(name, query)` pair, sorted case-insensitively by name. Entries
+/// whose query is not a string are skipped.
+pub fn list(
+    conn: &mut SqliteConnection,
+    kind: NamedSearches,
+) -> Result<Vec<(String, String)>, CalibreError> {
+    let mut entries: Vec<(String, String)> = preferences::get_object(conn, kind.pref_key())?
+        .into_iter()
+        .filter_map(|(name, query)| Some((name, query.as_str()?.to_string())))
+        .collect();
+    entries.sort_by(|(a, _), (b, _)| a.to_lowercase().cmp(&b.to_lowercase()).then(a.cmp(b)));
+    Ok(entries)
+}
+
+/// Add or replace the entry `name`. The query must compile: a saved search
+/// that does not parse, or names an unknown column, saved search or virtual
+/// library, is a [`CalibreError::InvalidSearch`]. A virtual library may have
+/// an empty query, meaning the whole library.
+pub fn set(
+    conn: &mut SqliteConnection,
+    kind: NamedSearches,
+    name: &str,
+    query: &str,
+) -> Result<(), CalibreError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(CalibreError::InvalidSearch(
+            "a name is required".to_string(),
+        ));
+    }
+    let query = query.trim();
+    if !(query.is_empty() && kind == NamedSearches::VirtualLibraries) {
+        search::compile(conn, query)?;
+    }
+
+    conn.transaction::<(), CalibreError, _>(|conn| {
+        let mut entries = preferences::get_object(conn, kind.pref_key())?;
+        entries.insert(name.to_string(), serde_json::Value::from(query));
+        preferences::set(conn, kind.pref_key(), &serde_json::Value::Object(entries))
+    })
+}
+
+/// Remove the entry `name`. Returns whether it existed.
+pub fn remove(
+    conn: &mut SqliteConnection,
+    kind: NamedSearches,
+    name: &str,
+) -> Result<bool, CalibreError> {
+    conn.transaction::<bool, CalibreError, _>(|conn| {
+        let mut entries = preferences::get_object(conn, kind.pref_key())?;
+        if entries.remove(name).is_none() {
+            return Ok(false);
+        }
+        preferences::set(conn, kind.pref_key(), &serde_json::Value::Object(entries))?;
+        Ok(true)
+    })
+}
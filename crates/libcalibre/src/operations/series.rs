@@ -0,0 +1,165 @@
+//! Renaming, merging and renumbering series across the whole library.
+//!
+//! Like the tag operations, each runs in one transaction, marks the books it
+//! changed as dirty and returns them for the caller to rewrite their OPFs.
+
+use diesel::{Connection, SqliteConnection};
+
+use crate::{
+    entities::series::Series,
+    library::{SeriesNumbering, SeriesSummary},
+    queries::{books, series},
+    types::BookId,
+    CalibreError,
+};
+
+fn existing(conn: &mut SqliteConnection, series_id: i32) -> Result<Series, CalibreError> {
+    series::get(conn, series_id)?.ok_or(CalibreError::SeriesNotFound(series_id))
+}
+
+fn summary(series: Series, book_count: usize) -> SeriesSummary {
+    SeriesSummary {
+        id: series.id,
+        name: series.name,
+        book_count: book_count as i64,
+    }
+}
+
+fn book_ids(books: &[(BookId, f32)]) -> Vec<BookId> {
+    books.iter().map(|(book_id, _)| *book_id).collect()
+}
+
+/// Rename a series. Series names are unique regardless of case, so renaming
+/// onto another series' name merges the two; the surviving series is
+/// returned.
+pub fn rename(
+    conn: &mut SqliteConnection,
+    series_id: i32,
+    new_name: &str,
+) -> Result<(SeriesSummary, Vec<BookId>), CalibreError> {
+    let new_name = new_name.trim();
+    if new_name.is_empty() {
+        return Err(CalibreError::InvalidName(
+            "a series name cannot be empty".to_string(),
+        ));
+    }
+
+    conn.transaction::<_, CalibreError, _>(|conn| {
+        let renamed = existing(conn, series_id)?;
+        match series::find_by_name_case_insensitive(conn, new_name)? {
+            Some(other) if other.id != renamed.id => merge_existing(conn, renamed, other),
+            _ => {
+                series::rename(conn, renamed.id, new_name)?;
+                let changed = book_ids(&series::find_books_with_index(conn, renamed.id)?);
+                books::mark_dirty(conn, &changed)?;
+                let renamed = Series {
+                    name: new_name.to_string(),
+                    ..renamed
+                };
+                Ok((summary(renamed, changed.len()), changed))
+            }
+        }
+    })
+}
+
+/// Move every book of series `from` into series `into`, keeping their
+/// indices, then delete `from`. Colliding indices are left for
+/// [`numbering`] to report.
+pub fn merge(
+    conn: &mut SqliteConnection,
+    from: i32,
+    into: i32,
+) -> Result<(SeriesSummary, Vec<BookId>), CalibreError> {
+    conn.transaction::<_, CalibreError, _>(|conn| {
+        let from = existing(conn, from)?;
+        let into = existing(conn, into)?;
+        if from.id == into.id {
+            let book_count = series::find_books_with_index(conn, into.id)?.len();
+            return Ok((summary(into, book_count), vec![]));
+        }
+        merge_existing(conn, from, into)
+    })
+}
+
+fn merge_existing(
+    conn: &mut SqliteConnection,
+    from: Series,
+    into: Series,
+) -> Result<(SeriesSummary, Vec<BookId>), CalibreError> {
+    let moved = book_ids(&series::find_books_with_index(conn, from.id)?);
+    series::move_links(conn, from.id, into.id)?;
+    series::delete(conn, from.id)?;
+    books::mark_dirty(conn, &moved)?;
+    let book_count = series::find_books_with_index(conn, into.id)?.len();
+    Ok((summary(into, book_count), moved))
+}
+
+/// Number the series' books `start`, `start + 1`, ... in the given order,
+/// which must list each book of the series exactly once. Returns the books
+/// whose index changed.
+pub fn renumber(
+    conn: &mut SqliteConnection,
+    series_id: i32,
+    order: &[BookId],
+    start: f32,
+) -> Result<Vec<BookId>, CalibreError> {
+    conn.transaction::<_, CalibreError, _>(|conn| {
+        existing(conn, series_id)?;
+        let current = series::find_books_with_index(conn, series_id)?;
+
+        let mut expected = book_ids(&current);
+        let mut given = order.to_vec();
+        expected.sort_by_key(|book_id| book_id.as_i32());
+        given.sort_by_key(|book_id| book_id.as_i32());
+        if expected != given {
+            return Err(CalibreError::InvalidSeriesOrder(format!(
+                "the order must list each of the series' {} books exactly once",
+                expected.len()
+            )));
+        }
+
+        let mut changed = Vec::new();
+        for (position, &book_id) in order.iter().enumerate() {
+            let index = start + position as f32;
+            let unchanged = current
+                .iter()
+                .any(|&(id, old_index)| id == book_id && old_index == index);
+            if !unchanged {
+                series::set_book_index(conn, book_id, index)?;
+                changed.push(book_id);
+            }
+        }
+        books::mark_dirty(conn, &changed)?;
+        Ok(changed)
+    })
+}
+
+/// The series' books in reading order, with the whole numbers missing from
+/// its numbering and the indices more than one book shares.
+pub fn numbering(
+    conn: &mut SqliteConnection,
+    series_id: i32,
+) -> Result<SeriesNumbering, CalibreError> {
+    let series_row = existing(conn, series_id)?;
+    let numbered = series::find_books_with_index(conn, series_id)?;
+
+    // Fractional indices (a novella at 2.5) neither fill nor open a gap.
+    let highest = numbered.iter().map(|(_, index)| *index).fold(0.0, f32::max);
+    let gaps = (1..=highest.floor() as i32)
+        .filter(|&whole| !numbered.iter().any(|(_, index)| *index == whole as f32))
+        .collect();
+
+    let mut duplicates: Vec<(f32, Vec<BookId>)> = Vec::new();
+    for same_index in numbered.chunk_by(|(_, a), (_, b)| a == b) {
+        if same_index.len() > 1 {
+            duplicates.push((same_index[0].1, book_ids(same_index)));
+        }
+    }
+
+    Ok(SeriesNumbering {
+        series: summary(series_row, numbered.len()),
+        books: numbered,
+        gaps,
+        duplicates,
+    })
+}
@@ -0,0 +1,112 @@
+//! Renaming, merging and deleting tags across the whole library.
+//!
+//! Each operation runs in one transaction and marks every book whose tags
+//! changed as dirty, so its `metadata.opf` gets rewritten. They return the
+//! affected books for the caller to rewrite straight away.
+
+use diesel::{Connection, SqliteConnection};
+
+use crate::{
+    library::TagSummary,
+    queries::{books, tags},
+    types::BookId,
+    CalibreError, Tag,
+};
+
+fn existing(conn: &mut SqliteConnection, tag_id: i32) -> Result<Tag, CalibreError> {
+    tags::get(conn, tag_id)?.ok_or(CalibreError::TagNotFound(tag_id))
+}
+
+fn summary(tag: Tag) -> TagSummary {
+    TagSummary {
+        id: tag.id,
+        name: tag.name,
+    }
+}
+
+/// Rename a tag. Tag names are unique regardless of case, so renaming onto
+/// another tag's name merges the two, as in Calibre; the surviving tag is
+/// returned.
+pub fn rename(
+    conn: &mut SqliteConnection,
+    tag_id: i32,
+    new_name: &str,
+) -> Result<(TagSummary, Vec<BookId>), CalibreError> {
+    let new_name = new_name.trim();
+    if new_name.is_empty() {
+        return Err(CalibreError::InvalidName(
+            "a tag name cannot be empty".to_string(),
+        ));
+    }
+
+    conn.transaction::<_, CalibreError, _>(|conn| {
+        let tag = existing(conn, tag_id)?;
+        match tags::find_by_name_case_insensitive(conn, new_name)? {
+            Some(other) if other.id != tag.id => merge_existing(conn, tag, other),
+            _ => {
+                tags::rename(conn, tag.id, new_name)?;
+                let book_ids = tags::find_books(conn, tag.id)?;
+                books::mark_dirty(conn, &book_ids)?;
+                Ok((
+                    TagSummary {
+                        id: tag.id,
+                        name: new_name.to_string(),
+                    },
+                    book_ids,
+                ))
+            }
+        }
+    })
+}
+
+/// Move every book of tag `from` to tag `into`, then delete `from`.
+pub fn merge(
+    conn: &mut SqliteConnection,
+    from: i32,
+    into: i32,
+) -> Result<(TagSummary, Vec<BookId>), CalibreError> {
+    conn.transaction::<_, CalibreError, _>(|conn| {
+        let from = existing(conn, from)?;
+        let into = existing(conn, into)?;
+        if from.id == into.id {
+            return Ok((summary(into), vec![]));
+        }
+        merge_existing(conn, from, into)
+    })
+}
+
+fn merge_existing(
+    conn: &mut SqliteConnection,
+    from: Tag,
+    into: Tag,
+) -> Result<(TagSummary, Vec<BookId>), CalibreError> {
+    let book_ids = tags::find_books(conn, from.id)?;
+    tags::move_links(conn, from.id, into.id)?;
+    tags::delete(conn, from.id)?;
+    books::mark_dirty(conn, &book_ids)?;
+    Ok((summary(into), book_ids))
+}
+
+/// Remove a tag from every book and from the library. Returns the books it
+/// was removed from.
+pub fn delete(conn: &mut SqliteConnection, tag_id: i32) -> Result<Vec<BookId>, CalibreError> {
+    conn.transaction::<_, CalibreError, _>(|conn| {
+        let tag = existing(conn, tag_id)?;
+        let book_ids = tags::find_books(conn, tag.id)?;
+        tags::unlink_all(conn, tag.id)?;
+        tags::delete(conn, tag.id)?;
+        books::mark_dirty(conn, &book_ids)?;
+        Ok(book_ids)
+    })
+}
+
+/// Delete every tag no book uses. Returns the deleted tags; no book changes.
+pub fn prune_unused(conn: &mut SqliteConnection) -> Result<Vec<TagSummary>, CalibreError> {
+    conn.transaction::<_, CalibreError, _>(|conn| {
+        let unused = tags::unused(conn)?;
+        for tag in &unused {
+            tags::delete(conn, tag.id)?;
+        }
+        Ok(unused.into_iter().map(summary).collect())
+    })
+}
@@ -0,0 +1,610 @@
+//! The library's recycle bin, laid out like Calibre 7's.
+//!
+//! Calibre never deletes a removed book outright: its folder is moved to
+//! `.caltrash/b/<book id>/` in the library root, `metadata.opf` included, and
+//! a removed format goes to `.caltrash/f/<book id>/<format>` next to a
+//! `metadata.json` holding the book's title and authors (`db/backend.py`,
+//! `move_book_to_trash` and `remove_formats`). An entry's age is the
+//! modification time of its folder. Either application can list, restore or
+//! expire what the other trashed.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use diesel::{Connection, SqliteConnection};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    assets::{self, COVER_FILENAME},
+    custom_columns,
+    entities::book_file::NewBookFile,
+    library::{BookUpdate, TrashEntry, TrashEntryKind},
+    operations::{book_paths, books as book_operations},
+    opf,
+    queries::{authors, book_descriptions, book_files, book_identifiers, books},
+    sorting,
+    types::{AuthorId, BookFileId, BookId},
+    CalibreError, NewBook, UpdateBookData,
+};
+
+pub const TRASH_DIR_NAME: &str = ".caltrash";
+const BOOKS_DIR_NAME: &str = "b";
+const FORMATS_DIR_NAME: &str = "f";
+const OPF_FILENAME: &str = "metadata.opf";
+const FORMAT_METADATA_FILENAME: &str = "metadata.json";
+
+/// What Calibre records about the book a trashed format belonged to.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct FormatMetadata {
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    authors: Vec<String>,
+}
+
+fn trash_dir(library_root: &str) -> PathBuf {
+    Path::new(library_root).join(TRASH_DIR_NAME)
+}
+
+pub(crate) fn book_trash_dir(library_root: &str, book_id: BookId) -> PathBuf {
+    trash_dir(library_root)
+        .join(BOOKS_DIR_NAME)
+        .join(book_id.as_i32().to_string())
+}
+
+fn format_trash_dir(library_root: &str, book_id: BookId) -> PathBuf {
+    trash_dir(library_root)
+        .join(FORMATS_DIR_NAME)
+        .join(book_id.as_i32().to_string())
+}
+
+/// Move a book's folder to `.caltrash/b/<id>` and delete its database rows.
+///
+/// The folder is moved first and moved back if the rows cannot be deleted. A
+/// book trashed earlier under the same id is replaced, as in Calibre. The
+/// caller should refresh `metadata.opf` beforehand: it is all the trash keeps
+/// of the book's metadata.
+pub fn move_book_to_trash(
+    library_root: &String,
+    conn: &mut SqliteConnection,
+    book_id: BookId,
+) -> Result<(), CalibreError> {
+    let book = books::get(conn, book_id)?.ok_or(CalibreError::BookNotFound(book_id))?;
+    let root = Path::new(library_root);
+    let book_dir = root.join(&book.path);
+
+    let mut moves = Vec::new();
+    if let Err(e) = move_book_dir_to_trash(library_root, book_id, &book_dir, &mut moves) {
+        undo_moves(&moves);
+        let _ = fs::remove_dir_all(book_trash_dir(library_root, book_id));
+        return Err(e);
+    }
+
+    if let Err(e) = conn.transaction(|conn| delete_book_records(conn, book_id)) {
+        undo_moves(&moves);
+        let _ = fs::remove_dir_all(book_trash_dir(library_root, book_id));
+        return Err(e);
+    }
+
+    book_paths::remove_dir_if_empty(root, &book_dir);
+    Ok(())
+}
+
+/// The folder half of [`move_book_to_trash`]: move everything in `book_dir`
+/// to `.caltrash/b/<id>`, recording each move for [`undo_moves`]. A missing
+/// folder moves nothing.
+pub(crate) fn move_book_dir_to_trash(
+    library_root: &str,
+    book_id: BookId,
+    book_dir: &Path,
+    moves: &mut Vec<(PathBuf, PathBuf)>,
+) -> Result<(), CalibreError> {
+    if !book_dir.is_dir() {
+        return Ok(());
+    }
+    let dest = book_trash_dir(library_root, book_id);
+    if dest.exists() {
+        fs::remove_dir_all(&dest)?;
+    }
+    // A fresh folder, so its mtime records when the book was trashed.
+    fs::create_dir_all(&dest)?;
+    move_entries(book_dir, &dest, moves)
+}
+
+/// Move one format file of a book to `.caltrash/f/<id>/<format>` and delete
+/// its `data` row. A file already missing from disk is only warned about.
+pub fn move_format_to_trash(
+    library_root: &String,
+    conn: &mut SqliteConnection,
+    book_id: BookId,
+    file_format: &str,
+) -> Result<(), CalibreError> {
+    let book = books::get(conn, book_id)?.ok_or(CalibreError::BookNotFound(book_id))?;
+    let file = book_files::find_by_book_and_format(conn, book_id, file_format.to_string())?.ok_or(
+        CalibreError::BookFileNotFound(book_id, file_format.to_string()),
+    )?;
+
+    let source = Path::new(library_root)
+        .join(&book.path)
+        .join(assets::book_file_name(&file.name, &file.format));
+
+    let mut moves = Vec::new();
+    if source.is_file() {
+        let dest_dir = format_trash_dir(library_root, book_id);
+        fs::create_dir_all(&dest_dir)?;
+        write_format_metadata(conn, &dest_dir, &book.title, book_id)?;
+
+        let dest = dest_dir.join(file.format.to_lowercase());
+        if dest.exists() {
+            fs::remove_file(&dest)?;
+        }
+        fs::rename(&source, &dest)?;
+        moves.push((source, dest));
+    } else {
+        eprintln!("WARNING: Format file {:?} is missing; not trashed", source);
+    }
+
+    if let Err(e) = book_files::delete(conn, BookFileId(file.id)) {
+        undo_moves(&moves);
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// Recreate a trashed book under its original id from
+/// `.caltrash/b/<id>/metadata.opf`, and move its folder back into the
+/// library. Fails if another book has taken the id since.
+///
+/// The database rows are written in one transaction and the files moved back
+/// only inside it, so a failure leaves the trash entry as it was.
+pub fn restore_book(
+    library_root: &String,
+    conn: &mut SqliteConnection,
+    book_id: BookId,
+) -> Result<(), CalibreError> {
+    let trashed_dir = book_trash_dir(library_root, book_id);
+    if !trashed_dir.is_dir() {
+        return Err(CalibreError::TrashEntryNotFound(book_id));
+    }
+    if books::get(conn, book_id)?.is_some() {
+        return Err(CalibreError::BannedFunctionInvocation(format!(
+            "Cannot restore from trash: a book with id {} already exists",
+            book_id.as_i32()
+        )));
+    }
+
+    let metadata = opf::parse(&fs::read_to_string(trashed_dir.join(OPF_FILENAME))?)?;
+    let primary_author = metadata.authors.first().cloned().unwrap_or_default();
+    let title = metadata.title.clone().unwrap_or_default();
+    let book_dir_relative = assets::book_dir_relative(&primary_author, &title, book_id.as_i32());
+    let book_dir = Path::new(library_root).join(&book_dir_relative);
+    let book_dir_relative = book_dir_relative
+        .to_str()
+        .ok_or_else(|| CalibreError::FileSystem("Book path is not valid UTF-8".to_string()))?
+        .to_string();
+
+    let mut moves = Vec::new();
+    let result = conn.transaction::<(), CalibreError, _>(|conn| {
+        create_book_record(conn, book_id, &metadata)?;
+        restore_custom_values(conn, book_id, &metadata)?;
+
+        for (format, stem, size) in book_format_files(&trashed_dir)? {
+            book_files::create(
+                conn,
+                NewBookFile {
+                    book: book_id.as_i32(),
+                    format,
+                    uncompressed_size: size as i32,
+                    name: stem,
+                },
+            )?;
+        }
+
+        books::update(
+            conn,
+            book_id,
+            UpdateBookData {
+                path: Some(book_dir_relative),
+                has_cover: Some(trashed_dir.join(COVER_FILENAME).is_file()),
+                ..Default::default()
+            },
+        )?;
+
+        fs::create_dir_all(&book_dir)?;
+        move_entries(&trashed_dir, &book_dir, &mut moves)
+    });
+
+    if let Err(e) = result {
+        undo_moves(&moves);
+        book_paths::remove_dir_if_empty(Path::new(library_root), &book_dir);
+        return Err(e);
+    }
+
+    let _ = fs::remove_dir(&trashed_dir);
+    Ok(())
+}
+
+/// Put a trashed format back on its book, replacing any file of that format
+/// the book has gained since.
+pub fn restore_format(
+    library_root: &String,
+    conn: &mut SqliteConnection,
+    book_id: BookId,
+    file_format: &str,
+) -> Result<(), CalibreError> {
+    let trashed_dir = format_trash_dir(library_root, book_id);
+    let trashed_file = trashed_dir.join(file_format.to_lowercase());
+    if !trashed_file.is_file() {
+        return Err(CalibreError::BookFileNotFound(
+            book_id,
+            file_format.to_string(),
+        ));
+    }
+
+    let book = books::get(conn, book_id)?.ok_or(CalibreError::BookNotFound(book_id))?;
+    let format = file_format.to_uppercase();
+    let stem = match book_files::find_by_book_id(conn, book_id)?.first() {
+        Some(file) => file.name.clone(),
+        None => {
+            let primary_author = match books::find_authors(conn, book_id)?.first() {
+                Some(author_id) => authors::get(conn, *author_id)?
+                    .map(|author| author.name)
+                    .unwrap_or_default(),
+                None => String::new(),
+            };
+            assets::book_file_stem(&book.title, &primary_author)
+        }
+    };
+
+    let book_dir = Path::new(library_root).join(&book.path);
+    let dest = book_dir.join(assets::book_file_name(&stem, &format));
+    let size = fs::metadata(&trashed_file)?.len();
+
+    let mut moves = Vec::new();
+    let result = conn.transaction::<(), CalibreError, _>(|conn| {
+        if let Some(existing) = book_files::find_by_book_and_format(conn, book_id, format.clone())?
+        {
+            book_files::delete(conn, BookFileId(existing.id))?;
+        }
+        book_files::create(
+            conn,
+            NewBookFile {
+                book: book_id.as_i32(),
+                format: format.clone(),
+                uncompressed_size: size as i32,
+                name: stem.clone(),
+            },
+        )?;
+
+        fs::create_dir_all(&book_dir)?;
+        fs::rename(&trashed_file, &dest)?;
+        moves.push((trashed_file.clone(), dest.clone()));
+        Ok(())
+    });
+
+    if let Err(e) = result {
+        undo_moves(&moves);
+        return Err(e);
+    }
+
+    if trashed_formats_in(&trashed_dir).is_empty() {
+        let _ = fs::remove_dir_all(&trashed_dir);
+    }
+    Ok(())
+}
+
+/// Everything in the trash, books first, each group newest first. Entries
+/// whose metadata cannot be read are skipped with a warning, as Calibre does.
+pub fn list_trash(library_root: &str) -> Result<Vec<TrashEntry>, CalibreError> {
+    let mut entries = Vec::new();
+
+    for (book_id, dir) in trash_entry_dirs(library_root, BOOKS_DIR_NAME)? {
+        let metadata = match fs::read_to_string(dir.join(OPF_FILENAME))
+            .map_err(CalibreError::from)
+            .and_then(|contents| opf::parse(&contents))
+        {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                eprintln!("WARNING: Skipping unreadable trash entry {:?}: {}", dir, e);
+                continue;
+            }
+        };
+        entries.push(TrashEntry {
+            book_id,
+            kind: TrashEntryKind::Book,
+            title: metadata.title.unwrap_or_default(),
+            authors: metadata.authors,
+            formats: book_format_files(&dir)?
+                .into_iter()
+                .map(|(format, _, _)| format)
+                .collect(),
+            trashed_at: modified_at(&dir)?,
+        });
+    }
+
+    for (book_id, dir) in trash_entry_dirs(library_root, FORMATS_DIR_NAME)? {
+        let metadata = fs::read(dir.join(FORMAT_METADATA_FILENAME))
+            .ok()
+            .and_then(|contents| serde_json::from_slice::<FormatMetadata>(&contents).ok())
+            .unwrap_or_default();
+        entries.push(TrashEntry {
+            book_id,
+            kind: TrashEntryKind::Formats,
+            title: metadata.title,
+            authors: metadata.authors,
+            formats: trashed_formats_in(&dir),
+            trashed_at: modified_at(&dir)?,
+        });
+    }
+
+    entries.sort_by(|a, b| {
+        (a.kind == TrashEntryKind::Formats)
+            .cmp(&(b.kind == TrashEntryKind::Formats))
+            .then(b.trashed_at.cmp(&a.trashed_at))
+            .then(a.book_id.as_i32().cmp(&b.book_id.as_i32()))
+    });
+    Ok(entries)
+}
+
+/// Permanently delete trash entries trashed longer than `older_than` ago, or
+/// every entry when `None`. Returns how many entries were deleted.
+pub fn empty_trash(
+    library_root: &str,
+    older_than: Option<Duration>,
+) -> Result<usize, CalibreError> {
+    let cutoff = older_than.and_then(|age| SystemTime::now().checked_sub(age));
+    let mut removed = 0;
+
+    for kind in [BOOKS_DIR_NAME, FORMATS_DIR_NAME] {
+        for (_, dir) in trash_entry_dirs(library_root, kind)? {
+            if let Some(cutoff) = cutoff {
+                if fs::metadata(&dir)?.modified()? > cutoff {
+                    continue;
+                }
+            }
+            fs::remove_dir_all(&dir)?;
+            removed += 1;
+        }
+    }
+
+    Ok(removed)
+}
+
+/// Delete a book's row and the rows that hang off it.
+pub(crate) fn delete_book_records(
+    conn: &mut SqliteConnection,
+    book_id: BookId,
+) -> Result<(), CalibreError> {
+    book_files::delete_all(conn, book_id)?;
+    book_descriptions::delete(conn, book_id)?;
+    book_identifiers::delete_all(conn, book_id)?;
+    // Custom values live on in the trashed OPF, not in the database.
+    for column in custom_columns::list(conn)? {
+        if column.kind.supports_value_io() {
+            custom_columns::set_value(conn, &column, book_id, None)?;
+        }
+    }
+
+    for author_id in books::find_authors(conn, book_id)? {
+        authors::unlink_book(conn, author_id, book_id)?;
+    }
+
+    books::delete(conn, book_id)?;
+    Ok(())
+}
+
+/// Give a restored book back the custom column values its `metadata.opf`
+/// records, read state included. Values for columns the library no longer
+/// has, or that no longer fit their column, are skipped with a warning.
+fn restore_custom_values(
+    conn: &mut SqliteConnection,
+    book_id: BookId,
+    metadata: &opf::OpfMetadata,
+) -> Result<(), CalibreError> {
+    let columns = custom_columns::list(conn)?;
+    for field in &metadata.user_metadata {
+        let Some(column) = columns.iter().find(|column| column.label == field.label) else {
+            continue;
+        };
+        if let Err(e) =
+            custom_columns::set_opf_value(conn, column, book_id, &field.value, field.extra)
+        {
+            eprintln!(
+                "WARNING: Could not restore #{} for book {}: {}",
+                field.label,
+                book_id.as_i32(),
+                e
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Insert the `books` row for a book read back from its `metadata.opf`,
+/// keeping its id and uuid, and link the rest of its metadata.
+pub(crate) fn create_book_record(
+    conn: &mut SqliteConnection,
+    book_id: BookId,
+    metadata: &opf::OpfMetadata,
+) -> Result<(), CalibreError> {
+    books::create_with_id(
+        conn,
+        book_id,
+        NewBook {
+            title: metadata
+                .title
+                .clone()
+                .unwrap_or_else(|| "Unknown".to_string()),
+            timestamp: metadata.timestamp,
+            pubdate: metadata.publication_date,
+            series_index: metadata.series_index.unwrap_or(1.0),
+            has_cover: None,
+        },
+    )?;
+    if let Some(uuid) = &metadata.uuid {
+        books::set_uuid(conn, book_id, uuid)?;
+    }
+
+    let author_names = if metadata.authors.is_empty() {
+        vec!["Unknown".to_string()]
+    } else {
+        metadata.authors.clone()
+    };
+    for name in &author_names {
+        let author = authors::create_if_not_exists(conn, name)?;
+        authors::link_book(conn, AuthorId(author.id), book_id)?;
+    }
+    let author_sort = metadata.author_sort.clone().unwrap_or_else(|| {
+        author_names
+            .iter()
+            .map(|name| sorting::sort_author_name_apa(name))
+            .collect::<Vec<String>>()
+            .join(" & ")
+    });
+    books::update(
+        conn,
+        book_id,
+        UpdateBookData {
+            author_sort: Some(author_sort),
+            ..Default::default()
+        },
+    )?;
+
+    book_operations::update_book(
+        conn,
+        book_id,
+        BookUpdate {
+            tags: Some(metadata.tags.clone()),
+            series: metadata.series.clone(),
+            language_codes: Some(metadata.language_codes.clone()),
+            publisher: metadata.publisher.clone(),
+            rating: metadata.rating,
+            comments: metadata.comments.clone(),
+            identifiers: Some(metadata.identifiers.clone()),
+            ..Default::default()
+        },
+    )
+}
+
+fn write_format_metadata(
+    conn: &mut SqliteConnection,
+    dir: &Path,
+    title: &str,
+    book_id: BookId,
+) -> Result<(), CalibreError> {
+    let author_ids = books::find_authors(conn, book_id)?;
+    let metadata = FormatMetadata {
+        title: title.to_string(),
+        authors: authors::get_many(conn, author_ids)?
+            .into_iter()
+            .map(|author| author.name)
+            .collect(),
+    };
+    let contents = serde_json::to_vec(&metadata).map_err(CalibreError::unknown)?;
+    fs::write(dir.join(FORMAT_METADATA_FILENAME), contents)?;
+    Ok(())
+}
+
+/// `(book id, folder)` for each entry under `.caltrash/<kind>`; folders not
+/// named after a book id are ignored.
+fn trash_entry_dirs(
+    library_root: &str,
+    kind: &str,
+) -> Result<Vec<(BookId, PathBuf)>, CalibreError> {
+    let dir = trash_dir(library_root).join(kind);
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(&dir)? {
+        let entry = entry?;
+        let Some(book_id) = entry.file_name().to_str().and_then(|n| n.parse().ok()) else {
+            continue;
+        };
+        if entry.file_type()?.is_dir() {
+            entries.push((BookId(book_id), entry.path()));
+        }
+    }
+    Ok(entries)
+}
+
+/// `(FORMAT, file stem, size)` for the format files in a book folder, in the
+/// library or the trash: every file with an extension other than the cover
+/// and `metadata.opf`.
+pub(crate) fn book_format_files(dir: &Path) -> Result<Vec<(String, String, u64)>, CalibreError> {
+    let mut formats = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let name = entry.file_name();
+        if !entry.file_type()?.is_file() || name == COVER_FILENAME || name == OPF_FILENAME {
+            continue;
+        }
+        let (Some(stem), Some(extension)) = (
+            path.file_stem().and_then(|s| s.to_str()),
+            path.extension().and_then(|e| e.to_str()),
+        ) else {
+            continue;
+        };
+        formats.push((
+            extension.to_uppercase(),
+            stem.to_string(),
+            entry.metadata()?.len(),
+        ));
+    }
+    formats.sort();
+    Ok(formats)
+}
+
+/// The formats in a `.caltrash/f/<id>` folder, uppercased.
+fn trashed_formats_in(dir: &Path) -> Vec<String> {
+    let mut formats: Vec<String> = fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter(|entry| entry.file_type().is_ok_and(|t| t.is_file()))
+                .filter_map(|entry| entry.file_name().to_str().map(str::to_string))
+                .filter(|name| name != FORMAT_METADATA_FILENAME)
+                .map(|name| name.to_uppercase())
+                .collect()
+        })
+        .unwrap_or_default();
+    formats.sort();
+    formats
+}
+
+fn modified_at(path: &Path) -> Result<NaiveDateTime, CalibreError> {
+    let modified = fs::metadata(path)?.modified()?;
+    Ok(DateTime::<Utc>::from(modified).naive_utc())
+}
+
+/// Rename every entry of `from` into `to`, recording each in `moves` as
+/// `(from, to)` so a failure part-way can be undone.
+fn move_entries(
+    from: &Path,
+    to: &Path,
+    moves: &mut Vec<(PathBuf, PathBuf)>,
+) -> Result<(), CalibreError> {
+    for entry in fs::read_dir(from)? {
+        let name = entry?.file_name();
+        let (source, dest) = (from.join(&name), to.join(&name));
+        fs::rename(&source, &dest)?;
+        moves.push((source, dest));
+    }
+    Ok(())
+}
+
+/// Best-effort undo of renames, newest first.
+pub(crate) fn undo_moves(moves: &[(PathBuf, PathBuf)]) {
+    for (source, dest) in moves.iter().rev() {
+        if let Some(parent) = source.parent() {
+            let _ = fs::create_dir_all(parent);
+        }
+        if let Err(e) = fs::rename(dest, source) {
+            eprintln!("WARNING: Failed to move {:?} back: {}", dest, e);
+        }
+    }
+}
@@ -0,0 +1,769 @@
+//! Reading and writing Calibre's per-book `metadata.opf`.
+//!
+//! Calibre keeps an OPF 2.0 copy of every book's metadata in its folder. It is
+//! the only metadata a trashed book keeps, and what Calibre's "Restore
+//! database" rebuilds a library from. [`write`] produces the same document
+//! Calibre's `metadata_to_opf` does (`ebooks/metadata/opf2.py`), field for
+//! field and in the same order; [`parse`] reads it back.
+
+use std::collections::HashMap;
+use std::io;
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Timelike};
+use quick_xml::events::{BytesEnd, BytesStart, BytesText, Event};
+use quick_xml::{Reader, Writer};
+use serde_json::{json, Value};
+
+use crate::CalibreError;
+
+/// Who wrote the file, in Calibre's `book_producer` (`bkp`) contributor.
+const BOOK_PRODUCER: &str = "citadel (1.0.0) [https://github.com/every-day-things/citadel]";
+
+/// The metadata recorded in a `metadata.opf`. Absent elements are `None` or
+/// empty.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct OpfMetadata {
+    pub calibre_id: Option<i32>,
+    pub uuid: Option<String>,
+    pub title: Option<String>,
+    pub title_sort: Option<String>,
+    pub authors: Vec<String>,
+    /// The book's `author_sort`, which Calibre writes as `opf:file-as` on the
+    /// creators.
+    pub author_sort: Option<String>,
+    /// Every `dc:identifier` other than Calibre's own id and uuid, keyed by
+    /// lowercased scheme.
+    pub identifiers: HashMap<String, String>,
+    pub publisher: Option<String>,
+    pub comments: Option<String>,
+    pub publication_date: Option<NaiveDateTime>,
+    pub timestamp: Option<NaiveDateTime>,
+    pub language_codes: Vec<String>,
+    pub tags: Vec<String>,
+    pub series: Option<String>,
+    pub series_index: Option<f32>,
+    /// Calibre's 0–10 scale.
+    pub rating: Option<i32>,
+    /// Author name to link, for authors that have one.
+    pub author_links: Vec<(String, String)>,
+    /// Every custom column with this book's value, in column order.
+    pub user_metadata: Vec<UserMetadataField>,
+    /// Whether the guide points at a cover image.
+    pub has_cover: bool,
+}
+
+/// One custom column and the book's value for it, as Calibre records it in a
+/// `calibre:user_metadata:#label` meta element: enough to recreate the column
+/// as well as the value.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct UserMetadataField {
+    /// Lookup name without the leading `#`.
+    pub label: String,
+    pub name: String,
+    pub datatype: String,
+    pub is_multiple: bool,
+    pub is_editable: bool,
+    /// The column's id, which names its tables.
+    pub colnum: i32,
+    /// The column's `display` settings.
+    pub display: Value,
+    /// Calibre's `#value#`: a string, list, number, bool, encoded datetime or
+    /// `null`.
+    pub value: Value,
+    /// Calibre's `#extra#`: the series index of series columns.
+    pub extra: Option<f64>,
+}
+
+/// Parse the contents of a `metadata.opf`.
+pub(crate) fn parse(xml: &str) -> Result<OpfMetadata, CalibreError> {
+    let mut reader = Reader::from_str(xml);
+    reader.config_mut().trim_text(true);
+
+    let mut metadata = OpfMetadata::default();
+    // Elements still open, and whether a <metadata> element was seen: a
+    // truncated file must not pass for a book without metadata.
+    let mut depth = 0usize;
+    let mut has_metadata = false;
+
+    loop {
+        match reader.read_event().map_err(opf_error)? {
+            Event::Start(element) => {
+                let name = local_name(&element);
+                has_metadata |= name == "metadata";
+                if is_text_element(&name) {
+                    let text = reader.read_text(element.name()).map_err(opf_error)?;
+                    let text = quick_xml::escape::unescape(&text)
+                        .map_err(opf_error)?
+                        .trim()
+                        .to_string();
+                    read_text_element(&mut metadata, &name, &element, text)?;
+                } else {
+                    depth += 1;
+                    read_empty_element(&mut metadata, &name, &element)?;
+                }
+            }
+            Event::Empty(element) => {
+                let name = local_name(&element);
+                has_metadata |= name == "metadata";
+                read_empty_element(&mut metadata, &name, &element)?;
+            }
+            Event::End(_) => depth = depth.saturating_sub(1),
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    if depth > 0 {
+        return Err(opf_error("unexpected end of document"));
+    }
+    if !has_metadata {
+        return Err(opf_error("no <metadata> element"));
+    }
+    Ok(metadata)
+}
+
+fn is_text_element(name: &str) -> bool {
+    matches!(
+        name,
+        "identifier"
+            | "title"
+            | "creator"
+            | "publisher"
+            | "description"
+            | "date"
+            | "language"
+            | "subject"
+    )
+}
+
+fn read_text_element(
+    metadata: &mut OpfMetadata,
+    name: &str,
+    element: &BytesStart,
+    text: String,
+) -> Result<(), CalibreError> {
+    if text.is_empty() {
+        return Ok(());
+    }
+
+    match name {
+        "identifier" => match attribute(element, "scheme")?.map(|s| s.to_lowercase()) {
+            Some(scheme) if scheme == "calibre" => metadata.calibre_id = text.parse().ok(),
+            Some(scheme) if scheme == "uuid" => metadata.uuid = Some(text),
+            Some(scheme) => {
+                metadata.identifiers.insert(scheme, text);
+            }
+            None => {}
+        },
+        "title" => metadata.title = Some(text),
+        "creator" => {
+            let role = attribute(element, "role")?;
+            if role.is_none() || role.as_deref() == Some("aut") {
+                if metadata.author_sort.is_none() {
+                    metadata.author_sort = attribute(element, "file-as")?;
+                }
+                metadata.authors.push(text);
+            }
+        }
+        "publisher" => metadata.publisher = Some(text),
+        "description" => metadata.comments = Some(text),
+        "date" => metadata.publication_date = parse_date(&text),
+        "language" if text != "und" => metadata.language_codes.push(text),
+        "subject" => metadata.tags.push(text),
+        _ => {}
+    }
+
+    Ok(())
+}
+
+fn read_empty_element(
+    metadata: &mut OpfMetadata,
+    name: &str,
+    element: &BytesStart,
+) -> Result<(), CalibreError> {
+    match name {
+        "meta" => {
+            let (Some(meta_name), Some(content)) =
+                (attribute(element, "name")?, attribute(element, "content")?)
+            else {
+                return Ok(());
+            };
+            match meta_name.as_str() {
+                "calibre:title_sort" if !content.is_empty() => metadata.title_sort = Some(content),
+                "calibre:series" if !content.is_empty() => metadata.series = Some(content),
+                "calibre:series_index" => metadata.series_index = content.parse().ok(),
+                "calibre:rating" => {
+                    metadata.rating = content
+                        .parse::<f32>()
+                        .ok()
+                        .map(|rating| rating.round() as i32)
+                        .filter(|rating| *rating > 0)
+                }
+                "calibre:timestamp" => metadata.timestamp = parse_date(&content),
+                "calibre:author_link_map" => {
+                    let links: HashMap<String, String> =
+                        serde_json::from_str(&content).map_err(opf_error)?;
+                    metadata.author_links = links
+                        .into_iter()
+                        .filter(|(_, link)| !link.is_empty())
+                        .collect();
+                    metadata.author_links.sort();
+                }
+                other => {
+                    if let Some(lookup_name) = other.strip_prefix("calibre:user_metadata:") {
+                        let field: Value = serde_json::from_str(&content).map_err(opf_error)?;
+                        metadata
+                            .user_metadata
+                            .push(read_user_metadata(lookup_name, &field));
+                    }
+                }
+            }
+        }
+        "reference" if attribute(element, "type")?.as_deref() == Some("cover") => {
+            metadata.has_cover = true;
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+fn read_user_metadata(lookup_name: &str, field: &Value) -> UserMetadataField {
+    let label = field["label"]
+        .as_str()
+        .unwrap_or_else(|| lookup_name.trim_start_matches('#'))
+        .to_string();
+    UserMetadataField {
+        name: field["name"].as_str().unwrap_or(&label).to_string(),
+        label,
+        datatype: field["datatype"].as_str().unwrap_or_default().to_string(),
+        // Calibre encodes `is_multiple` as its separator character, or null.
+        is_multiple: field["is_multiple"]
+            .as_str()
+            .is_some_and(|separator| !separator.is_empty()),
+        is_editable: field["is_editable"].as_bool().unwrap_or(true),
+        colnum: field["colnum"].as_i64().unwrap_or_default() as i32,
+        display: match &field["display"] {
+            Value::Null => json!({}),
+            display => display.clone(),
+        },
+        value: field["#value#"].clone(),
+        extra: field["#extra#"].as_f64(),
+    }
+}
+
+/// Dates as Calibre writes them (RFC 3339, e.g. `2020-01-31T00:00:00+00:00`),
+/// plus the plain `YYYY-MM-DD[ HH:MM:SS]` forms other tools use.
+pub(crate) fn parse_date(value: &str) -> Option<NaiveDateTime> {
+    let value = value.trim().trim_end_matches(" UTC");
+    if let Ok(date_time) = DateTime::parse_from_rfc3339(value) {
+        return Some(date_time.naive_utc());
+    }
+    ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(value.get(..10)?, "%Y-%m-%d")
+                .ok()
+                .map(|date| date.and_time(chrono::NaiveTime::MIN))
+        })
+}
+
+/// Render `metadata` as a `metadata.opf` document.
+pub(crate) fn write(metadata: &OpfMetadata) -> String {
+    let mut writer = Writer::new_with_indent(Vec::new(), b' ', 4);
+    write_document(&mut writer, metadata).expect("writing to a Vec cannot fail");
+
+    let mut document = String::from("<?xml version='1.0' encoding='utf-8'?>\n");
+    document.push_str(&String::from_utf8(writer.into_inner()).expect("input is UTF-8"));
+    document.push('\n');
+    document
+}
+
+fn write_document(writer: &mut Writer<Vec<u8>>, metadata: &OpfMetadata) -> io::Result<()> {
+    writer.write_event(Event::Start(BytesStart::new("package").with_attributes([
+        ("xmlns", "http://www.idpf.org/2007/opf"),
+        ("unique-identifier", "uuid_id"),
+        ("version", "2.0"),
+    ])))?;
+    writer.write_event(Event::Start(BytesStart::new("metadata").with_attributes([
+        ("xmlns:dc", "http://purl.org/dc/elements/1.1/"),
+        ("xmlns:opf", "http://www.idpf.org/2007/opf"),
+    ])))?;
+
+    let calibre_id = metadata.calibre_id.map(|id| id.to_string());
+    text_element(
+        writer,
+        "dc:identifier",
+        &[("opf:scheme", "calibre"), ("id", "calibre_id")],
+        calibre_id.as_deref().unwrap_or_default(),
+    )?;
+    text_element(
+        writer,
+        "dc:identifier",
+        &[("opf:scheme", "uuid"), ("id", "uuid_id")],
+        metadata.uuid.as_deref().unwrap_or_default(),
+    )?;
+    text_element(
+        writer,
+        "dc:title",
+        &[],
+        metadata.title.as_deref().unwrap_or_default(),
+    )?;
+
+    // Calibre reads the combined author sort from the first creator only.
+    let author_sort = metadata.author_sort.clone().unwrap_or_default();
+    for (position, author) in metadata.authors.iter().enumerate() {
+        let attributes: &[(&str, &str)] = if position == 0 {
+            &[("opf:file-as", &author_sort), ("opf:role", "aut")]
+        } else {
+            &[("opf:role", "aut")]
+        };
+        text_element(writer, "dc:creator", attributes, author)?;
+    }
+    text_element(
+        writer,
+        "dc:contributor",
+        &[("opf:file-as", "calibre"), ("opf:role", "bkp")],
+        BOOK_PRODUCER,
+    )?;
+
+    if let Some(date) = metadata.publication_date {
+        text_element(writer, "dc:date", &[], &isoformat(date))?;
+    }
+    if let Some(comments) = &metadata.comments {
+        text_element(writer, "dc:description", &[], comments)?;
+    }
+    if let Some(publisher) = &metadata.publisher {
+        text_element(writer, "dc:publisher", &[], publisher)?;
+    }
+
+    let mut identifiers: Vec<_> = metadata.identifiers.iter().collect();
+    identifiers.sort();
+    for (scheme, value) in identifiers {
+        text_element(
+            writer,
+            "dc:identifier",
+            &[("opf:scheme", &scheme.to_uppercase())],
+            value,
+        )?;
+    }
+
+    // Calibre falls back to "und" (undetermined) when a book has no language.
+    if metadata.language_codes.is_empty() {
+        text_element(writer, "dc:language", &[], "und")?;
+    }
+    for code in &metadata.language_codes {
+        text_element(writer, "dc:language", &[], code)?;
+    }
+    for tag in &metadata.tags {
+        text_element(writer, "dc:subject", &[], tag)?;
+    }
+
+    let author_links: serde_json::Map<String, Value> = metadata
+        .authors
+        .iter()
+        .map(|author| {
+            let link = metadata
+                .author_links
+                .iter()
+                .find(|(name, _)| name == author)
+                .map(|(_, link)| link.clone())
+                .unwrap_or_default();
+            (author.clone(), Value::String(link))
+        })
+        .collect();
+    meta_element(
+        writer,
+        "calibre:author_link_map",
+        &python_json(&Value::Object(author_links)),
+    )?;
+    if let Some(series) = &metadata.series {
+        meta_element(writer, "calibre:series", series)?;
+    }
+    if let Some(series_index) = metadata.series_index {
+        meta_element(
+            writer,
+            "calibre:series_index",
+            &format_series_index(series_index),
+        )?;
+    }
+    if let Some(rating) = metadata.rating {
+        meta_element(writer, "calibre:rating", &rating.to_string())?;
+    }
+    if let Some(timestamp) = metadata.timestamp {
+        meta_element(writer, "calibre:timestamp", &isoformat(timestamp))?;
+    }
+    if let Some(title_sort) = &metadata.title_sort {
+        meta_element(writer, "calibre:title_sort", title_sort)?;
+    }
+    for field in &metadata.user_metadata {
+        meta_element(
+            writer,
+            &format!("calibre:user_metadata:#{}", field.label),
+            &python_json(&user_metadata_json(field)),
+        )?;
+    }
+
+    writer.write_event(Event::End(BytesEnd::new("metadata")))?;
+
+    if metadata.has_cover {
+        writer.write_event(Event::Start(BytesStart::new("guide")))?;
+        writer.write_event(Event::Empty(BytesStart::new("reference").with_attributes(
+            [("type", "cover"), ("title", "Cover"), ("href", "cover.jpg")],
+        )))?;
+        writer.write_event(Event::End(BytesEnd::new("guide")))?;
+    } else {
+        writer.write_event(Event::Empty(BytesStart::new("guide")))?;
+    }
+
+    writer.write_event(Event::End(BytesEnd::new("package")))?;
+    Ok(())
+}
+
+fn text_element(
+    writer: &mut Writer<Vec<u8>>,
+    name: &str,
+    attributes: &[(&str, &str)],
+    text: &str,
+) -> io::Result<()> {
+    let attributes = attributes
+        .iter()
+        .map(|(key, value)| (*key, clean_xml_text(value)))
+        .collect::<Vec<_>>();
+    let start = BytesStart::new(name)
+        .with_attributes(attributes.iter().map(|(key, value)| (*key, value.as_str())));
+    writer.write_event(Event::Start(start))?;
+    writer.write_event(Event::Text(BytesText::new(&clean_xml_text(text))))?;
+    writer.write_event(Event::End(BytesEnd::new(name)))?;
+    Ok(())
+}
+
+fn meta_element(writer: &mut Writer<Vec<u8>>, name: &str, content: &str) -> io::Result<()> {
+    writer.write_event(Event::Empty(
+        BytesStart::new("meta")
+            .with_attributes([("name", name), ("content", &clean_xml_text(content))]),
+    ))?;
+    Ok(())
+}
+
+/// Drop characters XML 1.0 cannot carry (most C0 controls), as Calibre's
+/// `clean_xml_chars` does; they would make the whole file unparseable.
+fn clean_xml_text(text: &str) -> String {
+    text.chars()
+        .filter(|c| {
+            matches!(c, '\t' | '\n' | '\r') || (*c >= ' ' && *c != '\u{FFFE}' && *c != '\u{FFFF}')
+        })
+        .collect()
+}
+
+/// Python's `datetime.isoformat()` for a UTC time, as Calibre writes dates.
+fn isoformat(date_time: NaiveDateTime) -> String {
+    if date_time.nanosecond() == 0 {
+        date_time.format("%Y-%m-%dT%H:%M:%S+00:00").to_string()
+    } else {
+        date_time.format("%Y-%m-%dT%H:%M:%S%.6f+00:00").to_string()
+    }
+}
+
+/// Calibre's `fmt_sidx`: whole numbers without decimals, others to two
+/// places.
+fn format_series_index(series_index: f32) -> String {
+    if series_index.fract() == 0.0 {
+        format!("{}", series_index as i64)
+    } else {
+        format!("{:.2}", series_index)
+    }
+}
+
+/// The field metadata Calibre serializes for a custom column
+/// (`serialize_user_metadata`, after `encode_is_multiple`).
+fn user_metadata_json(field: &UserMetadataField) -> Value {
+    let is_names = field.display["is_names"].as_bool().unwrap_or(false);
+    let is_multiple2 = if !field.is_multiple {
+        json!({})
+    } else if is_names {
+        json!({"cache_to_list": "|", "ui_to_list": "&", "list_to_ui": " & "})
+    } else {
+        json!({"cache_to_list": "|", "ui_to_list": ",", "list_to_ui": ", "})
+    };
+    let is_multiple = match (field.is_multiple, field.datatype.as_str()) {
+        (false, _) => Value::Null,
+        (true, "composite") => Value::from(","),
+        (true, _) => Value::from("|"),
+    };
+    let is_category = matches!(
+        field.datatype.as_str(),
+        "text" | "rating" | "enumeration" | "series"
+    ) || (field.datatype == "composite"
+        && field.display["make_category"].as_bool().unwrap_or(false));
+
+    json!({
+        "table": format!("custom_column_{}", field.colnum),
+        "column": "value",
+        "datatype": field.datatype,
+        "is_multiple": is_multiple,
+        "kind": "field",
+        "name": field.name,
+        "search_terms": [format!("#{}", field.label)],
+        "label": field.label,
+        "colnum": field.colnum,
+        "display": field.display,
+        "is_custom": true,
+        "is_category": is_category,
+        "link_column": "value",
+        "category_sort": "value",
+        "is_csp": false,
+        "is_editable": field.is_editable,
+        "#value#": field.value,
+        "#extra#": field.extra,
+        "is_multiple2": is_multiple2,
+    })
+}
+
+/// JSON spaced like Python's `json.dumps` (`", "` and `": "`), which is how
+/// Calibre writes the JSON it embeds in OPF attributes.
+fn python_json(value: &Value) -> String {
+    struct PythonFormatter;
+
+    impl serde_json::ser::Formatter for PythonFormatter {
+        fn begin_array_value<W: ?Sized + io::Write>(
+            &mut self,
+            writer: &mut W,
+            first: bool,
+        ) -> io::Result<()> {
+            if first {
+                Ok(())
+            } else {
+                writer.write_all(b", ")
+            }
+        }
+
+        fn begin_object_key<W: ?Sized + io::Write>(
+            &mut self,
+            writer: &mut W,
+            first: bool,
+        ) -> io::Result<()> {
+            if first {
+                Ok(())
+            } else {
+                writer.write_all(b", ")
+            }
+        }
+
+        fn begin_object_value<W: ?Sized + io::Write>(&mut self, writer: &mut W) -> io::Result<()> {
+            writer.write_all(b": ")
+        }
+    }
+
+    let mut out = Vec::new();
+    let mut serializer = serde_json::Serializer::with_formatter(&mut out, PythonFormatter);
+    serde::Serialize::serialize(value, &mut serializer).expect("serializing a Value cannot fail");
+    String::from_utf8(out).expect("serde_json writes UTF-8")
+}
+
+fn local_name(element: &BytesStart) -> String {
+    String::from_utf8_lossy(element.local_name().as_ref()).into_owned()
+}
+
+/// The value of the attribute whose local name is `name`, whatever its
+/// namespace prefix (`opf:scheme` and `scheme` both occur in the wild).
+fn attribute(element: &BytesStart, name: &str) -> Result<Option<String>, CalibreError> {
+    for attribute in element.attributes() {
+        let attribute = attribute.map_err(opf_error)?;
+        if attribute.key.local_name().as_ref() == name.as_bytes() {
+            return Ok(Some(
+                attribute.unescape_value().map_err(opf_error)?.into_owned(),
+            ));
+        }
+    }
+    Ok(None)
+}
+
+fn opf_error<E: std::fmt::Display>(error: E) -> CalibreError {
+    CalibreError::FileSystem(format!("Invalid metadata.opf: {error}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CALIBRE_OPF: &str = r#"<?xml version='1.0' encoding='utf-8'?>
+<package xmlns="http://www.idpf.org/2007/opf" unique-identifier="uuid_id" version="2.0">
+    <metadata xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:opf="http://www.idpf.org/2007/opf">
+        <dc:identifier opf:scheme="calibre" id="calibre_id">12</dc:identifier>
+        <dc:identifier opf:scheme="uuid" id="uuid_id">0b5b5c9e-7f1c-4a43-9f4a-2f3bd0c1d4e5</dc:identifier>
+        <dc:title>Pride &amp; Prejudice</dc:title>
+        <dc:creator opf:file-as="Austen, Jane" opf:role="aut">Jane Austen</dc:creator>
+        <dc:contributor opf:file-as="calibre" opf:role="bkp">calibre (7.2.0) [https://calibre-ebook.com]</dc:contributor>
+        <dc:date>1813-01-28T00:00:00+00:00</dc:date>
+        <dc:description>&lt;p&gt;A novel.&lt;/p&gt;</dc:description>
+        <dc:publisher>T. Egerton</dc:publisher>
+        <dc:identifier opf:scheme="ISBN">9780141439518</dc:identifier>
+        <dc:language>eng</dc:language>
+        <dc:subject>Classics</dc:subject>
+        <dc:subject>Romance</dc:subject>
+        <meta name="calibre:series" content="Standalone"/>
+        <meta name="calibre:series_index" content="2.5"/>
+        <meta name="calibre:rating" content="8.0"/>
+        <meta name="calibre:timestamp" content="2023-05-01T10:20:30.123456+00:00"/>
+        <meta name="calibre:title_sort" content="Pride &amp; Prejudice"/>
+    </metadata>
+    <guide>
+        <reference type="cover" title="Cover" href="cover.jpg"/>
+    </guide>
+</package>"#;
+
+    #[test]
+    fn test_parses_calibre_opf() {
+        let metadata = parse(CALIBRE_OPF).unwrap();
+
+        assert_eq!(metadata.calibre_id, Some(12));
+        assert_eq!(
+            metadata.uuid.as_deref(),
+            Some("0b5b5c9e-7f1c-4a43-9f4a-2f3bd0c1d4e5")
+        );
+        assert_eq!(metadata.title.as_deref(), Some("Pride & Prejudice"));
+        assert_eq!(metadata.authors, vec!["Jane Austen"]);
+        assert_eq!(metadata.author_sort.as_deref(), Some("Austen, Jane"));
+        assert_eq!(metadata.comments.as_deref(), Some("<p>A novel.</p>"));
+        assert_eq!(metadata.publisher.as_deref(), Some("T. Egerton"));
+        assert_eq!(metadata.identifiers.get("isbn").unwrap(), "9780141439518");
+        assert_eq!(metadata.language_codes, vec!["eng"]);
+        assert_eq!(metadata.tags, vec!["Classics", "Romance"]);
+        assert_eq!(metadata.series.as_deref(), Some("Standalone"));
+        assert_eq!(metadata.series_index, Some(2.5));
+        assert_eq!(metadata.rating, Some(8));
+        assert_eq!(
+            metadata.publication_date.unwrap().date(),
+            NaiveDate::from_ymd_opt(1813, 1, 28).unwrap()
+        );
+        assert!(metadata.timestamp.is_some());
+        assert!(metadata.has_cover);
+    }
+
+    #[test]
+    fn test_parses_plain_date_forms() {
+        let expected = NaiveDate::from_ymd_opt(2020, 1, 31)
+            .unwrap()
+            .and_hms_opt(12, 30, 0)
+            .unwrap();
+
+        assert_eq!(parse_date("2020-01-31 12:30:00"), Some(expected));
+        assert_eq!(parse_date("2020-01-31 12:30:00 UTC"), Some(expected));
+        assert_eq!(
+            parse_date("2020-01-31"),
+            Some(expected.date().and_time(chrono::NaiveTime::MIN))
+        );
+        assert_eq!(parse_date("not a date"), None);
+    }
+
+    fn full_metadata() -> OpfMetadata {
+        OpfMetadata {
+            calibre_id: Some(7),
+            uuid: Some("0b5b5c9e-7f1c-4a43-9f4a-2f3bd0c1d4e5".to_string()),
+            title: Some("Tom & Jerry <Unabridged>".to_string()),
+            title_sort: Some("Tom & Jerry".to_string()),
+            authors: vec![
+                "Joseph \"Joe\" Barbera".to_string(),
+                "William Hanna".to_string(),
+            ],
+            author_sort: Some("Barbera, Joseph & Hanna, William".to_string()),
+            identifiers: HashMap::from([
+                ("isbn".to_string(), "9780141439518".to_string()),
+                ("goodreads".to_string(), "1885".to_string()),
+            ]),
+            publisher: Some("Hanna-Barbera & Sons".to_string()),
+            comments: Some("<p>Cat & mouse.</p>".to_string()),
+            publication_date: parse_date("1940-02-10T00:00:00+00:00"),
+            timestamp: parse_date("2024-05-01T09:15:30.250000+00:00"),
+            language_codes: vec!["eng".to_string(), "fra".to_string()],
+            tags: vec!["Cartoons".to_string(), "Cats & Mice".to_string()],
+            series: Some("Tom <and> Jerry".to_string()),
+            series_index: Some(2.5),
+            rating: Some(8),
+            author_links: vec![(
+                "William Hanna".to_string(),
+                "https://example.com/?a=1&b=2".to_string(),
+            )],
+            user_metadata: vec![UserMetadataField {
+                label: "shelf".to_string(),
+                name: "Shelf".to_string(),
+                datatype: "text".to_string(),
+                is_multiple: true,
+                is_editable: true,
+                colnum: 3,
+                display: json!({"is_names": false}),
+                value: json!(["Top & Bottom", "Left"]),
+                extra: None,
+            }],
+            has_cover: true,
+        }
+    }
+
+    #[test]
+    fn test_write_round_trips_every_field() {
+        let metadata = full_metadata();
+
+        let written = write(&metadata);
+
+        assert_eq!(parse(&written).unwrap(), metadata);
+    }
+
+    #[test]
+    fn test_write_escapes_markup() {
+        let written = write(&full_metadata());
+
+        assert!(written.contains("<dc:title>Tom &amp; Jerry &lt;Unabridged&gt;</dc:title>"));
+        assert!(written.contains("&lt;p&gt;Cat &amp; mouse.&lt;/p&gt;"));
+        assert!(written.contains(r#"opf:file-as="Barbera, Joseph &amp; Hanna, William""#));
+        assert_eq!(written.matches("opf:file-as=\"Barbera").count(), 1);
+        assert!(written.contains(r#"<dc:creator opf:role="aut">William Hanna</dc:creator>"#));
+        assert!(!written.contains("<p>"));
+    }
+
+    #[test]
+    fn test_write_matches_calibre_layout() {
+        let written = write(&full_metadata());
+
+        assert!(written.starts_with("<?xml version='1.0' encoding='utf-8'?>\n<package"));
+        assert!(written.contains(r#"<dc:identifier opf:scheme="GOODREADS">1885</dc:identifier>"#));
+        assert!(written.contains("<dc:date>1940-02-10T00:00:00+00:00</dc:date>"));
+        assert!(written.contains(r#"<meta name="calibre:series_index" content="2.50"/>"#));
+        assert!(written.contains(r#"<meta name="calibre:rating" content="8"/>"#));
+        assert!(written.contains(
+            r#"<meta name="calibre:timestamp" content="2024-05-01T09:15:30.250000+00:00"/>"#
+        ));
+        assert!(written.contains(r#"<reference type="cover" title="Cover" href="cover.jpg"/>"#));
+        // Calibre reads these attributes with Python's json module; keep its
+        // spacing so files diff cleanly against Calibre's own.
+        assert!(written
+            .contains("content=\"{&quot;Joseph \\&quot;Joe\\&quot; Barbera&quot;: &quot;&quot;, "));
+    }
+
+    #[test]
+    fn test_write_drops_xml_invalid_characters() {
+        let metadata = OpfMetadata {
+            title: Some("Bell\u{7} and tab\t".to_string()),
+            ..Default::default()
+        };
+
+        let parsed = parse(&write(&metadata)).unwrap();
+
+        assert_eq!(parsed.title.as_deref(), Some("Bell and tab"));
+        assert_eq!(parsed.language_codes, Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_write_formats_whole_series_index_without_decimals() {
+        assert_eq!(format_series_index(3.0), "3");
+        assert_eq!(format_series_index(1.25), "1.25");
+    }
+
+    #[test]
+    fn test_rejects_truncated_or_foreign_documents() {
+        assert!(parse("<package><metadata>").is_err());
+        assert!(parse("<html><body>Not an OPF</body></html>").is_err());
+        assert!(parse("not xml at all").is_err());
+    }
+}
@@ -41,15 +41,15 @@ pub fn sort_author_name(name: String) -> String {
 /// - Cascade delete related records when a book is deleted
 ///
 /// Triggers are registered using "DROP TRIGGER IF EXISTS" for idempotency,
-/// so this function can be safely called multiple times.
+/// so this function can be safely called multiple times. Their bodies match
+/// Calibre 7.x's statement for statement (see `new_library.sql`).
 pub fn register_triggers(conn: &mut SqliteConnection) -> Result<(), diesel::result::Error> {
     // Books insert trigger - auto-generate sort and uuid
     sql_query("DROP TRIGGER IF EXISTS books_insert_trg").execute(conn)?;
     sql_query(
         "CREATE TRIGGER books_insert_trg AFTER INSERT ON books
          BEGIN
-             UPDATE books SET sort=title_sort(NEW.title), uuid=uuid4()
-             WHERE id=NEW.id;
+             UPDATE books SET sort=title_sort(NEW.title),uuid=uuid4() WHERE id=NEW.id;
          END;",
     )
     .execute(conn)?;
@@ -77,6 +77,8 @@ pub fn register_triggers(conn: &mut SqliteConnection) -> Result<(), diesel::resu
              DELETE FROM books_tags_link WHERE book=OLD.id;
              DELETE FROM books_languages_link WHERE book=OLD.id;
              DELETE FROM data WHERE book=OLD.id;
+             DELETE FROM last_read_positions WHERE book=OLD.id;
+             DELETE FROM annotations WHERE book=OLD.id;
              DELETE FROM comments WHERE book=OLD.id;
              DELETE FROM conversion_options WHERE book=OLD.id;
              DELETE FROM books_plugin_data WHERE book=OLD.id;
@@ -95,14 +97,34 @@ pub fn register_triggers(conn: &mut SqliteConnection) -> Result<(), diesel::resu
 define_sql_function!(fn title_sort(title: diesel::sql_types::Text) -> diesel::sql_types::Text);
 define_sql_function!(fn uuid4() -> diesel::sql_types::Text);
 define_sql_function!(fn author_to_author_sort(name: diesel::sql_types::Text) -> diesel::sql_types::Text);
+// Backs SQLite's `value REGEXP pattern` operator, which search queries
+// (`title:~pattern`) compile to.
+define_sql_function!(fn regexp(pattern: diesel::sql_types::Text, value: diesel::sql_types::Nullable<diesel::sql_types::Text>) -> diesel::sql_types::Bool);
 
 /// Register Calibre's custom SQL functions (`title_sort`, `uuid4`,
-/// `author_to_author_sort`) on `conn`. These are per-connection, so they must
-/// be registered on every connection — including each one a pool hands out.
+/// `author_to_author_sort`), plus `regexp`, on `conn`. These are
+/// per-connection, so they must be registered on every connection —
+/// including each one a pool hands out.
 pub fn register_sql_functions(conn: &mut SqliteConnection) -> Result<(), diesel::result::Error> {
     title_sort_utils::register_impl(conn, sort_book_title)?;
     uuid4_utils::register_impl(conn, || uuid::Uuid::new_v4().to_string())?;
     author_to_author_sort_utils::register_impl(conn, sort_author_name)?;
+    // A query calls `regexp` once per row with the same pattern; keep the
+    // last compiled one. Invalid patterns match nothing (search queries
+    // validate theirs before running).
+    let last_regex: std::sync::Mutex<Option<regex::Regex>> = std::sync::Mutex::new(None);
+    regexp_utils::register_impl(conn, move |pattern: String, value: Option<String>| {
+        let Some(value) = value else {
+            return false;
+        };
+        let Ok(mut last) = last_regex.lock() else {
+            return false;
+        };
+        if last.as_ref().map(regex::Regex::as_str) != Some(pattern.as_str()) {
+            *last = regex::Regex::new(&pattern).ok();
+        }
+        last.as_ref().is_some_and(|regex| regex.is_match(&value))
+    })?;
     Ok(())
 }
 
@@ -0,0 +1,289 @@
+//! Calibre's per-library preferences.
+//!
+//! The `preferences` table maps a key to a JSON value. Calibre reads it with
+//! `json.loads` and writes it with `json.dumps(indent=2)`, so any JSON that
+//! parses is loadable, but each well-known key has a shape Calibre expects:
+//! `user_categories` is an object of `[item, category, 0]` triples, for
+//! example. [`Preferences`] gives typed access to those keys and raw JSON
+//! access to everything else.
+//!
+//! Typed setters rewrite only the key they own; for keys holding an object
+//! with fields this crate does not model (the book list state, say), the
+//! unmodelled fields are kept as they were.
+
+use std::collections::BTreeMap;
+
+use diesel::{Connection, SqliteConnection};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::queries::preferences;
+use crate::CalibreError;
+
+const BOOLS_ARE_TRISTATE: &str = "bools_are_tristate";
+const USER_CATEGORIES: &str = "user_categories";
+const GROUPED_SEARCH_TERMS: &str = "grouped_search_terms";
+const TAG_BROWSER_HIDDEN_CATEGORIES: &str = "tag_browser_hidden_categories";
+const TAG_BROWSER_CATEGORY_ORDER: &str = "tag_browser_category_order";
+const BOOK_DISPLAY_FIELDS: &str = "book_display_fields";
+const VIRTUAL_LIB_ON_STARTUP: &str = "virtual_lib_on_startup";
+const FIELD_METADATA: &str = "field_metadata";
+const BOOK_LIST_STATE: &str = "library_view books view state";
+
+/// One entry of a user category: an item of another category, e.g. the
+/// author "Terry Pratchett" or the tag "Fantasy".
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UserCategoryItem {
+    pub name: String,
+    /// The lookup key of the category the item belongs to (`authors`,
+    /// `tags`, `#genre`, ...).
+    pub category: String,
+}
+
+/// What Calibre knows about one field, from the `field_metadata` preference
+/// it rewrites every time it opens the library.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct FieldMetadata {
+    /// The display name, if the field has one.
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub datatype: Option<String>,
+    #[serde(default)]
+    pub is_custom: bool,
+    #[serde(default)]
+    pub is_category: bool,
+}
+
+/// How the book list's columns are laid out in Calibre's main window.
+/// Column keys are lookup names (`title`, `authors`, `#genre`, ...).
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ColumnDisplay {
+    #[serde(default)]
+    pub hidden_columns: Vec<String>,
+    /// Left-to-right position of each column.
+    #[serde(default)]
+    pub column_positions: BTreeMap<String, i64>,
+    /// Width of each column in pixels.
+    #[serde(default)]
+    pub column_sizes: BTreeMap<String, i64>,
+    /// `left`, `center` or `right`; columns not listed use their default.
+    #[serde(default)]
+    pub column_alignment: BTreeMap<String, String>,
+}
+
+/// Read and write access to a library's preferences. Returned by
+/// [`crate::Library::preferences`].
+pub struct Preferences<'a> {
+    conn: &'a mut SqliteConnection,
+}
+
+impl<'a> Preferences<'a> {
+    pub(crate) fn new(conn: &'a mut SqliteConnection) -> Self {
+        Preferences { conn }
+    }
+
+    // -------------------------------------------------------------------------
+    // Raw JSON
+    // -------------------------------------------------------------------------
+
+    /// Every preference key, sorted.
+    pub fn keys(&mut self) -> Result<Vec<String>, CalibreError> {
+        preferences::keys(self.conn)
+    }
+
+    /// The value stored under `key`, or `None` when the key is absent.
+    pub fn get_raw(&mut self, key: &str) -> Result<Option<Value>, CalibreError> {
+        preferences::get(self.conn, key)
+    }
+
+    /// Store `value` under `key` as Calibre would, replacing any previous
+    /// value. Nothing checks that the value has the shape Calibre expects for
+    /// the key; prefer a typed setter where there is one.
+    pub fn set_raw(&mut self, key: &str, value: &Value) -> Result<(), CalibreError> {
+        preferences::set(self.conn, key, value)
+    }
+
+    /// Delete `key`, so Calibre falls back to its default. Returns whether
+    /// it was present.
+    pub fn remove(&mut self, key: &str) -> Result<bool, CalibreError> {
+        preferences::remove(self.conn, key)
+    }
+
+    // -------------------------------------------------------------------------
+    // Well-known keys
+    // -------------------------------------------------------------------------
+
+    /// Whether yes/no columns have a third "undefined" state. Defaults to
+    /// `true`.
+    pub fn bools_are_tristate(&mut self) -> Result<bool, CalibreError> {
+        self.get_typed(BOOLS_ARE_TRISTATE, true)
+    }
+
+    pub fn set_bools_are_tristate(&mut self, tristate: bool) -> Result<(), CalibreError> {
+        self.set_typed(BOOLS_ARE_TRISTATE, &tristate)
+    }
+
+    /// The user categories shown in the tag browser, by name. A `.` in a
+    /// name nests it under the category named by the part before the dot.
+    pub fn user_categories(
+        &mut self,
+    ) -> Result<BTreeMap<String, Vec<UserCategoryItem>>, CalibreError> {
+        let stored: BTreeMap<String, Vec<(String, String, Value)>> =
+            self.get_typed(USER_CATEGORIES, BTreeMap::new())?;
+        Ok(stored
+            .into_iter()
+            .map(|(name, items)| {
+                let items = items
+                    .into_iter()
+                    .map(|(name, category, _)| UserCategoryItem { name, category })
+                    .collect();
+                (name, items)
+            })
+            .collect())
+    }
+
+    pub fn set_user_categories(
+        &mut self,
+        categories: &BTreeMap<String, Vec<UserCategoryItem>>,
+    ) -> Result<(), CalibreError> {
+        let mut stored = serde_json::Map::new();
+        for (name, items) in categories {
+            if name.trim().is_empty() || name.split('.').any(str::is_empty) {
+                return Err(CalibreError::InvalidPreference(format!(
+                    "'{name}' is not a valid user category name"
+                )));
+            }
+            // Calibre keeps a third, unused element in each entry.
+            let items = items
+                .iter()
+                .map(|item| serde_json::json!([item.name, item.category, 0]))
+                .collect();
+            stored.insert(name.clone(), Value::Array(items));
+        }
+        self.set_raw(USER_CATEGORIES, &Value::Object(stored))
+    }
+
+    /// Search terms that search several columns at once, e.g. `people` for
+    /// `authors` and `#translators`.
+    pub fn grouped_search_terms(&mut self) -> Result<BTreeMap<String, Vec<String>>, CalibreError> {
+        self.get_typed(GROUPED_SEARCH_TERMS, BTreeMap::new())
+    }
+
+    /// Calibre only recognizes lower-case grouped search term names.
+    pub fn set_grouped_search_terms(
+        &mut self,
+        terms: &BTreeMap<String, Vec<String>>,
+    ) -> Result<(), CalibreError> {
+        if let Some(name) = terms
+            .keys()
+            .find(|name| name.is_empty() || **name != name.to_lowercase())
+        {
+            return Err(CalibreError::InvalidPreference(format!(
+                "grouped search term '{name}' must be a non-empty lower-case name"
+            )));
+        }
+        self.set_typed(GROUPED_SEARCH_TERMS, terms)
+    }
+
+    /// Lookup keys of the categories hidden from the tag browser.
+    pub fn tag_browser_hidden_categories(&mut self) -> Result<Vec<String>, CalibreError> {
+        self.get_typed(TAG_BROWSER_HIDDEN_CATEGORIES, Vec::new())
+    }
+
+    pub fn set_tag_browser_hidden_categories(
+        &mut self,
+        categories: &[String],
+    ) -> Result<(), CalibreError> {
+        self.set_typed(TAG_BROWSER_HIDDEN_CATEGORIES, &categories)
+    }
+
+    /// Lookup keys of the tag browser's categories, in display order.
+    pub fn tag_browser_category_order(&mut self) -> Result<Vec<String>, CalibreError> {
+        self.get_typed(TAG_BROWSER_CATEGORY_ORDER, Vec::new())
+    }
+
+    pub fn set_tag_browser_category_order(
+        &mut self,
+        categories: &[String],
+    ) -> Result<(), CalibreError> {
+        self.set_typed(TAG_BROWSER_CATEGORY_ORDER, &categories)
+    }
+
+    /// The fields of the book details panel, in order, each with whether it
+    /// is shown. Empty when Calibre has never saved a layout.
+    pub fn book_display_fields(&mut self) -> Result<Vec<(String, bool)>, CalibreError> {
+        self.get_typed(BOOK_DISPLAY_FIELDS, Vec::new())
+    }
+
+    pub fn set_book_display_fields(
+        &mut self,
+        fields: &[(String, bool)],
+    ) -> Result<(), CalibreError> {
+        self.set_typed(BOOK_DISPLAY_FIELDS, &fields)
+    }
+
+    /// The virtual library Calibre selects when it opens the library; empty
+    /// for the whole library.
+    pub fn virtual_library_on_startup(&mut self) -> Result<String, CalibreError> {
+        self.get_typed(VIRTUAL_LIB_ON_STARTUP, String::new())
+    }
+
+    pub fn set_virtual_library_on_startup(&mut self, name: &str) -> Result<(), CalibreError> {
+        self.set_typed(VIRTUAL_LIB_ON_STARTUP, &name)
+    }
+
+    /// Calibre's description of every field, by lookup key. Read-only:
+    /// Calibre regenerates it from the schema whenever it opens the library.
+    pub fn field_metadata(&mut self) -> Result<BTreeMap<String, FieldMetadata>, CalibreError> {
+        self.get_typed(FIELD_METADATA, BTreeMap::new())
+    }
+
+    /// The layout of Calibre's book list columns.
+    pub fn column_display(&mut self) -> Result<ColumnDisplay, CalibreError> {
+        self.get_typed(BOOK_LIST_STATE, ColumnDisplay::default())
+    }
+
+    /// Replace the column layout, keeping the rest of the book list state
+    /// (sort history and the like) as Calibre saved it.
+    pub fn set_column_display(&mut self, display: &ColumnDisplay) -> Result<(), CalibreError> {
+        let Value::Object(fields) = serde_json::to_value(display).map_err(CalibreError::unknown)?
+        else {
+            unreachable!("ColumnDisplay serializes to an object");
+        };
+        self.conn.transaction::<(), CalibreError, _>(|conn| {
+            let mut state = preferences::get_object(conn, BOOK_LIST_STATE)?;
+            state.extend(fields);
+            preferences::set(conn, BOOK_LIST_STATE, &Value::Object(state))
+        })
+    }
+
+    // -------------------------------------------------------------------------
+    // Helpers
+    // -------------------------------------------------------------------------
+
+    /// The value under `key` as a `T`, or `default` when the key is absent.
+    /// A value of the wrong shape is a [`CalibreError::DatabaseIntegrity`]
+    /// error rather than silently replaced, so it is never overwritten by
+    /// accident.
+    fn get_typed<T: DeserializeOwned>(&mut self, key: &str, default: T) -> Result<T, CalibreError> {
+        match self.get_raw(key)? {
+            None | Some(Value::Null) => Ok(default),
+            Some(value) => serde_json::from_value(value).map_err(|e| {
+                CalibreError::DatabaseIntegrity(format!(
+                    "Preference '{key}' does not have the expected shape: {e}"
+                ))
+            }),
+        }
+    }
+
+    fn set_typed<T: Serialize + ?Sized>(
+        &mut self,
+        key: &str,
+        value: &T,
+    ) -> Result<(), CalibreError> {
+        let value = serde_json::to_value(value).map_err(CalibreError::unknown)?;
+        self.set_raw(key, &value)
+    }
+}
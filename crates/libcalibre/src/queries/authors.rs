@@ -10,6 +10,8 @@
 use std::collections::HashMap;
 
 use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::Integer;
 use diesel::{QueryDsl, RunQueryDsl, SqliteConnection};
 
 use crate::entities::author::{NewAuthor, UpdateAuthorData};
@@ -201,6 +203,28 @@ pub(crate) fn unlink_book(
     Ok(())
 }
 
+/// Relink every book of `from_author` to `into_author`, keeping each link's
+/// place in the book's author order. Books that already have both keep only
+/// their `into_author` link.
+pub(crate) fn move_links(
+    conn: &mut SqliteConnection,
+    from_author: AuthorId,
+    into_author: AuthorId,
+) -> Result<(), CalibreError> {
+    use crate::schema::books_authors_link::dsl::*;
+
+    sql_query("UPDATE OR IGNORE books_authors_link SET author = ? WHERE author = ?")
+        .bind::<Integer, _>(into_author.as_i32())
+        .bind::<Integer, _>(from_author.as_i32())
+        .execute(conn)
+        .map_err(CalibreError::from)?;
+    diesel::delete(books_authors_link.filter(author.eq(from_author.as_i32())))
+        .execute(conn)
+        .map_err(CalibreError::from)?;
+
+    Ok(())
+}
+
 pub(crate) fn find_author_ids_by_book_ids(
     conn: &mut SqliteConnection,
     book_ids: Vec<BookId>,
@@ -211,6 +235,7 @@ pub(crate) fn find_author_ids_by_book_ids(
 
     let links = books_authors_link
         .filter(book.eq_any(ids))
+        .order(id.asc())
         .select((book, author))
         .load::<(i32, i32)>(conn)
         .map_err(CalibreError::from)?;
@@ -47,6 +47,37 @@ pub(crate) fn delete_all(conn: &mut SqliteConnection, book_id: BookId) -> Result
         .map_err(CalibreError::from)
 }
 
+/// Set the on-disk file stem (`data.name`) of every format of a book.
+pub(crate) fn set_name_for_book(
+    conn: &mut SqliteConnection,
+    book_id: BookId,
+    file_name: &str,
+) -> Result<(), CalibreError> {
+    use crate::schema::data::dsl::*;
+
+    diesel::update(data.filter(book.eq(book_id.as_i32())))
+        .set(name.eq(file_name))
+        .execute(conn)
+        .map(|_| ())
+        .map_err(CalibreError::from)
+}
+
+/// Hand a format over to another book, under that book's file stem.
+pub(crate) fn relink(
+    conn: &mut SqliteConnection,
+    file_id: BookFileId,
+    to_book: BookId,
+    file_name: &str,
+) -> Result<(), CalibreError> {
+    use crate::schema::data::dsl::*;
+
+    diesel::update(data.filter(id.eq(file_id.as_i32())))
+        .set((book.eq(to_book.as_i32()), name.eq(file_name)))
+        .execute(conn)
+        .map(|_| ())
+        .map_err(CalibreError::from)
+}
+
 // =============================================================================
 // Relationships
 // =============================================================================
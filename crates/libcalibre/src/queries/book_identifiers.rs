@@ -3,7 +3,7 @@
 //! Provides functions to interact with `identifiers` in the Calibre database.
 //! All functions use type-safe IDs and accept a mutable SQLite connection.
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 use diesel::prelude::*;
 use diesel::{QueryDsl, RunQueryDsl, SqliteConnection};
@@ -33,6 +33,56 @@ pub(crate) fn delete_all(conn: &mut SqliteConnection, book_id: BookId) -> Result
         .map_err(CalibreError::from)
 }
 
+/// Clean an identifier pair the way Calibre's `clean_identifier` does: the
+/// type is lowercased and stripped of `:` and `,` (the separators of
+/// Calibre's `type:val,type:val` text form), the value has `,` replaced by
+/// `|`. Returns `None` when either side is empty after cleaning.
+pub(crate) fn clean_identifier(id_type: &str, id_value: &str) -> Option<(String, String)> {
+    let id_type = id_type.trim().to_lowercase().replace([':', ','], "");
+    let id_value = id_value.trim().replace(',', "|");
+
+    if id_type.is_empty() || id_value.is_empty() {
+        None
+    } else {
+        Some((id_type, id_value))
+    }
+}
+
+/// Replace all of a book's identifiers with `new_identifiers` (type -> value).
+/// Pairs that are empty after [`clean_identifier`] are skipped; an empty map
+/// clears every identifier.
+pub(crate) fn set_for_book(
+    conn: &mut SqliteConnection,
+    book_id: BookId,
+    new_identifiers: &HashMap<String, String>,
+) -> Result<(), CalibreError> {
+    use crate::schema::identifiers::dsl::*;
+
+    delete_all(conn, book_id)?;
+
+    // Two raw types can clean to the same key; keep one row per type, as
+    // `UNIQUE(book, type)` requires. Ordered so row ids are deterministic.
+    let mut cleaned: BTreeMap<String, String> = BTreeMap::new();
+    for (raw_type, raw_value) in new_identifiers {
+        if let Some((id_type, id_value)) = clean_identifier(raw_type, raw_value) {
+            cleaned.insert(id_type, id_value);
+        }
+    }
+
+    for (id_type, id_value) in cleaned {
+        diesel::insert_into(identifiers)
+            .values((
+                book.eq(book_id.as_i32()),
+                type_.eq(id_type),
+                val.eq(id_value),
+            ))
+            .execute(conn)
+            .map_err(CalibreError::from)?;
+    }
+
+    Ok(())
+}
+
 // =============================================================================
 // Relationships
 // =============================================================================
@@ -61,3 +111,27 @@ pub(crate) fn find_many_by_book_ids(
 
     Ok(grouped)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clean_identifier_normalizes_type_and_value() {
+        assert_eq!(
+            clean_identifier(" ISBN ", " 978-0-00-000000-0 "),
+            Some(("isbn".to_string(), "978-0-00-000000-0".to_string()))
+        );
+        assert_eq!(
+            clean_identifier("my:type,", "a,b"),
+            Some(("mytype".to_string(), "a|b".to_string()))
+        );
+    }
+
+    #[test]
+    fn clean_identifier_rejects_empty_sides() {
+        assert_eq!(clean_identifier("", "value"), None);
+        assert_eq!(clean_identifier(":,", "value"), None);
+        assert_eq!(clean_identifier("isbn", "   "), None);
+    }
+}
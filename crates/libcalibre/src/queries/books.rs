@@ -4,10 +4,14 @@
 //! All functions use type-safe IDs and accept a mutable SQLite connection.
 
 use diesel::prelude::*;
-use diesel::sql_types::{BigInt, Integer, Text};
+use diesel::query_builder::{BoxedSqlQuery, SqlQuery};
+use diesel::sql_types::{BigInt, Double, Integer, Nullable, Text};
+use diesel::sqlite::Sqlite;
 use diesel::{sql_query, QueryDsl, QueryableByName, RunQueryDsl, SqliteConnection};
 
-use crate::library::BookSortOrder;
+use crate::custom_columns::{self, CustomColumn, CustomColumnKind};
+use crate::library::{BookFacets, BookSortOrder, DateRange, FacetCount};
+use crate::search::{index as search_index, CompiledSearch, SqlBind};
 use crate::types::AuthorId;
 use crate::{types::BookId, CalibreError};
 use crate::{BookRow, NewBook, UpdateBookData};
@@ -106,6 +110,25 @@ pub(crate) struct BookPageFilters<'a> {
     /// Id of the `read` bool custom column. When set, books marked read are
     /// excluded.
     pub hide_read_column: Option<i32>,
+    pub tags_any: &'a [i32],
+    pub tags_all: &'a [i32],
+    /// Canonical language codes.
+    pub language_codes: Vec<String>,
+    /// Uppercase format names.
+    pub formats: Vec<String>,
+    pub publisher_id: Option<i32>,
+    pub min_rating: Option<i32>,
+    pub max_rating: Option<i32>,
+    pub has_cover: Option<bool>,
+    pub with_identifiers: &'a [String],
+    pub without_identifiers: &'a [String],
+    pub added: DateRange,
+    pub published: DateRange,
+    /// A Calibre search-language query, already compiled.
+    pub search: Option<&'a CompiledSearch>,
+    /// Answer `text` from the attached metadata search index when it is long
+    /// enough for the index to match.
+    pub use_search_index: bool,
 }
 
 #[derive(QueryableByName)]
@@ -120,21 +143,41 @@ struct CountRow {
     total: i64,
 }
 
-fn like_pattern(text: &str) -> String {
-    let escaped = text
-        .replace('\\', "\\\\")
+/// Escape LIKE wildcards for use with `ESCAPE '\\'`.
+pub(crate) fn escape_like(text: &str) -> String {
+    text.replace('\\', "\\\\")
         .replace('%', "\\%")
-        .replace('_', "\\_");
-    format!("%{escaped}%")
+        .replace('_', "\\_")
 }
 
-/// WHERE clause shared by [`query_page`] and [`query_count`]. The text filter
-/// uses three `?` placeholders (title, author name, series name); all other
-/// filters interpolate plain integers.
-fn filter_where_sql(filters: &BookPageFilters) -> String {
-    let mut clauses: Vec<String> = vec!["1=1".to_string()];
+pub(crate) fn like_pattern(text: &str) -> String {
+    format!("%{}%", escape_like(text))
+}
 
-    if filters.text.is_some() {
+/// WHERE clause shared by [`query_page`] and [`query_count`], with the values
+/// for its `?` placeholders in order. The text filter binds one FTS query
+/// when the search index can answer it and otherwise its LIKE pattern three
+/// times (title, author name, series name); language, format and identifier
+/// filters bind each of their values; a search binds its own values; all
+/// other filters interpolate plain integers and dates.
+fn filter_where_sql(filters: &BookPageFilters) -> (String, Vec<SqlBind>) {
+    let mut clauses: Vec<String> = vec!["1=1".to_string()];
+    let mut binds: Vec<SqlBind> = Vec::new();
+
+    if let Some(text) = filters.text.filter(|text| {
+        filters.use_search_index && text.chars().count() >= search_index::MIN_INDEXED_NEEDLE_CHARS
+    }) {
+        binds.push(SqlBind::Text(search_index::match_query(
+            &["title", "authors", "series"],
+            text,
+        )));
+        clauses.push(
+            "books.id IN (SELECT rowid FROM citadel_search.metadata_fts WHERE metadata_fts MATCH ?)"
+                .to_string(),
+        );
+    } else if let Some(text) = filters.text {
+        let pattern = like_pattern(text);
+        binds.extend(std::iter::repeat_n(SqlBind::Text(pattern), 3));
         clauses.push(
             "(books.title LIKE ? ESCAPE '\\' \
               OR EXISTS (SELECT 1 FROM books_authors_link bal \
@@ -169,22 +212,208 @@ fn filter_where_sql(filters: &BookPageFilters) -> String {
         ));
     }
 
-    clauses.join(" AND ")
+    if !filters.tags_any.is_empty() {
+        clauses.push(format!(
+            "EXISTS (SELECT 1 FROM books_tags_link btl \
+             WHERE btl.book = books.id AND btl.tag IN ({}))",
+            id_list(filters.tags_any)
+        ));
+    }
+
+    for tag in filters.tags_all {
+        clauses.push(format!(
+            "EXISTS (SELECT 1 FROM books_tags_link btl \
+             WHERE btl.book = books.id AND btl.tag = {tag})"
+        ));
+    }
+
+    if !filters.language_codes.is_empty() {
+        clauses.push(format!(
+            "EXISTS (SELECT 1 FROM books_languages_link bll \
+             JOIN languages l ON l.id = bll.lang_code \
+             WHERE bll.book = books.id AND l.lang_code IN ({}))",
+            placeholders(filters.language_codes.len())
+        ));
+        binds.extend(filters.language_codes.iter().cloned().map(SqlBind::Text));
+    }
+
+    if !filters.formats.is_empty() {
+        clauses.push(format!(
+            "EXISTS (SELECT 1 FROM data d \
+             WHERE d.book = books.id AND upper(d.format) IN ({}))",
+            placeholders(filters.formats.len())
+        ));
+        binds.extend(filters.formats.iter().cloned().map(SqlBind::Text));
+    }
+
+    if let Some(publisher_id) = filters.publisher_id {
+        clauses.push(format!(
+            "EXISTS (SELECT 1 FROM books_publishers_link bpl \
+             WHERE bpl.book = books.id AND bpl.publisher = {publisher_id})"
+        ));
+    }
+
+    if filters.min_rating.is_some() || filters.max_rating.is_some() {
+        // A rating of 0 is Calibre's "unrated".
+        let min = filters.min_rating.unwrap_or(1).max(1);
+        let max = filters.max_rating.unwrap_or(10);
+        clauses.push(format!(
+            "EXISTS (SELECT 1 FROM books_ratings_link brl \
+             JOIN ratings r ON r.id = brl.rating \
+             WHERE brl.book = books.id AND r.rating BETWEEN {min} AND {max})"
+        ));
+    }
+
+    match filters.has_cover {
+        Some(true) => clauses.push("books.has_cover = 1".to_string()),
+        Some(false) => clauses.push("COALESCE(books.has_cover, 0) = 0".to_string()),
+        None => {}
+    }
+
+    for (types, negate) in [
+        (filters.with_identifiers, ""),
+        (filters.without_identifiers, "NOT "),
+    ] {
+        for identifier_type in types {
+            clauses.push(format!(
+                "{negate}EXISTS (SELECT 1 FROM identifiers i \
+                 WHERE i.book = books.id AND i.type = ? COLLATE NOCASE)"
+            ));
+            binds.push(SqlBind::Text(identifier_type.trim().to_string()));
+        }
+    }
+
+    clauses.extend(date_range_sql("books.timestamp", filters.added));
+    if filters.published != DateRange::default() {
+        // Year 101 is Calibre's "undefined date". The bound is a full date so
+        // the column's NUMERIC affinity cannot turn it into a number.
+        clauses.push("books.pubdate >= '0102-01-01'".to_string());
+        clauses.extend(date_range_sql("books.pubdate", filters.published));
+    }
+
+    if let Some(search) = filters.search {
+        clauses.push(search.sql.clone());
+        binds.extend(search.binds.iter().cloned());
+    }
+
+    (clauses.join(" AND "), binds)
+}
+
+fn id_list(ids: &[i32]) -> String {
+    ids.iter()
+        .map(i32::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn placeholders(count: usize) -> String {
+    vec!["?"; count].join(", ")
+}
+
+/// Conditions keeping `column`, one of Calibre's `YYYY-MM-DD HH:MM:SS+00:00`
+/// timestamps, within `range`. Comparing the text is enough: the format sorts
+/// chronologically.
+fn date_range_sql(column: &str, range: DateRange) -> Vec<String> {
+    let mut clauses = Vec::new();
+    if let Some(from) = range.from {
+        clauses.push(format!("{column} >= '{}'", from.format("%Y-%m-%d")));
+    }
+    if let Some(to) = range.to.and_then(|to| to.succ_opt()) {
+        clauses.push(format!("{column} < '{}'", to.format("%Y-%m-%d")));
+    }
+    clauses
+}
+
+fn with_binds<'a>(sql: String, binds: &'a [SqlBind]) -> BoxedSqlQuery<'a, Sqlite, SqlQuery> {
+    binds
+        .iter()
+        .fold(sql_query(sql).into_boxed(), |query, bind| match bind {
+            SqlBind::Text(text) => query.bind::<Text, _>(text),
+            SqlBind::Double(number) => query.bind::<Double, _>(*number),
+        })
 }
 
 /// ORDER BY clause for [`query_page`]. Uses Calibre's precomputed sort
 /// columns (`books.sort` for titles, the primary linked author's
-/// `authors.sort` for authors), with `books.id` as a stable tiebreak.
-fn order_by_sql(sort: BookSortOrder) -> String {
+/// `authors.sort` for authors), with `books.id` as a stable tiebreak. Books
+/// with no value for the sort field (no series, unrated, no files, ...) come
+/// first ascending and last descending, as in Calibre.
+fn order_by_sql(conn: &mut SqliteConnection, sort: BookSortOrder) -> Result<String, CalibreError> {
     const AUTHOR_SORT: &str = "(SELECT a.sort FROM books_authors_link bal \
          JOIN authors a ON a.id = bal.author \
          WHERE bal.book = books.id ORDER BY bal.id LIMIT 1)";
+    const SERIES_SORT: &str = "(SELECT COALESCE(s.sort, s.name) FROM books_series_link bsl \
+         JOIN series s ON s.id = bsl.series WHERE bsl.book = books.id)";
+    const RATING: &str = "(SELECT r.rating FROM books_ratings_link brl \
+         JOIN ratings r ON r.id = brl.rating WHERE brl.book = books.id)";
+    const PUBLISHER: &str = "(SELECT p.name FROM books_publishers_link bpl \
+         JOIN publishers p ON p.id = bpl.publisher WHERE bpl.book = books.id)";
+    // Calibre's "size" is that of the largest format.
+    const SIZE: &str = "(SELECT MAX(d.uncompressed_size) FROM data d WHERE d.book = books.id)";
+
+    use BookSortOrder::*;
+    let keys = match sort {
+        TitleAsc | TitleDesc => vec!["books.sort".to_string()],
+        AuthorAsc | AuthorDesc => vec![AUTHOR_SORT.to_string()],
+        AddedAsc | AddedDesc => vec!["books.timestamp".to_string()],
+        ModifiedAsc | ModifiedDesc => vec!["books.last_modified".to_string()],
+        PublishedAsc | PublishedDesc => vec!["books.pubdate".to_string()],
+        SeriesAsc | SeriesDesc => vec![SERIES_SORT.to_string(), "books.series_index".to_string()],
+        RatingAsc | RatingDesc => vec![RATING.to_string()],
+        PublisherAsc | PublisherDesc => vec![PUBLISHER.to_string()],
+        SizeAsc | SizeDesc => vec![SIZE.to_string()],
+        CustomColumnAsc(column_id) | CustomColumnDesc(column_id) => {
+            custom_column_sort_keys(&custom_columns::get_column(conn, column_id)?)?
+        }
+    };
+    let direction = match sort {
+        TitleAsc | AuthorAsc | AddedAsc | ModifiedAsc | PublishedAsc | SeriesAsc | RatingAsc
+        | PublisherAsc | SizeAsc | CustomColumnAsc(_) => "ASC",
+        _ => "DESC",
+    };
 
-    match sort {
-        BookSortOrder::TitleAsc => "books.sort ASC, books.id ASC".to_string(),
-        BookSortOrder::TitleDesc => "books.sort DESC, books.id DESC".to_string(),
-        BookSortOrder::AuthorAsc => format!("{AUTHOR_SORT} ASC, books.id ASC"),
-        BookSortOrder::AuthorDesc => format!("{AUTHOR_SORT} DESC, books.id DESC"),
+    Ok(keys
+        .iter()
+        .map(String::as_str)
+        .chain(["books.id"])
+        .map(|key| format!("{key} {direction}"))
+        .collect::<Vec<_>>()
+        .join(", "))
+}
+
+/// Sort keys for a custom column: its value (the smallest, for multi-valued
+/// columns), then the series index for series columns. Composite and
+/// comments columns are not sortable, as in Calibre.
+fn custom_column_sort_keys(column: &CustomColumn) -> Result<Vec<String>, CalibreError> {
+    let n = column.id;
+    let linked = format!(
+        "(SELECT MIN(v.value) FROM books_custom_column_{n}_link l \
+         JOIN custom_column_{n} v ON v.id = l.value WHERE l.book = books.id)"
+    );
+    let per_book = format!("(SELECT v.value FROM custom_column_{n} v WHERE v.book = books.id)");
+
+    match column.kind {
+        CustomColumnKind::Text | CustomColumnKind::Enumeration | CustomColumnKind::Rating => {
+            Ok(vec![linked])
+        }
+        CustomColumnKind::Series => Ok(vec![
+            linked,
+            format!(
+                "(SELECT MIN(l.extra) FROM books_custom_column_{n}_link l \
+                 WHERE l.book = books.id)"
+            ),
+        ]),
+        CustomColumnKind::Bool
+        | CustomColumnKind::Int
+        | CustomColumnKind::Float
+        | CustomColumnKind::Datetime => Ok(vec![per_book]),
+        CustomColumnKind::Comments | CustomColumnKind::Composite | CustomColumnKind::Other(_) => {
+            Err(CalibreError::UnsupportedCustomColumn(format!(
+                "cannot sort by column '{}' ({} column)",
+                column.label,
+                column.kind.datatype()
+            )))
+        }
     }
 }
 
@@ -197,8 +426,8 @@ pub(crate) fn query_page(
     limit: Option<i64>,
     offset: i64,
 ) -> Result<Vec<BookId>, CalibreError> {
-    let where_sql = filter_where_sql(filters);
-    let order_sql = order_by_sql(sort);
+    let (where_sql, binds) = filter_where_sql(filters);
+    let order_sql = order_by_sql(conn, sort)?;
     // SQLite treats a negative LIMIT as "no limit"; OFFSET still applies.
     let limit = limit.map(|l| l.max(0)).unwrap_or(-1);
     let offset = offset.max(0);
@@ -208,18 +437,9 @@ pub(crate) fn query_page(
          ORDER BY {order_sql} LIMIT {limit} OFFSET {offset}"
     );
 
-    let rows: Vec<IdRow> = match filters.text {
-        Some(text) => {
-            let pattern = like_pattern(text);
-            sql_query(sql)
-                .bind::<Text, _>(&pattern)
-                .bind::<Text, _>(&pattern)
-                .bind::<Text, _>(&pattern)
-                .load(conn)
-                .map_err(CalibreError::from)?
-        }
-        None => sql_query(sql).load(conn).map_err(CalibreError::from)?,
-    };
+    let rows: Vec<IdRow> = with_binds(sql, &binds)
+        .load(conn)
+        .map_err(CalibreError::from)?;
 
     Ok(rows.into_iter().map(|row| BookId(row.id)).collect())
 }
@@ -229,25 +449,116 @@ pub(crate) fn query_count(
     conn: &mut SqliteConnection,
     filters: &BookPageFilters,
 ) -> Result<i64, CalibreError> {
-    let where_sql = filter_where_sql(filters);
+    let (where_sql, binds) = filter_where_sql(filters);
     let sql = format!("SELECT COUNT(*) AS total FROM books WHERE {where_sql}");
 
-    let rows: Vec<CountRow> = match filters.text {
-        Some(text) => {
-            let pattern = like_pattern(text);
-            sql_query(sql)
-                .bind::<Text, _>(&pattern)
-                .bind::<Text, _>(&pattern)
-                .bind::<Text, _>(&pattern)
-                .load(conn)
-                .map_err(CalibreError::from)?
-        }
-        None => sql_query(sql).load(conn).map_err(CalibreError::from)?,
-    };
+    let rows: Vec<CountRow> = with_binds(sql, &binds)
+        .load(conn)
+        .map_err(CalibreError::from)?;
 
     Ok(rows.first().map(|row| row.total).unwrap_or(0))
 }
 
+/// Per-category counts over the books matching `filters` (the same WHERE as
+/// [`query_page`]), in one query: the matches are a CTE that every
+/// category's GROUP BY joins against.
+pub(crate) fn facets(
+    conn: &mut SqliteConnection,
+    filters: &BookPageFilters,
+) -> Result<BookFacets, CalibreError> {
+    #[derive(QueryableByName)]
+    struct FacetRow {
+        #[diesel(sql_type = Text)]
+        category: String,
+        #[diesel(sql_type = Nullable<Integer>)]
+        id: Option<i32>,
+        #[diesel(sql_type = Text)]
+        name: String,
+        #[diesel(sql_type = BigInt)]
+        book_count: i64,
+    }
+
+    let (where_sql, binds) = filter_where_sql(filters);
+    // `sort_key` orders values within a category; the `total` row carries
+    // the number of matches.
+    let sql = format!(
+        "WITH matches AS (SELECT books.id AS book FROM books WHERE {where_sql}) \
+         SELECT category, id, name, book_count FROM ( \
+           SELECT 'total' AS category, NULL AS id, '' AS name, '' AS sort_key, \
+                  COUNT(*) AS book_count FROM matches \
+           UNION ALL \
+           SELECT 'author', a.id, a.name, COALESCE(a.sort, a.name), COUNT(*) \
+           FROM matches m JOIN books_authors_link l ON l.book = m.book \
+           JOIN authors a ON a.id = l.author GROUP BY a.id \
+           UNION ALL \
+           SELECT 'series', s.id, s.name, s.name, COUNT(*) \
+           FROM matches m JOIN books_series_link l ON l.book = m.book \
+           JOIN series s ON s.id = l.series GROUP BY s.id \
+           UNION ALL \
+           SELECT 'tag', t.id, t.name, t.name, COUNT(*) \
+           FROM matches m JOIN books_tags_link l ON l.book = m.book \
+           JOIN tags t ON t.id = l.tag GROUP BY t.id \
+           UNION ALL \
+           SELECT 'publisher', p.id, p.name, p.name, COUNT(*) \
+           FROM matches m JOIN books_publishers_link l ON l.book = m.book \
+           JOIN publishers p ON p.id = l.publisher GROUP BY p.id \
+           UNION ALL \
+           SELECT 'language', NULL, lang.lang_code, lang.lang_code, COUNT(*) \
+           FROM matches m JOIN books_languages_link l ON l.book = m.book \
+           JOIN languages lang ON lang.id = l.lang_code GROUP BY lang.lang_code \
+           UNION ALL \
+           SELECT 'format', NULL, upper(d.format), upper(d.format), COUNT(DISTINCT m.book) \
+           FROM matches m JOIN data d ON d.book = m.book GROUP BY upper(d.format) \
+           UNION ALL \
+           SELECT 'rating', r.rating, '', printf('%02d', 10 - r.rating), COUNT(*) \
+           FROM matches m JOIN books_ratings_link l ON l.book = m.book \
+           JOIN ratings r ON r.id = l.rating WHERE r.rating > 0 GROUP BY r.rating \
+         ) ORDER BY category, sort_key COLLATE NOCASE, id"
+    );
+
+    let rows: Vec<FacetRow> = with_binds(sql, &binds)
+        .load(conn)
+        .map_err(CalibreError::from)?;
+
+    fn count<V>(value: V, row: &FacetRow) -> FacetCount<V> {
+        FacetCount {
+            value,
+            name: row.name.clone(),
+            book_count: row.book_count,
+        }
+    }
+
+    let mut facets = BookFacets::default();
+    for row in rows {
+        let id = row.id.unwrap_or_default();
+        match row.category.as_str() {
+            "total" => facets.total = row.book_count,
+            "author" => facets.authors.push(count(AuthorId(id), &row)),
+            "series" => facets.series.push(count(id, &row)),
+            "tag" => facets.tags.push(count(id, &row)),
+            "publisher" => facets.publishers.push(count(id, &row)),
+            "language" => facets.languages.push(count(row.name.clone(), &row)),
+            "format" => facets.formats.push(count(row.name.clone(), &row)),
+            "rating" => facets.ratings.push(FacetCount {
+                value: id,
+                name: stars(id),
+                book_count: row.book_count,
+            }),
+            _ => {}
+        }
+    }
+    Ok(facets)
+}
+
+/// A 0–10 rating as Calibre shows it in stars: `4`, `4.5`.
+fn stars(rating: i32) -> String {
+    if rating % 2 == 0 {
+        (rating / 2).to_string()
+    } else {
+        format!("{}.5", rating / 2)
+    }
+}
+
 pub(crate) fn create(conn: &mut SqliteConnection, book: NewBook) -> Result<BookRow, CalibreError> {
     use crate::schema::books::dsl::*;
 
@@ -258,6 +569,37 @@ pub(crate) fn create(conn: &mut SqliteConnection, book: NewBook) -> Result<BookR
         .map_err(CalibreError::from)
 }
 
+/// Insert a book under a given id, as when restoring one from the trash.
+pub(crate) fn create_with_id(
+    conn: &mut SqliteConnection,
+    book_id: BookId,
+    book: NewBook,
+) -> Result<BookRow, CalibreError> {
+    use crate::schema::books::dsl::*;
+
+    diesel::insert_into(books)
+        .values((id.eq(book_id.as_i32()), book))
+        .returning(BookRow::as_returning())
+        .get_result(conn)
+        .map_err(CalibreError::from)
+}
+
+/// Replace the uuid the insert trigger generated, so a restored book keeps
+/// its identity.
+pub(crate) fn set_uuid(
+    conn: &mut SqliteConnection,
+    book_id: BookId,
+    new_uuid: &str,
+) -> Result<(), CalibreError> {
+    use crate::schema::books::dsl::*;
+
+    diesel::update(books.filter(id.eq(book_id.as_i32())))
+        .set(uuid.eq(new_uuid))
+        .execute(conn)
+        .map(|_| ())
+        .map_err(CalibreError::from)
+}
+
 pub(crate) fn update(
     conn: &mut SqliteConnection,
     book_id: BookId,
@@ -280,6 +622,105 @@ pub(crate) fn delete(conn: &mut SqliteConnection, book_id: BookId) -> Result<boo
         .map(|affected_rows| affected_rows > 0)
 }
 
+/// Record that the books' `metadata.opf` no longer matches the database.
+/// Calibre rewrites the OPF of every book in `metadata_dirtied` the next time
+/// it runs, so the mark outlives an OPF write that fails.
+pub(crate) fn mark_dirty(
+    conn: &mut SqliteConnection,
+    book_ids: &[BookId],
+) -> Result<(), CalibreError> {
+    use crate::schema::metadata_dirtied::dsl::*;
+
+    for book_id in book_ids {
+        diesel::insert_or_ignore_into(metadata_dirtied)
+            .values(book.eq(book_id.as_i32()))
+            .execute(conn)
+            .map_err(CalibreError::from)?;
+    }
+    Ok(())
+}
+
+/// Forget the dirty mark of a book whose OPF has been rewritten.
+pub(crate) fn clear_dirty(
+    conn: &mut SqliteConnection,
+    book_id: BookId,
+) -> Result<(), CalibreError> {
+    use crate::schema::metadata_dirtied::dsl::*;
+
+    diesel::delete(metadata_dirtied.filter(book.eq(book_id.as_i32())))
+        .execute(conn)
+        .map(|_| ())
+        .map_err(CalibreError::from)
+}
+
+/// Books that could duplicate one being added, for duplicate detection to
+/// compare in full: those with an `isbn` identifier equal to one of `isbns`
+/// (compact forms; case, hyphens, spaces and an `ISBN:` prefix in the stored
+/// value are ignored), those by any of `author_ids`, those with a format file
+/// of one of `file_sizes` bytes and, given `authorless`, those with no author
+/// outside it. Ordered by id.
+pub(crate) fn duplicate_candidates(
+    conn: &mut SqliteConnection,
+    isbns: &[String],
+    author_ids: &[AuthorId],
+    authorless: Option<&[AuthorId]>,
+    file_sizes: &[i64],
+) -> Result<Vec<BookId>, CalibreError> {
+    let id_list = |ids: &[AuthorId]| {
+        ids.iter()
+            .map(|id| id.as_i32().to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let mut sources: Vec<String> = Vec::new();
+    let mut binds: Vec<SqlBind> = Vec::new();
+
+    if !isbns.is_empty() {
+        sources.push(format!(
+            "SELECT book FROM identifiers WHERE lower(type) = 'isbn' \
+             AND replace(replace(replace(upper(val), 'ISBN:', ''), '-', ''), ' ', '') IN ({})",
+            vec!["?"; isbns.len()].join(", ")
+        ));
+        binds.extend(isbns.iter().cloned().map(SqlBind::Text));
+    }
+    if !author_ids.is_empty() {
+        sources.push(format!(
+            "SELECT book FROM books_authors_link WHERE author IN ({})",
+            id_list(author_ids)
+        ));
+    }
+    if let Some(authorless) = authorless {
+        sources.push(format!(
+            "SELECT id FROM books WHERE id NOT IN \
+             (SELECT book FROM books_authors_link WHERE author NOT IN ({}))",
+            id_list(authorless)
+        ));
+    }
+    if !file_sizes.is_empty() {
+        sources.push(format!(
+            "SELECT book FROM data WHERE uncompressed_size IN ({})",
+            file_sizes
+                .iter()
+                .map(i64::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+    if sources.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let sql = format!(
+        "SELECT id FROM books WHERE id IN ({}) ORDER BY id",
+        sources.join(" UNION ")
+    );
+    let rows: Vec<IdRow> = with_binds(sql, &binds)
+        .load(conn)
+        .map_err(CalibreError::from)?;
+
+    Ok(rows.into_iter().map(|row| BookId(row.id)).collect())
+}
+
 // =============================================================================
 // Relationships
 // =============================================================================
@@ -290,8 +731,10 @@ pub(crate) fn find_authors(
 ) -> Result<Vec<AuthorId>, CalibreError> {
     use crate::schema::books_authors_link::dsl::*;
 
+    // Link order is author order; the first is the book's primary author.
     let author_ids: Vec<AuthorId> = books_authors_link
         .filter(book.eq(book_id.as_i32()))
+        .order(id.asc())
         .select(author)
         .load(conn)
         .map(|ids: Vec<i32>| ids.into_iter().map(AuthorId).collect())
@@ -4,5 +4,8 @@ pub mod book_files;
 pub mod book_identifiers;
 pub mod books;
 pub mod languages;
+pub mod preferences;
+pub mod publishers;
+pub mod ratings;
 pub mod series;
 pub mod tags;
@@ -0,0 +1,84 @@
+//! Calibre's `preferences` table: one JSON value per key.
+
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::Text;
+use diesel::{RunQueryDsl, SqliteConnection};
+
+use crate::CalibreError;
+
+#[derive(QueryableByName)]
+struct PreferenceRow {
+    #[diesel(sql_type = Text)]
+    val: String,
+}
+
+#[derive(QueryableByName)]
+struct PreferenceKeyRow {
+    #[diesel(sql_type = Text)]
+    key: String,
+}
+
+/// Every key in the table, sorted.
+pub(crate) fn keys(conn: &mut SqliteConnection) -> Result<Vec<String>, CalibreError> {
+    let rows = sql_query("SELECT key FROM preferences ORDER BY key")
+        .load::<PreferenceKeyRow>(conn)
+        .map_err(CalibreError::from)?;
+    Ok(rows.into_iter().map(|row| row.key).collect())
+}
+
+/// The value stored under `key`, or `None` when the key is absent.
+pub(crate) fn get(
+    conn: &mut SqliteConnection,
+    key: &str,
+) -> Result<Option<serde_json::Value>, CalibreError> {
+    let row = sql_query("SELECT val FROM preferences WHERE key = ?")
+        .bind::<Text, _>(key)
+        .get_result::<PreferenceRow>(conn)
+        .optional()
+        .map_err(CalibreError::from)?;
+
+    row.map(|row| {
+        serde_json::from_str(&row.val).map_err(|e| {
+            CalibreError::DatabaseIntegrity(format!("Preference '{key}' is not valid JSON: {e}"))
+        })
+    })
+    .transpose()
+}
+
+/// Store `value` under `key`, replacing any previous value.
+pub(crate) fn set(
+    conn: &mut SqliteConnection,
+    key: &str,
+    value: &serde_json::Value,
+) -> Result<(), CalibreError> {
+    // Calibre stores preference values as indented JSON.
+    let val = serde_json::to_string_pretty(value).map_err(CalibreError::unknown)?;
+    sql_query("INSERT OR REPLACE INTO preferences (key, val) VALUES (?, ?)")
+        .bind::<Text, _>(key)
+        .bind::<Text, _>(val)
+        .execute(conn)
+        .map(|_| ())
+        .map_err(CalibreError::from)
+}
+
+/// Delete `key`. Returns whether it was present.
+pub(crate) fn remove(conn: &mut SqliteConnection, key: &str) -> Result<bool, CalibreError> {
+    sql_query("DELETE FROM preferences WHERE key = ?")
+        .bind::<Text, _>(key)
+        .execute(conn)
+        .map(|deleted| deleted > 0)
+        .map_err(CalibreError::from)
+}
+
+/// A preference holding a JSON object, as Calibre's `saved_searches` and
+/// `virtual_libraries` do. An absent or non-object preference is empty.
+pub(crate) fn get_object(
+    conn: &mut SqliteConnection,
+    key: &str,
+) -> Result<serde_json::Map<String, serde_json::Value>, CalibreError> {
+    Ok(match get(conn, key)? {
+        Some(serde_json::Value::Object(map)) => map,
+        _ => serde_json::Map::new(),
+    })
+}
@@ -0,0 +1,146 @@
+//! Publisher queries
+//!
+//! Provides functions to interact with `publishers` and
+//! `books_publishers_link` in the Calibre database. A book has at most one
+//! publisher (`UNIQUE(book)` on the link table).
+
+use std::collections::HashMap;
+
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::{Integer, Text};
+use diesel::{QueryDsl, RunQueryDsl, SqliteConnection};
+
+use crate::entities::publisher::{NewPublisher, Publisher};
+use crate::types::BookId;
+use crate::CalibreError;
+
+pub(crate) fn find_by_name_case_insensitive(
+    conn: &mut SqliteConnection,
+    publisher_name: &str,
+) -> Result<Option<Publisher>, CalibreError> {
+    sql_query("SELECT id, name FROM publishers WHERE name = ? COLLATE NOCASE LIMIT 1")
+        .bind::<Text, _>(publisher_name)
+        .get_result(conn)
+        .optional()
+        .map_err(CalibreError::from)
+}
+
+pub(crate) fn create(
+    conn: &mut SqliteConnection,
+    new_publisher: NewPublisher,
+) -> Result<Publisher, CalibreError> {
+    use crate::schema::publishers::dsl::*;
+
+    diesel::insert_into(publishers)
+        .values(new_publisher)
+        .returning(Publisher::as_returning())
+        .get_result(conn)
+        .map_err(CalibreError::from)
+}
+
+pub(crate) fn create_if_not_exists(
+    conn: &mut SqliteConnection,
+    publisher_name: &str,
+) -> Result<Publisher, CalibreError> {
+    match find_by_name_case_insensitive(conn, publisher_name)? {
+        Some(existing) => Ok(existing),
+        None => create(
+            conn,
+            NewPublisher {
+                name: publisher_name.to_string(),
+            },
+        ),
+    }
+}
+
+pub(crate) fn unlink_book(
+    conn: &mut SqliteConnection,
+    book_id: BookId,
+) -> Result<(), CalibreError> {
+    use crate::schema::books_publishers_link::dsl::*;
+
+    diesel::delete(books_publishers_link.filter(book.eq(book_id.as_i32())))
+        .execute(conn)
+        .map_err(CalibreError::from)?;
+
+    Ok(())
+}
+
+/// Makes `publisher_id` the book's only publisher, replacing any existing
+/// link.
+pub(crate) fn set_book_publisher(
+    conn: &mut SqliteConnection,
+    publisher_id: i32,
+    book_id: BookId,
+) -> Result<(), CalibreError> {
+    use crate::schema::books_publishers_link::dsl::*;
+
+    unlink_book(conn, book_id)?;
+    diesel::insert_into(books_publishers_link)
+        .values((publisher.eq(publisher_id), book.eq(book_id.as_i32())))
+        .execute(conn)
+        .map_err(CalibreError::from)?;
+
+    Ok(())
+}
+
+/// Delete every publisher no book links to any more. Calibre drops unused
+/// many-to-one items after each write (`db/write.py`, `many_one`) rather
+/// than leaving them to accumulate, and its `fkc_delete_on_publishers`
+/// trigger guarantees a still-linked row is never removed.
+pub(crate) fn delete_unused(conn: &mut SqliteConnection) -> Result<(), CalibreError> {
+    sql_query(
+        "DELETE FROM publishers
+         WHERE id NOT IN (SELECT publisher FROM books_publishers_link)",
+    )
+    .execute(conn)
+    .map(|_| ())
+    .map_err(CalibreError::from)
+}
+
+pub(crate) fn find_publisher_name_for_book(
+    conn: &mut SqliteConnection,
+    book_id: BookId,
+) -> Result<Option<String>, CalibreError> {
+    find_publisher_names_by_book_ids(conn, vec![book_id]).map(|mut names| names.remove(&book_id))
+}
+
+/// Publisher names for many books at once. Books with no publisher are
+/// absent from the map.
+pub(crate) fn find_publisher_names_by_book_ids(
+    conn: &mut SqliteConnection,
+    book_ids: Vec<BookId>,
+) -> Result<HashMap<BookId, String>, CalibreError> {
+    if book_ids.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    #[derive(QueryableByName)]
+    struct BookPublisherName {
+        #[diesel(sql_type = Integer)]
+        book_id: i32,
+        #[diesel(sql_type = Text)]
+        publisher_name: String,
+    }
+
+    let ids = book_ids
+        .iter()
+        .map(|book_id| book_id.as_i32().to_string())
+        .collect::<Vec<_>>()
+        .join(",");
+
+    let rows: Vec<BookPublisherName> = sql_query(format!(
+        "SELECT bpl.book AS book_id, p.name AS publisher_name
+         FROM books_publishers_link bpl
+         INNER JOIN publishers p ON p.id = bpl.publisher
+         WHERE bpl.book IN ({ids})"
+    ))
+    .load(conn)
+    .map_err(CalibreError::from)?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| (BookId(row.book_id), row.publisher_name))
+        .collect())
+}
@@ -0,0 +1,169 @@
+//! Rating queries
+//!
+//! Provides functions to interact with `ratings` and `books_ratings_link` in
+//! the Calibre database. `ratings` holds one row per distinct value on
+//! Calibre's 0–10 scale (two points per displayed star); books link to the
+//! row for their value. A book has at most one rating.
+
+use std::collections::HashMap;
+
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::Integer;
+use diesel::{QueryDsl, RunQueryDsl, SqliteConnection};
+
+use crate::entities::rating::{NewRating, Rating};
+use crate::types::BookId;
+use crate::CalibreError;
+
+/// Highest value Calibre's `ratings.rating` CHECK constraint accepts.
+pub(crate) const MAX_RATING: i32 = 10;
+
+/// Normalize a rating the way Calibre's rating adapter does: clamp into
+/// `0..=10`, with `0` meaning "no rating" (returned as `None`).
+pub(crate) fn normalize(value: i32) -> Option<i32> {
+    match value.clamp(0, MAX_RATING) {
+        0 => None,
+        clamped => Some(clamped),
+    }
+}
+
+fn find_by_value(conn: &mut SqliteConnection, value: i32) -> Result<Option<Rating>, CalibreError> {
+    use crate::schema::ratings::dsl::*;
+
+    ratings
+        .filter(rating.eq(value))
+        .select(Rating::as_select())
+        .first(conn)
+        .optional()
+        .map_err(CalibreError::from)
+}
+
+fn create(conn: &mut SqliteConnection, new_rating: NewRating) -> Result<Rating, CalibreError> {
+    use crate::schema::ratings::dsl::*;
+
+    diesel::insert_into(ratings)
+        .values(new_rating)
+        .returning(Rating::as_returning())
+        .get_result(conn)
+        .map_err(CalibreError::from)
+}
+
+/// Find the `ratings` row for `value`, creating it if absent.
+pub(crate) fn create_if_not_exists(
+    conn: &mut SqliteConnection,
+    value: i32,
+) -> Result<Rating, CalibreError> {
+    match find_by_value(conn, value)? {
+        Some(existing) => Ok(existing),
+        None => create(conn, NewRating { rating: value }),
+    }
+}
+
+pub(crate) fn unlink_book(
+    conn: &mut SqliteConnection,
+    book_id: BookId,
+) -> Result<(), CalibreError> {
+    use crate::schema::books_ratings_link::dsl::*;
+
+    diesel::delete(books_ratings_link.filter(book.eq(book_id.as_i32())))
+        .execute(conn)
+        .map_err(CalibreError::from)?;
+
+    Ok(())
+}
+
+/// Makes `rating_id` the book's only rating, replacing any existing link.
+pub(crate) fn set_book_rating(
+    conn: &mut SqliteConnection,
+    rating_id: i32,
+    book_id: BookId,
+) -> Result<(), CalibreError> {
+    use crate::schema::books_ratings_link::dsl::*;
+
+    unlink_book(conn, book_id)?;
+    diesel::insert_into(books_ratings_link)
+        .values((rating.eq(rating_id), book.eq(book_id.as_i32())))
+        .execute(conn)
+        .map_err(CalibreError::from)?;
+
+    Ok(())
+}
+
+/// Delete every rating value no book links to any more, matching Calibre's
+/// post-write cleanup of unused many-to-one items.
+pub(crate) fn delete_unused(conn: &mut SqliteConnection) -> Result<(), CalibreError> {
+    sql_query(
+        "DELETE FROM ratings
+         WHERE id NOT IN (SELECT rating FROM books_ratings_link)",
+    )
+    .execute(conn)
+    .map(|_| ())
+    .map_err(CalibreError::from)
+}
+
+/// The book's rating on Calibre's 0–10 scale, or `None` when unrated. A
+/// stored `0` is Calibre's "no rating" and also yields `None`.
+pub(crate) fn find_rating_for_book(
+    conn: &mut SqliteConnection,
+    book_id: BookId,
+) -> Result<Option<i32>, CalibreError> {
+    find_ratings_by_book_ids(conn, vec![book_id]).map(|mut found| found.remove(&book_id))
+}
+
+/// Ratings for many books at once. Unrated books are absent from the map.
+pub(crate) fn find_ratings_by_book_ids(
+    conn: &mut SqliteConnection,
+    book_ids: Vec<BookId>,
+) -> Result<HashMap<BookId, i32>, CalibreError> {
+    if book_ids.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    #[derive(QueryableByName)]
+    struct BookRating {
+        #[diesel(sql_type = Integer)]
+        book_id: i32,
+        #[diesel(sql_type = Integer)]
+        rating: i32,
+    }
+
+    let ids = book_ids
+        .iter()
+        .map(|book_id| book_id.as_i32().to_string())
+        .collect::<Vec<_>>()
+        .join(",");
+
+    let rows: Vec<BookRating> = sql_query(format!(
+        "SELECT brl.book AS book_id, r.rating AS rating
+         FROM books_ratings_link brl
+         INNER JOIN ratings r ON r.id = brl.rating
+         WHERE brl.book IN ({ids}) AND r.rating > 0"
+    ))
+    .load(conn)
+    .map_err(CalibreError::from)?;
+
+    Ok(rows
+        .into_iter()
+        .filter_map(|row| normalize(row.rating).map(|rating| (BookId(row.book_id), rating)))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_treats_zero_as_no_rating() {
+        assert_eq!(normalize(0), None);
+        assert_eq!(normalize(-3), None);
+    }
+
+    #[test]
+    fn normalize_clamps_to_calibre_scale() {
+        assert_eq!(normalize(1), Some(1));
+        assert_eq!(normalize(8), Some(8));
+        assert_eq!(normalize(10), Some(10));
+        assert_eq!(normalize(42), Some(10));
+    }
+}
@@ -43,6 +43,20 @@ pub(crate) fn list_with_book_counts(
         .collect())
 }
 
+pub(crate) fn get(
+    conn: &mut SqliteConnection,
+    series_id: i32,
+) -> Result<Option<Series>, CalibreError> {
+    use crate::schema::series::dsl::*;
+
+    series
+        .filter(id.eq(series_id))
+        .select(Series::as_select())
+        .first(conn)
+        .optional()
+        .map_err(CalibreError::from)
+}
+
 pub(crate) fn find_by_name_case_insensitive(
     conn: &mut SqliteConnection,
     series_name: &str,
@@ -172,3 +186,80 @@ pub(crate) fn find_series_names_by_book_ids(
         .map(|row| (BookId(row.book_id), row.series_name))
         .collect())
 }
+
+/// The books in a series with their `series_index`, in reading order (index,
+/// then id).
+pub(crate) fn find_books_with_index(
+    conn: &mut SqliteConnection,
+    series_id: i32,
+) -> Result<Vec<(BookId, f32)>, CalibreError> {
+    use crate::schema::{books, books_series_link};
+
+    books::table
+        .inner_join(books_series_link::table.on(books_series_link::book.eq(books::id)))
+        .filter(books_series_link::series.eq(series_id))
+        .select((books::id, books::series_index))
+        .order((books::series_index.asc(), books::id.asc()))
+        .load::<(i32, f32)>(conn)
+        .map(|rows| {
+            rows.into_iter()
+                .map(|(book_id, index)| (BookId(book_id), index))
+                .collect()
+        })
+        .map_err(CalibreError::from)
+}
+
+pub(crate) fn rename(
+    conn: &mut SqliteConnection,
+    series_id: i32,
+    new_name: &str,
+) -> Result<(), CalibreError> {
+    use crate::schema::series::dsl::*;
+
+    diesel::update(series.filter(id.eq(series_id)))
+        .set(name.eq(new_name))
+        .execute(conn)
+        .map(|_| ())
+        .map_err(CalibreError::from)
+}
+
+/// Relink every book of `from_series` to `into_series`. A book is in at most
+/// one series, so no link can collide.
+pub(crate) fn move_links(
+    conn: &mut SqliteConnection,
+    from_series: i32,
+    into_series: i32,
+) -> Result<(), CalibreError> {
+    use crate::schema::books_series_link::dsl::*;
+
+    diesel::update(books_series_link.filter(series.eq(from_series)))
+        .set(series.eq(into_series))
+        .execute(conn)
+        .map(|_| ())
+        .map_err(CalibreError::from)
+}
+
+/// Delete the series itself. Calibre's foreign key trigger refuses while any
+/// book is still in it.
+pub(crate) fn delete(conn: &mut SqliteConnection, series_id: i32) -> Result<(), CalibreError> {
+    use crate::schema::series::dsl::*;
+
+    diesel::delete(series.filter(id.eq(series_id)))
+        .execute(conn)
+        .map(|_| ())
+        .map_err(CalibreError::from)
+}
+
+pub(crate) fn set_book_index(
+    conn: &mut SqliteConnection,
+    book_id: BookId,
+    index: f32,
+) -> Result<(), CalibreError> {
+    use crate::schema::books::dsl::*;
+
+    diesel::update(books.filter(id.eq(book_id.as_i32())))
+        .set(series_index.eq(index))
+        .execute(conn)
+        .map(|_| ())
+        .map_err(CalibreError::from)
+}
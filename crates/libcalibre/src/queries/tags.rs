@@ -29,6 +29,16 @@ pub(crate) fn list_all(conn: &mut SqliteConnection) -> Result<Vec<TagSummary>, C
         .map_err(CalibreError::from)
 }
 
+pub(crate) fn get(conn: &mut SqliteConnection, tag_id: i32) -> Result<Option<Tag>, CalibreError> {
+    use crate::schema::tags::dsl::*;
+
+    tags.filter(id.eq(tag_id))
+        .select(Tag::as_select())
+        .first(conn)
+        .optional()
+        .map_err(CalibreError::from)
+}
+
 pub(crate) fn find_by_name_case_insensitive(
     conn: &mut SqliteConnection,
     tag_name: &str,
@@ -155,3 +165,79 @@ pub(crate) fn find_tag_names_by_book_ids(
 
     Ok(grouped)
 }
+
+/// The books linked to a tag, in id order.
+pub(crate) fn find_books(
+    conn: &mut SqliteConnection,
+    tag_id: i32,
+) -> Result<Vec<BookId>, CalibreError> {
+    use crate::schema::books_tags_link::dsl::*;
+
+    books_tags_link
+        .filter(tag.eq(tag_id))
+        .select(book)
+        .order(book.asc())
+        .load::<i32>(conn)
+        .map(|ids| ids.into_iter().map(BookId).collect())
+        .map_err(CalibreError::from)
+}
+
+pub(crate) fn rename(
+    conn: &mut SqliteConnection,
+    tag_id: i32,
+    new_name: &str,
+) -> Result<(), CalibreError> {
+    use crate::schema::tags::dsl::*;
+
+    diesel::update(tags.filter(id.eq(tag_id)))
+        .set(name.eq(new_name))
+        .execute(conn)
+        .map(|_| ())
+        .map_err(CalibreError::from)
+}
+
+/// Relink every book of `from_tag` to `into_tag`. Books that already have
+/// both keep a single link.
+pub(crate) fn move_links(
+    conn: &mut SqliteConnection,
+    from_tag: i32,
+    into_tag: i32,
+) -> Result<(), CalibreError> {
+    sql_query("UPDATE OR IGNORE books_tags_link SET tag = ? WHERE tag = ?")
+        .bind::<Integer, _>(into_tag)
+        .bind::<Integer, _>(from_tag)
+        .execute(conn)
+        .map_err(CalibreError::from)?;
+    unlink_all(conn, from_tag)
+}
+
+pub(crate) fn unlink_all(conn: &mut SqliteConnection, tag_id: i32) -> Result<(), CalibreError> {
+    use crate::schema::books_tags_link::dsl::*;
+
+    diesel::delete(books_tags_link.filter(tag.eq(tag_id)))
+        .execute(conn)
+        .map(|_| ())
+        .map_err(CalibreError::from)
+}
+
+/// Delete the tag itself. Calibre's foreign key trigger refuses while any
+/// book still links to it, so unlink first.
+pub(crate) fn delete(conn: &mut SqliteConnection, tag_id: i32) -> Result<(), CalibreError> {
+    use crate::schema::tags::dsl::*;
+
+    diesel::delete(tags.filter(id.eq(tag_id)))
+        .execute(conn)
+        .map(|_| ())
+        .map_err(CalibreError::from)
+}
+
+/// Tags no book links to, sorted like [`list_all`].
+pub(crate) fn unused(conn: &mut SqliteConnection) -> Result<Vec<Tag>, CalibreError> {
+    sql_query(
+        "SELECT id, name FROM tags
+         WHERE id NOT IN (SELECT tag FROM books_tags_link)
+         ORDER BY LOWER(name), name",
+    )
+    .load(conn)
+    .map_err(CalibreError::from)
+}
@@ -0,0 +1,311 @@
+//! Detecting and upgrading the schema version of a library's database.
+//!
+//! Calibre numbers its `metadata.db` schema in `PRAGMA user_version` and, on
+//! open, runs `upgrade_version_<n>` from `db/schema_upgrades.py` for each
+//! version below its own, bumping `user_version` after each step, all in one
+//! exclusive transaction. This does the same for the steps that are plain
+//! SQL — everything from version 20 on; older libraries need a Calibre to
+//! upgrade them first. Unlike Calibre, opening a library only detects that
+//! it needs upgrading: the upgrade waits for the user to agree to it.
+//! Databases from a newer Calibre are opened as they are and reported, since
+//! their extra tables and columns are invisible to us.
+
+use std::fs;
+use std::path::Path;
+
+use diesel::connection::SimpleConnection;
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::{Integer, Text};
+
+use crate::new_library::CALIBRE_SCHEMA_VERSION;
+use crate::persistence::register_triggers;
+use crate::CalibreError;
+
+/// The oldest schema version this crate can upgrade. Calibre's step from 19
+/// to 20 rewrites pickled conversion options and only Calibre can run it.
+pub const MIN_UPGRADABLE_SCHEMA_VERSION: i32 = 20;
+
+/// The schema version of a library's database, and whether it needs or has
+/// had an upgrade.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SchemaStatus {
+    /// The database is at the version this crate was written against.
+    Current { version: i32 },
+    /// An older Calibre created the database. Nothing has been written to
+    /// it; [`crate::Library::upgrade_schema`] brings it up to date.
+    NeedsUpgrade { from: i32, to: i32 },
+    /// The database was upgraded by [`crate::Library::upgrade_schema`].
+    Upgraded { from: i32, to: i32 },
+    /// The database was written by a newer Calibre. It is left untouched;
+    /// Calibre's own upgrades have so far only added tables and columns, so
+    /// reading and editing the parts this crate knows about stays safe.
+    Newer { version: i32, supported: i32 },
+}
+
+#[derive(QueryableByName)]
+struct UserVersionRow {
+    #[diesel(sql_type = Integer)]
+    user_version: i32,
+}
+
+/// The database's `PRAGMA user_version`.
+pub(crate) fn schema_version(conn: &mut SqliteConnection) -> Result<i32, CalibreError> {
+    sql_query("PRAGMA user_version")
+        .get_result::<UserVersionRow>(conn)
+        .map(|row| row.user_version)
+        .map_err(CalibreError::from)
+}
+
+/// Compare the database's version with [`CALIBRE_SCHEMA_VERSION`] without
+/// changing anything. Fails for a database too old for this crate to upgrade.
+pub(crate) fn detect_schema(conn: &mut SqliteConnection) -> Result<SchemaStatus, CalibreError> {
+    let from = schema_version(conn)?;
+    if from == CALIBRE_SCHEMA_VERSION {
+        return Ok(SchemaStatus::Current { version: from });
+    }
+    if from > CALIBRE_SCHEMA_VERSION {
+        eprintln!(
+            "WARNING: Library schema version {} is newer than the supported {}",
+            from, CALIBRE_SCHEMA_VERSION
+        );
+        return Ok(SchemaStatus::Newer {
+            version: from,
+            supported: CALIBRE_SCHEMA_VERSION,
+        });
+    }
+    if from < MIN_UPGRADABLE_SCHEMA_VERSION {
+        return Err(CalibreError::UnsupportedSchemaVersion(from));
+    }
+    Ok(SchemaStatus::NeedsUpgrade {
+        from,
+        to: CALIBRE_SCHEMA_VERSION,
+    })
+}
+
+/// Bring the database up to [`CALIBRE_SCHEMA_VERSION`] if it is older, and
+/// report what was done. Every step runs in one exclusive transaction, so a
+/// failed upgrade leaves the database at its original version.
+pub(crate) fn upgrade_schema(conn: &mut SqliteConnection) -> Result<SchemaStatus, CalibreError> {
+    let (from, to) = match detect_schema(conn)? {
+        SchemaStatus::NeedsUpgrade { from, to } => (from, to),
+        status => return Ok(status),
+    };
+
+    conn.exclusive_transaction::<(), CalibreError, _>(|conn| {
+        for version in from..to {
+            upgrade_from(conn, version)?;
+            conn.batch_execute(&format!("PRAGMA user_version = {}", version + 1))?;
+        }
+        // Calibre recreates `books_delete_trg` whenever a step adds a table
+        // holding per-book rows; ours already names every such table.
+        register_triggers(conn)?;
+        Ok(())
+    })?;
+
+    Ok(SchemaStatus::Upgraded { from, to })
+}
+
+/// Write a consistent copy of the database to `to`, replacing any file
+/// already there.
+pub(crate) fn backup_database(conn: &mut SqliteConnection, to: &Path) -> Result<(), CalibreError> {
+    if to.exists() {
+        fs::remove_file(to)?;
+    }
+    let to = to
+        .to_str()
+        .ok_or_else(|| CalibreError::FileSystem("Backup path is not valid UTF-8".to_string()))?;
+    sql_query("VACUUM INTO ?")
+        .bind::<Text, _>(to)
+        .execute(conn)
+        .map(|_| ())
+        .map_err(CalibreError::from)
+}
+
+/// Calibre's `upgrade_version_<version>`.
+fn upgrade_from(conn: &mut SqliteConnection, version: i32) -> Result<(), CalibreError> {
+    match version {
+        20 => conn.batch_execute(UPGRADE_VERSION_20)?,
+        21 => conn.batch_execute(UPGRADE_VERSION_21)?,
+        22 => conn.batch_execute(UPGRADE_VERSION_22)?,
+        23 => conn.batch_execute(UPGRADE_VERSION_23)?,
+        24 => conn.batch_execute(UPGRADE_VERSION_24)?,
+        25 => upgrade_version_25(conn)?,
+        _ => {
+            return Err(CalibreError::UnsupportedSchemaVersion(version));
+        }
+    }
+    Ok(())
+}
+
+/// Add a link column to the authors table.
+const UPGRADE_VERSION_20: &str = r#"
+ALTER TABLE authors ADD COLUMN link TEXT NOT NULL DEFAULT "";
+"#;
+
+/// Write the series sort into the existing sort column in the series table.
+const UPGRADE_VERSION_21: &str = r#"
+DROP TRIGGER IF EXISTS series_insert_trg;
+DROP TRIGGER IF EXISTS series_update_trg;
+
+UPDATE series SET sort=title_sort(name);
+
+CREATE TRIGGER series_insert_trg
+        AFTER INSERT ON series
+        BEGIN
+          UPDATE series SET sort=title_sort(NEW.name) WHERE id=NEW.id;
+        END;
+
+CREATE TRIGGER series_update_trg
+        AFTER UPDATE ON series
+        BEGIN
+          UPDATE series SET sort=title_sort(NEW.name) WHERE id=NEW.id;
+        END;
+"#;
+
+/// Create the last_read_positions table.
+const UPGRADE_VERSION_22: &str = r#"
+DROP TABLE IF EXISTS last_read_positions;
+CREATE TABLE last_read_positions ( id INTEGER PRIMARY KEY,
+	book INTEGER NOT NULL,
+	format TEXT NOT NULL COLLATE NOCASE,
+	user TEXT NOT NULL,
+	device TEXT NOT NULL,
+	cfi TEXT NOT NULL,
+	epoch REAL NOT NULL,
+	pos_frac REAL NOT NULL DEFAULT 0,
+	UNIQUE(user, device, book, format)
+);
+DROP INDEX IF EXISTS lrp_idx;
+CREATE INDEX lrp_idx ON last_read_positions (book);
+
+DROP TRIGGER IF EXISTS fkc_lrp_insert;
+DROP TRIGGER IF EXISTS fkc_lrp_update;
+CREATE TRIGGER fkc_lrp_insert
+        BEFORE INSERT ON last_read_positions
+        BEGIN
+            SELECT CASE
+                WHEN (SELECT id from books WHERE id=NEW.book) IS NULL
+                THEN RAISE(ABORT, 'Foreign key violation: book not in books')
+            END;
+        END;
+
+CREATE TRIGGER fkc_lrp_update
+        BEFORE UPDATE OF book ON last_read_positions
+        BEGIN
+            SELECT CASE
+                WHEN (SELECT id from books WHERE id=NEW.book) IS NULL
+                THEN RAISE(ABORT, 'Foreign key violation: book not in books')
+            END;
+        END;
+"#;
+
+/// Create the annotations table and its full-text indexes.
+const UPGRADE_VERSION_23: &str = r#"
+DROP TABLE IF EXISTS annotations_dirtied;
+CREATE TABLE annotations_dirtied(id INTEGER PRIMARY KEY,
+                             book INTEGER NOT NULL,
+                             UNIQUE(book));
+
+DROP TABLE IF EXISTS annotations;
+CREATE TABLE annotations ( id INTEGER PRIMARY KEY,
+	book INTEGER NOT NULL,
+	format TEXT NOT NULL COLLATE NOCASE,
+	user_type TEXT NOT NULL,
+	user TEXT NOT NULL,
+	timestamp REAL NOT NULL,
+	annot_id TEXT NOT NULL,
+	annot_type TEXT NOT NULL,
+	annot_data TEXT NOT NULL,
+    searchable_text TEXT NOT NULL DEFAULT '',
+    UNIQUE(book, user_type, user, format, annot_type, annot_id)
+);
+DROP INDEX IF EXISTS annot_idx;
+CREATE INDEX annot_idx ON annotations (book);
+
+DROP TABLE IF EXISTS annotations_fts;
+DROP TABLE IF EXISTS annotations_fts_stemmed;
+CREATE VIRTUAL TABLE annotations_fts USING fts5(searchable_text, content = 'annotations', content_rowid = 'id', tokenize = 'unicode61 remove_diacritics 2');
+CREATE VIRTUAL TABLE annotations_fts_stemmed USING fts5(searchable_text, content = 'annotations', content_rowid = 'id', tokenize = 'porter unicode61 remove_diacritics 2');
+
+DROP TRIGGER IF EXISTS annotations_fts_insert_trg;
+CREATE TRIGGER annotations_fts_insert_trg AFTER INSERT ON annotations
+BEGIN
+    INSERT INTO annotations_fts(rowid, searchable_text) VALUES (NEW.id, NEW.searchable_text);
+    INSERT INTO annotations_fts_stemmed(rowid, searchable_text) VALUES (NEW.id, NEW.searchable_text);
+END;
+
+DROP TRIGGER IF EXISTS annotations_fts_delete_trg;
+CREATE TRIGGER annotations_fts_delete_trg AFTER DELETE ON annotations
+BEGIN
+    INSERT INTO annotations_fts(annotations_fts, rowid, searchable_text) VALUES('delete', OLD.id, OLD.searchable_text);
+    INSERT INTO annotations_fts_stemmed(annotations_fts_stemmed, rowid, searchable_text) VALUES('delete', OLD.id, OLD.searchable_text);
+END;
+
+DROP TRIGGER IF EXISTS annotations_fts_update_trg;
+CREATE TRIGGER annotations_fts_update_trg AFTER UPDATE ON annotations
+BEGIN
+    INSERT INTO annotations_fts(annotations_fts, rowid, searchable_text) VALUES('delete', OLD.id, OLD.searchable_text);
+    INSERT INTO annotations_fts(rowid, searchable_text) VALUES (NEW.id, NEW.searchable_text);
+    INSERT INTO annotations_fts_stemmed(annotations_fts_stemmed, rowid, searchable_text) VALUES('delete', OLD.id, OLD.searchable_text);
+    INSERT INTO annotations_fts_stemmed(rowid, searchable_text) VALUES (NEW.id, NEW.searchable_text);
+END;
+
+DROP TRIGGER IF EXISTS fkc_annot_insert;
+DROP TRIGGER IF EXISTS fkc_annot_update;
+CREATE TRIGGER fkc_annot_insert
+        BEFORE INSERT ON annotations
+        BEGIN
+            SELECT CASE
+                WHEN (SELECT id from books WHERE id=NEW.book) IS NULL
+                THEN RAISE(ABORT, 'Foreign key violation: book not in books')
+            END;
+        END;
+
+CREATE TRIGGER fkc_annot_update
+        BEFORE UPDATE OF book ON annotations
+        BEGIN
+            SELECT CASE
+                WHEN (SELECT id from books WHERE id=NEW.book) IS NULL
+                THEN RAISE(ABORT, 'Foreign key violation: book not in books')
+            END;
+        END;
+"#;
+
+/// Rebuild the annotations full-text indexes (Calibre's
+/// `reindex_annotations`).
+const UPGRADE_VERSION_24: &str = r#"
+INSERT INTO annotations_fts(annotations_fts) VALUES('rebuild');
+INSERT INTO annotations_fts_stemmed(annotations_fts_stemmed) VALUES('rebuild');
+"#;
+
+#[derive(QueryableByName)]
+struct ColumnIdRow {
+    #[diesel(sql_type = Integer)]
+    id: i32,
+}
+
+/// Add a link column to the remaining item tables, including those of
+/// normalized custom columns.
+fn upgrade_version_25(conn: &mut SqliteConnection) -> Result<(), CalibreError> {
+    let normalized_columns = sql_query("SELECT id FROM custom_columns WHERE normalized")
+        .load::<ColumnIdRow>(conn)
+        .map_err(CalibreError::from)?;
+
+    let mut alters: Vec<String> = normalized_columns
+        .iter()
+        .map(|column| {
+            format!(
+                r#"ALTER TABLE custom_column_{} ADD COLUMN link TEXT NOT NULL DEFAULT "";"#,
+                column.id
+            )
+        })
+        .collect();
+    for table in ["publishers", "series", "tags", "ratings", "languages"] {
+        alters.push(format!(
+            r#"ALTER TABLE {table} ADD COLUMN link TEXT NOT NULL DEFAULT "";"#
+        ));
+    }
+    conn.batch_execute(&alters.join("\n"))?;
+    Ok(())
+}
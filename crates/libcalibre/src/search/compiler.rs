@@ -0,0 +1,663 @@
+//! Compiling a [`SearchExpr`] to a SQL condition over `books`.
+//!
+//! Each term becomes a condition on the current `books` row — mostly an
+//! `EXISTS` over the field's link table — that is never NULL, so `NOT`
+//! inverts it exactly. Values are bound as parameters, never interpolated;
+//! only custom column numbers, which come from the database, are.
+//!
+//! Text fields match case-insensitively: by substring by default, exactly
+//! with a `=` prefix, and by regular expression with `~`. Numeric and date
+//! fields take `=`, `!=`, `<`, `<=`, `>` and `>=`. Most fields also accept
+//! `true` / `false` for "has a value" / "has none". Where Calibre would
+//! fold accents or compare in local time, this compares as stored (UTC).
+
+use chrono::{Datelike, Duration, NaiveDate, Utc};
+use diesel::prelude::*;
+
+use super::parser::{parse, SearchExpr};
+use crate::custom_columns::{self, CustomColumn, CustomColumnKind};
+use crate::queries::books::{escape_like, like_pattern};
+use crate::queries::preferences;
+use crate::CalibreError;
+
+/// The preference holding Calibre's saved searches: name to query.
+pub(crate) const SAVED_SEARCHES_PREF: &str = "saved_searches";
+/// The preference holding Calibre's virtual libraries: name to query.
+pub(crate) const VIRTUAL_LIBRARIES_PREF: &str = "virtual_libraries";
+
+/// A value bound to one `?` placeholder of a compiled search.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum SqlBind {
+    Text(String),
+    Double(f64),
+}
+
+/// A search compiled to a condition over `books`, with its bind values in
+/// placeholder order.
+#[derive(Clone, Debug)]
+pub(crate) struct CompiledSearch {
+    pub sql: String,
+    pub binds: Vec<SqlBind>,
+}
+
+impl CompiledSearch {
+    /// Both searches, with the binds of `self` first.
+    pub(crate) fn and(mut self, other: CompiledSearch) -> CompiledSearch {
+        self.binds.extend(other.binds);
+        CompiledSearch {
+            sql: format!("({} AND {})", self.sql, other.sql),
+            binds: self.binds,
+        }
+    }
+}
+
+/// Parse `query` and compile it against the library's custom columns and
+/// preferences.
+pub(crate) fn compile(
+    conn: &mut SqliteConnection,
+    query: &str,
+) -> Result<CompiledSearch, CalibreError> {
+    compile_expr(conn, &parse(query)?)
+}
+
+/// Compile the search of the virtual library `name`, as `vl:name` would.
+pub(crate) fn compile_virtual_library(
+    conn: &mut SqliteConnection,
+    name: &str,
+) -> Result<CompiledSearch, CalibreError> {
+    compile_expr(
+        conn,
+        &SearchExpr::Term {
+            location: Some("vl".to_string()),
+            value: name.to_string(),
+        },
+    )
+}
+
+fn compile_expr(
+    conn: &mut SqliteConnection,
+    expr: &SearchExpr,
+) -> Result<CompiledSearch, CalibreError> {
+    let mut compiler = Compiler {
+        columns: custom_columns::list(conn)?,
+        bools_are_tristate: bools_are_tristate(conn)?,
+        saved_searches: preferences::get_object(conn, SAVED_SEARCHES_PREF)?,
+        virtual_libraries: preferences::get_object(conn, VIRTUAL_LIBRARIES_PREF)?,
+        expanding: Vec::new(),
+        today: Utc::now().date_naive(),
+        binds: Vec::new(),
+    };
+    let sql = compiler.expr(expr)?;
+    Ok(CompiledSearch {
+        sql,
+        binds: compiler.binds,
+    })
+}
+
+/// Calibre's `bools_are_tristate` preference (default on): whether an unset
+/// yes/no column is distinct from "no".
+fn bools_are_tristate(conn: &mut SqliteConnection) -> Result<bool, CalibreError> {
+    Ok(preferences::get(conn, "bools_are_tristate")? != Some(serde_json::Value::Bool(false)))
+}
+
+/// Where a field's values live: a subquery body yielding one row per value
+/// of the current book, and the value expression within it.
+struct ValueSource {
+    from: String,
+    value: &'static str,
+}
+
+impl ValueSource {
+    fn linked(link_table: &str, link_column: &str, table: &str, value: &'static str) -> Self {
+        Self {
+            from: format!(
+                "{link_table} l JOIN {table} v ON v.id = l.{link_column} WHERE l.book = books.id"
+            ),
+            value,
+        }
+    }
+
+    fn per_book(table: &str, value: &'static str) -> Self {
+        Self {
+            from: format!("{table} v WHERE v.book = books.id"),
+            value,
+        }
+    }
+
+    fn exists(&self, condition: &str) -> String {
+        format!("EXISTS (SELECT 1 FROM {} AND {condition})", self.from)
+    }
+
+    fn has_any(&self) -> String {
+        format!("EXISTS (SELECT 1 FROM {})", self.from)
+    }
+}
+
+fn authors_source() -> ValueSource {
+    ValueSource::linked("books_authors_link", "author", "authors", "v.name")
+}
+
+fn tags_source() -> ValueSource {
+    ValueSource::linked("books_tags_link", "tag", "tags", "v.name")
+}
+
+fn series_source() -> ValueSource {
+    ValueSource::linked("books_series_link", "series", "series", "v.name")
+}
+
+fn publisher_source() -> ValueSource {
+    ValueSource::linked("books_publishers_link", "publisher", "publishers", "v.name")
+}
+
+fn comments_source() -> ValueSource {
+    ValueSource::per_book("comments", "v.text")
+}
+
+enum TextMatch {
+    Contains(String),
+    Exact(String),
+    Regex(String),
+}
+
+impl TextMatch {
+    fn parse(value: &str) -> Result<Self, CalibreError> {
+        if let Some(exact) = value.strip_prefix('=') {
+            Ok(Self::Exact(exact.to_string()))
+        } else if let Some(pattern) = value.strip_prefix('~') {
+            // Calibre's regular expressions are case-insensitive.
+            let pattern = format!("(?i){pattern}");
+            regex::Regex::new(&pattern)
+                .map_err(|e| CalibreError::InvalidSearch(format!("bad regular expression: {e}")))?;
+            Ok(Self::Regex(pattern))
+        } else {
+            // A leading backslash escapes a literal `=` or `~`.
+            Ok(Self::Contains(
+                value.strip_prefix('\\').unwrap_or(value).to_string(),
+            ))
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Relop {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Relop {
+    /// Split a comparison operator off the front of `value`; none means `=`.
+    fn split(value: &str) -> (Self, &str) {
+        for (prefix, op) in [
+            (">=", Self::Ge),
+            ("<=", Self::Le),
+            ("!=", Self::Ne),
+            ("=", Self::Eq),
+            (">", Self::Gt),
+            ("<", Self::Lt),
+        ] {
+            if let Some(rest) = value.strip_prefix(prefix) {
+                return (op, rest.trim_start());
+            }
+        }
+        (Self::Eq, value)
+    }
+
+    fn sql(self) -> &'static str {
+        match self {
+            Self::Eq => "=",
+            Self::Ne => "!=",
+            Self::Lt => "<",
+            Self::Le => "<=",
+            Self::Gt => ">",
+            Self::Ge => ">=",
+        }
+    }
+}
+
+/// `true` / `false` as Calibre accepts them for "has a value" / "has none".
+fn presence(value: &str) -> Option<bool> {
+    match value.to_lowercase().as_str() {
+        "true" => Some(true),
+        "false" => Some(false),
+        _ => None,
+    }
+}
+
+/// Calibre's date precision: a query names a year, a month or a day.
+fn date_range(value: &str, today: NaiveDate) -> Option<(NaiveDate, NaiveDate)> {
+    let value = value.to_lowercase();
+    let day = |date: NaiveDate| Some((date, date + Duration::days(1)));
+    match value.as_str() {
+        "today" => return day(today),
+        "yesterday" => return day(today - Duration::days(1)),
+        "thismonth" => {
+            let start = today.with_day(1)?;
+            return Some((start, start.checked_add_months(chrono::Months::new(1))?));
+        }
+        _ => {}
+    }
+    if let Some(days) = value.strip_suffix("daysago") {
+        return day(today - Duration::days(days.parse().ok()?));
+    }
+
+    let parts: Vec<&str> = value.split(['-', '/']).collect();
+    let year: i32 = parts.first()?.parse().ok()?;
+    match parts[1..] {
+        [] => Some((
+            NaiveDate::from_ymd_opt(year, 1, 1)?,
+            NaiveDate::from_ymd_opt(year + 1, 1, 1)?,
+        )),
+        [month] => {
+            let start = NaiveDate::from_ymd_opt(year, month.parse().ok()?, 1)?;
+            Some((start, start.checked_add_months(chrono::Months::new(1))?))
+        }
+        [month, date] => day(NaiveDate::from_ymd_opt(
+            year,
+            month.parse().ok()?,
+            date.parse().ok()?,
+        )?),
+        _ => None,
+    }
+}
+
+struct Compiler {
+    columns: Vec<CustomColumn>,
+    bools_are_tristate: bool,
+    saved_searches: serde_json::Map<String, serde_json::Value>,
+    virtual_libraries: serde_json::Map<String, serde_json::Value>,
+    /// The saved searches and virtual libraries being compiled, innermost
+    /// last, to reject ones that refer to themselves.
+    expanding: Vec<(&'static str, String)>,
+    today: NaiveDate,
+    binds: Vec<SqlBind>,
+}
+
+impl Compiler {
+    fn expr(&mut self, expr: &SearchExpr) -> Result<String, CalibreError> {
+        Ok(match expr {
+            SearchExpr::And(left, right) => {
+                format!("({} AND {})", self.expr(left)?, self.expr(right)?)
+            }
+            SearchExpr::Or(left, right) => {
+                format!("({} OR {})", self.expr(left)?, self.expr(right)?)
+            }
+            SearchExpr::Not(inner) => format!("NOT {}", self.expr(inner)?),
+            SearchExpr::Term { location, value } => match location {
+                None => self.any_field(value)?,
+                Some(location) => self.term(location, value)?,
+            },
+        })
+    }
+
+    /// A bare term: a text match on any of the fields Calibre searches by
+    /// default.
+    fn any_field(&mut self, value: &str) -> Result<String, CalibreError> {
+        let text_match = TextMatch::parse(value)?;
+        let mut alternatives = vec![self.text_condition("books.title", &text_match)];
+        for source in [
+            authors_source(),
+            tags_source(),
+            series_source(),
+            publisher_source(),
+            comments_source(),
+        ] {
+            let condition = self.text_condition(source.value, &text_match);
+            alternatives.push(source.exists(&condition));
+        }
+        Ok(format!("({})", alternatives.join(" OR ")))
+    }
+
+    fn term(&mut self, location: &str, value: &str) -> Result<String, CalibreError> {
+        if let Some(label) = location.strip_prefix('#') {
+            return self.custom_column(label, value);
+        }
+        match location {
+            "title" => self.column_text("books.title", value),
+            "author_sort" => self.column_text("books.author_sort", value),
+            "uuid" => self.column_text("books.uuid", value),
+            "authors" => self.source_text(authors_source(), value),
+            "tags" => self.source_text(tags_source(), value),
+            "series" => self.source_text(series_source(), value),
+            "publisher" => self.source_text(publisher_source(), value),
+            "comments" => self.source_text(comments_source(), value),
+            "languages" => self.source_text(
+                ValueSource::linked(
+                    "books_languages_link",
+                    "lang_code",
+                    "languages",
+                    "v.lang_code",
+                ),
+                value,
+            ),
+            "formats" => self.source_text(ValueSource::per_book("data", "v.format"), value),
+            "identifiers" => self.identifiers(value),
+            "rating" => self.rating(
+                ValueSource::linked("books_ratings_link", "rating", "ratings", "v.rating"),
+                value,
+            ),
+            "series_index" => self.column_number("books.series_index", value, false),
+            "id" => self.column_number("books.id", value, false),
+            "size" => self.column_number(
+                "(SELECT MAX(d.uncompressed_size) FROM data d WHERE d.book = books.id)",
+                value,
+                true,
+            ),
+            "pubdate" => self.date_condition("books.pubdate", value),
+            "timestamp" => self.date_condition("books.timestamp", value),
+            "last_modified" => self.date_condition("books.last_modified", value),
+            "search" => self.named_search("saved search", value),
+            "vl" => self.named_search("virtual library", value),
+            "cover" => match presence(value) {
+                Some(true) => Ok("COALESCE(books.has_cover, 0) != 0".to_string()),
+                Some(false) => Ok("COALESCE(books.has_cover, 0) = 0".to_string()),
+                None => Err(CalibreError::InvalidSearch(format!(
+                    "cover: takes true or false, not '{value}'"
+                ))),
+            },
+            other => Err(CalibreError::InvalidSearch(format!(
+                "unknown location '{other}'"
+            ))),
+        }
+    }
+
+    // -------------------------------------------------------------------------
+    // Saved searches and virtual libraries
+    // -------------------------------------------------------------------------
+
+    /// `search:name` and `vl:name`: the named query, compiled in place. An
+    /// empty query (a virtual library of the whole library) matches every
+    /// book.
+    fn named_search(&mut self, kind: &'static str, name: &str) -> Result<String, CalibreError> {
+        let name = name.strip_prefix('=').unwrap_or(name);
+        let searches = match kind {
+            "saved search" => &self.saved_searches,
+            _ => &self.virtual_libraries,
+        };
+        let query = searches
+            .get(name)
+            .and_then(|query| query.as_str())
+            .ok_or_else(|| CalibreError::InvalidSearch(format!("no {kind} named '{name}'")))?
+            .to_string();
+        if query.trim().is_empty() {
+            return Ok("1".to_string());
+        }
+
+        let key = (kind, name.to_string());
+        if self.expanding.contains(&key) {
+            return Err(CalibreError::InvalidSearch(format!(
+                "{kind} '{name}' refers to itself"
+            )));
+        }
+        self.expanding.push(key);
+        let compiled = parse(&query).and_then(|expr| self.expr(&expr));
+        self.expanding.pop();
+        Ok(format!("({})", compiled?))
+    }
+
+    // -------------------------------------------------------------------------
+    // Text
+    // -------------------------------------------------------------------------
+
+    fn text_condition(&mut self, column: &str, text_match: &TextMatch) -> String {
+        match text_match {
+            TextMatch::Contains(text) => {
+                self.binds.push(SqlBind::Text(like_pattern(text)));
+                format!("{column} LIKE ? ESCAPE '\\'")
+            }
+            TextMatch::Exact(text) => {
+                self.binds.push(SqlBind::Text(text.clone()));
+                format!("{column} = ? COLLATE NOCASE")
+            }
+            TextMatch::Regex(pattern) => {
+                self.binds.push(SqlBind::Text(pattern.clone()));
+                format!("{column} REGEXP ?")
+            }
+        }
+    }
+
+    /// A text field stored on `books` itself.
+    fn column_text(&mut self, column: &str, value: &str) -> Result<String, CalibreError> {
+        let text_match = TextMatch::parse(value)?;
+        Ok(self.text_condition(column, &text_match))
+    }
+
+    /// A text field with zero or more values per book. `.name` matches the
+    /// hierarchical item `name` and everything below it (`name.child`).
+    fn source_text(&mut self, source: ValueSource, value: &str) -> Result<String, CalibreError> {
+        match presence(value) {
+            Some(true) => return Ok(source.has_any()),
+            Some(false) => return Ok(format!("NOT {}", source.has_any())),
+            None => {}
+        }
+        let hierarchy = value.strip_prefix('=').unwrap_or(value).strip_prefix('.');
+        if let Some(name) = hierarchy.filter(|name| !name.is_empty()) {
+            self.binds.push(SqlBind::Text(name.to_string()));
+            self.binds
+                .push(SqlBind::Text(format!("{}.%", escape_like(name))));
+            return Ok(source.exists(&format!(
+                "({v} = ? COLLATE NOCASE OR {v} LIKE ? ESCAPE '\\')",
+                v = source.value
+            )));
+        }
+        let text_match = TextMatch::parse(value)?;
+        let condition = self.text_condition(source.value, &text_match);
+        Ok(source.exists(&condition))
+    }
+
+    /// `identifiers:isbn:978...` matches by type and value,
+    /// `identifiers:isbn:` any ISBN, and a value without a colon either part.
+    fn identifiers(&mut self, value: &str) -> Result<String, CalibreError> {
+        let source = ValueSource::per_book("identifiers", "v.type || ':' || v.val");
+        match presence(value) {
+            Some(true) => return Ok(source.has_any()),
+            Some(false) => return Ok(format!("NOT {}", source.has_any())),
+            None => {}
+        }
+        let condition = match TextMatch::parse(value)? {
+            TextMatch::Exact(text) => match text.split_once(':') {
+                Some((kind, "")) => {
+                    self.binds.push(SqlBind::Text(kind.to_string()));
+                    "v.type = ? COLLATE NOCASE".to_string()
+                }
+                Some((kind, val)) => {
+                    self.binds.push(SqlBind::Text(kind.to_string()));
+                    self.binds.push(SqlBind::Text(val.to_string()));
+                    "(v.type = ? COLLATE NOCASE AND v.val = ? COLLATE NOCASE)".to_string()
+                }
+                None => {
+                    self.binds.push(SqlBind::Text(text.clone()));
+                    self.binds.push(SqlBind::Text(text));
+                    "(v.type = ? COLLATE NOCASE OR v.val = ? COLLATE NOCASE)".to_string()
+                }
+            },
+            text_match => self.text_condition(source.value, &text_match),
+        };
+        Ok(source.exists(&condition))
+    }
+
+    // -------------------------------------------------------------------------
+    // Numbers
+    // -------------------------------------------------------------------------
+
+    fn number(value: &str, size_units: bool) -> Result<(Relop, f64), CalibreError> {
+        let (op, number) = Relop::split(value);
+        let (number, multiplier) = match number.chars().last().map(|c| c.to_ascii_lowercase()) {
+            Some('k') if size_units => (&number[..number.len() - 1], 1024.0),
+            Some('m') if size_units => (&number[..number.len() - 1], 1024.0 * 1024.0),
+            Some('g') if size_units => (&number[..number.len() - 1], 1024.0 * 1024.0 * 1024.0),
+            _ => (number, 1.0),
+        };
+        let number: f64 = number
+            .parse()
+            .map_err(|_| CalibreError::InvalidSearch(format!("'{value}' is not a number")))?;
+        Ok((op, number * multiplier))
+    }
+
+    /// A number stored on `books` (or computed per book). `size` takes `k`,
+    /// `m` and `g` suffixes.
+    fn column_number(
+        &mut self,
+        column: &str,
+        value: &str,
+        size_units: bool,
+    ) -> Result<String, CalibreError> {
+        match presence(value) {
+            Some(true) => return Ok(format!("{column} IS NOT NULL")),
+            Some(false) => return Ok(format!("{column} IS NULL")),
+            None => {}
+        }
+        let (op, number) = Self::number(value, size_units)?;
+        self.binds.push(SqlBind::Double(number));
+        Ok(format!("COALESCE({column} {} ?, 0)", op.sql()))
+    }
+
+    fn source_number(&mut self, source: ValueSource, value: &str) -> Result<String, CalibreError> {
+        match presence(value) {
+            Some(true) => return Ok(source.has_any()),
+            Some(false) => return Ok(format!("NOT {}", source.has_any())),
+            None => {}
+        }
+        let (op, number) = Self::number(value, false)?;
+        self.binds.push(SqlBind::Double(number));
+        Ok(source.exists(&format!("{} {} ?", source.value, op.sql())))
+    }
+
+    /// Ratings are searched in stars (`rating:>=4`, `rating:3.5`) but stored
+    /// out of 10. A rating of 0 counts as none.
+    fn rating(&mut self, source: ValueSource, value: &str) -> Result<String, CalibreError> {
+        let rated = source.exists(&format!("{} > 0", source.value));
+        match presence(value) {
+            Some(true) => return Ok(rated),
+            Some(false) => return Ok(format!("NOT {rated}")),
+            None => {}
+        }
+        let (op, stars) = Self::number(value, false)?;
+        self.binds.push(SqlBind::Double(stars * 2.0));
+        Ok(source.exists(&format!("{v} > 0 AND {v} {} ?", op.sql(), v = source.value)))
+    }
+
+    // -------------------------------------------------------------------------
+    // Dates
+    // -------------------------------------------------------------------------
+
+    /// Compare a stored timestamp (`YYYY-MM-DD HH:MM:SS+00:00`) with a date
+    /// range at the query's precision. Calibre's undefined date (year 101)
+    /// counts as no date. The bound is a full date: timestamp columns have
+    /// NUMERIC affinity, which would turn a bare `'0102'` into a number that
+    /// every stored text sorts after.
+    fn date_condition(&mut self, column: &str, value: &str) -> Result<String, CalibreError> {
+        let defined = format!("({column} IS NOT NULL AND {column} >= '0102-01-01')");
+        match presence(value) {
+            Some(true) => return Ok(defined),
+            Some(false) => return Ok(format!("NOT {defined}")),
+            None => {}
+        }
+        let (op, date) = Relop::split(value);
+        let (start, end) = date_range(date, self.today)
+            .ok_or_else(|| CalibreError::InvalidSearch(format!("'{date}' is not a date")))?;
+        let start = start.format("%Y-%m-%d").to_string();
+        let end = end.format("%Y-%m-%d").to_string();
+        let comparison = match op {
+            Relop::Eq | Relop::Ne => {
+                self.binds.push(SqlBind::Text(start));
+                self.binds.push(SqlBind::Text(end));
+                let within = format!("{column} >= ? AND {column} < ?");
+                if matches!(op, Relop::Eq) {
+                    within
+                } else {
+                    format!("NOT ({within})")
+                }
+            }
+            Relop::Lt | Relop::Ge => {
+                self.binds.push(SqlBind::Text(start));
+                format!("{column} {} ?", op.sql())
+            }
+            // Up to the end of the named period, or after it.
+            Relop::Le => {
+                self.binds.push(SqlBind::Text(end));
+                format!("{column} < ?")
+            }
+            Relop::Gt => {
+                self.binds.push(SqlBind::Text(end));
+                format!("{column} >= ?")
+            }
+        };
+        Ok(format!("({defined} AND {comparison})"))
+    }
+
+    // -------------------------------------------------------------------------
+    // Custom columns
+    // -------------------------------------------------------------------------
+
+    fn custom_column(&mut self, label: &str, value: &str) -> Result<String, CalibreError> {
+        let column = self
+            .columns
+            .iter()
+            .find(|column| column.label.eq_ignore_ascii_case(label))
+            .cloned()
+            .ok_or_else(|| CalibreError::InvalidSearch(format!("unknown column #{label}")))?;
+        let n = column.id;
+        let normalized = ValueSource::linked(
+            &format!("books_custom_column_{n}_link"),
+            "value",
+            &format!("custom_column_{n}"),
+            "v.value",
+        );
+        let per_book = ValueSource::per_book(&format!("custom_column_{n}"), "v.value");
+
+        match column.kind {
+            CustomColumnKind::Text | CustomColumnKind::Enumeration | CustomColumnKind::Series => {
+                self.source_text(normalized, value)
+            }
+            CustomColumnKind::Comments => self.source_text(per_book, value),
+            CustomColumnKind::Int | CustomColumnKind::Float => self.source_number(per_book, value),
+            CustomColumnKind::Rating => self.rating(normalized, value),
+            CustomColumnKind::Datetime => match presence(value) {
+                Some(false) => Ok(format!(
+                    "NOT {}",
+                    per_book.exists("v.value >= '0102-01-01'")
+                )),
+                _ => {
+                    let condition = self.date_condition("v.value", value)?;
+                    Ok(per_book.exists(&condition))
+                }
+            },
+            CustomColumnKind::Bool => self.bool_column(per_book, value),
+            CustomColumnKind::Composite | CustomColumnKind::Other(_) => {
+                Err(CalibreError::InvalidSearch(format!(
+                    "cannot search #{} ({} column)",
+                    column.label,
+                    column.kind.datatype()
+                )))
+            }
+        }
+    }
+
+    /// Calibre's yes/no search. With `bools_are_tristate` (the default),
+    /// `false` and `empty` find books with no value and `no` those set to
+    /// no; otherwise an unset value counts as no.
+    fn bool_column(&mut self, source: ValueSource, value: &str) -> Result<String, CalibreError> {
+        let yes = source.exists("v.value != 0");
+        let no = source.exists("v.value = 0");
+        let set = source.exists("v.value IS NOT NULL");
+        let query = value.to_lowercase();
+        let query = query.strip_prefix('_').unwrap_or(&query);
+        Ok(match (query, self.bools_are_tristate) {
+            ("true" | "yes" | "checked", _) => yes,
+            ("no" | "unchecked", true) => no,
+            ("false" | "empty" | "blank", true) => format!("NOT {set}"),
+            ("false" | "no" | "unchecked", false) => format!("NOT {yes}"),
+            // Without tristate no book is ever empty.
+            ("empty" | "blank", false) => "0".to_string(),
+            _ => {
+                return Err(CalibreError::InvalidSearch(format!(
+                    "invalid yes/no query '{value}'"
+                )))
+            }
+        })
+    }
+}
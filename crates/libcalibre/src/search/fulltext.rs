@@ -0,0 +1,346 @@
+//! Searching the text of books, as extracted into Calibre's
+//! `full-text-search.db` and into Citadel's own search index.
+//!
+//! Calibre 6+ keeps a `books_text` table of extracted text next to
+//! `metadata.db`, indexed by FTS5 tables that use Calibre's own `calibre`
+//! tokenizer. That tokenizer only exists inside Calibre, so plain SQLite can
+//! neither query those FTS tables nor keep them in sync with new rows, and
+//! writing to `books_text` without updating them would corrupt them. So:
+//!
+//! - Searching reads `books_text` directly.
+//! - Text Citadel extracts goes into a `books_text` table of the same shape
+//!   in the search index sidecar, and the format is queued in Calibre's
+//!   `dirtied_formats` so Calibre indexes it itself the next time it runs.
+//!   Calibre's own row wins once it exists.
+
+use std::path::Path;
+
+use diesel::connection::SimpleConnection;
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::{BigInt, Integer, Text};
+
+use crate::queries::books::like_pattern;
+use crate::CalibreError;
+
+const CALIBRE_FTS_FILENAME: &str = "full-text-search.db";
+
+/// Characters of context kept on each side of the first match in a snippet.
+const SNIPPET_CONTEXT_CHARS: i64 = 120;
+
+/// Attach Calibre's full-text database, if the library has one. Returns
+/// whether it was attached.
+pub(crate) fn attach_calibre(
+    conn: &mut SqliteConnection,
+    library_root: &str,
+) -> Result<bool, CalibreError> {
+    let path = Path::new(library_root).join(CALIBRE_FTS_FILENAME);
+    if !path.is_file() {
+        return Ok(false);
+    }
+    sql_query("ATTACH DATABASE ? AS fts_db")
+        .bind::<Text, _>(path.to_string_lossy())
+        .execute(conn)
+        .map_err(CalibreError::from)?;
+
+    #[derive(QueryableByName)]
+    struct TableCount {
+        #[diesel(sql_type = BigInt)]
+        tables: i64,
+    }
+    let tables = sql_query(
+        "SELECT COUNT(*) AS tables FROM fts_db.sqlite_master \
+         WHERE type = 'table' AND name IN ('books_text', 'dirtied_formats')",
+    )
+    .get_result::<TableCount>(conn)
+    .map_err(CalibreError::from)?
+    .tables;
+    if tables != 2 {
+        conn.batch_execute("DETACH DATABASE fts_db;")?;
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+/// Create the search index's `books_text` table, the same shape as
+/// Calibre's. Kept across index rebuilds: extraction is expensive.
+pub(super) fn create_text_table(conn: &mut SqliteConnection) -> Result<(), CalibreError> {
+    conn.batch_execute(
+        "CREATE TABLE IF NOT EXISTS citadel_search.books_text (
+             id INTEGER PRIMARY KEY,
+             book INTEGER NOT NULL,
+             timestamp REAL NOT NULL,
+             format TEXT NOT NULL COLLATE NOCASE,
+             format_size INTEGER NOT NULL DEFAULT 0,
+             format_hash TEXT NOT NULL DEFAULT '',
+             searchable_text TEXT NOT NULL DEFAULT '',
+             text_size INTEGER NOT NULL DEFAULT 0,
+             text_hash TEXT NOT NULL DEFAULT '',
+             err_msg TEXT DEFAULT '',
+             UNIQUE(book, format)
+         );",
+    )?;
+    Ok(())
+}
+
+/// Where extracted text can be read from and written to.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct TextSources {
+    /// Calibre's `full-text-search.db` is attached as `fts_db`.
+    pub calibre: bool,
+    /// The search index sidecar is attached as `citadel_search`.
+    pub citadel: bool,
+}
+
+/// The hash of the file the stored text of a format was extracted from.
+pub(crate) fn stored_format_hash(
+    conn: &mut SqliteConnection,
+    book_id: i32,
+    format: &str,
+) -> Result<Option<String>, CalibreError> {
+    #[derive(QueryableByName)]
+    struct HashRow {
+        #[diesel(sql_type = Text)]
+        format_hash: String,
+    }
+    sql_query("SELECT format_hash FROM citadel_search.books_text WHERE book = ? AND format = ?")
+        .bind::<Integer, _>(book_id)
+        .bind::<Text, _>(format)
+        .get_result::<HashRow>(conn)
+        .optional()
+        .map(|row| row.map(|row| row.format_hash))
+        .map_err(CalibreError::from)
+}
+
+/// Text extracted from one format of a book, ready to store.
+pub(crate) struct ExtractedText<'a> {
+    pub book_id: i32,
+    pub format: &'a str,
+    pub format_size: i64,
+    pub format_hash: &'a str,
+    /// The text, or why it could not be extracted.
+    pub text: Result<String, String>,
+}
+
+/// Store extracted text in the search index and, unless Calibre already
+/// holds text for this exact file, queue the format for Calibre's indexer.
+pub(crate) fn store(
+    conn: &mut SqliteConnection,
+    sources: TextSources,
+    extracted: ExtractedText,
+) -> Result<(), CalibreError> {
+    let (text, err_msg) = match extracted.text {
+        Ok(text) => (text, String::new()),
+        Err(message) => (String::new(), message),
+    };
+    let text_hash = sha1_hex(text.as_bytes());
+    let timestamp = chrono::Utc::now().timestamp_millis() as f64 / 1000.0;
+
+    conn.transaction::<(), CalibreError, _>(|conn| {
+        sql_query(
+            "INSERT OR REPLACE INTO citadel_search.books_text \
+             (book, timestamp, format, format_size, format_hash, searchable_text, \
+              text_size, text_hash, err_msg) \
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind::<Integer, _>(extracted.book_id)
+        .bind::<diesel::sql_types::Double, _>(timestamp)
+        .bind::<Text, _>(extracted.format.to_uppercase())
+        .bind::<BigInt, _>(extracted.format_size)
+        .bind::<Text, _>(extracted.format_hash)
+        .bind::<Text, _>(&text)
+        .bind::<BigInt, _>(text.len() as i64)
+        .bind::<Text, _>(&text_hash)
+        .bind::<Text, _>(&err_msg)
+        .execute(conn)
+        .map_err(CalibreError::from)?;
+
+        if sources.calibre {
+            sql_query(
+                "INSERT OR IGNORE INTO fts_db.dirtied_formats (book, format) \
+                 SELECT ?1, ?2 WHERE NOT EXISTS (SELECT 1 FROM fts_db.books_text \
+                 WHERE book = ?1 AND format = ?2 AND format_hash = ?3)",
+            )
+            .bind::<Integer, _>(extracted.book_id)
+            .bind::<Text, _>(extracted.format.to_uppercase())
+            .bind::<Text, _>(extracted.format_hash)
+            .execute(conn)
+            .map_err(CalibreError::from)?;
+        }
+        Ok(())
+    })
+}
+
+pub(crate) fn sha1_hex(bytes: &[u8]) -> String {
+    use sha1::{Digest, Sha1};
+    Sha1::digest(bytes)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// Split a query into terms: words, or phrases in double quotes.
+pub(crate) fn query_terms(query: &str) -> Vec<String> {
+    query
+        .split('"')
+        .enumerate()
+        .flat_map(|(i, part)| {
+            if i % 2 == 1 {
+                vec![part.split_whitespace().collect::<Vec<_>>().join(" ")]
+            } else {
+                part.split_whitespace().map(str::to_string).collect()
+            }
+        })
+        .filter(|term| !term.is_empty())
+        .collect()
+}
+
+/// A book whose text contains every term, with the text around the first
+/// term's first occurrence.
+#[derive(QueryableByName)]
+pub(crate) struct TextHit {
+    #[diesel(sql_type = Integer)]
+    pub book: i32,
+    #[diesel(sql_type = Text)]
+    pub format: String,
+    #[diesel(sql_type = Text)]
+    pub excerpt: String,
+    /// Whether text precedes or follows the excerpt.
+    #[diesel(sql_type = Integer)]
+    pub truncated_start: i32,
+    #[diesel(sql_type = Integer)]
+    pub truncated_end: i32,
+}
+
+/// Books whose extracted text contains every term (case-insensitively, as
+/// `LIKE`), one format per book, by book id. Only formats the book still has
+/// are searched.
+pub(crate) fn find(
+    conn: &mut SqliteConnection,
+    sources: TextSources,
+    terms: &[String],
+    limit: i64,
+) -> Result<Vec<TextHit>, CalibreError> {
+    let mut texts: Vec<&str> = Vec::new();
+    if sources.calibre {
+        texts.push(
+            "SELECT book, format, searchable_text FROM fts_db.books_text \
+             WHERE searchable_text != ''",
+        );
+    }
+    if sources.citadel {
+        texts.push(if sources.calibre {
+            "SELECT c.book, c.format, c.searchable_text FROM citadel_search.books_text c \
+             WHERE c.searchable_text != '' AND NOT EXISTS (SELECT 1 FROM fts_db.books_text f \
+             WHERE f.book = c.book AND f.format = c.format AND f.searchable_text != '')"
+        } else {
+            "SELECT book, format, searchable_text FROM citadel_search.books_text \
+             WHERE searchable_text != ''"
+        });
+    }
+    if texts.is_empty() || terms.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let matches = vec!["t.searchable_text LIKE ? ESCAPE '\\'"; terms.len()].join(" AND ");
+    let sql = format!(
+        "WITH texts AS ({texts}),
+         hits AS (
+             SELECT t.book, t.format, t.searchable_text AS text,
+                 instr(lower(t.searchable_text), lower(?)) AS position,
+                 ROW_NUMBER() OVER (PARTITION BY t.book ORDER BY t.format) AS rank
+             FROM texts t
+             WHERE EXISTS (SELECT 1 FROM data d
+                           WHERE d.book = t.book AND d.format = t.format COLLATE NOCASE)
+               AND {matches}
+         )
+         SELECT book, format,
+             substr(text, max(1, position - {SNIPPET_CONTEXT_CHARS}),
+                    length(?) + 2 * {SNIPPET_CONTEXT_CHARS}) AS excerpt,
+             position - {SNIPPET_CONTEXT_CHARS} > 1 AS truncated_start,
+             position + length(?) + {SNIPPET_CONTEXT_CHARS} <= length(text) AS truncated_end
+         FROM hits WHERE rank = 1 ORDER BY book LIMIT ?",
+        texts = texts.join(" UNION ALL "),
+    );
+
+    let mut query = sql_query(sql).into_boxed().bind::<Text, _>(&terms[0]);
+    for term in terms {
+        query = query.bind::<Text, _>(like_pattern(term));
+    }
+    query
+        .bind::<Text, _>(&terms[0])
+        .bind::<Text, _>(&terms[0])
+        .bind::<BigInt, _>(limit)
+        .load::<TextHit>(conn)
+        .map_err(CalibreError::from)
+}
+
+/// Split `excerpt` into runs of plain and matched text, matching every term
+/// ASCII-case-insensitively, as `LIKE` does.
+pub(crate) fn highlight(excerpt: &str, terms: &[String]) -> Vec<(String, bool)> {
+    let folded = excerpt.to_ascii_lowercase();
+    let mut marked: Vec<(usize, usize)> = Vec::new();
+    for term in terms {
+        let term = term.to_ascii_lowercase();
+        let mut from = 0;
+        while let Some(offset) = folded[from..].find(&term) {
+            let start = from + offset;
+            marked.push((start, start + term.len()));
+            from = start + term.len();
+        }
+    }
+    marked.sort();
+
+    let mut spans: Vec<(String, bool)> = Vec::new();
+    let mut position = 0;
+    for (start, end) in marked {
+        let start = start.max(position);
+        if start >= end {
+            continue;
+        }
+        if start > position {
+            spans.push((excerpt[position..start].to_string(), false));
+        }
+        match spans.last_mut() {
+            Some((text, true)) if start == position => text.push_str(&excerpt[start..end]),
+            _ => spans.push((excerpt[start..end].to_string(), true)),
+        }
+        position = end;
+    }
+    if position < excerpt.len() {
+        spans.push((excerpt[position..].to_string(), false));
+    }
+    spans
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_query_terms() {
+        assert_eq!(
+            query_terms(r#"spice "the  Atreides   house" melange"#),
+            vec!["spice", "the Atreides house", "melange"]
+        );
+        assert!(query_terms("  \"\" ").is_empty());
+    }
+
+    #[test]
+    fn test_highlight() {
+        let spans = highlight("The spice must flow. SPICE!", &["spice".to_string()]);
+        assert_eq!(
+            spans,
+            vec![
+                ("The ".to_string(), false),
+                ("spice".to_string(), true),
+                (" must flow. ".to_string(), false),
+                ("SPICE".to_string(), true),
+                ("!".to_string(), false),
+            ]
+        );
+        // Overlapping and adjacent matches merge.
+        let spans = highlight("abcd", &["abc".to_string(), "bcd".to_string()]);
+        assert_eq!(spans, vec![("abcd".to_string(), true)]);
+    }
+}
@@ -0,0 +1,264 @@
+//! A full-text index over book metadata, kept in a Citadel-owned sidecar
+//! database so Calibre's `metadata.db` is never touched.
+//!
+//! The sidecar (`.citadel/search-index.db` under the library root) holds an
+//! FTS5 table with one row per book: title, authors and series, the fields
+//! the library's text filter searches. Its trigram tokenizer matches any
+//! substring of three or more characters, case-insensitively, so it answers
+//! the same questions as `LIKE '%text%'` without scanning every book.
+//!
+//! The sidecar is ATTACHed to the library's connection, and TEMP triggers —
+//! which live only on that connection, not in `metadata.db` — reindex a book
+//! whenever anything it is indexed by changes. Edits made while Citadel was
+//! closed (by Calibre, say) are caught on open: the index records each
+//! book's `last_modified`, and books whose stamp moved are reindexed.
+//!
+//! The sidecar also holds the book text Citadel extracts; see
+//! [`super::fulltext`].
+
+use std::path::Path;
+
+use diesel::connection::SimpleConnection;
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::{Integer, Text};
+
+use crate::CalibreError;
+
+const INDEX_DIR: &str = ".citadel";
+const INDEX_FILENAME: &str = "search-index.db";
+/// Bumped whenever the index's tables change; an index at another version
+/// is dropped and rebuilt.
+const INDEX_VERSION: i32 = 3;
+/// Separates the values of multi-valued fields, so a match cannot span two
+/// authors.
+const VALUE_SEPARATOR: &str = "char(31)";
+
+/// The trigram tokenizer cannot match needles shorter than this.
+pub(crate) const MIN_INDEXED_NEEDLE_CHARS: usize = 3;
+
+#[derive(QueryableByName)]
+struct VersionRow {
+    #[diesel(sql_type = Integer)]
+    user_version: i32,
+}
+
+/// Open (creating if needed) the library's search index on `conn`, install
+/// the triggers that keep it current, and catch up with changes made while
+/// it was not attached. Returns the number of books reindexed.
+pub(crate) fn attach(
+    conn: &mut SqliteConnection,
+    library_root: &str,
+) -> Result<usize, CalibreError> {
+    let dir = Path::new(library_root).join(INDEX_DIR);
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join(INDEX_FILENAME);
+    let path = path
+        .to_str()
+        .ok_or_else(|| CalibreError::FileSystem("search index path is not UTF-8".to_string()))?;
+
+    sql_query("ATTACH DATABASE ? AS citadel_search")
+        .bind::<Text, _>(path)
+        .execute(conn)
+        .map_err(CalibreError::from)?;
+
+    let prepared = (|| {
+        conn.batch_execute("PRAGMA citadel_search.journal_mode = WAL;")?;
+        let version = sql_query("PRAGMA citadel_search.user_version")
+            .get_result::<VersionRow>(conn)
+            .map_err(CalibreError::from)?
+            .user_version;
+        if version != INDEX_VERSION {
+            create_tables(conn)?;
+        }
+        super::fulltext::create_text_table(conn)?;
+        create_triggers(conn)?;
+        refresh(conn)
+    })();
+    if prepared.is_err() {
+        // Without the index no trigger may be left writing to it.
+        let _ = detach(conn);
+    }
+    prepared
+}
+
+#[derive(QueryableByName)]
+struct NameRow {
+    #[diesel(sql_type = Text)]
+    name: String,
+}
+
+/// Drop the index triggers and detach the sidecar.
+fn detach(conn: &mut SqliteConnection) -> Result<(), CalibreError> {
+    let triggers = sql_query(
+        "SELECT name FROM sqlite_temp_master \
+         WHERE type = 'trigger' AND name LIKE 'citadel\\_search\\_%' ESCAPE '\\'",
+    )
+    .load::<NameRow>(conn)
+    .map_err(CalibreError::from)?;
+    for trigger in triggers {
+        conn.batch_execute(&format!("DROP TRIGGER temp.{};", trigger.name))?;
+    }
+    conn.batch_execute("DETACH DATABASE citadel_search;")?;
+    Ok(())
+}
+
+fn create_tables(conn: &mut SqliteConnection) -> Result<(), CalibreError> {
+    conn.batch_execute(&format!(
+        "DROP TABLE IF EXISTS citadel_search.books_fts;
+         DROP TABLE IF EXISTS citadel_search.metadata_fts;
+         DROP TABLE IF EXISTS citadel_search.indexed_books;
+         CREATE VIRTUAL TABLE citadel_search.metadata_fts USING fts5(
+             title, authors, series,
+             tokenize = 'trigram'
+         );
+         CREATE TABLE citadel_search.indexed_books (
+             book INTEGER PRIMARY KEY,
+             last_modified TEXT
+         );
+         PRAGMA citadel_search.user_version = {INDEX_VERSION};"
+    ))?;
+    Ok(())
+}
+
+/// Statements that rewrite the index rows of the books matching `filter`, a
+/// condition on `b.id`. Unqualified table names, as trigger bodies require;
+/// `metadata_fts` and `indexed_books` exist only in the sidecar, and their
+/// names must not clash with Calibre's `full-text-search.db`, which may be
+/// attached too.
+fn reindex_sql(filter: &str) -> String {
+    let values = |link: &str, column: &str, table: &str| {
+        format!(
+            "(SELECT group_concat(v.name, {VALUE_SEPARATOR}) FROM {link} l \
+             JOIN {table} v ON v.id = l.{column} WHERE l.book = b.id)"
+        )
+    };
+    format!(
+        "DELETE FROM metadata_fts WHERE rowid IN (SELECT b.id FROM books b WHERE {filter});
+         INSERT INTO metadata_fts (rowid, title, authors, series)
+         SELECT b.id, b.title, {authors}, {series}
+         FROM books b WHERE {filter};
+         INSERT OR REPLACE INTO indexed_books (book, last_modified)
+         SELECT b.id, b.last_modified FROM books b WHERE {filter};",
+        authors = values("books_authors_link", "author", "authors"),
+        series = values("books_series_link", "series", "series"),
+    )
+}
+
+fn create_triggers(conn: &mut SqliteConnection) -> Result<(), CalibreError> {
+    let mut triggers: Vec<(String, String, String)> = vec![
+        (
+            "books_insert".to_string(),
+            "AFTER INSERT ON main.books".to_string(),
+            reindex_sql("b.id = NEW.id"),
+        ),
+        (
+            "books_update".to_string(),
+            "AFTER UPDATE ON main.books".to_string(),
+            reindex_sql("b.id = NEW.id"),
+        ),
+        (
+            "books_delete".to_string(),
+            "AFTER DELETE ON main.books".to_string(),
+            "DELETE FROM metadata_fts WHERE rowid = OLD.id;
+             DELETE FROM indexed_books WHERE book = OLD.id;"
+                .to_string(),
+        ),
+    ];
+    // Tables with one row per book and value.
+    for table in ["books_authors_link", "books_series_link"] {
+        triggers.push((
+            format!("{table}_insert"),
+            format!("AFTER INSERT ON main.{table}"),
+            reindex_sql("b.id = NEW.book"),
+        ));
+        triggers.push((
+            format!("{table}_update"),
+            format!("AFTER UPDATE ON main.{table}"),
+            reindex_sql("b.id IN (OLD.book, NEW.book)"),
+        ));
+        triggers.push((
+            format!("{table}_delete"),
+            format!("AFTER DELETE ON main.{table}"),
+            reindex_sql("b.id = OLD.book"),
+        ));
+    }
+    // Renaming an item reindexes every book linked to it.
+    for (table, link, column) in [
+        ("authors", "books_authors_link", "author"),
+        ("series", "books_series_link", "series"),
+    ] {
+        triggers.push((
+            format!("{table}_rename"),
+            format!("AFTER UPDATE OF name ON main.{table}"),
+            reindex_sql(&format!(
+                "b.id IN (SELECT l.book FROM {link} l WHERE l.{column} = NEW.id)"
+            )),
+        ));
+    }
+
+    let ddl: String = triggers
+        .into_iter()
+        .map(|(name, event, body)| {
+            format!(
+                "DROP TRIGGER IF EXISTS temp.citadel_search_{name};
+                 CREATE TEMP TRIGGER citadel_search_{name} {event} BEGIN {body} END;"
+            )
+        })
+        .collect();
+    conn.batch_execute(&ddl)?;
+    Ok(())
+}
+
+/// Reindex books added or modified since they were last indexed, and drop
+/// books that no longer exist. Returns the number of books reindexed.
+pub(crate) fn refresh(conn: &mut SqliteConnection) -> Result<usize, CalibreError> {
+    #[derive(QueryableByName)]
+    struct CountRow {
+        #[diesel(sql_type = Integer)]
+        stale: i32,
+    }
+
+    const STALE: &str = "b.id IN (SELECT bb.id FROM books bb \
+         LEFT JOIN indexed_books ib ON ib.book = bb.id \
+         WHERE ib.book IS NULL OR ib.last_modified IS NOT bb.last_modified)";
+
+    conn.transaction::<usize, CalibreError, _>(|conn| {
+        let stale = sql_query(format!(
+            "SELECT COUNT(*) AS stale FROM books b WHERE {STALE}"
+        ))
+        .get_result::<CountRow>(conn)
+        .map_err(CalibreError::from)?
+        .stale;
+        if stale > 0 {
+            conn.batch_execute(&reindex_sql(STALE))?;
+        }
+        conn.batch_execute(
+            "DELETE FROM metadata_fts WHERE rowid NOT IN (SELECT id FROM books);
+             DELETE FROM indexed_books WHERE book NOT IN (SELECT id FROM books);
+             DELETE FROM citadel_search.books_text WHERE book NOT IN (SELECT id FROM books);",
+        )?;
+        Ok(stale as usize)
+    })
+}
+
+/// Throw the index away and index every book again.
+pub(crate) fn rebuild(conn: &mut SqliteConnection) -> Result<(), CalibreError> {
+    conn.transaction::<(), CalibreError, _>(|conn| {
+        conn.batch_execute(
+            "DELETE FROM metadata_fts;
+             DELETE FROM indexed_books;",
+        )?;
+        conn.batch_execute(&reindex_sql("1=1"))?;
+        Ok(())
+    })
+}
+
+/// An FTS5 query matching `needle` as a substring of the given columns.
+pub(crate) fn match_query(columns: &[&str], needle: &str) -> String {
+    format!(
+        "{{{}}} : \"{}\"",
+        columns.join(" "),
+        needle.replace('"', "\"\"")
+    )
+}
@@ -0,0 +1,18 @@
+//! Calibre's search language.
+//!
+//! Queries such as `tag:fantasy and not series:"Discworld" rating:>=4` are
+//! parsed ([`parser`]) and compiled to a SQL condition over `books`
+//! ([`compiler`]) that the paged book queries AND into their WHERE clause,
+//! so paging and totals stay correct. [`index`] keeps the full-text index
+//! that plain-text book queries use instead of scanning with `LIKE`, and
+//! [`fulltext`] searches the text of the books themselves.
+
+mod compiler;
+pub(crate) mod fulltext;
+pub(crate) mod index;
+mod parser;
+
+pub(crate) use compiler::{
+    compile, compile_virtual_library, CompiledSearch, SqlBind, SAVED_SEARCHES_PREF,
+    VIRTUAL_LIBRARIES_PREF,
+};
@@ -0,0 +1,378 @@
+//! Parsing Calibre search queries into a [`SearchExpr`] tree.
+//!
+//! The grammar follows Calibre's `search_query_parser.py`:
+//!
+//! ```text
+//! or_expr  := and_expr ( "or" and_expr )*
+//! and_expr := not_expr ( ["and"] not_expr )*
+//! not_expr := "not" not_expr | "(" or_expr ")" | term
+//! term     := [location ":"] value
+//! ```
+//!
+//! Adjacent terms are ANDed. Operators are case-insensitive. A value may be
+//! quoted to include spaces and parentheses (`series:"The Expanse (2011)"`),
+//! with `\"` and `\\` escapes; a fully quoted term is never split into a
+//! location and a value. `location:` prefixes naming no known field are
+//! searched for as plain text, as in Calibre.
+
+use crate::CalibreError;
+
+/// A parsed search query.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum SearchExpr {
+    And(Box<SearchExpr>, Box<SearchExpr>),
+    Or(Box<SearchExpr>, Box<SearchExpr>),
+    Not(Box<SearchExpr>),
+    /// `location:value`, or a bare `value` (location `None`) searched across
+    /// the default fields. The value still carries any match-mode or
+    /// comparison prefix (`=`, `~`, `>=`, ...); what it means depends on the
+    /// field, so the compiler interprets it.
+    Term {
+        location: Option<String>,
+        value: String,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    LParen,
+    RParen,
+    And,
+    Or,
+    Not,
+    Term {
+        location: Option<String>,
+        value: String,
+    },
+}
+
+/// Calibre's name for a search location, resolving aliases. Custom columns
+/// (`#label`) keep their lowercased label. `all` is the same as no location.
+fn canonical_location(name: &str) -> Option<Option<String>> {
+    let name = name.to_lowercase();
+    if let Some(label) = name.strip_prefix('#') {
+        return (!label.is_empty()).then(|| Some(name.clone()));
+    }
+    let canonical = match name.as_str() {
+        "all" => return Some(None),
+        "title" => "title",
+        "author" | "authors" => "authors",
+        "author_sort" => "author_sort",
+        "tag" | "tags" => "tags",
+        "series" => "series",
+        "series_index" => "series_index",
+        "publisher" => "publisher",
+        "language" | "languages" => "languages",
+        "format" | "formats" => "formats",
+        "identifier" | "identifiers" => "identifiers",
+        "comment" | "comments" => "comments",
+        "rating" => "rating",
+        "pubdate" | "published" => "pubdate",
+        "date" | "timestamp" => "timestamp",
+        "last_modified" => "last_modified",
+        "size" => "size",
+        "cover" => "cover",
+        "id" => "id",
+        "uuid" => "uuid",
+        "search" => "search",
+        "vl" => "vl",
+        _ => return None,
+    };
+    Some(Some(canonical.to_string()))
+}
+
+fn tokenize(query: &str) -> Result<Vec<Token>, CalibreError> {
+    let chars: Vec<char> = query.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        match chars[i] {
+            c if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            '(' => {
+                tokens.push(Token::LParen);
+                i += 1;
+                continue;
+            }
+            ')' => {
+                tokens.push(Token::RParen);
+                i += 1;
+                continue;
+            }
+            _ => {}
+        }
+
+        // A term runs up to whitespace or a parenthesis outside quotes. Only
+        // a colon before any quoted part separates a location.
+        let mut text = String::new();
+        let mut colon: Option<usize> = None;
+        let mut quoted = false;
+        while i < chars.len() && !chars[i].is_whitespace() && chars[i] != '(' && chars[i] != ')' {
+            if chars[i] == '"' {
+                quoted = true;
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        None => {
+                            return Err(CalibreError::InvalidSearch(format!(
+                                "unterminated quote in '{query}'"
+                            )))
+                        }
+                        Some('\\') if i + 1 < chars.len() => {
+                            text.push(chars[i + 1]);
+                            i += 2;
+                        }
+                        Some('"') => {
+                            i += 1;
+                            break;
+                        }
+                        Some(&c) => {
+                            text.push(c);
+                            i += 1;
+                        }
+                    }
+                }
+            } else {
+                if chars[i] == ':' && colon.is_none() && !quoted {
+                    colon = Some(text.len());
+                }
+                text.push(chars[i]);
+                i += 1;
+            }
+        }
+
+        if !quoted {
+            match text.to_lowercase().as_str() {
+                "and" => {
+                    tokens.push(Token::And);
+                    continue;
+                }
+                "or" => {
+                    tokens.push(Token::Or);
+                    continue;
+                }
+                "not" => {
+                    tokens.push(Token::Not);
+                    continue;
+                }
+                _ => {}
+            }
+        }
+
+        let located = colon.and_then(|colon| {
+            canonical_location(&text[..colon])
+                .map(|location| (location, text[colon + 1..].to_string()))
+        });
+        tokens.push(match located {
+            Some((location, value)) => Token::Term { location, value },
+            None => Token::Term {
+                location: None,
+                value: text,
+            },
+        });
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn or_expr(&mut self) -> Result<SearchExpr, CalibreError> {
+        let mut expr = self.and_expr()?;
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            let right = self.and_expr()?;
+            expr = SearchExpr::Or(Box::new(expr), Box::new(right));
+        }
+        Ok(expr)
+    }
+
+    fn and_expr(&mut self) -> Result<SearchExpr, CalibreError> {
+        let mut expr = self.not_expr()?;
+        loop {
+            match self.peek() {
+                Some(Token::And) => {
+                    self.next();
+                }
+                // Adjacent terms are implicitly ANDed.
+                Some(Token::Term { .. } | Token::LParen | Token::Not) => {}
+                _ => return Ok(expr),
+            }
+            let right = self.not_expr()?;
+            expr = SearchExpr::And(Box::new(expr), Box::new(right));
+        }
+    }
+
+    fn not_expr(&mut self) -> Result<SearchExpr, CalibreError> {
+        match self.next() {
+            Some(Token::Not) => Ok(SearchExpr::Not(Box::new(self.not_expr()?))),
+            Some(Token::LParen) => {
+                let expr = self.or_expr()?;
+                match self.next() {
+                    Some(Token::RParen) => Ok(expr),
+                    _ => Err(CalibreError::InvalidSearch(
+                        "missing closing parenthesis".to_string(),
+                    )),
+                }
+            }
+            Some(Token::Term { location, value }) => Ok(SearchExpr::Term { location, value }),
+            Some(Token::RParen) => Err(CalibreError::InvalidSearch(
+                "unexpected closing parenthesis".to_string(),
+            )),
+            Some(Token::And | Token::Or) => Err(CalibreError::InvalidSearch(
+                "'and' or 'or' without a term before it".to_string(),
+            )),
+            None => Err(CalibreError::InvalidSearch(
+                "query ends where a term was expected".to_string(),
+            )),
+        }
+    }
+}
+
+/// Parse a query in Calibre's search language.
+pub(crate) fn parse(query: &str) -> Result<SearchExpr, CalibreError> {
+    let mut parser = Parser {
+        tokens: tokenize(query)?,
+        position: 0,
+    };
+    if parser.tokens.is_empty() {
+        return Err(CalibreError::InvalidSearch("empty query".to_string()));
+    }
+    let expr = parser.or_expr()?;
+    match parser.peek() {
+        None => Ok(expr),
+        Some(Token::RParen) => Err(CalibreError::InvalidSearch(
+            "unexpected closing parenthesis".to_string(),
+        )),
+        Some(token) => Err(CalibreError::InvalidSearch(format!("unexpected {token:?}"))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn term(location: Option<&str>, value: &str) -> SearchExpr {
+        SearchExpr::Term {
+            location: location.map(str::to_string),
+            value: value.to_string(),
+        }
+    }
+
+    fn and(left: SearchExpr, right: SearchExpr) -> SearchExpr {
+        SearchExpr::And(Box::new(left), Box::new(right))
+    }
+
+    fn or(left: SearchExpr, right: SearchExpr) -> SearchExpr {
+        SearchExpr::Or(Box::new(left), Box::new(right))
+    }
+
+    fn not(expr: SearchExpr) -> SearchExpr {
+        SearchExpr::Not(Box::new(expr))
+    }
+
+    #[test]
+    fn test_locations_and_implicit_and() {
+        assert_eq!(
+            parse(r#"tag:fantasy and not series:"Discworld" rating:>=4 #read:false"#).unwrap(),
+            and(
+                and(
+                    and(
+                        term(Some("tags"), "fantasy"),
+                        not(term(Some("series"), "Discworld"))
+                    ),
+                    term(Some("rating"), ">=4")
+                ),
+                term(Some("#read"), "false")
+            )
+        );
+    }
+
+    #[test]
+    fn test_precedence_and_parentheses() {
+        assert_eq!(
+            parse("a or b c").unwrap(),
+            or(term(None, "a"), and(term(None, "b"), term(None, "c")))
+        );
+        assert_eq!(
+            parse("(a OR b) c").unwrap(),
+            and(or(term(None, "a"), term(None, "b")), term(None, "c"))
+        );
+        assert_eq!(parse("NOT not a").unwrap(), not(not(term(None, "a"))));
+    }
+
+    #[test]
+    fn test_quoting() {
+        assert_eq!(
+            parse(r#"title:"Dune (1965)" "and" "a \"b\" c""#).unwrap(),
+            and(
+                and(term(Some("title"), "Dune (1965)"), term(None, "and")),
+                term(None, r#"a "b" c"#)
+            )
+        );
+        assert_eq!(
+            parse(r#"title:="Dune""#).unwrap(),
+            term(Some("title"), "=Dune")
+        );
+        // A colon inside quotes is part of the value.
+        assert_eq!(
+            parse(r#""title: Dune""#).unwrap(),
+            term(None, "title: Dune")
+        );
+    }
+
+    #[test]
+    fn test_location_aliases_and_unknown_prefixes() {
+        assert_eq!(
+            parse(r#"Author:"=Le Guin""#).unwrap(),
+            term(Some("authors"), "=Le Guin")
+        );
+        assert_eq!(parse("all:dune").unwrap(), term(None, "dune"));
+        assert_eq!(
+            parse("#Shelf:attic").unwrap(),
+            term(Some("#shelf"), "attic")
+        );
+        assert_eq!(parse("dune:messiah").unwrap(), term(None, "dune:messiah"));
+        assert_eq!(
+            parse("identifiers:isbn:123").unwrap(),
+            term(Some("identifiers"), "isbn:123")
+        );
+    }
+
+    #[test]
+    fn test_syntax_errors() {
+        for query in [
+            "",
+            "   ",
+            "(a",
+            "a)",
+            "a and",
+            "or a",
+            "not",
+            r#"title:"Dune"#,
+            "()",
+        ] {
+            assert!(
+                matches!(parse(query), Err(CalibreError::InvalidSearch(_))),
+                "{query:?} should not parse"
+            );
+        }
+    }
+}
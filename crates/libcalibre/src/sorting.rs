@@ -2,6 +2,7 @@ use std::collections::HashSet;
 use std::ops::Not;
 
 use regex::Regex;
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
 
 /// Creates a sortable book title by moving leading articles to the end.
 ///
@@ -366,6 +367,71 @@ pub fn sort_author_name_apa(name: &str) -> String {
     }
 }
 
+/// A key under which spellings of the same author's name compare equal.
+///
+/// Used to find duplicate authors, such as those imports create from
+/// differently written `dc:creator` values. The name is put in sort order
+/// with [`sort_author_name_apa`] (so titles, degrees and bracketed notes
+/// drop out, and "Last, First" matches "First Last"), then accents, case,
+/// spacing and punctuation other than the sort comma are removed.
+///
+/// ## Examples
+/// ```
+/// use libcalibre::sorting::author_name_key;
+/// let key = author_name_key("J.R.R. Tolkien");
+/// assert_eq!(key, author_name_key("J. R. R. Tolkien"));
+/// assert_eq!(key, author_name_key("Tolkien, J. R. R."));
+/// assert_eq!(key, "tolkien,jrr");
+/// ```
+///
+/// ```
+/// use libcalibre::sorting::author_name_key;
+/// assert_eq!(author_name_key("Gabriel García Márquez"), author_name_key("gabriel garcia marquez"));
+/// ```
+pub fn author_name_key(name: &str) -> String {
+    fold_for_key(&sort_author_name_apa(name.trim()))
+        .split(',')
+        .map(|part| {
+            part.chars()
+                .filter(|c| c.is_alphanumeric())
+                .collect::<String>()
+        })
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+/// A key under which spellings of the same book title compare equal, for
+/// finding duplicate books. Accents, case, punctuation, spacing, bracketed
+/// notes such as "(Unabridged)" and a leading article are ignored.
+///
+/// ## Examples
+/// ```
+/// use libcalibre::sorting::title_key;
+/// let key = title_key("The Lord of the Rings: The Fellowship of the Ring");
+/// assert_eq!(key, title_key("Lord of the Rings - the Fellowship of the Ring (Illustrated)"));
+/// assert_eq!(key, "lordoftheringsthefellowshipofthering");
+/// ```
+pub fn title_key(title: &str) -> String {
+    let folded = fold_for_key(&remove_bracket_content(title));
+    let mut words: Vec<&str> = folded
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect();
+    if words.len() > 1 && ["a", "an", "the"].contains(&words[0]) {
+        words.remove(0);
+    }
+    words.concat()
+}
+
+/// Lowercase and strip accents, for the comparison keys above.
+fn fold_for_key(s: &str) -> String {
+    s.nfkd()
+        .filter(|c| !is_combining_mark(*c))
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -543,4 +609,39 @@ mod tests {
     fn test_title_sort_with_numbers() {
         assert_eq!(sort_book_title("The 39 Steps"), "39 Steps, The");
     }
+
+    #[test]
+    fn test_author_name_key_ignores_spacing_case_and_accents() {
+        let key = author_name_key("Gabriel García Márquez");
+        assert_eq!(key, author_name_key("GABRIEL GARCIA MARQUEZ"));
+        assert_eq!(key, author_name_key("  Márquez,  Gabriel García "));
+        assert_eq!(key, "marquez,gabrielgarcia");
+    }
+
+    #[test]
+    fn test_author_name_key_drops_titles_and_suffix_punctuation() {
+        assert_eq!(
+            author_name_key("Dr. Martin Luther King Jr."),
+            author_name_key("King, Martin Luther, Jr")
+        );
+        assert_eq!(
+            author_name_key("John Doe (Editor)"),
+            author_name_key("John Doe")
+        );
+    }
+
+    #[test]
+    fn test_author_name_key_keeps_different_names_apart() {
+        assert_ne!(author_name_key("John Doe"), author_name_key("Jane Doe"));
+        assert_ne!(author_name_key("Doe John"), author_name_key("John Doe"));
+        assert_eq!(author_name_key(""), "");
+    }
+
+    #[test]
+    fn test_title_key_ignores_article_punctuation_and_notes() {
+        assert_eq!(title_key("The Hobbit"), title_key("hobbit"));
+        assert_eq!(title_key("Les Misérables [Abridged]"), "lesmiserables");
+        assert_eq!(title_key("The"), "the");
+        assert_ne!(title_key("Dune"), title_key("Dune Messiah"));
+    }
 }
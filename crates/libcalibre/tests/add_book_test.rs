@@ -2,6 +2,9 @@ mod common;
 
 use common::{setup_with_library, standard_test_book, DatabaseSnapshot};
 use insta::assert_yaml_snapshot;
+use libcalibre::Library;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
 
 /// Test that adding a single book creates the expected database state.
 /// This test captures the ENTIRE database, so it will catch any unexpected
@@ -34,3 +37,212 @@ fn test_empty_library_state() {
 
     assert_yaml_snapshot!(snapshot);
 }
+
+// ---------------------------------------------------------------------------
+// Fault injection: a failed add must leave neither rows nor files behind.
+// ---------------------------------------------------------------------------
+
+fn row_count(lib: &Library, table: &str) -> i64 {
+    rusqlite::Connection::open(lib.database_path())
+        .unwrap()
+        .query_row(&format!("SELECT COUNT(*) FROM {table}"), [], |row| {
+            row.get(0)
+        })
+        .unwrap()
+}
+
+/// Row counts of every table an add writes to.
+fn book_row_counts(lib: &Library) -> Vec<(&'static str, i64)> {
+    [
+        "books",
+        "data",
+        "authors",
+        "books_authors_link",
+        "tags",
+        "books_tags_link",
+        "series",
+        "books_series_link",
+    ]
+    .into_iter()
+    .map(|table| (table, row_count(lib, table)))
+    .collect()
+}
+
+/// Everything under the library root except `metadata.db` and its journals.
+fn library_files(root: &Path) -> Vec<PathBuf> {
+    fn walk(dir: &Path, out: &mut Vec<PathBuf>) {
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            out.push(path.clone());
+            if path.is_dir() {
+                walk(&path, out);
+            }
+        }
+    }
+
+    let mut files = Vec::new();
+    walk(root, &mut files);
+    files.retain(|path| {
+        !path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with("metadata.db"))
+    });
+    files.sort();
+    files
+}
+
+/// A book with tags, a series and the given files, written under `sources`.
+fn book_with_files(sources: &TempDir, names: &[&str]) -> libcalibre::BookAdd {
+    let file_paths = names
+        .iter()
+        .map(|name| {
+            let path = sources.path().join(name);
+            std::fs::write(&path, format!("contents of {name}")).unwrap();
+            path
+        })
+        .collect();
+
+    libcalibre::BookAdd {
+        tags: Some(vec!["fiction".to_string()]),
+        series: Some("The Series".to_string()),
+        file_paths,
+        ..standard_test_book()
+    }
+}
+
+#[test]
+fn test_add_book_with_missing_file_leaves_nothing_behind() {
+    let (temp, mut lib) = setup_with_library();
+    let sources = tempfile::tempdir().unwrap();
+    let counts_before = book_row_counts(&lib);
+    let files_before = library_files(temp.path());
+
+    let mut book = book_with_files(&sources, &["book.epub"]);
+    book.file_paths.push(sources.path().join("missing.pdf"));
+
+    assert!(lib.add_book(book).is_err());
+    assert_eq!(book_row_counts(&lib), counts_before);
+    assert_eq!(library_files(temp.path()), files_before);
+}
+
+#[test]
+fn test_add_book_with_unwritable_folder_leaves_nothing_behind() {
+    let (temp, mut lib) = setup_with_library();
+    let sources = tempfile::tempdir().unwrap();
+    // A regular file where the author folder should go.
+    std::fs::write(temp.path().join("John Doe"), "in the way").unwrap();
+    let counts_before = book_row_counts(&lib);
+    let files_before = library_files(temp.path());
+
+    let result = lib.add_book(book_with_files(&sources, &["book.txt"]));
+
+    assert!(result.is_err());
+    assert_eq!(book_row_counts(&lib), counts_before);
+    assert_eq!(library_files(temp.path()), files_before);
+}
+
+#[test]
+fn test_add_book_database_failure_removes_copied_files() {
+    let (temp, mut lib) = setup_with_library();
+    let sources = tempfile::tempdir().unwrap();
+    // Fail the second format row, after the first file has been copied.
+    rusqlite::Connection::open(lib.database_path())
+        .unwrap()
+        .execute_batch(
+            "CREATE TRIGGER inject_fault BEFORE INSERT ON data
+             WHEN (SELECT COUNT(*) FROM data) > 0
+             BEGIN SELECT RAISE(ABORT, 'injected fault'); END;",
+        )
+        .unwrap();
+    let counts_before = book_row_counts(&lib);
+    let files_before = library_files(temp.path());
+
+    let result = lib.add_book(book_with_files(&sources, &["book.epub", "book.txt"]));
+
+    assert!(result.is_err());
+    assert_eq!(book_row_counts(&lib), counts_before);
+    assert_eq!(library_files(temp.path()), files_before);
+}
+
+#[test]
+fn test_failed_add_keeps_existing_author_folder() {
+    let (temp, mut lib) = setup_with_library();
+    let sources = tempfile::tempdir().unwrap();
+    let existing = lib
+        .add_book(book_with_files(&sources, &["first.txt"]))
+        .unwrap();
+    let counts_before = book_row_counts(&lib);
+    let files_before = library_files(temp.path());
+
+    let mut book = book_with_files(&sources, &["second.txt"]);
+    book.title = "Another Book".to_string();
+    book.file_paths.push(sources.path().join("missing.txt"));
+
+    assert!(lib.add_book(book).is_err());
+    assert_eq!(book_row_counts(&lib), counts_before);
+    assert_eq!(library_files(temp.path()), files_before);
+    assert!(temp.path().join(&existing.book_dir_path).is_dir());
+}
+
+#[test]
+fn test_add_book_copies_files_and_records_their_size() {
+    let (temp, mut lib) = setup_with_library();
+    let sources = tempfile::tempdir().unwrap();
+
+    let book = lib
+        .add_book(book_with_files(&sources, &["book.txt"]))
+        .unwrap();
+
+    let book_dir = temp.path().join(&book.book_dir_path);
+    let file = &book.files[0];
+    let copied = book_dir.join(format!("{}.txt", file.name));
+    assert_eq!(
+        std::fs::read_to_string(&copied).unwrap(),
+        "contents of book.txt"
+    );
+    assert_eq!(file.uncompressed_size, "contents of book.txt".len() as i32);
+    assert!(book_dir.join("metadata.opf").exists());
+}
+
+#[test]
+fn test_added_formats_are_named_after_the_book() {
+    let (temp, mut lib) = setup_with_library();
+    let sources = tempfile::tempdir().unwrap();
+    let book = lib.add_book(standard_test_book()).unwrap();
+
+    lib.add_book_file_from_bytes(book.id, "EPUB".to_string(), b"an epub".to_vec())
+        .unwrap();
+    let txt = sources.path().join("anything.txt");
+    std::fs::write(&txt, "some text").unwrap();
+    lib.add_book_file_from_path(book.id, "TXT".to_string(), txt.display().to_string())
+        .unwrap();
+
+    let book = lib.get_book(book.id).unwrap();
+    let book_dir = temp.path().join(&book.book_dir_path);
+    let mut names: Vec<(&str, &str)> = book
+        .files
+        .iter()
+        .map(|file| (file.format.as_str(), file.name.as_str()))
+        .collect();
+    names.sort();
+    assert_eq!(
+        names,
+        vec![
+            ("EPUB", "The Test Book - John Doe"),
+            ("TXT", "The Test Book - John Doe")
+        ]
+    );
+    assert_eq!(
+        std::fs::read(book_dir.join("The Test Book - John Doe.epub")).unwrap(),
+        b"an epub"
+    );
+    assert_eq!(
+        std::fs::read_to_string(book_dir.join("The Test Book - John Doe.txt")).unwrap(),
+        "some text"
+    );
+    assert_eq!(
+        lib.get_book_file_path(book.id, "TXT").unwrap(),
+        book_dir.join("The Test Book - John Doe.txt")
+    );
+}
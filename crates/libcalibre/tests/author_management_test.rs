@@ -0,0 +1,213 @@
+// Tests for merging duplicate authors and re-deriving author sorts
+// This binary uses only a subset of the shared test helpers.
+#[allow(dead_code, unused_imports)]
+mod common;
+
+use common::{conn, setup_with_library, standard_test_book};
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::{Integer, Nullable, Text};
+use libcalibre::{AuthorId, AuthorSortUpdate, BookAdd, BookId, CalibreError, Library};
+use std::path::Path;
+use tempfile::TempDir;
+
+fn add_book(lib: &mut Library, sources: &TempDir, title: &str, authors: &[&str]) -> BookId {
+    let source = sources.path().join(format!("{title}.txt"));
+    std::fs::write(&source, title).unwrap();
+    lib.add_book(BookAdd {
+        title: title.to_string(),
+        author_names: authors.iter().map(|a| (*a).to_string()).collect(),
+        file_paths: vec![source],
+        ..standard_test_book()
+    })
+    .unwrap()
+    .id
+}
+
+fn author_id(lib: &mut Library, name: &str) -> AuthorId {
+    lib.authors()
+        .unwrap()
+        .into_iter()
+        .find(|author| author.name == name)
+        .unwrap()
+        .id
+}
+
+fn author_names(lib: &mut Library, book_id: BookId) -> Vec<String> {
+    lib.get_book(book_id)
+        .unwrap()
+        .authors
+        .into_iter()
+        .map(|author| author.name)
+        .collect()
+}
+
+#[derive(QueryableByName)]
+struct AuthorSortRow {
+    #[diesel(sql_type = Nullable<Text>)]
+    author_sort: Option<String>,
+}
+
+fn book_author_sort(lib: &Library, book_id: BookId) -> Option<String> {
+    sql_query("SELECT author_sort FROM books WHERE id = ?")
+        .bind::<Integer, _>(book_id.as_i32())
+        .get_result::<AuthorSortRow>(&mut conn(lib))
+        .unwrap()
+        .author_sort
+}
+
+fn opf(lib: &mut Library, book_id: BookId) -> String {
+    let book = lib.get_book(book_id).unwrap();
+    std::fs::read_to_string(
+        Path::new(lib.library_path())
+            .join(book.book_dir_path)
+            .join("metadata.opf"),
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_merge_authors_relinks_books_and_moves_folders() {
+    let sources = tempfile::tempdir().unwrap();
+    let (_temp, mut lib) = setup_with_library();
+    let hobbit = add_book(&mut lib, &sources, "The Hobbit", &["J.R.R. Tolkien"]);
+    let silmarillion = add_book(
+        &mut lib,
+        &sources,
+        "The Silmarillion",
+        &["J. R. R. Tolkien"],
+    );
+    let from = author_id(&mut lib, "J.R.R. Tolkien");
+    let into = author_id(&mut lib, "J. R. R. Tolkien");
+    let old_book_dir = lib.get_book(hobbit).unwrap().book_dir_path;
+    let old_dir = Path::new(lib.library_path()).join(old_book_dir);
+
+    let merged = lib.merge_authors(from, into).unwrap();
+    assert_eq!(merged.id, into);
+    assert_eq!(merged.name, "J. R. R. Tolkien");
+    assert!(matches!(
+        lib.get_author(from),
+        Err(CalibreError::AuthorNotFound(_))
+    ));
+    assert_eq!(author_names(&mut lib, hobbit), vec!["J. R. R. Tolkien"]);
+    assert_eq!(
+        book_author_sort(&lib, hobbit),
+        book_author_sort(&lib, silmarillion)
+    );
+
+    // The book now lives in the surviving author's folder, with its OPF.
+    let book = lib.get_book(hobbit).unwrap();
+    let author_dir = |dir: &str| Path::new(dir).parent().unwrap().to_path_buf();
+    let survivor_dir = lib.get_book(silmarillion).unwrap().book_dir_path;
+    assert_eq!(author_dir(&book.book_dir_path), author_dir(&survivor_dir));
+    let new_dir = Path::new(lib.library_path()).join(&book.book_dir_path);
+    assert!(new_dir.join(format!("{}.txt", book.files[0].name)).exists());
+    assert!(!old_dir.exists());
+    assert!(opf(&mut lib, hobbit).contains(">J. R. R. Tolkien</dc:creator>"));
+}
+
+#[test]
+fn test_merge_authors_keeps_author_order_and_one_link() {
+    let sources = tempfile::tempdir().unwrap();
+    let (_temp, mut lib) = setup_with_library();
+    let omens = add_book(
+        &mut lib,
+        &sources,
+        "Good Omens",
+        &["Terry Pratchet", "Neil Gaiman"],
+    );
+    let both = add_book(
+        &mut lib,
+        &sources,
+        "Both Spellings",
+        &["Terry Pratchett", "Terry Pratchet"],
+    );
+    let from = author_id(&mut lib, "Terry Pratchet");
+    let into = author_id(&mut lib, "Terry Pratchett");
+
+    lib.merge_authors(from, into).unwrap();
+    assert_eq!(
+        author_names(&mut lib, omens),
+        vec!["Terry Pratchett", "Neil Gaiman"]
+    );
+    assert_eq!(
+        book_author_sort(&lib, omens).as_deref(),
+        Some("Pratchett, Terry & Gaiman, Neil")
+    );
+    assert_eq!(author_names(&mut lib, both), vec!["Terry Pratchett"]);
+    assert_eq!(
+        book_author_sort(&lib, both).as_deref(),
+        Some("Pratchett, Terry")
+    );
+}
+
+#[test]
+fn test_merge_authors_rejects_unknown_authors() {
+    let sources = tempfile::tempdir().unwrap();
+    let (_temp, mut lib) = setup_with_library();
+    let book = add_book(&mut lib, &sources, "Mort", &["Terry Pratchett"]);
+    let pratchett = author_id(&mut lib, "Terry Pratchett");
+
+    assert!(matches!(
+        lib.merge_authors(AuthorId::from(9999), pratchett),
+        Err(CalibreError::AuthorNotFound(_))
+    ));
+    assert!(matches!(
+        lib.merge_authors(pratchett, AuthorId::from(9999)),
+        Err(CalibreError::AuthorNotFound(_))
+    ));
+    assert_eq!(
+        lib.merge_authors(pratchett, pratchett).unwrap().id,
+        pratchett
+    );
+    assert_eq!(author_names(&mut lib, book), vec!["Terry Pratchett"]);
+}
+
+#[test]
+fn test_recompute_author_sorts() {
+    let sources = tempfile::tempdir().unwrap();
+    let (_temp, mut lib) = setup_with_library();
+    let omens = add_book(
+        &mut lib,
+        &sources,
+        "Good Omens",
+        &["Terry Pratchett", "Neil Gaiman"],
+    );
+    let mort = add_book(&mut lib, &sources, "Mort", &["Terry Pratchett"]);
+    let dune = add_book(&mut lib, &sources, "Dune", &["Frank Herbert"]);
+    // Sorts typed in by hand, or left stale by older tools.
+    let mut conn = conn(&lib);
+    sql_query("UPDATE authors SET sort = 'pratchett' WHERE name = 'Terry Pratchett'")
+        .execute(&mut conn)
+        .unwrap();
+    sql_query("UPDATE books SET author_sort = 'Herbert' WHERE id = ?")
+        .bind::<Integer, _>(dune.as_i32())
+        .execute(&mut conn)
+        .unwrap();
+
+    let update = lib.recompute_author_sorts().unwrap();
+    assert_eq!(
+        update,
+        AuthorSortUpdate {
+            authors_updated: 1,
+            books_updated: 1,
+        }
+    );
+    assert_eq!(
+        book_author_sort(&lib, dune).as_deref(),
+        Some("Herbert, Frank")
+    );
+    assert_eq!(
+        book_author_sort(&lib, omens).as_deref(),
+        Some("Pratchett, Terry & Gaiman, Neil")
+    );
+    let pratchett = author_id(&mut lib, "Terry Pratchett");
+    assert_eq!(lib.get_author(pratchett).unwrap().sort, "Pratchett, Terry");
+    assert!(opf(&mut lib, mort).contains("opf:file-as=\"Pratchett, Terry\""));
+
+    // Nothing left to change.
+    assert_eq!(
+        lib.recompute_author_sorts().unwrap(),
+        AuthorSortUpdate::default()
+    );
+}
@@ -5,7 +5,9 @@ use common::setup_with_library;
 use diesel::prelude::*;
 use diesel::sql_query;
 use diesel::sql_types::BigInt;
-use libcalibre::{AuthorAdd, BookAdd, BookId, BookUpdate};
+use libcalibre::{
+    AuthorAdd, BookAdd, BookId, BookUpdate, CustomColumnKind, CustomColumnSpec, CustomValue,
+};
 use std::collections::HashMap;
 
 fn empty_book(title: &str) -> BookAdd {
@@ -166,6 +168,70 @@ fn test_metadata_opf_emits_language_codes() {
     assert!(!opf.contains("<dc:language>spa</dc:language>"));
 }
 
+#[test]
+fn test_metadata_opf_records_every_field() {
+    let (_temp, mut lib) = setup_with_library();
+    let library_path = lib.library_path().to_string();
+    let book = lib
+        .add_book(BookAdd {
+            author_names: vec!["Susanna Clarke".to_string()],
+            tags: Some(vec!["Fantasy".to_string(), "Magic & Magicians".to_string()]),
+            series: Some("Standalones".to_string()),
+            series_index: Some(2.0),
+            ..empty_book("Jonathan Strange & Mr Norrell")
+        })
+        .unwrap();
+    lib.update_book(
+        book.id,
+        BookUpdate {
+            publisher: Some("Bloomsbury".to_string()),
+            rating: Some(8),
+            description: Some("<p>Two magicians.</p>".to_string()),
+            identifiers: Some(HashMap::from([(
+                "isbn".to_string(),
+                "9781582344164".to_string(),
+            )])),
+            ..empty_update()
+        },
+    )
+    .unwrap();
+    let column = lib
+        .create_custom_column(CustomColumnSpec {
+            label: "shelf".to_string(),
+            name: "Shelf".to_string(),
+            kind: CustomColumnKind::Text,
+            is_multiple: false,
+            enum_values: vec![],
+            display: None,
+        })
+        .unwrap();
+    lib.set_custom_value(
+        book.id,
+        column.id,
+        Some(CustomValue::Text("Living room".to_string())),
+    )
+    .unwrap();
+
+    let opf = std::fs::read_to_string(
+        std::path::Path::new(&library_path)
+            .join(&book.book_dir_path)
+            .join("metadata.opf"),
+    )
+    .unwrap();
+
+    assert!(opf.contains("<dc:title>Jonathan Strange &amp; Mr Norrell</dc:title>"));
+    assert!(opf.contains("<dc:subject>Magic &amp; Magicians</dc:subject>"));
+    assert!(opf.contains("<dc:publisher>Bloomsbury</dc:publisher>"));
+    assert!(opf.contains("<dc:description>&lt;p&gt;Two magicians.&lt;/p&gt;</dc:description>"));
+    assert!(opf.contains(r#"<dc:identifier opf:scheme="ISBN">9781582344164</dc:identifier>"#));
+    assert!(opf.contains(r#"<meta name="calibre:series" content="Standalones"/>"#));
+    assert!(opf.contains(r#"<meta name="calibre:series_index" content="2"/>"#));
+    assert!(opf.contains(r#"<meta name="calibre:rating" content="8"/>"#));
+    assert!(opf.contains(r#"<meta name="calibre:author_link_map""#));
+    assert!(opf.contains(r#"<meta name="calibre:user_metadata:#shelf""#));
+    assert!(opf.contains("&quot;#value#&quot;: &quot;Living room&quot;"));
+}
+
 #[test]
 fn test_list_books_empty() {
     let (_temp, mut lib) = setup_with_library();
@@ -333,6 +399,30 @@ fn test_update_book_read_state() {
     assert!(!updated.is_read);
 }
 
+#[test]
+fn test_add_book_persists_identifiers() {
+    let (_temp, mut lib) = setup_with_library();
+
+    let mut book = empty_book("Test Book");
+    book.identifiers = HashMap::from([
+        ("ISBN".to_string(), "9780552131063".to_string()),
+        ("goodreads".to_string(), "386372".to_string()),
+    ]);
+    let book = lib.add_book(book).unwrap();
+
+    let fetched = lib.get_book(book.id).unwrap();
+    let mut identifiers: Vec<(&str, &str)> = fetched
+        .identifiers
+        .iter()
+        .map(|id| (id.label.as_str(), id.value.as_str()))
+        .collect();
+    identifiers.sort();
+    assert_eq!(
+        identifiers,
+        vec![("goodreads", "386372"), ("isbn", "9780552131063")]
+    );
+}
+
 #[test]
 fn test_upsert_book_identifier_create() {
     let (_temp, mut lib) = setup_with_library();
@@ -806,3 +896,348 @@ fn test_books_returns_read_state() {
     assert!(b1.is_read);
     assert!(!b2.is_read);
 }
+
+fn count_rows(temp: &tempfile::TempDir, sql: &str) -> i64 {
+    #[derive(QueryableByName)]
+    struct RowCount {
+        #[diesel(sql_type = BigInt)]
+        count: i64,
+    }
+
+    let db_path = temp.path().join("metadata.db");
+    let mut conn =
+        libcalibre::persistence::establish_connection(db_path.to_str().unwrap()).unwrap();
+    sql_query(sql)
+        .get_result::<RowCount>(&mut conn)
+        .unwrap()
+        .count
+}
+
+#[test]
+fn test_update_book_sets_replaces_and_clears_publisher() {
+    let (temp, mut lib) = setup_with_library();
+
+    let book = lib.add_book(empty_book("Published Book")).unwrap();
+
+    lib.update_book(
+        book.id,
+        BookUpdate {
+            publisher: Some("Tor Books".to_string()),
+            ..empty_update()
+        },
+    )
+    .unwrap();
+    assert_eq!(
+        count_rows(
+            &temp,
+            "SELECT COUNT(*) AS count FROM books_publishers_link bpl
+             JOIN publishers p ON p.id = bpl.publisher
+             WHERE p.name = 'Tor Books'"
+        ),
+        1
+    );
+
+    // Replacing the publisher drops the now-unused old row.
+    lib.update_book(
+        book.id,
+        BookUpdate {
+            publisher: Some("Orbit".to_string()),
+            ..empty_update()
+        },
+    )
+    .unwrap();
+    assert_eq!(
+        count_rows(&temp, "SELECT COUNT(*) AS count FROM publishers"),
+        1
+    );
+    assert_eq!(
+        count_rows(
+            &temp,
+            "SELECT COUNT(*) AS count FROM publishers WHERE name = 'Orbit'"
+        ),
+        1
+    );
+
+    // An empty name unlinks, and the orphaned publisher is removed.
+    lib.update_book(
+        book.id,
+        BookUpdate {
+            publisher: Some("  ".to_string()),
+            ..empty_update()
+        },
+    )
+    .unwrap();
+    assert_eq!(
+        count_rows(&temp, "SELECT COUNT(*) AS count FROM books_publishers_link"),
+        0
+    );
+    assert_eq!(
+        count_rows(&temp, "SELECT COUNT(*) AS count FROM publishers"),
+        0
+    );
+}
+
+#[test]
+fn test_update_book_publisher_shared_between_books_is_kept() {
+    let (temp, mut lib) = setup_with_library();
+
+    let first = lib.add_book(empty_book("First")).unwrap();
+    let second = lib.add_book(empty_book("Second")).unwrap();
+
+    for (book_id, name) in [(first.id, "Tor Books"), (second.id, "tor books")] {
+        lib.update_book(
+            book_id,
+            BookUpdate {
+                publisher: Some(name.to_string()),
+                ..empty_update()
+            },
+        )
+        .unwrap();
+    }
+    // Case-insensitive match reuses the existing row.
+    assert_eq!(
+        count_rows(&temp, "SELECT COUNT(*) AS count FROM publishers"),
+        1
+    );
+
+    lib.update_book(
+        first.id,
+        BookUpdate {
+            publisher: Some("".to_string()),
+            ..empty_update()
+        },
+    )
+    .unwrap();
+    // Still linked to the second book, so not an orphan.
+    assert_eq!(
+        count_rows(&temp, "SELECT COUNT(*) AS count FROM publishers"),
+        1
+    );
+}
+
+#[test]
+fn test_update_book_rating_uses_calibre_scale() {
+    let (temp, mut lib) = setup_with_library();
+
+    let book = lib.add_book(empty_book("Rated Book")).unwrap();
+
+    lib.update_book(
+        book.id,
+        BookUpdate {
+            rating: Some(8),
+            ..empty_update()
+        },
+    )
+    .unwrap();
+    assert_eq!(
+        count_rows(
+            &temp,
+            "SELECT r.rating AS count FROM books_ratings_link brl
+             JOIN ratings r ON r.id = brl.rating"
+        ),
+        8
+    );
+
+    // Out-of-range values clamp to 10, and the unused 8 row is dropped.
+    lib.update_book(
+        book.id,
+        BookUpdate {
+            rating: Some(15),
+            ..empty_update()
+        },
+    )
+    .unwrap();
+    assert_eq!(
+        count_rows(
+            &temp,
+            "SELECT r.rating AS count FROM books_ratings_link brl
+             JOIN ratings r ON r.id = brl.rating"
+        ),
+        10
+    );
+    assert_eq!(
+        count_rows(&temp, "SELECT COUNT(*) AS count FROM ratings"),
+        1
+    );
+
+    // Zero means "no rating".
+    lib.update_book(
+        book.id,
+        BookUpdate {
+            rating: Some(0),
+            ..empty_update()
+        },
+    )
+    .unwrap();
+    assert_eq!(
+        count_rows(&temp, "SELECT COUNT(*) AS count FROM books_ratings_link"),
+        0
+    );
+    assert_eq!(
+        count_rows(&temp, "SELECT COUNT(*) AS count FROM ratings"),
+        0
+    );
+}
+
+#[test]
+fn test_update_book_replaces_identifiers() {
+    let (_temp, mut lib) = setup_with_library();
+
+    let book = lib.add_book(empty_book("Identified Book")).unwrap();
+    lib.upsert_book_identifier(book.id, "asin".to_string(), "B000".to_string(), None)
+        .unwrap();
+
+    let updated = lib
+        .update_book(
+            book.id,
+            BookUpdate {
+                identifiers: Some(HashMap::from([
+                    ("ISBN".to_string(), " 9780000000000 ".to_string()),
+                    ("goodreads".to_string(), "123".to_string()),
+                    ("empty".to_string(), "".to_string()),
+                ])),
+                ..empty_update()
+            },
+        )
+        .unwrap();
+
+    let mut pairs: Vec<(String, String)> = updated
+        .identifiers
+        .iter()
+        .map(|id| (id.label.clone(), id.value.clone()))
+        .collect();
+    pairs.sort();
+    assert_eq!(
+        pairs,
+        vec![
+            ("goodreads".to_string(), "123".to_string()),
+            ("isbn".to_string(), "9780000000000".to_string()),
+        ]
+    );
+
+    let cleared = lib
+        .update_book(
+            book.id,
+            BookUpdate {
+                identifiers: Some(HashMap::new()),
+                ..empty_update()
+            },
+        )
+        .unwrap();
+    assert!(cleared.identifiers.is_empty());
+}
+
+#[test]
+fn test_update_book_comments_write_and_clear() {
+    let (temp, mut lib) = setup_with_library();
+
+    let book = lib.add_book(empty_book("Commented Book")).unwrap();
+
+    let updated = lib
+        .update_book(
+            book.id,
+            BookUpdate {
+                comments: Some("<p>Lovely.</p>".to_string()),
+                ..empty_update()
+            },
+        )
+        .unwrap();
+    assert_eq!(updated.description, Some("<p>Lovely.</p>".to_string()));
+
+    let cleared = lib
+        .update_book(
+            book.id,
+            BookUpdate {
+                comments: Some("".to_string()),
+                ..empty_update()
+            },
+        )
+        .unwrap();
+    assert_eq!(cleared.description, None);
+    assert_eq!(
+        count_rows(&temp, "SELECT COUNT(*) AS count FROM comments"),
+        0
+    );
+}
+
+#[test]
+fn test_update_book_rejects_description_and_comments_together() {
+    let (_temp, mut lib) = setup_with_library();
+
+    let book = lib.add_book(empty_book("Test Book")).unwrap();
+
+    let result = lib.update_book(
+        book.id,
+        BookUpdate {
+            description: Some("One".to_string()),
+            comments: Some("Two".to_string()),
+            ..empty_update()
+        },
+    );
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_books_hydrate_publisher_rating_and_publication_date() {
+    let (_temp, mut lib) = setup_with_library();
+
+    let pubdate = chrono::NaiveDate::from_ymd_opt(1965, 8, 1).unwrap();
+    let described = lib
+        .add_book(BookAdd {
+            publication_date: Some(pubdate),
+            ..empty_book("Dune")
+        })
+        .unwrap();
+    let plain = lib.add_book(empty_book("Plain")).unwrap();
+
+    lib.update_book(
+        described.id,
+        BookUpdate {
+            publisher: Some("Chilton Books".to_string()),
+            rating: Some(8),
+            ..empty_update()
+        },
+    )
+    .unwrap();
+
+    let single = lib.get_book(described.id).unwrap();
+    assert_eq!(single.publisher.as_deref(), Some("Chilton Books"));
+    assert_eq!(single.rating, Some(8));
+    assert_eq!(
+        single.publication_date.map(|date| date.date()),
+        Some(pubdate)
+    );
+
+    let page = lib.query_books(libcalibre::BookQuery::default()).unwrap();
+    let listed = page
+        .items
+        .iter()
+        .find(|book| book.id == described.id)
+        .unwrap();
+    assert_eq!(listed.publisher, single.publisher);
+    assert_eq!(listed.rating, single.rating);
+    assert_eq!(listed.publication_date, single.publication_date);
+
+    let bare = lib
+        .books()
+        .unwrap()
+        .into_iter()
+        .find(|book| book.id == plain.id)
+        .unwrap();
+    assert_eq!(bare.publisher, None);
+    assert_eq!(bare.rating, None);
+}
+
+#[test]
+fn test_calibre_undefined_publication_date_reads_as_none() {
+    let (_temp, mut lib) = setup_with_library();
+
+    let book = lib
+        .add_book(BookAdd {
+            publication_date: chrono::NaiveDate::from_ymd_opt(101, 1, 1),
+            ..empty_book("Undated")
+        })
+        .unwrap();
+
+    assert_eq!(lib.get_book(book.id).unwrap().publication_date, None);
+}
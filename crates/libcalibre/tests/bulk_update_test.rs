@@ -0,0 +1,253 @@
+// Tests for editing the metadata of many books at once
+// This binary uses only a subset of the shared test helpers.
+#[allow(dead_code, unused_imports)]
+mod common;
+
+use common::{setup_with_library, standard_test_book};
+use libcalibre::{
+    BookAdd, BookId, BulkEdit, BulkSeriesIndex, CalibreError, CustomColumnKind, CustomColumnSpec,
+    CustomValue, Library, SearchReplace, SearchReplaceField,
+};
+use std::collections::HashMap;
+use std::path::Path;
+
+fn add(lib: &mut Library, title: &str, tags: &[&str]) -> BookId {
+    lib.add_book(BookAdd {
+        title: title.to_string(),
+        author_names: vec!["Terry Pratchett".to_string()],
+        tags: Some(tags.iter().map(|tag| tag.to_string()).collect()),
+        ..standard_test_book()
+    })
+    .unwrap()
+    .id
+}
+
+fn search_replace(field: SearchReplaceField, pattern: &str, replacement: &str) -> SearchReplace {
+    SearchReplace {
+        field,
+        pattern: pattern.to_string(),
+        replacement: replacement.to_string(),
+        case_sensitive: false,
+    }
+}
+
+#[test]
+fn test_bulk_update_sets_fields_on_every_book() {
+    let (_temp, mut lib) = setup_with_library();
+    let mort = add(&mut lib, "Mort", &["Fantasy", "To Read"]);
+    let jingo = add(&mut lib, "Jingo", &["fantasy"]);
+
+    let report = lib
+        .bulk_update(
+            &[mort, jingo],
+            BulkEdit {
+                add_tags: vec!["Discworld".to_string(), "FANTASY".to_string()],
+                remove_tags: vec!["to read".to_string()],
+                publisher: Some("Corgi".to_string()),
+                language_codes: Some(vec!["en".to_string()]),
+                rating: Some(8),
+                is_read: Some(true),
+                ..BulkEdit::default()
+            },
+        )
+        .unwrap();
+    assert_eq!(report.updated, vec![mort, jingo]);
+    assert!(report.failed.is_empty());
+
+    for (book_id, tags) in [
+        (mort, vec!["Discworld", "Fantasy"]),
+        // Tags are shared, so Jingo's is the same "Fantasy".
+        (jingo, vec!["Discworld", "Fantasy"]),
+    ] {
+        let book = lib.get_book(book_id).unwrap();
+        let mut have = book.tags.clone();
+        have.sort();
+        assert_eq!(have, tags);
+        assert_eq!(book.publisher.as_deref(), Some("Corgi"));
+        assert_eq!(book.language_codes, vec!["eng"]);
+        assert_eq!(book.rating, Some(8));
+        assert!(book.is_read);
+
+        // OPFs are written once the edit commits.
+        let opf = std::fs::read_to_string(
+            Path::new(lib.library_path())
+                .join(&book.book_dir_path)
+                .join("metadata.opf"),
+        )
+        .unwrap();
+        assert!(opf.contains("Corgi"));
+        assert!(opf.contains("Discworld"));
+    }
+}
+
+#[test]
+fn test_bulk_update_numbers_books_in_a_series() {
+    let (_temp, mut lib) = setup_with_library();
+    let colour = add(&mut lib, "The Colour of Magic", &[]);
+    let mort = add(&mut lib, "Mort", &[]);
+    let sourcery = add(&mut lib, "Sourcery", &[]);
+
+    lib.bulk_update(
+        &[colour],
+        BulkEdit {
+            series: Some("Discworld".to_string()),
+            series_index: BulkSeriesIndex::From(3.5),
+            ..BulkEdit::default()
+        },
+    )
+    .unwrap();
+
+    // Continues after the highest whole index among the series' other books.
+    lib.bulk_update(
+        &[sourcery, mort],
+        BulkEdit {
+            series: Some("discworld".to_string()),
+            series_index: BulkSeriesIndex::Next,
+            ..BulkEdit::default()
+        },
+    )
+    .unwrap();
+
+    let index = |lib: &mut Library, book_id| {
+        let book = lib.get_book(book_id).unwrap();
+        assert_eq!(book.series.as_deref(), Some("Discworld"));
+        book.series_index.unwrap()
+    };
+    assert_eq!(index(&mut lib, colour), 3.5);
+    assert_eq!(index(&mut lib, sourcery), 4.0);
+    assert_eq!(index(&mut lib, mort), 5.0);
+
+    // Keeping indices leaves them alone.
+    lib.bulk_update(
+        &[mort],
+        BulkEdit {
+            series: Some("Discworld".to_string()),
+            ..BulkEdit::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(index(&mut lib, mort), 5.0);
+}
+
+#[test]
+fn test_bulk_update_search_and_replace() {
+    let (_temp, mut lib) = setup_with_library();
+    let mort = add(&mut lib, "Mort (Discworld 4)", &["science", "Fantasy"]);
+    let jingo = add(&mut lib, "Jingo (Discworld 21)", &["Sci-Fi"]);
+
+    let report = lib
+        .bulk_update(
+            &[mort, jingo],
+            BulkEdit {
+                search_replace: Some(search_replace(
+                    SearchReplaceField::Title,
+                    r"\s*\(discworld (\d+)\)",
+                    ", book $1",
+                )),
+                ..BulkEdit::default()
+            },
+        )
+        .unwrap();
+    assert_eq!(report.updated.len(), 2);
+    let book = lib.get_book(mort).unwrap();
+    assert_eq!(book.title, "Mort, book 4");
+    assert!(book.book_dir_path.contains("Mort, book 4"));
+    assert_eq!(lib.get_book(jingo).unwrap().title, "Jingo, book 21");
+
+    // Tags are replaced one by one; a tag replaced with nothing goes.
+    lib.bulk_update(
+        &[mort, jingo],
+        BulkEdit {
+            search_replace: Some(SearchReplace {
+                case_sensitive: true,
+                ..search_replace(SearchReplaceField::Tags, "^sci.*", "")
+            }),
+            ..BulkEdit::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(lib.get_book(mort).unwrap().tags, vec!["Fantasy"]);
+    assert_eq!(lib.get_book(jingo).unwrap().tags, vec!["Sci-Fi"]);
+
+    // A bad pattern changes nothing.
+    let bad = lib.bulk_update(
+        &[mort],
+        BulkEdit {
+            add_tags: vec!["Discworld".to_string()],
+            search_replace: Some(search_replace(SearchReplaceField::Title, "(", "")),
+            ..BulkEdit::default()
+        },
+    );
+    assert!(matches!(bad, Err(CalibreError::InvalidSearch(_))));
+    assert_eq!(lib.get_book(mort).unwrap().tags, vec!["Fantasy"]);
+}
+
+#[test]
+fn test_bulk_update_reports_books_it_cannot_edit() {
+    let (_temp, mut lib) = setup_with_library();
+    let mort = add(&mut lib, "Mort", &[]);
+    let eric = add(&mut lib, "Eric", &[]);
+    let jingo = add(&mut lib, "Jingo", &[]);
+    let missing = BookId::from(9999);
+
+    let shelf = lib
+        .create_custom_column(CustomColumnSpec {
+            label: "shelf".to_string(),
+            name: "Shelf".to_string(),
+            kind: CustomColumnKind::Text,
+            is_multiple: false,
+            enum_values: vec![],
+            display: None,
+        })
+        .unwrap();
+
+    // Removing the whole of "Eric" would leave it without a title.
+    let report = lib
+        .bulk_update(
+            &[mort, missing, eric, jingo],
+            BulkEdit {
+                add_tags: vec!["Discworld".to_string()],
+                series: Some("Discworld".to_string()),
+                series_index: BulkSeriesIndex::Next,
+                custom_values: HashMap::from([(
+                    shelf.id,
+                    Some(CustomValue::Text("Study".to_string())),
+                )]),
+                search_replace: Some(search_replace(SearchReplaceField::Title, "^eric$", "")),
+                ..BulkEdit::default()
+            },
+        )
+        .unwrap();
+
+    assert_eq!(report.updated, vec![mort, jingo]);
+    let failed: Vec<BookId> = report.failed.iter().map(|f| f.book_id).collect();
+    assert_eq!(failed, vec![missing, eric]);
+
+    // The books that failed are untouched, and numbering skips them.
+    let book = lib.get_book(eric).unwrap();
+    assert_eq!(book.title, "Eric");
+    assert!(book.tags.is_empty());
+    assert_eq!(book.series, None);
+    assert_eq!(lib.get_custom_value(eric, shelf.id).unwrap(), None);
+
+    let book = lib.get_book(jingo).unwrap();
+    assert_eq!(book.tags, vec!["Discworld"]);
+    assert_eq!(book.series_index, Some(2.0));
+    assert_eq!(
+        lib.get_custom_value(jingo, shelf.id).unwrap(),
+        Some(CustomValue::Text("Study".to_string()))
+    );
+
+    // An unknown custom column fails the whole edit.
+    let unknown = lib.bulk_update(
+        &[mort],
+        BulkEdit {
+            custom_values: HashMap::from([(999, None)]),
+            ..BulkEdit::default()
+        },
+    );
+    assert!(matches!(
+        unknown,
+        Err(CalibreError::CustomColumnNotFound(999))
+    ));
+}
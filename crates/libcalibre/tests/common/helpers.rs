@@ -34,6 +34,13 @@ pub fn setup_with_library() -> (TempDir, Library) {
     (temp_dir, library)
 }
 
+/// A second connection to the library's database, for reading or staging
+/// rows the `Library` API does not expose
+#[allow(dead_code)]
+pub fn conn(lib: &Library) -> diesel::SqliteConnection {
+    libcalibre::persistence::establish_connection(lib.database_path()).unwrap()
+}
+
 /// Standard test book used across tests
 pub fn standard_test_book() -> BookAdd {
     BookAdd {
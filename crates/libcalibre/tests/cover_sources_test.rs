@@ -86,3 +86,40 @@ fn cover_sources_for_empty_input_returns_empty() {
 
     assert!(lib.cover_sources_for(&[]).unwrap().is_empty());
 }
+
+#[test]
+fn remove_book_cover_deletes_file_and_clears_flag() {
+    let (temp, mut lib) = setup_with_library();
+    let book = lib.add_book(empty_book("Covered")).unwrap();
+    lib.set_book_cover(book.id, TINY_JPEG.to_vec()).unwrap();
+
+    let book_dir = temp.path().join(&book.book_dir_path);
+    assert!(book_dir.join("cover.jpg").exists());
+    let opf = std::fs::read_to_string(book_dir.join("metadata.opf")).unwrap();
+    assert!(opf.contains(r#"<reference type="cover""#));
+
+    lib.remove_book_cover(book.id).unwrap();
+
+    assert!(!book_dir.join("cover.jpg").exists());
+    assert!(!lib.get_book(book.id).unwrap().has_cover);
+    assert!(lib.cover_sources().unwrap().is_empty());
+    let opf = std::fs::read_to_string(book_dir.join("metadata.opf")).unwrap();
+    assert!(!opf.contains(r#"<reference type="cover""#));
+}
+
+#[test]
+fn remove_book_cover_without_cover_is_a_no_op() {
+    let (_temp, mut lib) = setup_with_library();
+    let book = lib.add_book(empty_book("Bare")).unwrap();
+
+    lib.remove_book_cover(book.id).unwrap();
+
+    assert!(!lib.get_book(book.id).unwrap().has_cover);
+}
+
+#[test]
+fn remove_book_cover_unknown_book_errors() {
+    let (_temp, mut lib) = setup_with_library();
+
+    assert!(lib.remove_book_cover(BookId::from(9999)).is_err());
+}
@@ -0,0 +1,136 @@
+// Tests for Library::create_library_at against a library Calibre 7.2 created
+#[allow(dead_code, unused_imports)]
+mod common;
+
+use common::standard_test_book;
+use libcalibre::Library;
+use rusqlite::Connection;
+use std::path::{Path, PathBuf};
+
+/// Extract `metadata.db` from the bundled Calibre 7.2 empty library zip.
+fn calibre_reference_db(dir: &Path) -> PathBuf {
+    let zip_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("fixtures")
+        .join("empty_7_2_calibre_lib.zip");
+    let file = std::fs::File::open(zip_path).unwrap();
+    zip::ZipArchive::new(file).unwrap().extract(dir).unwrap();
+    dir.join("metadata.db")
+}
+
+/// `(type, name, table, sql)` for every schema object, with runs of
+/// whitespace in the SQL collapsed so indentation does not count.
+fn schema_objects(db_path: &Path) -> Vec<(String, String, String, Option<String>)> {
+    let conn = Connection::open(db_path).unwrap();
+    let mut stmt = conn
+        .prepare("SELECT type, name, tbl_name, sql FROM sqlite_master ORDER BY type, name")
+        .unwrap();
+    stmt.query_map([], |row| {
+        let sql: Option<String> = row.get(3)?;
+        Ok((
+            row.get(0)?,
+            row.get(1)?,
+            row.get(2)?,
+            sql.map(|sql| sql.split_whitespace().collect::<Vec<_>>().join(" ")),
+        ))
+    })
+    .unwrap()
+    .collect::<Result<_, _>>()
+    .unwrap()
+}
+
+fn user_version(db_path: &Path) -> i64 {
+    Connection::open(db_path)
+        .unwrap()
+        .query_row("PRAGMA user_version", [], |row| row.get(0))
+        .unwrap()
+}
+
+fn library_uuid(db_path: &Path) -> String {
+    Connection::open(db_path)
+        .unwrap()
+        .query_row("SELECT uuid FROM library_id", [], |row| row.get(0))
+        .unwrap()
+}
+
+#[test]
+fn test_created_library_is_schema_identical_to_calibre() {
+    let temp = tempfile::tempdir().unwrap();
+    let reference = calibre_reference_db(&temp.path().join("reference"));
+    let root = temp.path().join("created");
+
+    let lib = Library::create_library_at(root.to_str().unwrap()).unwrap();
+    let created = PathBuf::from(lib.database_path());
+    drop(lib);
+
+    assert_eq!(schema_objects(&created), schema_objects(&reference));
+    assert_eq!(user_version(&created), user_version(&reference));
+}
+
+#[test]
+fn test_created_library_has_calibre_default_preferences() {
+    let temp = tempfile::tempdir().unwrap();
+    let reference = calibre_reference_db(&temp.path().join("reference"));
+    let lib = Library::create_library_at(temp.path().join("created").to_str().unwrap()).unwrap();
+
+    let reference_conn = Connection::open(reference).unwrap();
+    let created_conn = Connection::open(lib.database_path()).unwrap();
+    let mut stmt = created_conn
+        .prepare("SELECT key, val FROM preferences ORDER BY id")
+        .unwrap();
+    let created_prefs: Vec<(String, String)> = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+
+    assert!(!created_prefs.is_empty());
+    for (key, val) in created_prefs {
+        let reference_val: String = reference_conn
+            .query_row(
+                "SELECT val FROM preferences WHERE key = ?1",
+                [&key],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(val, reference_val, "preference {key}");
+    }
+}
+
+#[test]
+fn test_created_libraries_get_distinct_library_ids() {
+    let temp = tempfile::tempdir().unwrap();
+    let first = Library::create_library_at(temp.path().join("a").to_str().unwrap()).unwrap();
+    let second = Library::create_library_at(temp.path().join("b").to_str().unwrap()).unwrap();
+
+    let first_uuid = library_uuid(Path::new(first.database_path()));
+    let second_uuid = library_uuid(Path::new(second.database_path()));
+    assert_eq!(first_uuid.len(), 36);
+    assert_ne!(first_uuid, second_uuid);
+}
+
+#[test]
+fn test_create_library_refuses_existing_database() {
+    let temp = tempfile::tempdir().unwrap();
+    let root = temp.path().to_str().unwrap();
+    std::fs::write(temp.path().join("metadata.db"), b"not a database").unwrap();
+
+    assert!(Library::create_library_at(root).is_err());
+    assert_eq!(
+        std::fs::read(temp.path().join("metadata.db")).unwrap(),
+        b"not a database"
+    );
+}
+
+#[test]
+fn test_created_library_accepts_books() {
+    let temp = tempfile::tempdir().unwrap();
+    let mut lib = Library::create_library_at(temp.path().to_str().unwrap()).unwrap();
+
+    let book = lib.add_book(standard_test_book()).unwrap();
+    let fetched = lib.get_book(book.id).unwrap();
+
+    assert_eq!(fetched.title, "The Test Book");
+    assert_eq!(fetched.authors[0].name, "John Doe");
+    assert_eq!(fetched.uuid.len(), 36);
+}
@@ -0,0 +1,86 @@
+// Tests for grouping near-duplicate authors by their normalized name
+// This binary uses only a subset of the shared test helpers.
+#[allow(dead_code, unused_imports)]
+mod common;
+
+use common::{setup_with_library, standard_test_book};
+use libcalibre::{AuthorAdd, BookAdd, DuplicateAuthorGroup, Library};
+
+fn add_book(lib: &mut Library, title: &str, author: &str) {
+    lib.add_book(BookAdd {
+        title: title.to_string(),
+        author_names: vec![author.to_string()],
+        ..standard_test_book()
+    })
+    .unwrap();
+}
+
+fn names(group: &DuplicateAuthorGroup) -> Vec<(&str, i64)> {
+    group
+        .authors
+        .iter()
+        .map(|dup| (dup.author.name.as_str(), dup.book_count))
+        .collect()
+}
+
+#[test]
+fn test_find_duplicate_authors_groups_spellings() {
+    let (_temp, mut lib) = setup_with_library();
+    add_book(&mut lib, "The Hobbit", "J. R. R. Tolkien");
+    add_book(&mut lib, "The Silmarillion", "J. R. R. Tolkien");
+    add_book(&mut lib, "Unfinished Tales", "J.R.R. Tolkien");
+    add_book(
+        &mut lib,
+        "Love in the Time of Cholera",
+        "Gabriel García Márquez",
+    );
+    add_book(
+        &mut lib,
+        "Chronicle of a Death Foretold",
+        "Marquez, Gabriel Garcia",
+    );
+    add_book(&mut lib, "Dune", "Frank Herbert");
+    lib.add_author(AuthorAdd {
+        name: "Tolkien, J.R.R.".to_string(),
+        sort: None,
+        link: None,
+    })
+    .unwrap();
+
+    let groups = lib.find_duplicate_authors().unwrap();
+    assert_eq!(groups.len(), 2);
+    assert_eq!(groups[0].key, "marquez,gabrielgarcia");
+    assert_eq!(
+        names(&groups[0]),
+        vec![
+            ("Gabriel García Márquez", 1),
+            ("Marquez, Gabriel Garcia", 1)
+        ]
+    );
+    assert_eq!(groups[1].key, "tolkien,jrr");
+    assert_eq!(
+        names(&groups[1]),
+        vec![
+            ("J. R. R. Tolkien", 2),
+            ("J.R.R. Tolkien", 1),
+            ("Tolkien, J.R.R.", 0)
+        ]
+    );
+}
+
+#[test]
+fn test_find_duplicate_authors_then_merge() {
+    let (_temp, mut lib) = setup_with_library();
+    add_book(&mut lib, "Mort", "Terry Pratchett");
+    add_book(&mut lib, "Jingo", "Pratchett, Terry");
+    add_book(&mut lib, "Dune", "Frank Herbert");
+
+    let groups = lib.find_duplicate_authors().unwrap();
+    assert_eq!(groups.len(), 1);
+    let into = groups[0].authors[0].author.id;
+    for dup in &groups[0].authors[1..] {
+        lib.merge_authors(dup.author.id, into).unwrap();
+    }
+    assert!(lib.find_duplicate_authors().unwrap().is_empty());
+    assert_eq!(lib.author_book_counts().unwrap()[&into], 2);
+}
@@ -0,0 +1,292 @@
+// Tests for finding duplicate books on import and across the library
+// This binary uses only a subset of the shared test helpers.
+#[allow(dead_code, unused_imports)]
+mod common;
+
+use common::{setup_with_library, standard_test_book};
+use libcalibre::{
+    BookAdd, BookId, DuplicateBookGroup, DuplicateBookMatch, DuplicateReason, ImportOutcome,
+    Library, OnDuplicate,
+};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+fn source(sources: &TempDir, name: &str, content: &str) -> PathBuf {
+    let path = sources.path().join(name);
+    std::fs::write(&path, content).unwrap();
+    path
+}
+
+fn book_add(title: &str, author: &str, isbn: Option<&str>, files: Vec<PathBuf>) -> BookAdd {
+    BookAdd {
+        title: title.to_string(),
+        author_names: vec![author.to_string()],
+        identifiers: isbn
+            .map(|isbn| HashMap::from([("isbn".to_string(), isbn.to_string())]))
+            .unwrap_or_default(),
+        file_paths: files,
+        ..standard_test_book()
+    }
+}
+
+fn add(lib: &mut Library, book: BookAdd) -> BookId {
+    lib.add_book(book).unwrap().id
+}
+
+#[test]
+fn test_find_duplicates_of_by_each_reason() {
+    let sources = tempfile::tempdir().unwrap();
+    let (_temp, mut lib) = setup_with_library();
+    let thrones = add(
+        &mut lib,
+        book_add(
+            "A Game of Thrones",
+            "George R. R. Martin",
+            Some("978-0-553-10354-0"),
+            vec![],
+        ),
+    );
+    let hobbit = add(
+        &mut lib,
+        book_add("The Hobbit", "J.R.R. Tolkien", None, vec![]),
+    );
+    let dune_file = source(&sources, "dune.txt", "A beginning is a very delicate time.");
+    let dune = add(
+        &mut lib,
+        book_add("Dune", "Frank Herbert", None, vec![dune_file]),
+    );
+
+    // The ISBN-10 form of the same edition, under another title.
+    let by_isbn = book_add("Game of Thrones", "Someone", Some("0553103547"), vec![]);
+    assert_eq!(
+        lib.find_duplicates_of(&by_isbn).unwrap(),
+        vec![DuplicateBookMatch {
+            book_id: thrones,
+            reasons: vec![DuplicateReason::Isbn],
+        }]
+    );
+
+    let by_title = book_add("hobbit!", "Tolkien, J. R. R.", None, vec![]);
+    assert_eq!(
+        lib.find_duplicates_of(&by_title).unwrap(),
+        vec![DuplicateBookMatch {
+            book_id: hobbit,
+            reasons: vec![DuplicateReason::TitleAndAuthor],
+        }]
+    );
+
+    let copy = source(&sources, "copy.txt", "A beginning is a very delicate time.");
+    let by_file = book_add("Unknown", "Unknown", None, vec![copy]);
+    assert_eq!(
+        lib.find_duplicates_of(&by_file).unwrap(),
+        vec![DuplicateBookMatch {
+            book_id: dune,
+            reasons: vec![DuplicateReason::SameFile],
+        }]
+    );
+
+    // Same title by someone else, and a same-sized file with other content.
+    let other = source(
+        &sources,
+        "other.txt",
+        "A beginning is a very delicate tame.",
+    );
+    let unrelated = book_add("The Hobbit", "Someone Else", None, vec![other]);
+    assert!(lib.find_duplicates_of(&unrelated).unwrap().is_empty());
+}
+
+#[test]
+fn test_find_duplicates_of_stored_isbn_10_and_authorless_books() {
+    let (_temp, mut lib) = setup_with_library();
+    let thrones = add(
+        &mut lib,
+        book_add(
+            "A Game of Thrones",
+            "George R. R. Martin",
+            Some("ISBN: 0-553-10354-7"),
+            vec![],
+        ),
+    );
+    let anonymous = add(
+        &mut lib,
+        BookAdd {
+            title: "Beowulf".to_string(),
+            author_names: vec![],
+            ..standard_test_book()
+        },
+    );
+
+    let by_isbn = book_add("Thrones", "Someone", Some("9780553103540"), vec![]);
+    assert_eq!(
+        lib.find_duplicates_of(&by_isbn).unwrap(),
+        vec![DuplicateBookMatch {
+            book_id: thrones,
+            reasons: vec![DuplicateReason::Isbn],
+        }]
+    );
+
+    let by_title = BookAdd {
+        title: "Beowulf".to_string(),
+        author_names: vec![],
+        ..standard_test_book()
+    };
+    assert_eq!(
+        lib.find_duplicates_of(&by_title).unwrap(),
+        vec![DuplicateBookMatch {
+            book_id: anonymous,
+            reasons: vec![DuplicateReason::TitleAndAuthor],
+        }]
+    );
+}
+
+#[test]
+fn test_find_duplicate_books_groups_the_library() {
+    let sources = tempfile::tempdir().unwrap();
+    let (_temp, mut lib) = setup_with_library();
+    let first = add(
+        &mut lib,
+        book_add("Dune", "Frank Herbert", Some("9780441013593"), vec![]),
+    );
+    let second = add(
+        &mut lib,
+        book_add("Dune (Deluxe Edition)", "Herbert, Frank", None, vec![]),
+    );
+    let third = add(
+        &mut lib,
+        book_add("Dune: Book One", "F. Herbert", Some("0441013597"), vec![]),
+    );
+    let text = "I must not fear.";
+    let mort_a = add(
+        &mut lib,
+        book_add(
+            "Mort",
+            "Terry Pratchett",
+            None,
+            vec![source(&sources, "a.txt", text)],
+        ),
+    );
+    let mort_b = add(
+        &mut lib,
+        book_add(
+            "Mort (Discworld 4)",
+            "Pratchett",
+            None,
+            vec![source(&sources, "b.txt", text)],
+        ),
+    );
+    add(&mut lib, book_add("Jingo", "Terry Pratchett", None, vec![]));
+
+    assert_eq!(
+        lib.find_duplicate_books().unwrap(),
+        vec![
+            DuplicateBookGroup {
+                book_ids: vec![first, second, third],
+                reasons: vec![DuplicateReason::Isbn, DuplicateReason::TitleAndAuthor],
+            },
+            DuplicateBookGroup {
+                book_ids: vec![mort_a, mort_b],
+                reasons: vec![DuplicateReason::SameFile],
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_import_book_skip_and_add_anyway() {
+    let (_temp, mut lib) = setup_with_library();
+    let hobbit = add(
+        &mut lib,
+        book_add("The Hobbit", "J. R. R. Tolkien", None, vec![]),
+    );
+
+    let again = || book_add("The Hobbit", "J.R.R. Tolkien", None, vec![]);
+    match lib.import_book(again(), OnDuplicate::Skip).unwrap() {
+        ImportOutcome::Skipped { duplicates } => {
+            assert_eq!(duplicates.len(), 1);
+            assert_eq!(duplicates[0].book_id, hobbit);
+        }
+        other => panic!("expected the import to be skipped, got {other:?}"),
+    }
+    assert_eq!(lib.books().unwrap().len(), 1);
+
+    match lib.import_book(again(), OnDuplicate::AddAnyway).unwrap() {
+        ImportOutcome::Added(book) => assert_ne!(book.id, hobbit),
+        other => panic!("expected the book to be added, got {other:?}"),
+    }
+    assert_eq!(lib.books().unwrap().len(), 2);
+
+    // Nothing to match, so nothing to skip.
+    let dune = book_add("Dune", "Frank Herbert", None, vec![]);
+    assert!(matches!(
+        lib.import_book(dune, OnDuplicate::Skip).unwrap(),
+        ImportOutcome::Added(_)
+    ));
+}
+
+#[test]
+fn test_import_book_adds_new_formats_to_the_duplicate() {
+    let sources = tempfile::tempdir().unwrap();
+    let (_temp, mut lib) = setup_with_library();
+    let txt = source(&sources, "mort.txt", "Mort, as plain text.");
+    let mort = add(
+        &mut lib,
+        book_add("Mort", "Terry Pratchett", None, vec![txt.clone()]),
+    );
+
+    let epub = source(&sources, "mort.epub", "Mort, as an EPUB.");
+    let import = book_add("Mort", "Terry Pratchett", None, vec![txt, epub]);
+    match lib.import_book(import, OnDuplicate::AddFormats).unwrap() {
+        ImportOutcome::AddedFormats { book_id, formats } => {
+            assert_eq!(book_id, mort);
+            assert_eq!(formats, vec!["EPUB"]);
+        }
+        other => panic!("expected formats to be added, got {other:?}"),
+    }
+    assert_eq!(lib.books().unwrap().len(), 1);
+
+    // The new format sits beside the old one, named like it.
+    let book = lib.get_book(mort).unwrap();
+    let mut formats: Vec<&str> = book.files.iter().map(|f| f.format.as_str()).collect();
+    formats.sort();
+    assert_eq!(formats, vec!["EPUB", "TXT"]);
+    let book_dir = Path::new(lib.library_path()).join(&book.book_dir_path);
+    let epub_path = book_dir.join(format!("{}.epub", book.files[0].name));
+    assert_eq!(
+        std::fs::read_to_string(epub_path).unwrap(),
+        "Mort, as an EPUB."
+    );
+
+    // Importing the same files again adds nothing.
+    let epub_again = source(&sources, "again.epub", "Mort, as an EPUB.");
+    let import = book_add("Mort", "Terry Pratchett", None, vec![epub_again]);
+    assert!(matches!(
+        lib.import_book(import, OnDuplicate::AddFormats).unwrap(),
+        ImportOutcome::AddedFormats { formats, .. } if formats.is_empty()
+    ));
+}
+
+#[test]
+fn test_import_book_adds_no_formats_when_one_fails() {
+    let sources = tempfile::tempdir().unwrap();
+    let (_temp, mut lib) = setup_with_library();
+    let txt = source(&sources, "mort.txt", "Mort, as plain text.");
+    let mort = add(
+        &mut lib,
+        book_add("Mort", "Terry Pratchett", None, vec![txt]),
+    );
+
+    let epub = source(&sources, "mort.epub", "Mort, as an EPUB.");
+    let missing = sources.path().join("mort.pdf");
+    let import = book_add("Mort", "Terry Pratchett", None, vec![epub, missing]);
+    assert!(lib.import_book(import, OnDuplicate::AddFormats).is_err());
+
+    // The EPUB copied before the PDF failed is gone again, row and file.
+    let book = lib.get_book(mort).unwrap();
+    let formats: Vec<&str> = book.files.iter().map(|f| f.format.as_str()).collect();
+    assert_eq!(formats, vec!["TXT"]);
+    let book_dir = Path::new(lib.library_path()).join(&book.book_dir_path);
+    assert!(!book_dir
+        .join(format!("{}.epub", book.files[0].name))
+        .exists());
+}
@@ -0,0 +1,227 @@
+// Tests for per-category counts over a query's matches (Library::facets)
+// This binary uses only a subset of the shared test helpers.
+#[allow(dead_code, unused_imports)]
+mod common;
+
+use common::{setup_with_library, standard_test_book};
+use libcalibre::{BookAdd, BookFacets, BookQuery, BookUpdate, FacetCount, Library};
+use std::path::Path;
+
+/// Four books:
+///
+/// | title          | authors                     | tags             | series   | publisher | lang | formats   | stars |
+/// |----------------|-----------------------------|------------------|----------|-----------|------|-----------|-------|
+/// | Good Omens     | Terry Pratchett, Neil Gaiman| Fantasy, Humour  |          | Gollancz  | eng  | EPUB, PDF | 4.5   |
+/// | Mort           | Terry Pratchett             | Fantasy, Humour  | Discworld| Gollancz  | eng  | EPUB      | 4     |
+/// | Sourcery       | Terry Pratchett             | Fantasy          | Discworld| Corgi     | eng  | PDF       | 4     |
+/// | Neverwhere     | Neil Gaiman                 | Fantasy, Urban   |          |           | fra  |           |       |
+fn library_with_books(sources: &Path) -> (tempfile::TempDir, Library) {
+    let (temp, mut lib) = setup_with_library();
+    type Row<'a> = (
+        &'a str,
+        &'a [&'a str],
+        &'a [&'a str],
+        Option<&'a str>,
+        Option<&'a str>,
+        &'a str,
+        &'a [&'a str],
+        Option<i32>,
+    );
+    let rows: [Row; 4] = [
+        (
+            "Good Omens",
+            &["Terry Pratchett", "Neil Gaiman"],
+            &["Fantasy", "Humour"],
+            None,
+            Some("Gollancz"),
+            "eng",
+            &["epub", "pdf"],
+            Some(9),
+        ),
+        (
+            "Mort",
+            &["Terry Pratchett"],
+            &["Fantasy", "Humour"],
+            Some("Discworld"),
+            Some("Gollancz"),
+            "eng",
+            &["epub"],
+            Some(8),
+        ),
+        (
+            "Sourcery",
+            &["Terry Pratchett"],
+            &["Fantasy"],
+            Some("Discworld"),
+            Some("Corgi"),
+            "eng",
+            &["pdf"],
+            Some(8),
+        ),
+        (
+            "Neverwhere",
+            &["Neil Gaiman"],
+            &["Fantasy", "Urban"],
+            None,
+            None,
+            "fra",
+            &[],
+            None,
+        ),
+    ];
+
+    for (title, authors, tags, series, publisher, language, formats, rating) in rows {
+        let file_paths = formats
+            .iter()
+            .map(|format| {
+                let path = sources.join(format!("{title}.{format}"));
+                std::fs::write(&path, title).unwrap();
+                path
+            })
+            .collect();
+        let created = lib
+            .add_book(BookAdd {
+                title: title.to_string(),
+                author_names: authors.iter().map(|a| (*a).to_string()).collect(),
+                tags: Some(tags.iter().map(|t| (*t).to_string()).collect()),
+                series: series.map(str::to_string),
+                series_index: series.map(|_| 1.0),
+                language: Some(language.to_string()),
+                file_paths,
+                ..standard_test_book()
+            })
+            .unwrap();
+        lib.update_book(
+            created.id,
+            BookUpdate {
+                publisher: publisher.map(str::to_string),
+                rating,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    }
+    (temp, lib)
+}
+
+fn counts<V>(values: &[FacetCount<V>]) -> Vec<(&str, i64)> {
+    values
+        .iter()
+        .map(|value| (value.name.as_str(), value.book_count))
+        .collect()
+}
+
+fn facets(lib: &mut Library, query: BookQuery) -> BookFacets {
+    lib.facets(&query).unwrap()
+}
+
+#[test]
+fn test_facets_of_whole_library() {
+    let sources = tempfile::tempdir().unwrap();
+    let (_temp, mut lib) = library_with_books(sources.path());
+    let all = facets(&mut lib, BookQuery::default());
+
+    assert_eq!(all.total, 4);
+    // Authors are ordered by their sort name.
+    assert_eq!(
+        counts(&all.authors),
+        vec![("Neil Gaiman", 2), ("Terry Pratchett", 3)]
+    );
+    assert_eq!(counts(&all.series), vec![("Discworld", 2)]);
+    assert_eq!(
+        counts(&all.tags),
+        vec![("Fantasy", 4), ("Humour", 2), ("Urban", 1)]
+    );
+    assert_eq!(counts(&all.publishers), vec![("Corgi", 1), ("Gollancz", 2)]);
+    assert_eq!(counts(&all.languages), vec![("eng", 3), ("fra", 1)]);
+    assert_eq!(counts(&all.formats), vec![("EPUB", 2), ("PDF", 2)]);
+    // Highest rating first; the unrated book is not counted.
+    assert_eq!(counts(&all.ratings), vec![("4.5", 1), ("4", 2)]);
+    assert_eq!(all.ratings[0].value, 9);
+    assert_eq!(all.languages[1].value, "fra");
+}
+
+#[test]
+fn test_facets_narrow_with_the_query() {
+    let sources = tempfile::tempdir().unwrap();
+    let (_temp, mut lib) = library_with_books(sources.path());
+
+    let discworld = facets(
+        &mut lib,
+        BookQuery {
+            search: Some("series:Discworld".to_string()),
+            ..BookQuery::default()
+        },
+    );
+    assert_eq!(discworld.total, 2);
+    assert_eq!(counts(&discworld.authors), vec![("Terry Pratchett", 2)]);
+    assert_eq!(counts(&discworld.tags), vec![("Fantasy", 2), ("Humour", 1)]);
+    assert_eq!(counts(&discworld.formats), vec![("EPUB", 1), ("PDF", 1)]);
+    assert!(discworld.languages.iter().all(|l| l.name == "eng"));
+
+    // Every facet value narrows the query to exactly its count.
+    let all = facets(&mut lib, BookQuery::default());
+    for tag in &all.tags {
+        let page = lib
+            .query_books(BookQuery {
+                tags_any: vec![tag.value],
+                ..BookQuery::default()
+            })
+            .unwrap();
+        assert_eq!(page.total, tag.book_count, "tag {}", tag.name);
+    }
+    for author in &all.authors {
+        let narrowed = facets(
+            &mut lib,
+            BookQuery {
+                author_id: Some(author.value),
+                ..BookQuery::default()
+            },
+        );
+        assert_eq!(narrowed.total, author.book_count, "author {}", author.name);
+    }
+
+    let nothing = facets(
+        &mut lib,
+        BookQuery {
+            text: Some("no such book".to_string()),
+            ..BookQuery::default()
+        },
+    );
+    assert_eq!(
+        nothing,
+        BookFacets {
+            total: 0,
+            ..BookFacets::default()
+        }
+    );
+}
+
+#[test]
+fn test_facets_respect_hide_read_and_ignore_paging() {
+    let sources = tempfile::tempdir().unwrap();
+    let (_temp, mut lib) = library_with_books(sources.path());
+    let mort = lib
+        .books()
+        .unwrap()
+        .into_iter()
+        .find(|book| book.title == "Mort")
+        .unwrap();
+    lib.set_book_read_state(mort.id, true).unwrap();
+
+    let unread = facets(
+        &mut lib,
+        BookQuery {
+            hide_read: true,
+            limit: Some(1),
+            offset: 2,
+            ..BookQuery::default()
+        },
+    );
+    assert_eq!(unread.total, 3);
+    assert_eq!(counts(&unread.series), vec![("Discworld", 1)]);
+    assert_eq!(
+        counts(&unread.publishers),
+        vec![("Corgi", 1), ("Gollancz", 1)]
+    );
+}
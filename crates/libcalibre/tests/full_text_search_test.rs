@@ -0,0 +1,249 @@
+// Tests for searching book contents (Library::search_full_text)
+// This binary uses only a subset of the shared test helpers.
+#[allow(dead_code, unused_imports)]
+mod common;
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use common::{setup_with_library, standard_test_book};
+use libcalibre::util::get_db_path;
+use libcalibre::{BookAdd, FullTextMatch, Library, SnippetSpan};
+use rusqlite::Connection;
+
+fn book(title: &str, file_paths: Vec<PathBuf>) -> BookAdd {
+    BookAdd {
+        title: title.to_string(),
+        author_names: vec!["Frank Herbert".to_string()],
+        file_paths,
+        ..standard_test_book()
+    }
+}
+
+/// A minimal EPUB with one chapter per entry of `chapters` (XHTML bodies).
+fn write_epub(path: &Path, chapters: &[&str]) {
+    let mut zip = zip::ZipWriter::new(std::fs::File::create(path).unwrap());
+    let stored =
+        zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+    zip.start_file("mimetype", stored).unwrap();
+    zip.write_all(b"application/epub+zip").unwrap();
+    zip.start_file("META-INF/container.xml", stored).unwrap();
+    zip.write_all(
+        br#"<?xml version="1.0"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles><rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/></rootfiles>
+</container>"#,
+    )
+    .unwrap();
+
+    let manifest: String = (0..chapters.len())
+        .map(|i| {
+            format!(r#"<item id="c{i}" href="c{i}.xhtml" media-type="application/xhtml+xml"/>"#)
+        })
+        .collect();
+    let spine: String = (0..chapters.len())
+        .map(|i| format!(r#"<itemref idref="c{i}"/>"#))
+        .collect();
+    zip.start_file("OEBPS/content.opf", stored).unwrap();
+    write!(
+        zip,
+        r#"<?xml version="1.0"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="2.0" unique-identifier="id">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:title>Test</dc:title><dc:identifier id="id">test</dc:identifier>
+  </metadata>
+  <manifest>{manifest}</manifest>
+  <spine>{spine}</spine>
+</package>"#
+    )
+    .unwrap();
+    for (i, body) in chapters.iter().enumerate() {
+        zip.start_file(format!("OEBPS/c{i}.xhtml"), stored).unwrap();
+        write!(
+            zip,
+            r#"<?xml version="1.0"?>
+<html xmlns="http://www.w3.org/1999/xhtml"><head><title>c{i}</title></head><body>{body}</body></html>"#
+        )
+        .unwrap();
+    }
+    zip.finish().unwrap();
+}
+
+/// A `full-text-search.db` with the tables Calibre's `fts.sql` creates,
+/// minus the FTS tables: those need Calibre's own tokenizer.
+fn create_calibre_fts_db(library_root: &Path) -> Connection {
+    let conn = Connection::open(library_root.join("full-text-search.db")).unwrap();
+    conn.execute_batch(
+        "CREATE TABLE dirtied_formats ( id INTEGER PRIMARY KEY,
+             book INTEGER NOT NULL,
+             format TEXT NOT NULL COLLATE NOCASE,
+             in_progress INTEGER NOT NULL DEFAULT FALSE,
+             UNIQUE(book, format)
+         );
+         CREATE TABLE books_text ( id INTEGER PRIMARY KEY,
+             book INTEGER NOT NULL,
+             timestamp REAL NOT NULL,
+             format TEXT NOT NULL COLLATE NOCASE,
+             format_size INTEGER NOT NULL DEFAULT 0,
+             format_hash TEXT NOT NULL DEFAULT '',
+             searchable_text TEXT NOT NULL DEFAULT '',
+             text_size INTEGER NOT NULL DEFAULT 0,
+             text_hash TEXT NOT NULL DEFAULT '',
+             err_msg TEXT DEFAULT '',
+             UNIQUE(book, format)
+         );",
+    )
+    .unwrap();
+    conn
+}
+
+fn open(root: &Path) -> Library {
+    Library::new(get_db_path(root.to_str().unwrap()).unwrap()).unwrap()
+}
+
+fn highlighted(found: &FullTextMatch) -> Vec<&str> {
+    found
+        .snippet
+        .iter()
+        .filter(|span| span.highlighted)
+        .map(|span| span.text.as_str())
+        .collect()
+}
+
+fn snippet_text(found: &FullTextMatch) -> String {
+    found
+        .snippet
+        .iter()
+        .map(|span| span.text.as_str())
+        .collect()
+}
+
+#[test]
+fn test_extracted_text_is_searchable_with_snippets() {
+    let (_temp, mut lib) = setup_with_library();
+    let sources = tempfile::tempdir().unwrap();
+    let epub = sources.path().join("dune.epub");
+    write_epub(
+        &epub,
+        &[
+            "<h1>Book One</h1><p>A beginning is the time for taking the most delicate care.</p>",
+            "<p>I must not fear. Fear is the <b>mind-killer</b>.</p>",
+        ],
+    );
+    let txt = sources.path().join("notes.txt");
+    std::fs::write(&txt, "\u{feff}The spice   must flow.\n\nSPICE!").unwrap();
+
+    let dune_txt = sources.path().join("dune.txt");
+    std::fs::write(&dune_txt, "Arrakis").unwrap();
+    let dune = lib.add_book(book("Dune", vec![epub, dune_txt])).unwrap();
+    let notes = lib.add_book(book("Notes", vec![txt])).unwrap();
+    // Without Calibre's full-text database, nothing is extracted on add.
+    assert!(lib.search_full_text("spice", 10).unwrap().is_empty());
+
+    assert_eq!(lib.index_book_text(dune.id).unwrap(), 2);
+    assert_eq!(lib.index_book_text(notes.id).unwrap(), 1);
+    // Unchanged files are not extracted again.
+    assert_eq!(lib.index_book_text(dune.id).unwrap(), 0);
+
+    let found = lib.search_full_text("FEAR mind-killer", 10).unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].book_id, dune.id);
+    assert_eq!(found[0].format, "EPUB");
+    assert_eq!(highlighted(&found[0]), vec!["fear", "Fear", "mind-killer"]);
+    assert!(snippet_text(&found[0]).contains("delicate care.\nI must not fear."));
+    assert!(snippet_text(&found[0]).starts_with("Book One\n"));
+
+    let found = lib.search_full_text("spice", 10).unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(
+        found[0].snippet,
+        vec![
+            SnippetSpan {
+                text: "The ".to_string(),
+                highlighted: false
+            },
+            SnippetSpan {
+                text: "spice".to_string(),
+                highlighted: true
+            },
+            SnippetSpan {
+                text: " must flow.\n".to_string(),
+                highlighted: false
+            },
+            SnippetSpan {
+                text: "SPICE".to_string(),
+                highlighted: true
+            },
+            SnippetSpan {
+                text: "!".to_string(),
+                highlighted: false
+            },
+        ]
+    );
+
+    // Snippets of long texts are cut around the first match.
+    let long = sources.path().join("long.txt");
+    std::fs::write(
+        &long,
+        format!("{}needle{}", "hay ".repeat(100), " hay".repeat(100)),
+    )
+    .unwrap();
+    let haystack = lib.add_book(book("Haystack", vec![long])).unwrap();
+    lib.index_book_text(haystack.id).unwrap();
+    let found = lib.search_full_text("needle", 10).unwrap();
+    let snippet = &found[0].snippet;
+    assert_eq!(snippet.first().unwrap().text, "…");
+    assert_eq!(snippet.last().unwrap().text, "…");
+    assert!(snippet_text(&found[0]).len() < 300);
+
+    // Every term must occur; phrases match as a whole.
+    assert!(lib.search_full_text("spice fear", 10).unwrap().is_empty());
+    assert_eq!(lib.search_full_text("\"not fear\"", 10).unwrap().len(), 1);
+    assert!(lib.search_full_text("\"fear not\"", 10).unwrap().is_empty());
+    assert!(lib.search_full_text("  ", 10).unwrap().is_empty());
+
+    // A removed format's text no longer matches.
+    lib.remove_book_file(dune.id, "EPUB").unwrap();
+    assert!(lib.search_full_text("fear", 10).unwrap().is_empty());
+    assert_eq!(
+        lib.search_full_text("arrakis", 10).unwrap()[0].format,
+        "TXT"
+    );
+}
+
+#[test]
+fn test_calibre_full_text_database_is_read_and_fed() {
+    let (temp, lib) = setup_with_library();
+    drop(lib);
+    let calibre = create_calibre_fts_db(temp.path());
+    let mut lib = open(temp.path());
+    let sources = tempfile::tempdir().unwrap();
+    let txt = sources.path().join("dune.txt");
+    std::fs::write(&txt, "Arrakis. Dune. Desert planet.").unwrap();
+
+    // With Calibre's full-text search on, new books are extracted on add,
+    // and queued for Calibre's own indexer.
+    let dune = lib.add_book(book("Dune", vec![txt])).unwrap();
+    assert_eq!(lib.search_full_text("desert", 10).unwrap().len(), 1);
+    let queued: Vec<(i32, String)> = calibre
+        .prepare("SELECT book, format FROM dirtied_formats")
+        .unwrap()
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(queued, vec![(dune.id.as_i32(), "TXT".to_string())]);
+
+    // Once Calibre has extracted the format, its text is what is searched.
+    calibre
+        .execute(
+            "INSERT INTO books_text (book, timestamp, format, searchable_text) \
+             VALUES (?, 0, 'TXT', 'Calibre extracted: sandworms')",
+            [dune.id.as_i32()],
+        )
+        .unwrap();
+    assert!(lib.search_full_text("desert", 10).unwrap().is_empty());
+    let found = lib.search_full_text("sandworm", 10).unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(highlighted(&found[0]), vec!["sandworm"]);
+}
@@ -0,0 +1,260 @@
+// Tests for merging duplicate book records into one
+// This binary uses only a subset of the shared test helpers.
+#[allow(dead_code, unused_imports)]
+mod common;
+
+use common::{setup_with_library, standard_test_book};
+use libcalibre::{
+    BookAdd, BookId, BookUpdate, CustomColumnKind, CustomColumnSpec, CustomValue, Library,
+    LibraryBook, MergeCover,
+};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+fn source(sources: &TempDir, name: &str, content: &str) -> PathBuf {
+    let path = sources.path().join(name);
+    std::fs::write(&path, content).unwrap();
+    path
+}
+
+fn book_add(title: &str, files: Vec<PathBuf>) -> BookAdd {
+    BookAdd {
+        title: title.to_string(),
+        author_names: vec!["Terry Pratchett".to_string()],
+        file_paths: files,
+        ..standard_test_book()
+    }
+}
+
+fn add(lib: &mut Library, book: BookAdd) -> BookId {
+    lib.add_book(book).unwrap().id
+}
+
+fn file_path(lib: &Library, book: &LibraryBook, format: &str) -> PathBuf {
+    let file = book.files.iter().find(|f| f.format == format).unwrap();
+    Path::new(lib.library_path())
+        .join(&book.book_dir_path)
+        .join(format!("{}.{}", file.name, format.to_lowercase()))
+}
+
+fn cover_path(lib: &Library, book: &LibraryBook) -> PathBuf {
+    Path::new(lib.library_path())
+        .join(&book.book_dir_path)
+        .join("cover.jpg")
+}
+
+#[test]
+fn test_merge_books_unions_metadata_and_moves_formats() {
+    let sources = tempfile::tempdir().unwrap();
+    let (_temp, mut lib) = setup_with_library();
+
+    let mut target_add = book_add("Mort", vec![source(&sources, "a.txt", "Mort as text.")]);
+    target_add.tags = Some(vec!["Fantasy".to_string()]);
+    target_add.identifiers = HashMap::from([("isbn".to_string(), "9780552131063".to_string())]);
+    let target = add(&mut lib, target_add);
+
+    let mut source_add = book_add(
+        "Mort (Discworld 4)",
+        vec![
+            source(&sources, "b.txt", "Mort as other text."),
+            source(&sources, "b.epub", "Mort as an EPUB."),
+        ],
+    );
+    source_add.tags = Some(vec!["fantasy".to_string(), "Discworld".to_string()]);
+    source_add.identifiers = HashMap::from([
+        ("isbn".to_string(), "0552131067".to_string()),
+        ("goodreads".to_string(), "386372".to_string()),
+    ]);
+    let merged_source = add(&mut lib, source_add);
+    lib.update_book(
+        merged_source,
+        BookUpdate {
+            publisher: Some("Corgi".to_string()),
+            rating: Some(8),
+            comments: Some("Death takes an apprentice.".to_string()),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    let shelves = lib
+        .create_custom_column(CustomColumnSpec {
+            label: "shelves".to_string(),
+            name: "Shelves".to_string(),
+            kind: CustomColumnKind::Text,
+            is_multiple: true,
+            enum_values: vec![],
+            display: None,
+        })
+        .unwrap();
+    let pages = lib
+        .create_custom_column(CustomColumnSpec {
+            label: "pages".to_string(),
+            name: "Pages".to_string(),
+            kind: CustomColumnKind::Int,
+            is_multiple: false,
+            enum_values: vec![],
+            display: None,
+        })
+        .unwrap();
+    let shelf = |names: &[&str]| {
+        Some(CustomValue::TextMultiple(
+            names.iter().map(|n| n.to_string()).collect(),
+        ))
+    };
+    lib.set_custom_value(target, shelves.id, shelf(&["Owned"]))
+        .unwrap();
+    lib.set_custom_value(merged_source, shelves.id, shelf(&["Loaned", "Owned"]))
+        .unwrap();
+    lib.set_custom_value(merged_source, pages.id, Some(CustomValue::Int(272)))
+        .unwrap();
+
+    let book = lib
+        .merge_books(target, &[merged_source], MergeCover::Target)
+        .unwrap();
+
+    assert_eq!(book.title, "Mort");
+    assert_eq!(book.tags.len(), 2);
+    assert!(book.tags.contains(&"Discworld".to_string()));
+    assert_eq!(book.publisher.as_deref(), Some("Corgi"));
+    assert_eq!(book.rating, Some(8));
+    assert_eq!(
+        book.description.as_deref(),
+        Some("Death takes an apprentice.")
+    );
+    let identifiers: HashMap<&str, &str> = book
+        .identifiers
+        .iter()
+        .map(|id| (id.label.as_str(), id.value.as_str()))
+        .collect();
+    assert_eq!(
+        identifiers,
+        HashMap::from([("isbn", "9780552131063"), ("goodreads", "386372")])
+    );
+    assert_eq!(
+        lib.get_custom_value(target, shelves.id).unwrap(),
+        shelf(&["Owned", "Loaned"])
+    );
+    assert_eq!(
+        lib.get_custom_value(target, pages.id).unwrap(),
+        Some(CustomValue::Int(272))
+    );
+
+    // The target keeps its own TXT and takes the EPUB, named like its files.
+    let mut formats: Vec<&str> = book.files.iter().map(|f| f.format.as_str()).collect();
+    formats.sort();
+    assert_eq!(formats, vec!["EPUB", "TXT"]);
+    assert!(book.files.iter().all(|f| f.name == book.files[0].name));
+    assert_eq!(
+        std::fs::read_to_string(file_path(&lib, &book, "TXT")).unwrap(),
+        "Mort as text."
+    );
+    assert_eq!(
+        std::fs::read_to_string(file_path(&lib, &book, "EPUB")).unwrap(),
+        "Mort as an EPUB."
+    );
+
+    // The source is in the trash, with the format the target already had.
+    assert!(lib.get_book(merged_source).is_err());
+    let trash = lib.list_trash().unwrap();
+    assert_eq!(trash.len(), 1);
+    assert_eq!(trash[0].book_id, merged_source);
+    assert_eq!(trash[0].formats, vec!["TXT"]);
+
+    let opf = std::fs::read_to_string(
+        Path::new(lib.library_path())
+            .join(&book.book_dir_path)
+            .join("metadata.opf"),
+    )
+    .unwrap();
+    assert!(opf.contains("386372"));
+    assert!(opf.contains("Discworld"));
+}
+
+#[test]
+fn test_merge_books_cover_policies() {
+    let policies = [
+        (MergeCover::Target, "small"),
+        (MergeCover::Source, "large cover"),
+        (MergeCover::Largest, "large cover"),
+    ];
+    for (policy, expected) in policies {
+        let (_temp, mut lib) = setup_with_library();
+        let target = add(&mut lib, book_add("Mort", vec![]));
+        let other = add(&mut lib, book_add("Mort", vec![]));
+        let uncovered = add(&mut lib, book_add("Mort", vec![]));
+        lib.set_book_cover(target, b"small".to_vec()).unwrap();
+        lib.set_book_cover(other, b"large cover".to_vec()).unwrap();
+
+        let book = lib
+            .merge_books(target, &[uncovered, other], policy)
+            .unwrap();
+        assert!(book.has_cover);
+        assert_eq!(
+            std::fs::read(cover_path(&lib, &book)).unwrap(),
+            expected.as_bytes(),
+            "{policy:?}"
+        );
+    }
+
+    // A target without a cover takes one under any policy.
+    let (_temp, mut lib) = setup_with_library();
+    let target = add(&mut lib, book_add("Mort", vec![]));
+    let other = add(&mut lib, book_add("Mort", vec![]));
+    lib.set_book_cover(other, b"cover".to_vec()).unwrap();
+    let book = lib
+        .merge_books(target, &[other], MergeCover::Target)
+        .unwrap();
+    assert!(book.has_cover);
+    assert_eq!(std::fs::read(cover_path(&lib, &book)).unwrap(), b"cover");
+}
+
+#[test]
+fn test_merge_books_rolls_back_on_failure() {
+    let sources = tempfile::tempdir().unwrap();
+    let (_temp, mut lib) = setup_with_library();
+
+    let mut target_add = book_add("Mort", vec![]);
+    target_add.tags = Some(vec!["Fantasy".to_string()]);
+    let target = add(&mut lib, target_add);
+    lib.set_book_cover(target, b"target cover".to_vec())
+        .unwrap();
+
+    let mut source_add = book_add(
+        "Mort",
+        vec![
+            source(&sources, "b.epub", "Mort as an EPUB."),
+            source(&sources, "b.txt", "Mort as text."),
+        ],
+    );
+    source_add.tags = Some(vec!["Discworld".to_string()]);
+    let merged_source = add(&mut lib, source_add);
+    lib.set_book_cover(merged_source, b"source cover".to_vec())
+        .unwrap();
+
+    // One of the source's formats has gone missing from disk.
+    let before = lib.get_book(merged_source).unwrap();
+    std::fs::remove_file(file_path(&lib, &before, "TXT")).unwrap();
+
+    assert!(lib
+        .merge_books(target, &[merged_source], MergeCover::Source)
+        .is_err());
+
+    let target_book = lib.get_book(target).unwrap();
+    assert_eq!(target_book.tags, vec!["Fantasy"]);
+    assert!(target_book.files.is_empty());
+    assert_eq!(
+        std::fs::read(cover_path(&lib, &target_book)).unwrap(),
+        b"target cover"
+    );
+
+    let source_book = lib.get_book(merged_source).unwrap();
+    assert_eq!(source_book.files.len(), 2);
+    assert_eq!(
+        std::fs::read_to_string(file_path(&lib, &source_book, "EPUB")).unwrap(),
+        "Mort as an EPUB."
+    );
+    assert!(cover_path(&lib, &source_book).is_file());
+    assert!(lib.list_trash().unwrap().is_empty());
+}
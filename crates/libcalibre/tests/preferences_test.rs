@@ -0,0 +1,243 @@
+// Tests for typed and raw access to Calibre's preferences table
+// This binary uses only a subset of the shared test helpers.
+#[allow(dead_code, unused_imports)]
+mod common;
+
+use common::{conn, setup_with_library};
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::Text;
+use libcalibre::{CalibreError, ColumnDisplay, Library, UserCategoryItem};
+use serde_json::json;
+use std::collections::BTreeMap;
+
+#[derive(QueryableByName)]
+struct PreferenceRow {
+    #[diesel(sql_type = Text)]
+    val: String,
+}
+
+fn stored_text(lib: &Library, key: &str) -> String {
+    sql_query("SELECT val FROM preferences WHERE key = ?")
+        .bind::<Text, _>(key)
+        .get_result::<PreferenceRow>(&mut conn(lib))
+        .unwrap()
+        .val
+}
+
+fn write_preference(lib: &Library, key: &str, value: &str) {
+    sql_query("INSERT OR REPLACE INTO preferences (key, val) VALUES (?, ?)")
+        .bind::<Text, _>(key)
+        .bind::<Text, _>(value)
+        .execute(&mut conn(lib))
+        .unwrap();
+}
+
+#[test]
+fn test_defaults_of_a_new_library() {
+    let temp = tempfile::tempdir().unwrap();
+    let mut lib = Library::create_library_at(temp.path().to_str().unwrap()).unwrap();
+    let mut prefs = lib.preferences();
+
+    assert!(prefs.bools_are_tristate().unwrap());
+    assert!(prefs.user_categories().unwrap().is_empty());
+    assert!(prefs.grouped_search_terms().unwrap().is_empty());
+    assert!(prefs.tag_browser_hidden_categories().unwrap().is_empty());
+    assert_eq!(prefs.tag_browser_category_order().unwrap()[0], "authors");
+    assert!(prefs.book_display_fields().unwrap().is_empty());
+    assert_eq!(prefs.virtual_library_on_startup().unwrap(), "");
+    assert_eq!(prefs.column_display().unwrap(), ColumnDisplay::default());
+    assert!(prefs
+        .keys()
+        .unwrap()
+        .contains(&"saved_searches".to_string()));
+}
+
+#[test]
+fn test_reads_column_display_saved_by_calibre() {
+    let (_temp, mut lib) = setup_with_library();
+    let display = lib.preferences().column_display().unwrap();
+
+    assert!(display.hidden_columns.contains(&"path".to_string()));
+    assert_eq!(display.column_positions["title"], 2);
+    assert_eq!(display.column_sizes["title"], 245);
+}
+
+#[test]
+fn test_reads_values_written_by_calibre() {
+    let (_temp, mut lib) = setup_with_library();
+    write_preference(
+        &lib,
+        "user_categories",
+        "{\n  \"Favourites\": [\n    [\"Terry Pratchett\", \"authors\", 0],\n    [\"Fantasy\", \"tags\", 0]\n  ],\n  \"Favourites.Empty\": []\n}",
+    );
+    write_preference(
+        &lib,
+        "grouped_search_terms",
+        "{\n  \"people\": [\n    \"authors\",\n    \"#translators\"\n  ]\n}",
+    );
+    write_preference(
+        &lib,
+        "book_display_fields",
+        "[\n  [\"title\", false],\n  [\"authors\", true]\n]",
+    );
+    write_preference(
+        &lib,
+        "field_metadata",
+        "{\n  \"#genre\": {\"name\": \"Genre\", \"datatype\": \"text\", \"is_custom\": true, \"is_category\": true, \"display\": {}},\n  \"title\": {\"name\": \"Title\", \"datatype\": \"text\", \"is_custom\": false, \"is_category\": false}\n}",
+    );
+    let mut prefs = lib.preferences();
+
+    let categories = prefs.user_categories().unwrap();
+    assert_eq!(
+        categories["Favourites"],
+        vec![
+            UserCategoryItem {
+                name: "Terry Pratchett".to_string(),
+                category: "authors".to_string(),
+            },
+            UserCategoryItem {
+                name: "Fantasy".to_string(),
+                category: "tags".to_string(),
+            },
+        ]
+    );
+    assert!(categories["Favourites.Empty"].is_empty());
+    assert_eq!(
+        prefs.grouped_search_terms().unwrap()["people"],
+        vec!["authors", "#translators"]
+    );
+    assert_eq!(
+        prefs.book_display_fields().unwrap(),
+        vec![("title".to_string(), false), ("authors".to_string(), true)]
+    );
+    let fields = prefs.field_metadata().unwrap();
+    assert_eq!(fields["#genre"].name.as_deref(), Some("Genre"));
+    assert!(fields["#genre"].is_custom);
+    assert!(!fields["title"].is_category);
+}
+
+#[test]
+fn test_typed_writes_use_calibres_format() {
+    let (_temp, mut lib) = setup_with_library();
+    let mut categories = BTreeMap::new();
+    categories.insert(
+        "Favourites".to_string(),
+        vec![UserCategoryItem {
+            name: "Mort".to_string(),
+            category: "title".to_string(),
+        }],
+    );
+    {
+        let mut prefs = lib.preferences();
+        prefs.set_user_categories(&categories).unwrap();
+        prefs.set_bools_are_tristate(false).unwrap();
+        prefs
+            .set_book_display_fields(&[("title".to_string(), true)])
+            .unwrap();
+    }
+
+    // Indented JSON, with the unused third element Calibre expects.
+    assert_eq!(
+        stored_text(&lib, "user_categories"),
+        "{\n  \"Favourites\": [\n    [\n      \"Mort\",\n      \"title\",\n      0\n    ]\n  ]\n}"
+    );
+    assert_eq!(stored_text(&lib, "bools_are_tristate"), "false");
+
+    let mut prefs = lib.preferences();
+    assert_eq!(prefs.user_categories().unwrap(), categories);
+    assert!(!prefs.bools_are_tristate().unwrap());
+    assert_eq!(
+        prefs.book_display_fields().unwrap(),
+        vec![("title".to_string(), true)]
+    );
+}
+
+#[test]
+fn test_column_display_keeps_unmodelled_state() {
+    let (_temp, mut lib) = setup_with_library();
+    write_preference(
+        &lib,
+        "library_view books view state",
+        "{\n  \"hidden_columns\": [\"size\"],\n  \"sort_history\": [[\"timestamp\", false]],\n  \"column_sizes\": {\"title\": 300}\n}",
+    );
+    let mut prefs = lib.preferences();
+    let mut display = prefs.column_display().unwrap();
+    assert_eq!(display.hidden_columns, vec!["size"]);
+    assert_eq!(display.column_sizes["title"], 300);
+
+    display.hidden_columns.push("rating".to_string());
+    display.column_positions.insert("title".to_string(), 0);
+    prefs.set_column_display(&display).unwrap();
+
+    assert_eq!(
+        prefs.get_raw("library_view books view state").unwrap(),
+        Some(json!({
+            "hidden_columns": ["size", "rating"],
+            "sort_history": [["timestamp", false]],
+            "column_sizes": {"title": 300},
+            "column_positions": {"title": 0},
+            "column_alignment": {},
+        }))
+    );
+}
+
+#[test]
+fn test_raw_values_round_trip() {
+    let (_temp, mut lib) = setup_with_library();
+    // A datetime as Calibre's JSON encoder writes it, and non-ASCII text.
+    let news = json!({
+        "Le Monde": {
+            "last_downloaded": {"__class__": "datetime.datetime", "__value__": "2024-05-01T08:00:00+00:00"},
+            "schedule": [2, [7, 30]],
+            "title": "Le Monde – édition",
+            "ratio": 0.25
+        }
+    });
+    let mut prefs = lib.preferences();
+    prefs.set_raw("news_to_be_synced", &news).unwrap();
+    assert_eq!(prefs.get_raw("news_to_be_synced").unwrap(), Some(news));
+    assert_eq!(prefs.get_raw("no such key").unwrap(), None);
+
+    assert!(prefs.remove("news_to_be_synced").unwrap());
+    assert!(!prefs.remove("news_to_be_synced").unwrap());
+    assert!(!prefs
+        .keys()
+        .unwrap()
+        .contains(&"news_to_be_synced".to_string()));
+}
+
+#[test]
+fn test_invalid_values_are_rejected() {
+    let (_temp, mut lib) = setup_with_library();
+    write_preference(
+        &lib,
+        "grouped_search_terms",
+        "[\"not\", \"an\", \"object\"]",
+    );
+    let mut prefs = lib.preferences();
+
+    assert!(matches!(
+        prefs.grouped_search_terms(),
+        Err(CalibreError::DatabaseIntegrity(_))
+    ));
+
+    let mut terms = BTreeMap::new();
+    terms.insert("People".to_string(), vec!["authors".to_string()]);
+    assert!(matches!(
+        prefs.set_grouped_search_terms(&terms),
+        Err(CalibreError::InvalidPreference(_))
+    ));
+    let mut categories = BTreeMap::new();
+    categories.insert("Parent.".to_string(), vec![]);
+    assert!(matches!(
+        prefs.set_user_categories(&categories),
+        Err(CalibreError::InvalidPreference(_))
+    ));
+    // Rejected writes leave the stored values alone.
+    assert_eq!(
+        prefs.get_raw("grouped_search_terms").unwrap(),
+        Some(json!(["not", "an", "object"]))
+    );
+    assert!(prefs.user_categories().unwrap().is_empty());
+}
@@ -0,0 +1,345 @@
+// Tests for the BookQuery metadata filters (tags, languages, formats,
+// publisher, rating, cover, identifiers, dates).
+// This binary uses only a subset of the shared test helpers.
+#[allow(dead_code, unused_imports)]
+mod common;
+
+use chrono::{Duration, NaiveDate, Utc};
+use common::{conn, setup_with_library, standard_test_book};
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::{Integer, Text};
+use libcalibre::{BookAdd, BookQuery, BookUpdate, DateRange, Library};
+use std::path::Path;
+
+/// A 1x1 JPEG, enough for `set_book_cover` to flip `has_cover` to true.
+const TINY_JPEG: &[u8] = &[
+    0xFF, 0xD8, 0xFF, 0xDB, 0x00, 0x43, 0x00, 0x03, 0x02, 0x02, 0x02, 0x02, 0x02, 0x03, 0x02, 0x02,
+    0x02, 0x03, 0x03, 0x03, 0x03, 0x04, 0x06, 0x04, 0x04, 0x04, 0x04, 0x04, 0x08, 0x06, 0x06, 0x05,
+    0x06, 0x09, 0x08, 0x0A, 0x0A, 0x09, 0x08, 0x09, 0x09, 0x0A, 0x0C, 0x0F, 0x0C, 0x0A, 0x0B, 0x0E,
+    0x0B, 0x09, 0x09, 0x0D, 0x11, 0x0D, 0x0E, 0x0F, 0x10, 0x10, 0x11, 0x10, 0x0A, 0x0C, 0x12, 0x13,
+    0x12, 0x10, 0x13, 0x0F, 0x10, 0x10, 0x10, 0xFF, 0xC9, 0x00, 0x0B, 0x08, 0x00, 0x01, 0x00, 0x01,
+    0x01, 0x01, 0x11, 0x00, 0xFF, 0xCC, 0x00, 0x06, 0x00, 0x10, 0x10, 0x05, 0xFF, 0xDA, 0x00, 0x08,
+    0x01, 0x01, 0x00, 0x00, 0x3F, 0x00, 0xD2, 0xCF, 0x20, 0xFF, 0xD9,
+];
+
+struct Fixture<'a> {
+    title: &'a str,
+    tags: &'a [&'a str],
+    language: Option<&'a str>,
+    formats: &'a [&'a str],
+    publisher: Option<&'a str>,
+    rating: Option<i32>,
+    identifiers: &'a [(&'a str, &'a str)],
+    pubdate: Option<(i32, u32, u32)>,
+    cover: bool,
+}
+
+/// Four books:
+///
+/// | title        | tags             | lang | formats   | publisher | stars | identifiers | pubdate    | cover |
+/// |--------------|------------------|------|-----------|-----------|-------|-------------|------------|-------|
+/// | Dune         | SF, Classic      | eng  | EPUB, TXT | Chilton   | 5     | isbn        | 1965-08-01 | yes   |
+/// | Solaris      | SF               | pol  | PDF       |           | 4     | goodreads   | 1961-06-01 |       |
+/// | Emma         | Classic, Romance | eng  | EPUB      | Murray    | 3     | isbn, amazon| 1815-12-23 |       |
+/// | Untitled     |                  |      |           |           |       |             | (none)     |       |
+fn library_with_books(sources: &Path) -> (tempfile::TempDir, Library) {
+    let (temp, mut lib) = setup_with_library();
+    let fixtures = [
+        Fixture {
+            title: "Dune",
+            tags: &["SF", "Classic"],
+            language: Some("en"),
+            formats: &["epub", "txt"],
+            publisher: Some("Chilton"),
+            rating: Some(10),
+            identifiers: &[("isbn", "9780441013593")],
+            pubdate: Some((1965, 8, 1)),
+            cover: true,
+        },
+        Fixture {
+            title: "Solaris",
+            tags: &["SF"],
+            language: Some("pol"),
+            formats: &["pdf"],
+            publisher: None,
+            rating: Some(8),
+            identifiers: &[("goodreads", "95558")],
+            pubdate: Some((1961, 6, 1)),
+            cover: false,
+        },
+        Fixture {
+            title: "Emma",
+            tags: &["Classic", "Romance"],
+            language: Some("eng"),
+            formats: &["epub"],
+            publisher: Some("Murray"),
+            rating: Some(6),
+            identifiers: &[("isbn", "9780141439587"), ("amazon", "B008")],
+            pubdate: Some((1815, 12, 23)),
+            cover: false,
+        },
+        Fixture {
+            title: "Untitled",
+            tags: &[],
+            language: None,
+            formats: &[],
+            publisher: None,
+            rating: None,
+            identifiers: &[],
+            pubdate: None,
+            cover: false,
+        },
+    ];
+
+    for fixture in fixtures {
+        let file_paths = fixture
+            .formats
+            .iter()
+            .map(|format| {
+                let path = sources.join(format!("{}.{format}", fixture.title));
+                std::fs::write(&path, fixture.title).unwrap();
+                path
+            })
+            .collect();
+        let created = lib
+            .add_book(BookAdd {
+                title: fixture.title.to_string(),
+                author_names: vec!["Someone".to_string()],
+                tags: Some(fixture.tags.iter().map(|t| (*t).to_string()).collect()),
+                language: fixture.language.map(str::to_string),
+                file_paths,
+                ..standard_test_book()
+            })
+            .unwrap();
+        lib.update_book(
+            created.id,
+            BookUpdate {
+                publisher: fixture.publisher.map(str::to_string),
+                rating: fixture.rating,
+                identifiers: Some(
+                    fixture
+                        .identifiers
+                        .iter()
+                        .map(|(t, v)| ((*t).to_string(), (*v).to_string()))
+                        .collect(),
+                ),
+                publication_date: fixture
+                    .pubdate
+                    .map(|(y, m, d)| NaiveDate::from_ymd_opt(y, m, d).unwrap()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        if fixture.cover {
+            lib.set_book_cover(created.id, TINY_JPEG.to_vec()).unwrap();
+        }
+    }
+    // Untitled keeps no publication date: Calibre's "undefined" sentinel.
+    sql_query("UPDATE books SET pubdate = '0101-01-01 00:00:00+00:00' WHERE title = 'Untitled'")
+        .execute(&mut conn(&lib))
+        .unwrap();
+
+    (temp, lib)
+}
+
+fn id_by_name(lib: &Library, table: &str, name: &str) -> i32 {
+    #[derive(QueryableByName)]
+    struct IdRow {
+        #[diesel(sql_type = Integer)]
+        id: i32,
+    }
+    sql_query(format!("SELECT id FROM {table} WHERE name = ?"))
+        .bind::<Text, _>(name)
+        .get_result::<IdRow>(&mut conn(lib))
+        .unwrap()
+        .id
+}
+
+/// Titles matching `query`, sorted, after checking `total` agrees with them.
+fn titles(lib: &mut Library, query: BookQuery) -> Vec<String> {
+    let page = lib.query_books(query).unwrap();
+    assert_eq!(page.total, page.items.len() as i64);
+    let mut titles: Vec<String> = page.items.into_iter().map(|book| book.title).collect();
+    titles.sort();
+    titles
+}
+
+#[test]
+fn test_tag_filters() {
+    let sources = tempfile::tempdir().unwrap();
+    let (_temp, mut lib) = library_with_books(sources.path());
+    let sf = id_by_name(&lib, "tags", "SF");
+    let classic = id_by_name(&lib, "tags", "Classic");
+    let romance = id_by_name(&lib, "tags", "Romance");
+
+    let any = |tags: Vec<i32>| BookQuery {
+        tags_any: tags,
+        ..BookQuery::default()
+    };
+    let all = |tags: Vec<i32>| BookQuery {
+        tags_all: tags,
+        ..BookQuery::default()
+    };
+    assert_eq!(
+        titles(&mut lib, any(vec![sf, romance])),
+        vec!["Dune", "Emma", "Solaris"]
+    );
+    assert_eq!(titles(&mut lib, all(vec![sf, classic])), vec!["Dune"]);
+    assert!(titles(&mut lib, all(vec![sf, romance])).is_empty());
+    assert_eq!(titles(&mut lib, all(vec![])).len(), 4);
+}
+
+#[test]
+fn test_language_format_and_publisher_filters() {
+    let sources = tempfile::tempdir().unwrap();
+    let (_temp, mut lib) = library_with_books(sources.path());
+
+    let languages = |codes: &[&str]| BookQuery {
+        language_codes: codes.iter().map(|c| (*c).to_string()).collect(),
+        ..BookQuery::default()
+    };
+    // Any ISO 639 form of a language matches.
+    assert_eq!(titles(&mut lib, languages(&["en"])), vec!["Dune", "Emma"]);
+    assert_eq!(
+        titles(&mut lib, languages(&["eng", "pl"])),
+        vec!["Dune", "Emma", "Solaris"]
+    );
+
+    let formats = |formats: &[&str]| BookQuery {
+        formats: formats.iter().map(|f| (*f).to_string()).collect(),
+        ..BookQuery::default()
+    };
+    assert_eq!(titles(&mut lib, formats(&["epub"])), vec!["Dune", "Emma"]);
+    assert_eq!(
+        titles(&mut lib, formats(&["PDF", "txt"])),
+        vec!["Dune", "Solaris"]
+    );
+
+    let murray = id_by_name(&lib, "publishers", "Murray");
+    assert_eq!(
+        titles(
+            &mut lib,
+            BookQuery {
+                publisher_id: Some(murray),
+                ..BookQuery::default()
+            }
+        ),
+        vec!["Emma"]
+    );
+}
+
+#[test]
+fn test_rating_cover_and_identifier_filters() {
+    let sources = tempfile::tempdir().unwrap();
+    let (_temp, mut lib) = library_with_books(sources.path());
+
+    let rating = |min: Option<i32>, max: Option<i32>| BookQuery {
+        min_rating: min,
+        max_rating: max,
+        ..BookQuery::default()
+    };
+    assert_eq!(
+        titles(&mut lib, rating(Some(8), None)),
+        vec!["Dune", "Solaris"]
+    );
+    assert_eq!(
+        titles(&mut lib, rating(Some(6), Some(8))),
+        vec!["Emma", "Solaris"]
+    );
+    // Unrated books never match a bound.
+    assert_eq!(titles(&mut lib, rating(None, Some(6))), vec!["Emma"]);
+
+    let cover = |has_cover: bool| BookQuery {
+        has_cover: Some(has_cover),
+        ..BookQuery::default()
+    };
+    assert_eq!(titles(&mut lib, cover(true)), vec!["Dune"]);
+    assert_eq!(titles(&mut lib, cover(false)).len(), 3);
+
+    let identifiers = |with: &[&str], without: &[&str]| BookQuery {
+        with_identifiers: with.iter().map(|t| (*t).to_string()).collect(),
+        without_identifiers: without.iter().map(|t| (*t).to_string()).collect(),
+        ..BookQuery::default()
+    };
+    assert_eq!(
+        titles(&mut lib, identifiers(&["ISBN"], &[])),
+        vec!["Dune", "Emma"]
+    );
+    assert_eq!(
+        titles(&mut lib, identifiers(&["isbn", "amazon"], &[])),
+        vec!["Emma"]
+    );
+    assert_eq!(
+        titles(&mut lib, identifiers(&[], &["isbn"])),
+        vec!["Solaris", "Untitled"]
+    );
+    assert_eq!(
+        titles(&mut lib, identifiers(&["isbn"], &["amazon"])),
+        vec!["Dune"]
+    );
+}
+
+#[test]
+fn test_date_range_filters() {
+    let sources = tempfile::tempdir().unwrap();
+    let (_temp, mut lib) = library_with_books(sources.path());
+    let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d);
+
+    let published = |from, to| BookQuery {
+        published: DateRange { from, to },
+        ..BookQuery::default()
+    };
+    assert_eq!(
+        titles(&mut lib, published(date(1900, 1, 1), None)),
+        vec!["Dune", "Solaris"]
+    );
+    // Both ends are inclusive, to the day.
+    assert_eq!(
+        titles(&mut lib, published(date(1961, 6, 1), date(1965, 8, 1))),
+        vec!["Dune", "Solaris"]
+    );
+    // The undefined date is never in range.
+    assert_eq!(
+        titles(&mut lib, published(None, date(1900, 1, 1))),
+        vec!["Emma"]
+    );
+
+    let today = Utc::now().date_naive();
+    let added = |from, to| BookQuery {
+        added: DateRange { from, to },
+        ..BookQuery::default()
+    };
+    assert_eq!(titles(&mut lib, added(Some(today), Some(today))).len(), 4);
+    assert!(titles(&mut lib, added(None, Some(today - Duration::days(1)))).is_empty());
+}
+
+#[test]
+fn test_filters_compose_with_paging() {
+    let sources = tempfile::tempdir().unwrap();
+    let (_temp, mut lib) = library_with_books(sources.path());
+    let classic = id_by_name(&lib, "tags", "Classic");
+
+    let page = lib
+        .query_books(BookQuery {
+            tags_any: vec![classic],
+            formats: vec!["EPUB".to_string()],
+            min_rating: Some(6),
+            limit: Some(1),
+            ..BookQuery::default()
+        })
+        .unwrap();
+    assert_eq!(page.items.len(), 1);
+    assert_eq!(page.total, 2);
+
+    let page = lib
+        .query_books(BookQuery {
+            tags_any: vec![classic],
+            has_cover: Some(false),
+            text: Some("emm".to_string()),
+            ..BookQuery::default()
+        })
+        .unwrap();
+    assert_eq!(page.total, 1);
+    assert_eq!(page.items[0].title, "Emma");
+}
@@ -0,0 +1,298 @@
+// Tests for the BookQuery sort orders beyond title and author.
+// This binary uses only a subset of the shared test helpers.
+#[allow(dead_code, unused_imports)]
+mod common;
+
+use chrono::NaiveDate;
+use common::{conn, setup_with_library, standard_test_book};
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::{Integer, Text};
+use libcalibre::{
+    BookAdd, BookQuery, BookSortOrder, BookUpdate, CalibreError, CustomColumnKind,
+    CustomColumnSpec, CustomValue, Library,
+};
+use std::path::Path;
+
+/// Four books, listed in the order they are added:
+///
+/// | title | added      | modified   | pubdate    | series   | stars | publisher | file size | #pages |
+/// |-------|------------|------------|------------|----------|-------|-----------|-----------|--------|
+/// | Alpha | 2021-03-01 | 2024-01-01 | 1990-01-01 | Zeta #2  | 3     | Orbit     | 30        | 300    |
+/// | Beta  | 2023-07-15 | 2022-01-01 | (none)     | Zeta #1  |       | Ace       | 10        |        |
+/// | Gamma | 2019-11-30 | 2023-01-01 | 1975-06-01 | Arc #1   | 5     |           | 20        | 120    |
+/// | Delta | 2022-01-01 | 2021-01-01 | 2001-09-09 |          | 1     | Baen      |           | 980    |
+fn library_with_books(sources: &Path) -> (tempfile::TempDir, Library) {
+    let (temp, mut lib) = setup_with_library();
+    let pages = lib
+        .create_custom_column(CustomColumnSpec {
+            label: "pages".to_string(),
+            name: "Pages".to_string(),
+            kind: CustomColumnKind::Int,
+            is_multiple: false,
+            enum_values: vec![],
+            display: None,
+        })
+        .unwrap();
+
+    type Row<'a> = (
+        &'a str,
+        &'a str,
+        &'a str,
+        Option<(i32, u32, u32)>,
+        Option<(&'a str, f32)>,
+        Option<i32>,
+        Option<&'a str>,
+        usize,
+        Option<i64>,
+    );
+    let rows: [Row; 4] = [
+        (
+            "Alpha",
+            "2021-03-01 10:00:00+00:00",
+            "2024-01-01 00:00:00+00:00",
+            Some((1990, 1, 1)),
+            Some(("Zeta", 2.0)),
+            Some(6),
+            Some("Orbit"),
+            30,
+            Some(300),
+        ),
+        (
+            "Beta",
+            "2023-07-15 10:00:00+00:00",
+            "2022-01-01 00:00:00+00:00",
+            None,
+            Some(("Zeta", 1.0)),
+            None,
+            Some("Ace"),
+            10,
+            None,
+        ),
+        (
+            "Gamma",
+            "2019-11-30 10:00:00+00:00",
+            "2023-01-01 00:00:00+00:00",
+            Some((1975, 6, 1)),
+            Some(("Arc", 1.0)),
+            Some(10),
+            None,
+            20,
+            Some(120),
+        ),
+        (
+            "Delta",
+            "2022-01-01 10:00:00+00:00",
+            "2021-01-01 00:00:00+00:00",
+            Some((2001, 9, 9)),
+            None,
+            Some(2),
+            Some("Baen"),
+            0,
+            Some(980),
+        ),
+    ];
+
+    for (title, added, modified, pubdate, series, rating, publisher, size, page_count) in rows {
+        let file_paths = if size > 0 {
+            let path = sources.join(format!("{title}.txt"));
+            std::fs::write(&path, "x".repeat(size)).unwrap();
+            vec![path]
+        } else {
+            vec![]
+        };
+        let created = lib
+            .add_book(BookAdd {
+                title: title.to_string(),
+                author_names: vec!["Someone".to_string()],
+                series: series.map(|(name, _)| name.to_string()),
+                series_index: series.map(|(_, index)| index),
+                file_paths,
+                ..standard_test_book()
+            })
+            .unwrap();
+        lib.update_book(
+            created.id,
+            BookUpdate {
+                publisher: publisher.map(str::to_string),
+                rating,
+                publication_date: pubdate
+                    .map(|(y, m, d)| NaiveDate::from_ymd_opt(y, m, d).unwrap()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        if let Some(page_count) = page_count {
+            lib.set_custom_value(created.id, pages.id, Some(CustomValue::Int(page_count)))
+                .unwrap();
+        }
+
+        let pubdate_sql = if pubdate.is_none() {
+            ", pubdate = '0101-01-01 00:00:00+00:00'"
+        } else {
+            ""
+        };
+        sql_query(format!(
+            "UPDATE books SET timestamp = ?, last_modified = ?{pubdate_sql} WHERE id = ?"
+        ))
+        .bind::<Text, _>(added)
+        .bind::<Text, _>(modified)
+        .bind::<Integer, _>(created.id.as_i32())
+        .execute(&mut conn(&lib))
+        .unwrap();
+    }
+
+    (temp, lib)
+}
+
+fn custom_column_id(lib: &mut Library, label: &str) -> i32 {
+    lib.custom_columns()
+        .unwrap()
+        .into_iter()
+        .find(|column| column.label == label)
+        .unwrap()
+        .id
+}
+
+fn sorted(lib: &mut Library, sort: BookSortOrder) -> Vec<String> {
+    lib.query_books(BookQuery {
+        sort,
+        ..BookQuery::default()
+    })
+    .unwrap()
+    .items
+    .into_iter()
+    .map(|book| book.title)
+    .collect()
+}
+
+#[test]
+fn test_sort_by_dates() {
+    let sources = tempfile::tempdir().unwrap();
+    let (_temp, mut lib) = library_with_books(sources.path());
+
+    assert_eq!(
+        sorted(&mut lib, BookSortOrder::AddedDesc),
+        vec!["Beta", "Delta", "Alpha", "Gamma"]
+    );
+    assert_eq!(
+        sorted(&mut lib, BookSortOrder::AddedAsc),
+        vec!["Gamma", "Alpha", "Delta", "Beta"]
+    );
+    assert_eq!(
+        sorted(&mut lib, BookSortOrder::ModifiedDesc),
+        vec!["Alpha", "Gamma", "Beta", "Delta"]
+    );
+    assert_eq!(
+        sorted(&mut lib, BookSortOrder::ModifiedAsc),
+        vec!["Delta", "Beta", "Gamma", "Alpha"]
+    );
+    // The undefined publication date sorts before every real one.
+    assert_eq!(
+        sorted(&mut lib, BookSortOrder::PublishedAsc),
+        vec!["Beta", "Gamma", "Alpha", "Delta"]
+    );
+    assert_eq!(
+        sorted(&mut lib, BookSortOrder::PublishedDesc),
+        vec!["Delta", "Alpha", "Gamma", "Beta"]
+    );
+}
+
+#[test]
+fn test_sort_by_series_rating_publisher_and_size() {
+    let sources = tempfile::tempdir().unwrap();
+    let (_temp, mut lib) = library_with_books(sources.path());
+
+    assert_eq!(
+        sorted(&mut lib, BookSortOrder::SeriesAsc),
+        vec!["Delta", "Gamma", "Beta", "Alpha"]
+    );
+    assert_eq!(
+        sorted(&mut lib, BookSortOrder::SeriesDesc),
+        vec!["Alpha", "Beta", "Gamma", "Delta"]
+    );
+    assert_eq!(
+        sorted(&mut lib, BookSortOrder::RatingDesc),
+        vec!["Gamma", "Alpha", "Delta", "Beta"]
+    );
+    assert_eq!(
+        sorted(&mut lib, BookSortOrder::PublisherAsc),
+        vec!["Gamma", "Beta", "Delta", "Alpha"]
+    );
+    assert_eq!(
+        sorted(&mut lib, BookSortOrder::SizeDesc),
+        vec!["Alpha", "Gamma", "Beta", "Delta"]
+    );
+}
+
+#[test]
+fn test_sort_by_custom_column() {
+    let sources = tempfile::tempdir().unwrap();
+    let (_temp, mut lib) = library_with_books(sources.path());
+    let pages = custom_column_id(&mut lib, "pages");
+
+    assert_eq!(
+        sorted(&mut lib, BookSortOrder::CustomColumnAsc(pages)),
+        vec!["Beta", "Gamma", "Alpha", "Delta"]
+    );
+    assert_eq!(
+        sorted(&mut lib, BookSortOrder::CustomColumnDesc(pages)),
+        vec!["Delta", "Alpha", "Gamma", "Beta"]
+    );
+
+    let notes = lib
+        .create_custom_column(CustomColumnSpec {
+            label: "notes".to_string(),
+            name: "Notes".to_string(),
+            kind: CustomColumnKind::Comments,
+            is_multiple: false,
+            enum_values: vec![],
+            display: None,
+        })
+        .unwrap();
+    assert!(matches!(
+        lib.query_books(BookQuery {
+            sort: BookSortOrder::CustomColumnAsc(notes.id),
+            ..BookQuery::default()
+        }),
+        Err(CalibreError::UnsupportedCustomColumn(_))
+    ));
+    assert!(matches!(
+        lib.query_books(BookQuery {
+            sort: BookSortOrder::CustomColumnAsc(9999),
+            ..BookQuery::default()
+        }),
+        Err(CalibreError::CustomColumnNotFound(9999))
+    ));
+}
+
+#[test]
+fn test_new_sorts_tie_break_by_book_id_and_page() {
+    let sources = tempfile::tempdir().unwrap();
+    let (_temp, mut lib) = library_with_books(sources.path());
+    // Every book has the same (empty) value here, so only the id decides.
+    sql_query("UPDATE books SET timestamp = '2020-01-01 00:00:00+00:00'")
+        .execute(&mut conn(&lib))
+        .unwrap();
+
+    assert_eq!(
+        sorted(&mut lib, BookSortOrder::AddedAsc),
+        vec!["Alpha", "Beta", "Gamma", "Delta"]
+    );
+    assert_eq!(
+        sorted(&mut lib, BookSortOrder::AddedDesc),
+        vec!["Delta", "Gamma", "Beta", "Alpha"]
+    );
+
+    let page = lib
+        .query_books(BookQuery {
+            sort: BookSortOrder::RatingDesc,
+            limit: Some(2),
+            offset: 1,
+            ..BookQuery::default()
+        })
+        .unwrap();
+    assert_eq!(page.total, 4);
+    let titles: Vec<String> = page.items.into_iter().map(|book| book.title).collect();
+    assert_eq!(titles, vec!["Alpha", "Delta"]);
+}
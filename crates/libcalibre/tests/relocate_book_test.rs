@@ -0,0 +1,208 @@
+// Tests for moving book folders and files when the title or authors change
+#[allow(dead_code, unused_imports)]
+mod common;
+
+use common::{setup_with_library, standard_test_book};
+use libcalibre::{AuthorUpdate, BookAdd, BookUpdate, LibraryBook};
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+fn book_with_file(temp: &TempDir, title: &str, author: &str) -> BookAdd {
+    let source = temp.path().join(format!("source-{title}.txt"));
+    std::fs::write(&source, format!("contents of {title}")).unwrap();
+
+    BookAdd {
+        title: title.to_string(),
+        author_names: vec![author.to_string()],
+        file_paths: vec![source],
+        ..standard_test_book()
+    }
+}
+
+fn book_dir(temp: &TempDir, book: &LibraryBook) -> PathBuf {
+    temp.path().join(&book.book_dir_path)
+}
+
+fn txt_path(temp: &TempDir, book: &LibraryBook) -> PathBuf {
+    book_dir(temp, book).join(format!("{}.txt", book.files[0].name))
+}
+
+#[test]
+fn test_title_change_moves_folder_and_renames_files() {
+    let (temp, mut lib) = setup_with_library();
+    let book = lib
+        .add_book(book_with_file(&temp, "Dune", "Frank Herbert"))
+        .unwrap();
+    let old_dir = book_dir(&temp, &book);
+    assert!(txt_path(&temp, &book).exists());
+
+    let updated = lib
+        .update_book(
+            book.id,
+            BookUpdate {
+                title: Some("Dune Messiah".to_string()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+    assert_eq!(
+        Path::new(&updated.book_dir_path),
+        Path::new("Frank Herbert").join(format!("Dune Messiah ({})", book.id.as_i32()))
+    );
+    assert_eq!(updated.files[0].name, "Dune Messiah - Frank Herbert");
+    assert_eq!(
+        std::fs::read_to_string(txt_path(&temp, &updated)).unwrap(),
+        "contents of Dune"
+    );
+    assert!(book_dir(&temp, &updated).join("metadata.opf").exists());
+    assert!(!old_dir.exists());
+    assert!(temp.path().join("Frank Herbert").exists());
+}
+
+#[test]
+fn test_author_change_moves_folder_and_removes_empty_author_dir() {
+    let (temp, mut lib) = setup_with_library();
+    let book = lib
+        .add_book(book_with_file(&temp, "Emma", "Jane Austin"))
+        .unwrap();
+
+    let updated = lib
+        .update_book(
+            book.id,
+            BookUpdate {
+                author_names: Some(vec!["Jane Austen".to_string()]),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+    assert!(updated.book_dir_path.starts_with("Jane Austen"));
+    assert_eq!(updated.files[0].name, "Emma - Jane Austen");
+    assert!(txt_path(&temp, &updated).exists());
+    assert!(!temp.path().join("Jane Austin").exists());
+}
+
+#[test]
+fn test_author_dir_shared_with_other_books_is_kept() {
+    let (temp, mut lib) = setup_with_library();
+    let first = lib
+        .add_book(book_with_file(&temp, "One", "Shared Author"))
+        .unwrap();
+    let second = lib
+        .add_book(book_with_file(&temp, "Two", "Shared Author"))
+        .unwrap();
+
+    lib.update_book(
+        first.id,
+        BookUpdate {
+            author_names: Some(vec!["Someone Else".to_string()]),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    assert!(txt_path(&temp, &second).exists());
+    assert!(temp.path().join("Shared Author").exists());
+}
+
+#[test]
+fn test_unrelated_update_leaves_layout_alone() {
+    let (temp, mut lib) = setup_with_library();
+    let book = lib.add_book(book_with_file(&temp, "Still", "Put")).unwrap();
+
+    let updated = lib
+        .update_book(
+            book.id,
+            BookUpdate {
+                tags: Some(vec!["unchanged-path".to_string()]),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+    assert_eq!(updated.book_dir_path, book.book_dir_path);
+    assert_eq!(updated.files[0].name, book.files[0].name);
+    assert!(txt_path(&temp, &updated).exists());
+}
+
+#[test]
+fn test_leftovers_from_interrupted_relocation_are_replaced() {
+    let (temp, mut lib) = setup_with_library();
+    let book = lib
+        .add_book(book_with_file(&temp, "Draft", "Writer"))
+        .unwrap();
+
+    // A relocation that died before committing leaves a partial target.
+    let target = temp
+        .path()
+        .join("Writer")
+        .join(format!("Final ({})", book.id.as_i32()));
+    std::fs::create_dir_all(&target).unwrap();
+    std::fs::write(target.join("Final - Writer.txt"), "partial").unwrap();
+
+    let updated = lib
+        .update_book(
+            book.id,
+            BookUpdate {
+                title: Some("Final".to_string()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+    assert_eq!(book_dir(&temp, &updated), target);
+    assert_eq!(
+        std::fs::read_to_string(txt_path(&temp, &updated)).unwrap(),
+        "contents of Draft"
+    );
+}
+
+#[test]
+fn test_case_only_title_change_keeps_files() {
+    let (temp, mut lib) = setup_with_library();
+    let book = lib
+        .add_book(book_with_file(&temp, "dune", "Frank Herbert"))
+        .unwrap();
+
+    let updated = lib
+        .update_book(
+            book.id,
+            BookUpdate {
+                title: Some("Dune".to_string()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+    assert_eq!(updated.files[0].name, "Dune - Frank Herbert");
+    assert_eq!(
+        std::fs::read_to_string(txt_path(&temp, &updated)).unwrap(),
+        "contents of dune"
+    );
+}
+
+#[test]
+fn test_renaming_an_author_moves_their_books() {
+    let (temp, mut lib) = setup_with_library();
+    let book = lib
+        .add_book(book_with_file(&temp, "Ubik", "Phil Dick"))
+        .unwrap();
+    let author_id = book.authors[0].id;
+
+    lib.update_author(
+        author_id,
+        AuthorUpdate {
+            name: Some("Philip Dick".to_string()),
+            sort: None,
+            link: None,
+        },
+    )
+    .unwrap();
+
+    let moved = lib.get_book(book.id).unwrap();
+    assert!(moved.book_dir_path.starts_with("Philip Dick"));
+    assert_eq!(moved.files[0].name, "Ubik - Philip Dick");
+    assert!(txt_path(&temp, &moved).exists());
+    assert!(!temp.path().join("Phil Dick").exists());
+}
@@ -0,0 +1,279 @@
+// Tests for rebuilding metadata.db from the per-book metadata.opf files
+#[allow(dead_code, unused_imports)]
+mod common;
+
+use common::{setup_with_library, standard_test_book};
+use libcalibre::util::get_db_path;
+use libcalibre::{
+    AuthorUpdate, BookAdd, BookId, BookUpdate, CustomColumnKind, CustomColumnSpec, CustomValue,
+    Library,
+};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+fn fixture_library() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../../test-library/default")
+}
+
+fn book_from_fixture(title: &str, author: &str) -> BookAdd {
+    BookAdd {
+        title: title.to_string(),
+        author_names: vec![author.to_string()],
+        tags: Some(vec!["Classics".to_string(), "Whaling".to_string()]),
+        series: Some("Sea Stories".to_string()),
+        series_index: Some(1.5),
+        language: Some("eng".to_string()),
+        file_paths: vec![fixture_library().join("t1.txt")],
+        ..standard_test_book()
+    }
+}
+
+fn column_spec(label: &str, kind: CustomColumnKind, is_multiple: bool) -> CustomColumnSpec {
+    CustomColumnSpec {
+        label: label.to_string(),
+        name: label.to_uppercase(),
+        kind,
+        is_multiple,
+        enum_values: vec!["Owned".to_string(), "Wanted".to_string()],
+        display: None,
+    }
+}
+
+fn delete_database(root: &Path) {
+    for name in ["metadata.db", "metadata.db-wal", "metadata.db-shm"] {
+        let _ = std::fs::remove_file(root.join(name));
+    }
+}
+
+#[test]
+fn test_restores_books_after_database_is_deleted() {
+    let (temp, mut lib) = setup_with_library();
+    let root = lib.library_path().to_string();
+    let book = lib
+        .add_book(book_from_fixture("Moby Dick", "Herman Melville"))
+        .unwrap();
+    lib.update_book(
+        book.id,
+        BookUpdate {
+            publisher: Some("Harper & Brothers".to_string()),
+            rating: Some(10),
+            description: Some("<p>Call me Ishmael.</p>".to_string()),
+            identifiers: Some(HashMap::from([(
+                "isbn".to_string(),
+                "9780142437247".to_string(),
+            )])),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    lib.set_book_cover(
+        book.id,
+        std::fs::read(fixture_library().join("cover.jpg")).unwrap(),
+    )
+    .unwrap();
+    lib.update_author(
+        book.authors[0].id,
+        AuthorUpdate {
+            name: None,
+            sort: None,
+            link: Some("https://en.wikipedia.org/wiki/Herman_Melville".to_string()),
+        },
+    )
+    .unwrap();
+    let book = lib.get_book(book.id).unwrap();
+    drop(lib);
+
+    delete_database(temp.path());
+    let (mut restored_lib, report) = Library::restore_from_opf(&root).unwrap();
+
+    assert_eq!(report.restored, vec![book.id]);
+    assert!(report.failed.is_empty());
+    let restored = restored_lib.get_book(book.id).unwrap();
+    assert_eq!(restored.uuid, book.uuid);
+    assert_eq!(restored.title, "Moby Dick");
+    assert_eq!(restored.authors[0].name, "Herman Melville");
+    assert_eq!(
+        restored.authors[0].link.as_deref(),
+        Some("https://en.wikipedia.org/wiki/Herman_Melville")
+    );
+    assert_eq!(restored.tags, book.tags);
+    assert_eq!(restored.series.as_deref(), Some("Sea Stories"));
+    assert_eq!(restored.series_index, Some(1.5));
+    assert_eq!(restored.publisher.as_deref(), Some("Harper & Brothers"));
+    assert_eq!(restored.rating, Some(10));
+    assert_eq!(restored.description, book.description);
+    assert_eq!(restored.language_codes, vec!["eng"]);
+    assert_eq!(restored.identifiers[0].value, "9780142437247");
+    assert_eq!(restored.book_dir_path, book.book_dir_path);
+    assert!(restored.has_cover);
+    assert_eq!(restored.files.len(), 1);
+    assert_eq!(restored.files[0].name, book.files[0].name);
+    assert_eq!(restored.created_at, book.created_at);
+}
+
+#[test]
+fn test_restores_custom_columns_and_values() {
+    let (temp, mut lib) = setup_with_library();
+    let root = lib.library_path().to_string();
+    let book = lib
+        .add_book(book_from_fixture("Typee", "Herman Melville"))
+        .unwrap();
+    let shelves = lib
+        .create_custom_column(column_spec("shelves", CustomColumnKind::Text, true))
+        .unwrap();
+    let status = lib
+        .create_custom_column(column_spec("status", CustomColumnKind::Enumeration, false))
+        .unwrap();
+    let pages = lib
+        .create_custom_column(column_spec("pages", CustomColumnKind::Int, false))
+        .unwrap();
+    lib.set_custom_value(
+        book.id,
+        shelves.id,
+        Some(CustomValue::TextMultiple(vec![
+            "Attic".to_string(),
+            "Study".to_string(),
+        ])),
+    )
+    .unwrap();
+    lib.set_custom_value(
+        book.id,
+        status.id,
+        Some(CustomValue::Enumeration("Owned".to_string())),
+    )
+    .unwrap();
+    lib.set_custom_value(book.id, pages.id, Some(CustomValue::Int(301)))
+        .unwrap();
+    lib.set_book_read_state(book.id, true).unwrap();
+    drop(lib);
+
+    delete_database(temp.path());
+    let (mut restored_lib, report) = Library::restore_from_opf(&root).unwrap();
+
+    assert_eq!(report.restored, vec![book.id]);
+    let columns: HashMap<String, i32> = restored_lib
+        .custom_columns()
+        .unwrap()
+        .into_iter()
+        .map(|column| (column.label, column.id))
+        .collect();
+    let values = restored_lib.get_custom_values_for_book(book.id).unwrap();
+    assert_eq!(
+        values[&columns["shelves"]],
+        CustomValue::TextMultiple(vec!["Attic".to_string(), "Study".to_string()])
+    );
+    assert_eq!(
+        values[&columns["status"]],
+        CustomValue::Enumeration("Owned".to_string())
+    );
+    assert_eq!(values[&columns["pages"]], CustomValue::Int(301));
+    assert!(restored_lib.get_book_read_state(book.id).unwrap());
+}
+
+#[test]
+fn test_reports_folders_that_cannot_be_restored() {
+    let (temp, mut lib) = setup_with_library();
+    let root = lib.library_path().to_string();
+    let book = lib
+        .add_book(book_from_fixture("Omoo", "Herman Melville"))
+        .unwrap();
+    drop(lib);
+
+    let garbled = temp.path().join("Someone").join("Garbled (7)");
+    std::fs::create_dir_all(&garbled).unwrap();
+    std::fs::write(garbled.join("metadata.opf"), "<package><metadata>").unwrap();
+    let bare = temp.path().join("Someone").join("No Metadata (8)");
+    std::fs::create_dir_all(&bare).unwrap();
+    std::fs::write(bare.join("book.txt"), "text").unwrap();
+    let duplicate = temp.path().join("Someone").join("Duplicate (9)");
+    std::fs::create_dir_all(&duplicate).unwrap();
+    std::fs::copy(
+        temp.path().join(&book.book_dir_path).join("metadata.opf"),
+        duplicate.join("metadata.opf"),
+    )
+    .unwrap();
+
+    let (mut restored_lib, report) = Library::restore_from_opf(&root).unwrap();
+
+    assert_eq!(report.restored, vec![book.id]);
+    let mut failed: Vec<&str> = report.failed.iter().map(|f| f.path.as_str()).collect();
+    failed.sort();
+    assert_eq!(
+        failed,
+        vec![
+            "Someone/Duplicate (9)",
+            "Someone/Garbled (7)",
+            "Someone/No Metadata (8)"
+        ]
+    );
+    assert!(restored_lib.get_book(book.id).is_ok());
+    assert!(temp.path().join("metadata_pre_restore.db").is_file());
+}
+
+#[test]
+fn test_refuses_to_replace_an_earlier_pre_restore_backup() {
+    let (temp, mut lib) = setup_with_library();
+    let root = lib.library_path().to_string();
+    let book = lib
+        .add_book(book_from_fixture("Typee", "Herman Melville"))
+        .unwrap();
+    drop(lib);
+    let backup = temp.path().join("metadata_pre_restore.db");
+    std::fs::write(&backup, "earlier backup").unwrap();
+
+    assert!(Library::restore_from_opf(&root).is_err());
+
+    assert_eq!(std::fs::read_to_string(&backup).unwrap(), "earlier backup");
+    let mut lib = Library::new(get_db_path(&root).unwrap()).unwrap();
+    assert!(lib.get_book(book.id).is_ok());
+}
+
+#[test]
+fn test_restores_calibre_folder_using_folder_id() {
+    let (temp, lib) = setup_with_library();
+    let root = lib.library_path().to_string();
+    drop(lib);
+
+    let book_dir = temp.path().join("Octavia E. Butler").join("Kindred (42)");
+    std::fs::create_dir_all(&book_dir).unwrap();
+    std::fs::write(book_dir.join("Kindred - Octavia E. Butler.epub"), "epub").unwrap();
+    std::fs::write(
+        book_dir.join("metadata.opf"),
+        r#"<?xml version='1.0' encoding='utf-8'?>
+<package xmlns="http://www.idpf.org/2007/opf" unique-identifier="uuid_id" version="2.0">
+    <metadata xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:opf="http://www.idpf.org/2007/opf">
+        <dc:identifier opf:scheme="uuid" id="uuid_id">5d2c3b6a-9a3f-4bb5-8d8e-4f2b1c7e9a10</dc:identifier>
+        <dc:title>Kindred</dc:title>
+        <dc:creator opf:file-as="Butler, Octavia E." opf:role="aut">Octavia E. Butler</dc:creator>
+        <dc:language>eng</dc:language>
+        <meta name="calibre:user_metadata:#format" content="{&quot;table&quot;: &quot;custom_column_2&quot;, &quot;column&quot;: &quot;value&quot;, &quot;datatype&quot;: &quot;series&quot;, &quot;is_multiple&quot;: null, &quot;kind&quot;: &quot;field&quot;, &quot;name&quot;: &quot;Reading list&quot;, &quot;search_terms&quot;: [&quot;#format&quot;], &quot;label&quot;: &quot;format&quot;, &quot;colnum&quot;: 2, &quot;display&quot;: {}, &quot;is_custom&quot;: true, &quot;is_category&quot;: true, &quot;link_column&quot;: &quot;value&quot;, &quot;category_sort&quot;: &quot;value&quot;, &quot;is_csp&quot;: false, &quot;is_editable&quot;: true, &quot;#value#&quot;: &quot;Summer&quot;, &quot;#extra#&quot;: 3.0, &quot;is_multiple2&quot;: {}}"/>
+    </metadata>
+</package>"#,
+    )
+    .unwrap();
+
+    let (mut restored_lib, report) = Library::restore_from_opf(&root).unwrap();
+
+    assert_eq!(report.restored, vec![BookId::from(42)]);
+    let book = restored_lib.get_book(BookId::from(42)).unwrap();
+    assert_eq!(book.uuid, "5d2c3b6a-9a3f-4bb5-8d8e-4f2b1c7e9a10");
+    assert_eq!(book.book_dir_path, "Octavia E. Butler/Kindred (42)");
+    assert_eq!(book.files[0].format, "EPUB");
+    let columns = restored_lib.custom_columns().unwrap();
+    assert_eq!(columns[0].label, "format");
+    assert_eq!(columns[0].kind, CustomColumnKind::Series);
+
+    let conn = rusqlite::Connection::open(temp.path().join("metadata.db")).unwrap();
+    let (series, index): (String, f64) = conn
+        .query_row(
+            &format!(
+                "SELECT cc.value, l.extra FROM books_custom_column_{n}_link l
+                 JOIN custom_column_{n} cc ON cc.id = l.value WHERE l.book = 42",
+                n = columns[0].id
+            ),
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .unwrap();
+    assert_eq!((series.as_str(), index), ("Summer", 3.0));
+}
@@ -0,0 +1,280 @@
+// Tests for saved searches and virtual libraries kept in Calibre's preferences
+// This binary uses only a subset of the shared test helpers.
+#[allow(dead_code, unused_imports)]
+mod common;
+
+use common::{conn, setup_with_library, standard_test_book};
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::Text;
+use libcalibre::{BookAdd, BookQuery, CalibreError, Library, SavedSearch, VirtualLibrary};
+
+/// Four books:
+///
+/// | title      | author          | tags            |
+/// |------------|-----------------|-----------------|
+/// | Mort       | Terry Pratchett | Fantasy, Humour |
+/// | Sourcery   | Terry Pratchett | Fantasy         |
+/// | Neverwhere | Neil Gaiman     | Fantasy, Urban  |
+/// | Dune       | Frank Herbert   | Science Fiction |
+fn library_with_books() -> (tempfile::TempDir, Library) {
+    let (temp, mut lib) = setup_with_library();
+    let rows: [(&str, &str, &[&str]); 4] = [
+        ("Mort", "Terry Pratchett", &["Fantasy", "Humour"]),
+        ("Sourcery", "Terry Pratchett", &["Fantasy"]),
+        ("Neverwhere", "Neil Gaiman", &["Fantasy", "Urban"]),
+        ("Dune", "Frank Herbert", &["Science Fiction"]),
+    ];
+    for (title, author, tags) in rows {
+        lib.add_book(BookAdd {
+            title: title.to_string(),
+            author_names: vec![author.to_string()],
+            tags: Some(tags.iter().map(|t| (*t).to_string()).collect()),
+            ..standard_test_book()
+        })
+        .unwrap();
+    }
+    (temp, lib)
+}
+
+#[derive(QueryableByName)]
+struct PreferenceRow {
+    #[diesel(sql_type = Text)]
+    val: String,
+}
+
+fn raw_preference(lib: &Library, key: &str) -> Option<serde_json::Value> {
+    sql_query("SELECT val FROM preferences WHERE key = ?")
+        .bind::<Text, _>(key)
+        .get_result::<PreferenceRow>(&mut conn(lib))
+        .optional()
+        .unwrap()
+        .map(|row| serde_json::from_str(&row.val).unwrap())
+}
+
+fn write_preference(lib: &Library, key: &str, value: &str) {
+    sql_query("INSERT OR REPLACE INTO preferences (key, val) VALUES (?, ?)")
+        .bind::<Text, _>(key)
+        .bind::<Text, _>(value)
+        .execute(&mut conn(lib))
+        .unwrap();
+}
+
+fn titles(lib: &mut Library, query: BookQuery) -> Vec<String> {
+    let mut titles: Vec<String> = lib
+        .query_books(query)
+        .unwrap()
+        .items
+        .into_iter()
+        .map(|book| book.title)
+        .collect();
+    titles.sort();
+    titles
+}
+
+fn search(lib: &mut Library, search: &str) -> Result<Vec<String>, CalibreError> {
+    lib.query_books(BookQuery {
+        search: Some(search.to_string()),
+        ..BookQuery::default()
+    })
+    .map(|page| {
+        let mut titles: Vec<String> = page.items.into_iter().map(|book| book.title).collect();
+        titles.sort();
+        titles
+    })
+}
+
+#[test]
+fn test_reads_entries_written_by_calibre() {
+    let (_temp, mut lib) = library_with_books();
+    write_preference(
+        &lib,
+        "saved_searches",
+        "{\n  \"funny\": \"tag:Humour\",\n  \"Authors\": \"author:Gaiman\"\n}",
+    );
+    write_preference(
+        &lib,
+        "virtual_libraries",
+        "{\n  \"Pratchett\": \"author:\\\"=Terry Pratchett\\\"\",\n  \"Everything\": \"\"\n}",
+    );
+
+    assert_eq!(
+        lib.saved_searches().unwrap(),
+        vec![
+            SavedSearch {
+                name: "Authors".to_string(),
+                query: "author:Gaiman".to_string(),
+            },
+            SavedSearch {
+                name: "funny".to_string(),
+                query: "tag:Humour".to_string(),
+            },
+        ]
+    );
+    assert_eq!(
+        lib.virtual_libraries().unwrap(),
+        vec![
+            VirtualLibrary {
+                name: "Everything".to_string(),
+                search: String::new(),
+            },
+            VirtualLibrary {
+                name: "Pratchett".to_string(),
+                search: "author:\"=Terry Pratchett\"".to_string(),
+            },
+        ]
+    );
+
+    assert_eq!(search(&mut lib, "search:funny").unwrap(), vec!["Mort"]);
+    assert_eq!(
+        search(&mut lib, "vl:Pratchett and not search:funny").unwrap(),
+        vec!["Sourcery"]
+    );
+    assert_eq!(search(&mut lib, "vl:Everything").unwrap().len(), 4);
+}
+
+#[test]
+fn test_save_and_remove_round_trip() {
+    let (_temp, mut lib) = library_with_books();
+    write_preference(&lib, "saved_searches", "{\"keep\": \"tag:Urban\"}");
+
+    lib.save_search(" sf ", "tag:\"Science Fiction\"").unwrap();
+    lib.save_search("keep", "tag:Fantasy").unwrap();
+    lib.save_virtual_library("Gaiman", "author:Gaiman").unwrap();
+
+    assert_eq!(
+        raw_preference(&lib, "saved_searches"),
+        Some(serde_json::json!({
+            "keep": "tag:Fantasy",
+            "sf": "tag:\"Science Fiction\"",
+        }))
+    );
+    assert_eq!(
+        raw_preference(&lib, "virtual_libraries"),
+        Some(serde_json::json!({ "Gaiman": "author:Gaiman" }))
+    );
+    assert_eq!(search(&mut lib, "search:sf").unwrap(), vec!["Dune"]);
+
+    assert!(lib.remove_saved_search("sf").unwrap());
+    assert!(!lib.remove_saved_search("sf").unwrap());
+    assert!(lib.remove_virtual_library("Gaiman").unwrap());
+    assert_eq!(
+        raw_preference(&lib, "saved_searches"),
+        Some(serde_json::json!({ "keep": "tag:Fantasy" }))
+    );
+    assert_eq!(lib.virtual_libraries().unwrap(), vec![]);
+}
+
+#[test]
+fn test_virtual_library_restricts_query() {
+    let (_temp, mut lib) = library_with_books();
+    lib.save_virtual_library("Fantasy", "tag:Fantasy").unwrap();
+    lib.save_virtual_library("All", "").unwrap();
+
+    assert_eq!(
+        titles(
+            &mut lib,
+            BookQuery {
+                virtual_library: Some("Fantasy".to_string()),
+                ..BookQuery::default()
+            }
+        ),
+        vec!["Mort", "Neverwhere", "Sourcery"]
+    );
+    assert_eq!(
+        titles(
+            &mut lib,
+            BookQuery {
+                virtual_library: Some("Fantasy".to_string()),
+                search: Some("author:Pratchett".to_string()),
+                ..BookQuery::default()
+            }
+        ),
+        vec!["Mort", "Sourcery"]
+    );
+
+    let page = lib
+        .query_books(BookQuery {
+            virtual_library: Some("Fantasy".to_string()),
+            limit: Some(1),
+            offset: 1,
+            ..BookQuery::default()
+        })
+        .unwrap();
+    assert_eq!(page.total, 3);
+    assert_eq!(page.items.len(), 1);
+
+    let facets = lib
+        .facets(&BookQuery {
+            virtual_library: Some("Fantasy".to_string()),
+            ..BookQuery::default()
+        })
+        .unwrap();
+    assert_eq!(facets.total, 3);
+
+    assert_eq!(
+        titles(
+            &mut lib,
+            BookQuery {
+                virtual_library: Some("All".to_string()),
+                ..BookQuery::default()
+            }
+        )
+        .len(),
+        4
+    );
+}
+
+#[test]
+fn test_unknown_and_recursive_names_are_invalid() {
+    let (_temp, mut lib) = library_with_books();
+    assert!(matches!(
+        search(&mut lib, "search:missing"),
+        Err(CalibreError::InvalidSearch(_))
+    ));
+    assert!(matches!(
+        lib.query_books(BookQuery {
+            virtual_library: Some("missing".to_string()),
+            ..BookQuery::default()
+        }),
+        Err(CalibreError::InvalidSearch(_))
+    ));
+
+    write_preference(
+        &lib,
+        "saved_searches",
+        "{\"a\": \"search:b\", \"b\": \"tag:Fantasy or search:a\"}",
+    );
+    assert!(matches!(
+        search(&mut lib, "search:a"),
+        Err(CalibreError::InvalidSearch(message)) if message.contains("refers to itself")
+    ));
+}
+
+#[test]
+fn test_invalid_entries_are_rejected_on_save() {
+    let (_temp, mut lib) = library_with_books();
+    for (name, query) in [
+        ("broken", "tag:(Fantasy"),
+        ("unknown", "search:missing"),
+        ("empty", ""),
+        ("  ", "tag:Fantasy"),
+    ] {
+        assert!(
+            matches!(
+                lib.save_search(name, query),
+                Err(CalibreError::InvalidSearch(_))
+            ),
+            "{name:?}: {query:?}"
+        );
+    }
+    assert!(matches!(
+        lib.save_virtual_library("", ""),
+        Err(CalibreError::InvalidSearch(_))
+    ));
+    assert_eq!(lib.saved_searches().unwrap(), vec![]);
+    assert_eq!(
+        raw_preference(&lib, "saved_searches"),
+        Some(serde_json::json!({}))
+    );
+}
@@ -0,0 +1,249 @@
+// Tests for schema version detection and Calibre's schema upgrades
+#[allow(dead_code, unused_imports)]
+mod common;
+
+use libcalibre::util::get_db_path;
+use libcalibre::{CalibreError, Library, SchemaStatus};
+use rusqlite::Connection;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+const CURRENT_VERSION: i32 = 26;
+
+fn fixture_library() -> TempDir {
+    let temp = tempfile::tempdir().unwrap();
+    let fixture =
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/empty_library/metadata.db");
+    std::fs::copy(fixture, temp.path().join("metadata.db")).unwrap();
+    temp
+}
+
+/// Turn the Calibre 7 fixture back into a version 20 database: no links on
+/// item tables (the given custom columns' included), no reading positions or
+/// annotations, no series sort triggers.
+fn downgrade_to_version_20(root: &Path, custom_column_ids: &[i32]) {
+    let conn = Connection::open(root.join("metadata.db")).unwrap();
+    // Calibre's legacy `meta` view names a long-gone column, and the book
+    // delete trigger names the tables dropped here; SQLite refuses to drop
+    // any column while either exists. Opening the library recreates the
+    // trigger.
+    let meta_view: String = conn
+        .query_row(
+            "SELECT sql FROM sqlite_master WHERE name = 'meta'",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap();
+    conn.execute_batch(
+        "DROP VIEW meta;
+         DROP TRIGGER IF EXISTS books_delete_trg;
+         DROP TRIGGER series_insert_trg;
+         DROP TRIGGER series_update_trg;
+         DROP TABLE last_read_positions;
+         DROP TABLE annotations_fts;
+         DROP TABLE annotations_fts_stemmed;
+         DROP TABLE annotations;
+         DROP TABLE annotations_dirtied;
+         ALTER TABLE authors DROP COLUMN link;
+         ALTER TABLE publishers DROP COLUMN link;
+         ALTER TABLE series DROP COLUMN link;
+         ALTER TABLE tags DROP COLUMN link;
+         ALTER TABLE ratings DROP COLUMN link;
+         ALTER TABLE languages DROP COLUMN link;
+         INSERT INTO series (name, sort) VALUES ('The Expanse', 'The Expanse');
+         PRAGMA user_version = 20;",
+    )
+    .unwrap();
+    for id in custom_column_ids {
+        conn.execute_batch(&format!(
+            "DROP VIEW tag_browser_custom_column_{id};
+             DROP VIEW tag_browser_filtered_custom_column_{id};
+             ALTER TABLE custom_column_{id} DROP COLUMN link;"
+        ))
+        .unwrap();
+    }
+    conn.execute_batch(&meta_view).unwrap();
+}
+
+fn open(root: &Path) -> Result<Library, CalibreError> {
+    Library::new(get_db_path(root.to_str().unwrap()).unwrap())
+}
+
+fn user_version(root: &Path) -> i32 {
+    Connection::open(root.join("metadata.db"))
+        .unwrap()
+        .query_row("PRAGMA user_version", [], |row| row.get(0))
+        .unwrap()
+}
+
+fn has_column(root: &Path, table: &str, column: &str) -> bool {
+    Connection::open(root.join("metadata.db"))
+        .unwrap()
+        .prepare(&format!("SELECT {column} FROM {table}"))
+        .is_ok()
+}
+
+#[test]
+fn test_current_library_opens_unchanged() {
+    let temp = fixture_library();
+
+    let mut lib = open(temp.path()).unwrap();
+
+    let current = SchemaStatus::Current {
+        version: CURRENT_VERSION,
+    };
+    assert_eq!(lib.schema_status(), current);
+    assert_eq!(lib.upgrade_schema().unwrap(), current);
+    assert!(!temp
+        .path()
+        .join(format!("metadata_v{CURRENT_VERSION}_backup.db"))
+        .exists());
+}
+
+#[test]
+fn test_opening_older_library_does_not_upgrade_it() {
+    let temp = fixture_library();
+    downgrade_to_version_20(temp.path(), &[]);
+
+    let lib = open(temp.path()).unwrap();
+
+    assert_eq!(
+        lib.schema_status(),
+        SchemaStatus::NeedsUpgrade {
+            from: 20,
+            to: CURRENT_VERSION
+        }
+    );
+    assert_eq!(user_version(temp.path()), 20);
+    assert!(!has_column(temp.path(), "authors", "link"));
+    assert!(!temp.path().join("metadata_v20_backup.db").exists());
+}
+
+#[test]
+fn test_upgrades_version_20_library_to_current() {
+    let temp = fixture_library();
+    downgrade_to_version_20(temp.path(), &[]);
+
+    let mut lib = open(temp.path()).unwrap();
+    let upgraded = SchemaStatus::Upgraded {
+        from: 20,
+        to: CURRENT_VERSION,
+    };
+
+    assert_eq!(lib.upgrade_schema().unwrap(), upgraded);
+    assert_eq!(lib.schema_status(), upgraded);
+    assert_eq!(user_version(temp.path()), CURRENT_VERSION);
+    for table in [
+        "authors",
+        "publishers",
+        "series",
+        "tags",
+        "ratings",
+        "languages",
+    ] {
+        assert!(has_column(temp.path(), table, "link"), "{table}.link");
+    }
+    assert!(has_column(temp.path(), "last_read_positions", "cfi"));
+    assert!(has_column(temp.path(), "annotations", "searchable_text"));
+    assert!(has_column(temp.path(), "annotations_dirtied", "book"));
+
+    // Step 21 recomputed series sorts with Calibre's title_sort.
+    let sort: String = Connection::open(temp.path().join("metadata.db"))
+        .unwrap()
+        .query_row(
+            "SELECT sort FROM series WHERE name = 'The Expanse'",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(sort, "Expanse, The");
+
+    // The database as it was is kept beside the upgraded one.
+    let backup = temp.path().join("metadata_v20_backup.db");
+    let backup_version: i32 = Connection::open(&backup)
+        .unwrap()
+        .query_row("PRAGMA user_version", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(backup_version, 20);
+
+    // The upgraded library is usable: adding and removing books touches the
+    // restored tables through the triggers.
+    let book = lib.add_book(common::standard_test_book()).unwrap();
+    lib.remove_books(vec![book.id]).unwrap();
+}
+
+#[test]
+fn test_upgrades_custom_column_tables() {
+    let temp = fixture_library();
+    let column = open(temp.path())
+        .unwrap()
+        .create_custom_column(libcalibre::CustomColumnSpec {
+            label: "shelf".to_string(),
+            name: "Shelf".to_string(),
+            kind: libcalibre::CustomColumnKind::Text,
+            is_multiple: false,
+            enum_values: vec![],
+            display: None,
+        })
+        .unwrap();
+    downgrade_to_version_20(temp.path(), &[column.id]);
+
+    open(temp.path()).unwrap().upgrade_schema().unwrap();
+
+    assert!(has_column(
+        temp.path(),
+        &format!("custom_column_{}", column.id),
+        "link"
+    ));
+}
+
+#[test]
+fn test_failed_upgrade_rolls_back() {
+    let temp = fixture_library();
+    downgrade_to_version_20(temp.path(), &[]);
+    // Step 25 adds tags.link; having it already makes that step fail.
+    Connection::open(temp.path().join("metadata.db"))
+        .unwrap()
+        .execute_batch("ALTER TABLE tags ADD COLUMN link TEXT NOT NULL DEFAULT '';")
+        .unwrap();
+
+    assert!(open(temp.path()).unwrap().upgrade_schema().is_err());
+
+    assert_eq!(user_version(temp.path()), 20);
+    assert!(!has_column(temp.path(), "authors", "link"));
+    assert!(!has_column(temp.path(), "annotations", "id"));
+}
+
+#[test]
+fn test_refuses_library_too_old_to_upgrade() {
+    let temp = fixture_library();
+    Connection::open(temp.path().join("metadata.db"))
+        .unwrap()
+        .execute_batch("PRAGMA user_version = 18;")
+        .unwrap();
+
+    assert!(matches!(
+        open(temp.path()),
+        Err(CalibreError::UnsupportedSchemaVersion(18))
+    ));
+    assert_eq!(user_version(temp.path()), 18);
+}
+
+#[test]
+fn test_reports_newer_library_without_touching_it() {
+    let temp = fixture_library();
+    Connection::open(temp.path().join("metadata.db"))
+        .unwrap()
+        .execute_batch("PRAGMA user_version = 27;")
+        .unwrap();
+
+    let mut lib = open(temp.path()).unwrap();
+
+    let newer = SchemaStatus::Newer {
+        version: 27,
+        supported: CURRENT_VERSION,
+    };
+    assert_eq!(lib.schema_status(), newer);
+    assert_eq!(lib.upgrade_schema().unwrap(), newer);
+    assert_eq!(user_version(temp.path()), 27);
+}
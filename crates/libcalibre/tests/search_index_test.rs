@@ -0,0 +1,196 @@
+// Tests for the metadata search index in .citadel/search-index.db
+// This binary uses only a subset of the shared test helpers.
+#[allow(dead_code, unused_imports)]
+mod common;
+
+use common::{setup_with_library, standard_test_book};
+use diesel::RunQueryDsl;
+use libcalibre::persistence::establish_connection;
+use libcalibre::util::get_db_path;
+use libcalibre::{AuthorUpdate, BookAdd, BookQuery, BookUpdate, Library};
+use rusqlite::Connection;
+use std::path::Path;
+
+fn book(title: &str, author: &str, series: Option<&str>) -> BookAdd {
+    BookAdd {
+        title: title.to_string(),
+        author_names: vec![author.to_string()],
+        tags: Some(vec!["Fantasy".to_string()]),
+        series: series.map(str::to_string),
+        ..standard_test_book()
+    }
+}
+
+fn text_search(lib: &mut Library, text: &str) -> Vec<String> {
+    let mut titles: Vec<String> = lib
+        .query_books(BookQuery {
+            text: Some(text.to_string()),
+            ..BookQuery::default()
+        })
+        .unwrap()
+        .items
+        .into_iter()
+        .map(|book| book.title)
+        .collect();
+    titles.sort();
+    titles
+}
+
+fn open(root: &Path) -> Library {
+    Library::new(get_db_path(root.to_str().unwrap()).unwrap()).unwrap()
+}
+
+/// Book ids whose indexed `column` contains `needle`, read from the sidecar.
+fn indexed_matches(root: &Path, column: &str, needle: &str) -> Vec<i64> {
+    let conn = Connection::open(root.join(".citadel/search-index.db")).unwrap();
+    let mut statement = conn
+        .prepare("SELECT rowid FROM metadata_fts WHERE metadata_fts MATCH ? ORDER BY rowid")
+        .unwrap();
+    statement
+        .query_map([format!("{{{column}}} : \"{needle}\"")], |row| row.get(0))
+        .unwrap()
+        .map(Result::unwrap)
+        .collect()
+}
+
+#[test]
+fn test_text_query_matches_substrings_through_the_index() {
+    let (temp, mut lib) = setup_with_library();
+    lib.add_book(book("The Rust Programming Language", "Steve Klabnik", None))
+        .unwrap();
+    lib.add_book(book("Dune", "Frank Herbert", Some("Dune Chronicles")))
+        .unwrap();
+
+    assert!(temp.path().join(".citadel/search-index.db").is_file());
+    // Mid-word, across words, case-insensitive: the same as LIKE '%text%'.
+    assert_eq!(
+        text_search(&mut lib, "RUST pro"),
+        vec!["The Rust Programming Language"]
+    );
+    assert_eq!(
+        text_search(&mut lib, "abni"),
+        vec!["The Rust Programming Language"]
+    );
+    assert_eq!(text_search(&mut lib, "chronicle"), vec!["Dune"]);
+    // Needles too short for the index still match.
+    assert_eq!(text_search(&mut lib, "du"), vec!["Dune"]);
+    // Tags are not part of the text filter.
+    assert!(text_search(&mut lib, "fantasy").is_empty());
+}
+
+#[test]
+fn test_index_follows_edits() {
+    let (temp, mut lib) = setup_with_library();
+    let dune = lib.add_book(book("Dune", "Frank Herbert", None)).unwrap();
+    let other = lib
+        .add_book(book("Children of Dune", "Frank Herbert", None))
+        .unwrap();
+
+    lib.update_book(
+        dune.id,
+        BookUpdate {
+            title: Some("Dune Messiah".to_string()),
+            series: Some("Dune Chronicles".to_string()),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(text_search(&mut lib, "messiah"), vec!["Dune Messiah"]);
+    let root = temp.path();
+    assert_eq!(
+        indexed_matches(root, "series", "chronicles"),
+        vec![dune.id.as_i32() as i64]
+    );
+
+    // Renaming an author reindexes all of their books.
+    lib.update_author(
+        dune.authors[0].id,
+        AuthorUpdate {
+            name: Some("F. P. Herbert".to_string()),
+            sort: None,
+            link: None,
+        },
+    )
+    .unwrap();
+    assert_eq!(
+        text_search(&mut lib, "f. p."),
+        vec!["Children of Dune", "Dune Messiah"]
+    );
+
+    lib.remove_books(vec![other.id]).unwrap();
+    assert_eq!(text_search(&mut lib, "dune"), vec!["Dune Messiah"]);
+    assert!(indexed_matches(root, "title", "children").is_empty());
+}
+
+#[test]
+fn test_reopening_catches_up_with_outside_edits() {
+    let (temp, lib) = setup_with_library();
+    drop(lib);
+    let mut lib = open(temp.path());
+    let book = lib.add_book(book("Dune", "Frank Herbert", None)).unwrap();
+    drop(lib);
+
+    // Calibre edits the title while Citadel is closed.
+    let db_path = temp.path().join("metadata.db");
+    let mut conn = establish_connection(db_path.to_str().unwrap()).unwrap();
+    diesel::sql_query(
+        "UPDATE books SET title = 'Dune Messiah', \
+         last_modified = '2030-01-01 00:00:00+00:00' WHERE id = ?",
+    )
+    .bind::<diesel::sql_types::Integer, _>(book.id.as_i32())
+    .execute(&mut conn)
+    .unwrap();
+    drop(conn);
+
+    let mut lib = open(temp.path());
+    assert_eq!(text_search(&mut lib, "messiah"), vec!["Dune Messiah"]);
+}
+
+#[test]
+fn test_missing_index_is_rebuilt_and_metadata_db_untouched() {
+    let (temp, mut lib) = setup_with_library();
+    lib.add_book(book("Dune", "Frank Herbert", None)).unwrap();
+    drop(lib);
+    std::fs::remove_dir_all(temp.path().join(".citadel")).unwrap();
+
+    let mut lib = open(temp.path());
+    assert_eq!(text_search(&mut lib, "herbert"), vec!["Dune"]);
+    lib.rebuild_search_index().unwrap();
+    assert_eq!(text_search(&mut lib, "herbert"), vec!["Dune"]);
+
+    let citadel_objects: i64 = Connection::open(temp.path().join("metadata.db"))
+        .unwrap()
+        .query_row(
+            "SELECT COUNT(*) FROM sqlite_master WHERE name LIKE '%citadel%' OR sql LIKE '%metadata_fts%'",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(citadel_objects, 0);
+}
+
+#[test]
+fn test_outdated_index_is_rebuilt_with_only_the_searched_fields() {
+    let (temp, mut lib) = setup_with_library();
+    lib.add_book(book("Dune", "Frank Herbert", None)).unwrap();
+    drop(lib);
+    let index_path = temp.path().join(".citadel/search-index.db");
+    Connection::open(&index_path)
+        .unwrap()
+        .execute_batch("PRAGMA user_version = 2;")
+        .unwrap();
+
+    let mut lib = open(temp.path());
+    assert_eq!(text_search(&mut lib, "herbert"), vec!["Dune"]);
+
+    let conn = Connection::open(&index_path).unwrap();
+    let mut statement = conn
+        .prepare("SELECT name FROM pragma_table_info('metadata_fts')")
+        .unwrap();
+    let columns: Vec<String> = statement
+        .query_map([], |row| row.get(0))
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(columns, vec!["title", "authors", "series"]);
+}
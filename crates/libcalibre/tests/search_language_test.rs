@@ -0,0 +1,279 @@
+// Tests for Calibre search-language queries (BookQuery::search)
+// This binary uses only a subset of the shared test helpers.
+#[allow(dead_code, unused_imports)]
+mod common;
+
+use chrono::NaiveDate;
+use common::{setup_with_library, standard_test_book};
+use diesel::RunQueryDsl;
+use libcalibre::{
+    BookAdd, BookQuery, BookUpdate, CalibreError, CustomColumnKind, CustomColumnSpec, CustomValue,
+    Library,
+};
+use std::collections::HashMap;
+
+fn book(title: &str, author: &str, tags: &[&str], series: Option<&str>) -> BookAdd {
+    BookAdd {
+        title: title.to_string(),
+        author_names: vec![author.to_string()],
+        tags: Some(tags.iter().map(|t| (*t).to_string()).collect()),
+        series: series.map(str::to_string),
+        series_index: Some(1.0),
+        language: Some("eng".to_string()),
+        ..standard_test_book()
+    }
+}
+
+/// Four books:
+///
+/// | title                | author            | tags                   | series    | stars | pubdate    |
+/// |----------------------|-------------------|------------------------|-----------|-------|------------|
+/// | Guards! Guards!      | Terry Pratchett   | Fantasy, Humour        | Discworld | 5     | 1989-11-01 |
+/// | A Wizard of Earthsea | Ursula K. Le Guin | Fantasy                | Earthsea  | 4     | 1968-01-01 |
+/// | The Dispossessed     | Ursula K. Le Guin | Science Fiction.Utopia |           | 3     | 1974-05-01 |
+/// | Dune                 | Frank Herbert     | Science Fiction        |           |       | today      |
+fn library_with_books() -> (tempfile::TempDir, Library) {
+    let (temp, mut lib) = setup_with_library();
+    let books = [
+        (
+            book(
+                "Guards! Guards!",
+                "Terry Pratchett",
+                &["Fantasy", "Humour"],
+                Some("Discworld"),
+            ),
+            Some(10),
+            Some((1989, 11, 1)),
+            Some("9780575046061"),
+        ),
+        (
+            book(
+                "A Wizard of Earthsea",
+                "Ursula K. Le Guin",
+                &["Fantasy"],
+                Some("Earthsea"),
+            ),
+            Some(8),
+            Some((1968, 1, 1)),
+            None,
+        ),
+        (
+            book(
+                "The Dispossessed",
+                "Ursula K. Le Guin",
+                &["Science Fiction.Utopia"],
+                None,
+            ),
+            Some(6),
+            Some((1974, 5, 1)),
+            None,
+        ),
+        (
+            book("Dune", "Frank Herbert", &["Science Fiction"], None),
+            None,
+            None,
+            None,
+        ),
+    ];
+    for (add, rating, pubdate, isbn) in books {
+        let created = lib.add_book(add).unwrap();
+        lib.update_book(
+            created.id,
+            BookUpdate {
+                rating,
+                publication_date: pubdate
+                    .map(|(y, m, d)| NaiveDate::from_ymd_opt(y, m, d).unwrap()),
+                identifiers: isbn
+                    .map(|isbn| HashMap::from([("isbn".to_string(), isbn.to_string())])),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    }
+    (temp, lib)
+}
+
+fn search(lib: &mut Library, query: &str) -> Vec<String> {
+    let mut titles: Vec<String> = lib
+        .query_books(BookQuery {
+            search: Some(query.to_string()),
+            ..BookQuery::default()
+        })
+        .unwrap()
+        .items
+        .into_iter()
+        .map(|book| book.title)
+        .collect();
+    titles.sort();
+    titles
+}
+
+#[test]
+fn test_field_prefixes_and_bare_terms() {
+    let (_temp, mut lib) = library_with_books();
+
+    assert_eq!(search(&mut lib, "tag:fantasy").len(), 2);
+    assert_eq!(search(&mut lib, "authors:guin").len(), 2);
+    assert_eq!(search(&mut lib, "series:disc"), vec!["Guards! Guards!"]);
+    assert_eq!(search(&mut lib, "languages:eng").len(), 4);
+    // Bare terms match any default field: here a title and a tag.
+    assert_eq!(search(&mut lib, "dune"), vec!["Dune"]);
+    assert_eq!(search(&mut lib, "humour"), vec!["Guards! Guards!"]);
+    assert_eq!(
+        search(&mut lib, "identifiers:isbn:978057"),
+        vec!["Guards! Guards!"]
+    );
+    assert_eq!(search(&mut lib, "identifiers:false").len(), 3);
+}
+
+#[test]
+fn test_match_modes() {
+    let (_temp, mut lib) = library_with_books();
+
+    assert_eq!(search(&mut lib, "tags:\"science fiction\"").len(), 2);
+    assert_eq!(search(&mut lib, "tags:\"=science fiction\""), vec!["Dune"]);
+    // A leading period matches the tag and everything below it.
+    assert_eq!(search(&mut lib, "tags:\".science fiction\"").len(), 2);
+    assert_eq!(
+        search(&mut lib, r#"title:"~^(a|the) ""#),
+        vec!["A Wizard of Earthsea", "The Dispossessed"]
+    );
+    assert!(matches!(
+        lib.query_books(BookQuery {
+            search: Some("title:~(".to_string()),
+            ..BookQuery::default()
+        }),
+        Err(CalibreError::InvalidSearch(_))
+    ));
+}
+
+#[test]
+fn test_boolean_operators() {
+    let (_temp, mut lib) = library_with_books();
+
+    assert_eq!(
+        search(&mut lib, "tag:fantasy and not series:\"Discworld\""),
+        vec!["A Wizard of Earthsea"]
+    );
+    assert_eq!(
+        search(&mut lib, "series:discworld or title:dune"),
+        vec!["Dune", "Guards! Guards!"]
+    );
+    assert_eq!(
+        search(&mut lib, "authors:guin (series:earthsea or tags:utopia)").len(),
+        2
+    );
+    assert_eq!(search(&mut lib, "not series:true").len(), 2);
+}
+
+#[test]
+fn test_numeric_and_date_comparisons() {
+    let (_temp, mut lib) = library_with_books();
+
+    assert_eq!(search(&mut lib, "rating:>=4").len(), 2);
+    assert_eq!(search(&mut lib, "rating:3"), vec!["The Dispossessed"]);
+    assert_eq!(search(&mut lib, "rating:false"), vec!["Dune"]);
+    assert_eq!(
+        search(&mut lib, "pubdate:<1970"),
+        vec!["A Wizard of Earthsea"]
+    );
+    assert_eq!(
+        search(&mut lib, "pubdate:1974-05"),
+        vec!["The Dispossessed"]
+    );
+    assert_eq!(search(&mut lib, "pubdate:>=1974 pubdate:<=1989").len(), 2);
+    // Dune was added without a publication date, so it got today's.
+    assert_eq!(search(&mut lib, "not pubdate:<2000"), vec!["Dune"]);
+    assert_eq!(search(&mut lib, "date:today").len(), 4);
+    assert!(search(&mut lib, "pubdate:false").is_empty());
+    // Calibre's "undefined" publication date counts as no date.
+    let mut conn = libcalibre::persistence::establish_connection(lib.database_path()).unwrap();
+    diesel::sql_query(
+        "UPDATE books SET pubdate = '0101-01-01 00:00:00+00:00' WHERE title = 'Dune'",
+    )
+    .execute(&mut conn)
+    .unwrap();
+    assert_eq!(search(&mut lib, "pubdate:false"), vec!["Dune"]);
+    assert_eq!(search(&mut lib, "pubdate:true").len(), 3);
+    assert!(matches!(
+        lib.query_books(BookQuery {
+            search: Some("rating:>lots".to_string()),
+            ..BookQuery::default()
+        }),
+        Err(CalibreError::InvalidSearch(_))
+    ));
+}
+
+#[test]
+fn test_custom_columns() {
+    let (_temp, mut lib) = library_with_books();
+    let ids: HashMap<String, libcalibre::BookId> = lib
+        .books()
+        .unwrap()
+        .into_iter()
+        .map(|book| (book.title, book.id))
+        .collect();
+    let pages = lib
+        .create_custom_column(CustomColumnSpec {
+            label: "pages".to_string(),
+            name: "Pages".to_string(),
+            kind: CustomColumnKind::Int,
+            is_multiple: false,
+            enum_values: vec![],
+            display: None,
+        })
+        .unwrap();
+    lib.set_custom_value(ids["Dune"], pages.id, Some(CustomValue::Int(412)))
+        .unwrap();
+    lib.set_custom_value(
+        ids["Guards! Guards!"],
+        pages.id,
+        Some(CustomValue::Int(288)),
+    )
+    .unwrap();
+    lib.set_book_read_state(ids["Dune"], true).unwrap();
+    lib.set_book_read_state(ids["The Dispossessed"], false)
+        .unwrap();
+
+    assert_eq!(search(&mut lib, "#pages:>300"), vec!["Dune"]);
+    assert_eq!(search(&mut lib, "#pages:false").len(), 2);
+    assert_eq!(search(&mut lib, "#read:true"), vec!["Dune"]);
+    // Calibre's yes/no columns are tristate by default: `false` finds the
+    // books never marked, `no` the ones marked unread.
+    assert_eq!(
+        search(&mut lib, "#read:false"),
+        vec!["A Wizard of Earthsea", "Guards! Guards!"]
+    );
+    assert_eq!(search(&mut lib, "#read:no"), vec!["The Dispossessed"]);
+    assert!(matches!(
+        lib.query_books(BookQuery {
+            search: Some("#nonexistent:1".to_string()),
+            ..BookQuery::default()
+        }),
+        Err(CalibreError::InvalidSearch(_))
+    ));
+}
+
+#[test]
+fn test_search_composes_with_paging_and_filters() {
+    let (_temp, mut lib) = library_with_books();
+
+    let page = lib
+        .query_books(BookQuery {
+            search: Some("tag:fantasy or tag:fiction".to_string()),
+            text: Some("guin".to_string()),
+            limit: Some(1),
+            ..BookQuery::default()
+        })
+        .unwrap();
+    assert_eq!(page.items.len(), 1);
+    assert_eq!(page.total, 2);
+
+    let all = lib
+        .query_books(BookQuery {
+            search: Some("   ".to_string()),
+            ..BookQuery::default()
+        })
+        .unwrap();
+    assert_eq!(all.total, 4);
+}
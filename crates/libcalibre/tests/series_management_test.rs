@@ -0,0 +1,208 @@
+// Tests for renaming, merging, renumbering and checking series library-wide
+// This binary uses only a subset of the shared test helpers.
+#[allow(dead_code, unused_imports)]
+mod common;
+
+use common::{setup_with_library, standard_test_book};
+use libcalibre::{BookAdd, BookId, CalibreError, Library};
+use std::path::Path;
+
+fn add_book(lib: &mut Library, title: &str, series: &str, index: f32) -> BookId {
+    lib.add_book(BookAdd {
+        title: title.to_string(),
+        author_names: vec!["Robert Jordan".to_string()],
+        series: Some(series.to_string()),
+        series_index: Some(index),
+        ..standard_test_book()
+    })
+    .unwrap()
+    .id
+}
+
+fn series_id(lib: &mut Library, name: &str) -> i32 {
+    lib.list_series()
+        .unwrap()
+        .into_iter()
+        .find(|series| series.name == name)
+        .unwrap()
+        .id
+}
+
+fn series_of(lib: &mut Library, book_id: BookId) -> (Option<String>, Option<f32>) {
+    let book = lib.get_book(book_id).unwrap();
+    (book.series, book.series_index)
+}
+
+fn opf(lib: &mut Library, book_id: BookId) -> String {
+    let book = lib.get_book(book_id).unwrap();
+    std::fs::read_to_string(
+        Path::new(lib.library_path())
+            .join(book.book_dir_path)
+            .join("metadata.opf"),
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_rename_series_updates_books_and_opf() {
+    let (_temp, mut lib) = setup_with_library();
+    let eye = add_book(&mut lib, "The Eye of the World", "Wheel of Time", 1.0);
+    let hunt = add_book(&mut lib, "The Great Hunt", "Wheel of Time", 2.0);
+    let wheel = series_id(&mut lib, "Wheel of Time");
+
+    let renamed = lib.rename_series(wheel, "The Wheel of Time").unwrap();
+    assert_eq!(renamed.id, wheel);
+    assert_eq!(renamed.name, "The Wheel of Time");
+    assert_eq!(renamed.book_count, 2);
+    assert_eq!(
+        series_of(&mut lib, hunt),
+        (Some("The Wheel of Time".to_string()), Some(2.0))
+    );
+    let opf_text = opf(&mut lib, eye);
+    assert!(opf_text.contains("name=\"calibre:series\" content=\"The Wheel of Time\""));
+}
+
+#[test]
+fn test_merge_series_keeps_indices() {
+    let (_temp, mut lib) = setup_with_library();
+    let eye = add_book(&mut lib, "The Eye of the World", "Wheel of Time", 1.0);
+    let hunt = add_book(&mut lib, "The Great Hunt", "The Wheel of Time", 2.0);
+    let dragon = add_book(&mut lib, "The Dragon Reborn", "Wheel of Time", 2.0);
+    let from = series_id(&mut lib, "Wheel of Time");
+    let into = series_id(&mut lib, "The Wheel of Time");
+
+    let merged = lib.merge_series(from, into).unwrap();
+    assert_eq!(merged.id, into);
+    assert_eq!(merged.book_count, 3);
+    let names: Vec<String> = lib
+        .list_series()
+        .unwrap()
+        .into_iter()
+        .map(|series| series.name)
+        .collect();
+    assert_eq!(names, vec!["The Wheel of Time"]);
+    assert_eq!(
+        series_of(&mut lib, eye),
+        (Some("The Wheel of Time".to_string()), Some(1.0))
+    );
+    let opf_text = opf(&mut lib, dragon);
+    assert!(opf_text.contains("name=\"calibre:series\" content=\"The Wheel of Time\""));
+
+    // The two series both had a book 2.
+    let numbering = lib.series_numbering(into).unwrap();
+    assert_eq!(numbering.duplicates, vec![(2.0, vec![hunt, dragon])]);
+    assert!(matches!(
+        lib.merge_series(from, into),
+        Err(CalibreError::SeriesNotFound(id)) if id == from
+    ));
+}
+
+#[test]
+fn test_rename_onto_existing_series_merges() {
+    let (_temp, mut lib) = setup_with_library();
+    let eye = add_book(&mut lib, "The Eye of the World", "Wheel of Time", 1.0);
+    add_book(&mut lib, "The Great Hunt", "The Wheel of Time", 2.0);
+    let from = series_id(&mut lib, "Wheel of Time");
+    let into = series_id(&mut lib, "The Wheel of Time");
+
+    let merged = lib.rename_series(from, "the wheel of time").unwrap();
+    assert_eq!(merged.id, into);
+    assert_eq!(merged.name, "The Wheel of Time");
+    assert_eq!(lib.list_series().unwrap().len(), 1);
+    assert_eq!(
+        series_of(&mut lib, eye).0,
+        Some("The Wheel of Time".to_string())
+    );
+}
+
+#[test]
+fn test_renumber_series_from_chosen_order() {
+    let (_temp, mut lib) = setup_with_library();
+    let eye = add_book(&mut lib, "The Eye of the World", "Wheel of Time", 1.0);
+    let hunt = add_book(&mut lib, "The Great Hunt", "Wheel of Time", 3.0);
+    let spring = add_book(&mut lib, "New Spring", "Wheel of Time", 7.5);
+    let wheel = series_id(&mut lib, "Wheel of Time");
+
+    lib.renumber_series(wheel, &[spring, eye, hunt], 0.0)
+        .unwrap();
+    assert_eq!(series_of(&mut lib, spring).1, Some(0.0));
+    assert_eq!(series_of(&mut lib, eye).1, Some(1.0));
+    assert_eq!(series_of(&mut lib, hunt).1, Some(2.0));
+    let opf_text = opf(&mut lib, hunt);
+    assert!(opf_text.contains("name=\"calibre:series_index\" content=\"2\""));
+
+    let numbering = lib.series_numbering(wheel).unwrap();
+    assert_eq!(
+        numbering.books,
+        vec![(spring, 0.0), (eye, 1.0), (hunt, 2.0)]
+    );
+    assert!(numbering.gaps.is_empty());
+    assert!(numbering.duplicates.is_empty());
+}
+
+#[test]
+fn test_renumber_rejects_an_order_that_is_not_the_series() {
+    let (_temp, mut lib) = setup_with_library();
+    let eye = add_book(&mut lib, "The Eye of the World", "Wheel of Time", 1.0);
+    let hunt = add_book(&mut lib, "The Great Hunt", "Wheel of Time", 2.0);
+    let other = add_book(&mut lib, "Gardens of the Moon", "Malazan", 1.0);
+    let wheel = series_id(&mut lib, "Wheel of Time");
+
+    for order in [vec![eye], vec![eye, hunt, other], vec![eye, eye, hunt]] {
+        assert!(matches!(
+            lib.renumber_series(wheel, &order, 1.0),
+            Err(CalibreError::InvalidSeriesOrder(_))
+        ));
+    }
+    assert_eq!(series_of(&mut lib, hunt).1, Some(2.0));
+    assert!(matches!(
+        lib.renumber_series(9999, &[], 1.0),
+        Err(CalibreError::SeriesNotFound(9999))
+    ));
+}
+
+#[test]
+fn test_series_numbering_reports_gaps_and_duplicates() {
+    let (_temp, mut lib) = setup_with_library();
+    let one = add_book(&mut lib, "One", "Saga", 1.0);
+    let novella = add_book(&mut lib, "Novella", "Saga", 2.5);
+    let four_a = add_book(&mut lib, "Four", "Saga", 4.0);
+    let four_b = add_book(&mut lib, "Four, again", "Saga", 4.0);
+    let six = add_book(&mut lib, "Six", "Saga", 6.0);
+    let saga = series_id(&mut lib, "Saga");
+
+    let numbering = lib.series_numbering(saga).unwrap();
+    assert_eq!(numbering.series.book_count, 5);
+    assert_eq!(
+        numbering.books,
+        vec![
+            (one, 1.0),
+            (novella, 2.5),
+            (four_a, 4.0),
+            (four_b, 4.0),
+            (six, 6.0)
+        ]
+    );
+    assert_eq!(numbering.gaps, vec![2, 3, 5]);
+    assert_eq!(numbering.duplicates, vec![(4.0, vec![four_a, four_b])]);
+}
+
+#[test]
+fn test_invalid_series_names_are_rejected() {
+    let (_temp, mut lib) = setup_with_library();
+    let eye = add_book(&mut lib, "The Eye of the World", "Wheel of Time", 1.0);
+    let wheel = series_id(&mut lib, "Wheel of Time");
+
+    assert!(matches!(
+        lib.rename_series(wheel, " "),
+        Err(CalibreError::InvalidName(_))
+    ));
+    assert!(matches!(
+        lib.rename_series(9999, "Anything"),
+        Err(CalibreError::SeriesNotFound(9999))
+    ));
+    assert_eq!(
+        series_of(&mut lib, eye).0,
+        Some("Wheel of Time".to_string())
+    );
+}
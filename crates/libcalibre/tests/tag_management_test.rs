@@ -0,0 +1,200 @@
+// Tests for renaming, merging, deleting and pruning tags library-wide
+// This binary uses only a subset of the shared test helpers.
+#[allow(dead_code, unused_imports)]
+mod common;
+
+use common::{setup_with_library, standard_test_book};
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::Integer;
+use libcalibre::{BookAdd, BookId, BookUpdate, CalibreError, Library, TagSummary};
+use std::path::Path;
+
+fn add_book(lib: &mut Library, title: &str, tags: &[&str]) -> BookId {
+    lib.add_book(BookAdd {
+        title: title.to_string(),
+        author_names: vec!["Someone".to_string()],
+        tags: Some(tags.iter().map(|t| (*t).to_string()).collect()),
+        ..standard_test_book()
+    })
+    .unwrap()
+    .id
+}
+
+fn tag_id(lib: &mut Library, name: &str) -> i32 {
+    lib.list_tags()
+        .unwrap()
+        .into_iter()
+        .find(|tag| tag.name == name)
+        .unwrap()
+        .id
+}
+
+fn tag_names(lib: &mut Library) -> Vec<String> {
+    lib.list_tags()
+        .unwrap()
+        .into_iter()
+        .map(|tag| tag.name)
+        .collect()
+}
+
+fn book_tags(lib: &mut Library, book_id: BookId) -> Vec<String> {
+    lib.get_book(book_id).unwrap().tags
+}
+
+fn opf(lib: &mut Library, book_id: BookId) -> String {
+    let book = lib.get_book(book_id).unwrap();
+    std::fs::read_to_string(
+        Path::new(lib.library_path())
+            .join(book.book_dir_path)
+            .join("metadata.opf"),
+    )
+    .unwrap()
+}
+
+#[derive(QueryableByName)]
+struct Count {
+    #[diesel(sql_type = Integer)]
+    count: i32,
+}
+
+fn dirty_books(lib: &Library) -> i32 {
+    let mut conn = libcalibre::persistence::establish_connection(lib.database_path()).unwrap();
+    sql_query("SELECT COUNT(*) AS count FROM metadata_dirtied")
+        .get_result::<Count>(&mut conn)
+        .unwrap()
+        .count
+}
+
+#[test]
+fn test_rename_tag_updates_books_and_opf() {
+    let (_temp, mut lib) = setup_with_library();
+    let mort = add_book(&mut lib, "Mort", &["Fantasy", "Humour"]);
+    let dune = add_book(&mut lib, "Dune", &["SF"]);
+    let sf = tag_id(&mut lib, "SF");
+
+    let renamed = lib.rename_tag(sf, " Science Fiction ").unwrap();
+    assert_eq!(
+        renamed,
+        TagSummary {
+            id: sf,
+            name: "Science Fiction".to_string(),
+        }
+    );
+    assert_eq!(book_tags(&mut lib, dune), vec!["Science Fiction"]);
+    assert_eq!(book_tags(&mut lib, mort), vec!["Fantasy", "Humour"]);
+    let dune_opf = opf(&mut lib, dune);
+    assert!(dune_opf.contains("<dc:subject>Science Fiction</dc:subject>"));
+    assert!(!dune_opf.contains("<dc:subject>SF</dc:subject>"));
+    // Every OPF was written, so nothing is left for Calibre to redo.
+    assert_eq!(dirty_books(&lib), 0);
+
+    // A change of case only renames the tag itself.
+    let fantasy = tag_id(&mut lib, "Fantasy");
+    assert_eq!(lib.rename_tag(fantasy, "fantasy").unwrap().id, fantasy);
+    assert_eq!(book_tags(&mut lib, mort), vec!["fantasy", "Humour"]);
+}
+
+#[test]
+fn test_rename_onto_existing_tag_merges() {
+    let (_temp, mut lib) = setup_with_library();
+    let mort = add_book(&mut lib, "Mort", &["Fantasy", "Humor"]);
+    let jingo = add_book(&mut lib, "Jingo", &["Humour"]);
+    let humor = tag_id(&mut lib, "Humor");
+    let humour = tag_id(&mut lib, "Humour");
+
+    let merged = lib.rename_tag(humor, "HUMOUR").unwrap();
+    assert_eq!(merged.id, humour);
+    assert_eq!(merged.name, "Humour");
+    assert_eq!(tag_names(&mut lib), vec!["Fantasy", "Humour"]);
+    assert_eq!(book_tags(&mut lib, mort), vec!["Fantasy", "Humour"]);
+    assert_eq!(book_tags(&mut lib, jingo), vec!["Humour"]);
+}
+
+#[test]
+fn test_merge_tags_keeps_one_link_per_book() {
+    let (_temp, mut lib) = setup_with_library();
+    let both = add_book(&mut lib, "Both", &["Sci-Fi", "Science Fiction"]);
+    let only = add_book(&mut lib, "Only", &["Sci-Fi"]);
+    let from = tag_id(&mut lib, "Sci-Fi");
+    let into = tag_id(&mut lib, "Science Fiction");
+
+    let merged = lib.merge_tags(from, into).unwrap();
+    assert_eq!(merged.id, into);
+    assert_eq!(tag_names(&mut lib), vec!["Science Fiction"]);
+    assert_eq!(book_tags(&mut lib, both), vec!["Science Fiction"]);
+    assert_eq!(book_tags(&mut lib, only), vec!["Science Fiction"]);
+    assert!(opf(&mut lib, only).contains("<dc:subject>Science Fiction</dc:subject>"));
+
+    // Merging a tag into itself changes nothing.
+    assert_eq!(lib.merge_tags(into, into).unwrap().id, into);
+    assert!(matches!(
+        lib.merge_tags(from, into),
+        Err(CalibreError::TagNotFound(id)) if id == from
+    ));
+}
+
+#[test]
+fn test_delete_tag_removes_it_from_every_book() {
+    let (_temp, mut lib) = setup_with_library();
+    let mort = add_book(&mut lib, "Mort", &["Fantasy", "Humour"]);
+    let jingo = add_book(&mut lib, "Jingo", &["Humour"]);
+    let humour = tag_id(&mut lib, "Humour");
+
+    assert_eq!(lib.delete_tag(humour).unwrap(), 2);
+    assert_eq!(tag_names(&mut lib), vec!["Fantasy"]);
+    assert_eq!(book_tags(&mut lib, mort), vec!["Fantasy"]);
+    assert!(book_tags(&mut lib, jingo).is_empty());
+    assert!(!opf(&mut lib, mort).contains("Humour"));
+    assert!(matches!(
+        lib.delete_tag(humour),
+        Err(CalibreError::TagNotFound(_))
+    ));
+}
+
+#[test]
+fn test_prune_unused_tags() {
+    let (_temp, mut lib) = setup_with_library();
+    let mort = add_book(&mut lib, "Mort", &["Fantasy", "Humour", "To Read"]);
+    lib.update_book(
+        mort,
+        BookUpdate {
+            tags: Some(vec!["Fantasy".to_string()]),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(tag_names(&mut lib), vec!["Fantasy", "Humour", "To Read"]);
+
+    let pruned: Vec<String> = lib
+        .prune_unused_tags()
+        .unwrap()
+        .into_iter()
+        .map(|tag| tag.name)
+        .collect();
+    assert_eq!(pruned, vec!["Humour", "To Read"]);
+    assert_eq!(tag_names(&mut lib), vec!["Fantasy"]);
+    assert!(lib.prune_unused_tags().unwrap().is_empty());
+}
+
+#[test]
+fn test_invalid_tag_edits_change_nothing() {
+    let (_temp, mut lib) = setup_with_library();
+    let mort = add_book(&mut lib, "Mort", &["Fantasy"]);
+    let fantasy = tag_id(&mut lib, "Fantasy");
+
+    assert!(matches!(
+        lib.rename_tag(fantasy, "   "),
+        Err(CalibreError::InvalidName(_))
+    ));
+    assert!(matches!(
+        lib.rename_tag(9999, "Anything"),
+        Err(CalibreError::TagNotFound(9999))
+    ));
+    assert!(matches!(
+        lib.merge_tags(fantasy, 9999),
+        Err(CalibreError::TagNotFound(9999))
+    ));
+    assert_eq!(book_tags(&mut lib, mort), vec!["Fantasy"]);
+    assert_eq!(dirty_books(&lib), 0);
+}
@@ -0,0 +1,323 @@
+// Tests for the .caltrash recycle bin: trashing, listing, restoring, expiring
+#[allow(dead_code, unused_imports)]
+mod common;
+
+use common::{setup_with_library, standard_test_book};
+use diesel::{sql_query, RunQueryDsl};
+use libcalibre::persistence::establish_connection;
+use libcalibre::{
+    BookAdd, BookId, CustomColumnKind, CustomColumnSpec, CustomValue, LibraryBook, TrashEntryKind,
+};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tempfile::TempDir;
+
+fn book_with_formats(sources: &TempDir, title: &str, extensions: &[&str]) -> BookAdd {
+    let file_paths = extensions
+        .iter()
+        .map(|extension| {
+            let path = sources.path().join(format!("{title}.{extension}"));
+            std::fs::write(&path, format!("{title} as {extension}")).unwrap();
+            path
+        })
+        .collect();
+
+    BookAdd {
+        title: title.to_string(),
+        author_names: vec!["Ursula K. Le Guin".to_string()],
+        tags: Some(vec!["sf".to_string()]),
+        language: Some("eng".to_string()),
+        file_paths,
+        ..standard_test_book()
+    }
+}
+
+fn trash_path(root: &Path, kind: &str, book_id: BookId) -> PathBuf {
+    root.join(".caltrash")
+        .join(kind)
+        .join(book_id.as_i32().to_string())
+}
+
+fn format_path(root: &Path, book: &LibraryBook, format: &str) -> PathBuf {
+    let file = book
+        .files
+        .iter()
+        .find(|file| file.format == format)
+        .unwrap();
+    root.join(&book.book_dir_path)
+        .join(format!("{}.{}", file.name, format.to_lowercase()))
+}
+
+#[test]
+fn test_removed_book_moves_to_trash() {
+    let (temp, mut lib) = setup_with_library();
+    let sources = tempfile::tempdir().unwrap();
+    let book = lib
+        .add_book(book_with_formats(&sources, "The Dispossessed", &["txt"]))
+        .unwrap();
+    let book_dir = temp.path().join(&book.book_dir_path);
+
+    lib.remove_books(vec![book.id]).unwrap();
+
+    let trashed = trash_path(temp.path(), "b", book.id);
+    assert!(trashed.join("metadata.opf").is_file());
+    assert!(trashed
+        .join(format!("{}.txt", book.files[0].name))
+        .is_file());
+    assert!(!book_dir.exists());
+    assert!(!book_dir.parent().unwrap().exists());
+    assert!(lib.get_book(book.id).is_err());
+}
+
+#[test]
+fn test_list_trash_describes_removed_books() {
+    let (_temp, mut lib) = setup_with_library();
+    let sources = tempfile::tempdir().unwrap();
+    let book = lib
+        .add_book(book_with_formats(
+            &sources,
+            "The Lathe of Heaven",
+            &["epub", "txt"],
+        ))
+        .unwrap();
+
+    lib.remove_books(vec![book.id]).unwrap();
+    let entries = lib.list_trash().unwrap();
+
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].book_id, book.id);
+    assert_eq!(entries[0].kind, TrashEntryKind::Book);
+    assert_eq!(entries[0].title, "The Lathe of Heaven");
+    assert_eq!(entries[0].authors, vec!["Ursula K. Le Guin"]);
+    assert_eq!(entries[0].formats, vec!["EPUB", "TXT"]);
+}
+
+#[test]
+fn test_restore_book_brings_back_id_uuid_and_files() {
+    let (temp, mut lib) = setup_with_library();
+    let sources = tempfile::tempdir().unwrap();
+    let book = lib
+        .add_book(book_with_formats(&sources, "Always Coming Home", &["txt"]))
+        .unwrap();
+    lib.remove_books(vec![book.id]).unwrap();
+
+    let restored = lib.restore_book(book.id).unwrap();
+
+    assert_eq!(restored.id, book.id);
+    assert_eq!(restored.uuid, book.uuid);
+    assert_eq!(restored.title, book.title);
+    assert_eq!(restored.authors[0].name, "Ursula K. Le Guin");
+    assert_eq!(restored.book_dir_path, book.book_dir_path);
+    assert_eq!(restored.files.len(), 1);
+    assert_eq!(
+        std::fs::read_to_string(format_path(temp.path(), &restored, "TXT")).unwrap(),
+        "Always Coming Home as txt"
+    );
+    assert!(!trash_path(temp.path(), "b", book.id).exists());
+    assert!(lib.list_trash().unwrap().is_empty());
+}
+
+#[test]
+fn test_restore_book_keeps_metadata_written_by_citadel() {
+    let (_temp, mut lib) = setup_with_library();
+    let sources = tempfile::tempdir().unwrap();
+    let book = lib
+        .add_book(BookAdd {
+            series: Some("Hainish Cycle".to_string()),
+            series_index: Some(5.0),
+            ..book_with_formats(&sources, "The Word for World Is Forest", &["txt"])
+        })
+        .unwrap();
+    lib.remove_books(vec![book.id]).unwrap();
+
+    let restored = lib.restore_book(book.id).unwrap();
+
+    assert_eq!(restored.tags, vec!["sf"]);
+    assert_eq!(restored.series.as_deref(), Some("Hainish Cycle"));
+    assert_eq!(restored.series_index, Some(5.0));
+    assert_eq!(restored.language_codes, vec!["eng"]);
+}
+
+#[test]
+fn test_restore_book_keeps_custom_values_and_read_state() {
+    let (_temp, mut lib) = setup_with_library();
+    let sources = tempfile::tempdir().unwrap();
+    let book = lib
+        .add_book(book_with_formats(&sources, "The Dispossessed", &["txt"]))
+        .unwrap();
+    let shelf = lib
+        .create_custom_column(CustomColumnSpec {
+            label: "shelf".to_string(),
+            name: "Shelf".to_string(),
+            kind: CustomColumnKind::Text,
+            is_multiple: false,
+            enum_values: vec![],
+            display: None,
+        })
+        .unwrap();
+    lib.set_custom_value(
+        book.id,
+        shelf.id,
+        Some(CustomValue::Text("Study".to_string())),
+    )
+    .unwrap();
+    lib.set_book_read_state(book.id, true).unwrap();
+    lib.remove_books(vec![book.id]).unwrap();
+
+    let restored = lib.restore_book(book.id).unwrap();
+
+    assert!(restored.is_read);
+    assert_eq!(
+        lib.get_custom_value(book.id, shelf.id).unwrap(),
+        Some(CustomValue::Text("Study".to_string()))
+    );
+}
+
+#[test]
+fn test_restore_book_reads_calibre_trash_entry() {
+    let (temp, mut lib) = setup_with_library();
+    let trashed = trash_path(temp.path(), "b", BookId::from(42));
+    std::fs::create_dir_all(&trashed).unwrap();
+    std::fs::write(
+        trashed.join("Kindred - Octavia E. Butler.epub"),
+        "epub bytes",
+    )
+    .unwrap();
+    std::fs::write(
+        trashed.join("metadata.opf"),
+        r#"<?xml version='1.0' encoding='utf-8'?>
+<package xmlns="http://www.idpf.org/2007/opf" unique-identifier="uuid_id" version="2.0">
+    <metadata xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:opf="http://www.idpf.org/2007/opf">
+        <dc:identifier opf:scheme="calibre" id="calibre_id">42</dc:identifier>
+        <dc:identifier opf:scheme="uuid" id="uuid_id">5d2c3b6a-9a3f-4bb5-8d8e-4f2b1c7e9a10</dc:identifier>
+        <dc:title>Kindred</dc:title>
+        <dc:creator opf:file-as="Butler, Octavia E." opf:role="aut">Octavia E. Butler</dc:creator>
+        <dc:publisher>Doubleday</dc:publisher>
+        <dc:identifier opf:scheme="ISBN">9780807083697</dc:identifier>
+        <dc:language>eng</dc:language>
+        <dc:subject>Fiction</dc:subject>
+        <dc:subject>Time travel</dc:subject>
+        <meta name="calibre:series" content="Standalones"/>
+        <meta name="calibre:series_index" content="3.0"/>
+        <meta name="calibre:rating" content="10.0"/>
+    </metadata>
+</package>"#,
+    )
+    .unwrap();
+
+    let restored = lib.restore_book(BookId::from(42)).unwrap();
+
+    assert_eq!(restored.id, BookId::from(42));
+    assert_eq!(restored.uuid, "5d2c3b6a-9a3f-4bb5-8d8e-4f2b1c7e9a10");
+    assert_eq!(restored.title, "Kindred");
+    assert_eq!(restored.authors[0].name, "Octavia E. Butler");
+    assert_eq!(restored.tags, vec!["Fiction", "Time travel"]);
+    assert_eq!(restored.series.as_deref(), Some("Standalones"));
+    assert_eq!(restored.series_index, Some(3.0));
+    assert_eq!(restored.publisher.as_deref(), Some("Doubleday"));
+    assert_eq!(restored.rating, Some(10));
+    assert_eq!(restored.language_codes, vec!["eng"]);
+    assert_eq!(restored.identifiers[0].label, "isbn");
+    assert_eq!(restored.files[0].name, "Kindred - Octavia E. Butler");
+    assert!(format_path(temp.path(), &restored, "EPUB").is_file());
+}
+
+#[test]
+fn test_restore_book_refuses_taken_id_and_keeps_trash_entry() {
+    let (temp, mut lib) = setup_with_library();
+    let sources = tempfile::tempdir().unwrap();
+    let book = lib
+        .add_book(book_with_formats(&sources, "Lavinia", &["txt"]))
+        .unwrap();
+    lib.remove_books(vec![book.id]).unwrap();
+
+    // Another book takes the id; the insert trigger needs libcalibre's SQL
+    // functions, so go through its connection setup.
+    let mut conn = establish_connection(lib.database_path()).unwrap();
+    sql_query(format!(
+        "INSERT INTO books (id, title) VALUES ({}, 'Squatter')",
+        book.id.as_i32()
+    ))
+    .execute(&mut conn)
+    .unwrap();
+
+    assert!(lib.restore_book(book.id).is_err());
+    assert!(trash_path(temp.path(), "b", book.id)
+        .join("metadata.opf")
+        .is_file());
+}
+
+#[test]
+fn test_restore_missing_entry_is_an_error() {
+    let (_temp, mut lib) = setup_with_library();
+
+    assert!(matches!(
+        lib.restore_book(BookId::from(999)),
+        Err(libcalibre::CalibreError::TrashEntryNotFound(_))
+    ));
+}
+
+#[test]
+fn test_removed_format_moves_to_trash_and_restores() {
+    let (temp, mut lib) = setup_with_library();
+    let sources = tempfile::tempdir().unwrap();
+    let book = lib
+        .add_book(book_with_formats(&sources, "Tehanu", &["epub", "txt"]))
+        .unwrap();
+    let txt = format_path(temp.path(), &book, "TXT");
+
+    lib.remove_book_file(book.id, "TXT").unwrap();
+
+    let trashed = trash_path(temp.path(), "f", book.id);
+    assert!(!txt.exists());
+    assert!(trashed.join("txt").is_file());
+    let metadata: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(trashed.join("metadata.json")).unwrap()).unwrap();
+    assert_eq!(metadata["title"], "Tehanu");
+    assert_eq!(metadata["authors"][0], "Ursula K. Le Guin");
+    assert_eq!(lib.get_book(book.id).unwrap().files.len(), 1);
+
+    let entries = lib.list_trash().unwrap();
+    assert_eq!(entries[0].kind, TrashEntryKind::Formats);
+    assert_eq!(entries[0].formats, vec!["TXT"]);
+
+    lib.restore_book_file(book.id, "TXT").unwrap();
+
+    assert_eq!(std::fs::read_to_string(&txt).unwrap(), "Tehanu as txt");
+    assert_eq!(lib.get_book(book.id).unwrap().files.len(), 2);
+    assert!(!trashed.exists());
+}
+
+#[test]
+fn test_removing_last_format_trashes_whole_book() {
+    let (temp, mut lib) = setup_with_library();
+    let sources = tempfile::tempdir().unwrap();
+    let book = lib
+        .add_book(book_with_formats(&sources, "Voices", &["txt"]))
+        .unwrap();
+
+    lib.remove_book_file(book.id, "TXT").unwrap();
+
+    assert!(lib.get_book(book.id).is_err());
+    assert!(trash_path(temp.path(), "b", book.id).is_dir());
+}
+
+#[test]
+fn test_empty_trash_respects_age() {
+    let (temp, mut lib) = setup_with_library();
+    let sources = tempfile::tempdir().unwrap();
+    let book = lib
+        .add_book(book_with_formats(&sources, "Powers", &["txt"]))
+        .unwrap();
+    lib.remove_books(vec![book.id]).unwrap();
+
+    assert_eq!(
+        lib.empty_trash(Some(Duration::from_secs(60 * 60))).unwrap(),
+        0
+    );
+    assert_eq!(lib.list_trash().unwrap().len(), 1);
+
+    assert_eq!(lib.empty_trash(None).unwrap(), 1);
+    assert!(lib.list_trash().unwrap().is_empty());
+    assert!(!trash_path(temp.path(), "b", book.id).exists());
+}
@@ -1,3 +1,4 @@
+use serde::{Deserialize, Serialize};
 use tauri::Manager;
 
 use crate::book::ImportableBookMetadata;
@@ -7,8 +8,25 @@ use crate::libs::cover_thumbs::{self, CoverThumbnail};
 use crate::state::CitadelState;
 
 use super::custom_columns::CustomValueDto;
+use super::query::{LibraryDuplicateBookMatch, LibraryTag};
 use super::AuthorUpdate;
 use super::BookUpdate;
+use super::LibrarySchemaStatus;
+
+/// Upgrade the open library's database to the schema version citadel
+/// understands, after backing it up in the library folder. Run only once the
+/// user has agreed to an upgrade `init_client` reported as needed.
+#[tauri::command]
+#[specta::specta]
+pub fn clb_cmd_upgrade_schema(
+    state: tauri::State<CitadelState>,
+) -> Result<LibrarySchemaStatus, String> {
+    state.with_library(|lib| {
+        lib.upgrade_schema()
+            .map(LibrarySchemaStatus::from)
+            .map_err(|e| e.to_string())
+    })?
+}
 
 #[tauri::command]
 #[specta::specta]
@@ -28,6 +46,168 @@ pub fn clb_cmd_update_book(
     })?
 }
 
+/// The index setting a series gives each book in a bulk edit.
+#[derive(Deserialize, specta::Type, Clone, Copy)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum BulkSeriesIndex {
+    /// Each book keeps the index it has.
+    Keep,
+    /// Number the books in the order given, after the series' last book.
+    Next,
+    /// Number the books in the order given, from `start`.
+    From { start: f32 },
+}
+
+impl From<BulkSeriesIndex> for libcalibre::BulkSeriesIndex {
+    fn from(index: BulkSeriesIndex) -> Self {
+        match index {
+            BulkSeriesIndex::Keep => Self::Keep,
+            BulkSeriesIndex::Next => Self::Next,
+            BulkSeriesIndex::From { start } => Self::From(start),
+        }
+    }
+}
+
+#[derive(Deserialize, specta::Type, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchReplaceField {
+    Title,
+    Tags,
+    Series,
+    Publisher,
+    Comments,
+}
+
+impl From<SearchReplaceField> for libcalibre::SearchReplaceField {
+    fn from(field: SearchReplaceField) -> Self {
+        match field {
+            SearchReplaceField::Title => Self::Title,
+            SearchReplaceField::Tags => Self::Tags,
+            SearchReplaceField::Series => Self::Series,
+            SearchReplaceField::Publisher => Self::Publisher,
+            SearchReplaceField::Comments => Self::Comments,
+        }
+    }
+}
+
+/// A regular expression replaced throughout one field of each book.
+/// `replacement` may refer to capture groups as `$1`.
+#[derive(Deserialize, specta::Type)]
+pub struct SearchReplace {
+    pub field: SearchReplaceField,
+    pub pattern: String,
+    pub replacement: String,
+    pub case_sensitive: bool,
+}
+
+/// A custom column value to set (or clear, with `null`) on every book.
+#[derive(Deserialize, specta::Type)]
+pub struct BulkCustomValue {
+    pub column_id: i32,
+    pub value: Option<CustomValueDto>,
+}
+
+/// Changes applied to every book of a bulk edit. `null` and empty fields
+/// leave that metadata as it is.
+#[derive(Deserialize, specta::Type)]
+pub struct BulkEdit {
+    pub add_tags: Vec<String>,
+    pub remove_tags: Vec<String>,
+    /// An empty name unlinks the books from their series.
+    pub series: Option<String>,
+    pub series_index: BulkSeriesIndex,
+    pub publisher: Option<String>,
+    pub language_list: Option<Vec<String>>,
+    /// Calibre's 0–10 scale; 0 clears the rating.
+    pub rating: Option<i32>,
+    pub is_read: Option<bool>,
+    pub custom_values: Vec<BulkCustomValue>,
+    pub search_replace: Option<SearchReplace>,
+}
+
+impl BulkEdit {
+    fn into_library_edit(self) -> Result<libcalibre::BulkEdit, String> {
+        let mut custom_values = std::collections::HashMap::new();
+        for custom in self.custom_values {
+            let value = custom
+                .value
+                .map(libcalibre::CustomValue::try_from)
+                .transpose()?;
+            custom_values.insert(custom.column_id, value);
+        }
+        Ok(libcalibre::BulkEdit {
+            add_tags: self.add_tags,
+            remove_tags: self.remove_tags,
+            series: self.series,
+            series_index: self.series_index.into(),
+            publisher: self.publisher,
+            language_codes: self.language_list,
+            rating: self.rating,
+            is_read: self.is_read,
+            custom_values,
+            search_replace: self
+                .search_replace
+                .map(|search_replace| libcalibre::SearchReplace {
+                    field: search_replace.field.into(),
+                    pattern: search_replace.pattern,
+                    replacement: search_replace.replacement,
+                    case_sensitive: search_replace.case_sensitive,
+                }),
+        })
+    }
+}
+
+/// A book a bulk edit left as it was, and why.
+#[derive(Serialize, specta::Type)]
+pub struct LibraryBulkUpdateFailure {
+    pub book_id: String,
+    pub reason: String,
+}
+
+/// What [`clb_cmd_bulk_update_books`] did.
+#[derive(Serialize, specta::Type)]
+pub struct LibraryBulkUpdateReport {
+    pub updated: Vec<String>,
+    pub failed: Vec<LibraryBulkUpdateFailure>,
+}
+
+/// Apply one edit to many books in a single call and transaction. Books the
+/// edit cannot be applied to are reported; the rest are updated.
+#[tauri::command]
+#[specta::specta]
+pub fn clb_cmd_bulk_update_books(
+    state: tauri::State<CitadelState>,
+    book_ids: Vec<String>,
+    edit: BulkEdit,
+) -> Result<LibraryBulkUpdateReport, String> {
+    let book_ids = book_ids
+        .iter()
+        .map(|id| id.parse::<i32>().map(libcalibre::BookId::from))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    let edit = edit.into_library_edit()?;
+
+    let report = state
+        .with_library(|lib| lib.bulk_update(&book_ids, edit))?
+        .map_err(|e| e.to_string())?;
+
+    Ok(LibraryBulkUpdateReport {
+        updated: report
+            .updated
+            .iter()
+            .map(|book_id| book_id.as_i32().to_string())
+            .collect(),
+        failed: report
+            .failed
+            .into_iter()
+            .map(|failure| LibraryBulkUpdateFailure {
+                book_id: failure.book_id.as_i32().to_string(),
+                reason: failure.reason,
+            })
+            .collect(),
+    })
+}
+
 #[tauri::command]
 #[specta::specta]
 pub fn clb_cmd_set_custom_value(
@@ -46,33 +226,10 @@ pub fn clb_cmd_set_custom_value(
 
 #[tauri::command]
 #[specta::specta]
-pub fn clb_cmd_create_library(
-    handle: tauri::AppHandle,
-    library_root: String,
-) -> Result<(), String> {
-    let resource_path = handle
-        .path()
-        .resolve(
-            "resources/empty_7_2_calibre_lib.zip",
-            tauri::path::BaseDirectory::Resource,
-        )
-        .map_err(|e| format!("Failed to find default empty library: {}", e))?;
-
-    let file = std::fs::File::open(resource_path)
-        .map_err(|e| format!("Failed to open default empty library: {}", e))?;
-
-    let mut archive =
-        zip::ZipArchive::new(file).map_err(|e| format!("Failed to read zip archive: {}", e))?;
-    let result = archive.extract(&library_root).map_err(|e| e.to_string());
-
-    // Set a new UUID for the library
-    if let Some(db_path) = libcalibre::util::get_db_path(&library_root) {
-        if let Ok(mut lib) = libcalibre::Library::new(db_path) {
-            let _ = lib.randomize_library_uuid();
-        }
-    }
-
-    result
+pub fn clb_cmd_create_library(library_root: String) -> Result<(), String> {
+    libcalibre::Library::create_library_at(&library_root)
+        .map(|_| ())
+        .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
@@ -89,6 +246,133 @@ pub fn clb_cmd_create_book(
     })?
 }
 
+/// What importing does with a book the library already has.
+#[derive(Deserialize, specta::Type, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum OnDuplicate {
+    /// Add nothing, and report the duplicates.
+    Skip,
+    /// Add the file to the closest duplicate as a new format.
+    AddFormats,
+    AddAnyway,
+}
+
+impl From<OnDuplicate> for libcalibre::OnDuplicate {
+    fn from(on_duplicate: OnDuplicate) -> Self {
+        match on_duplicate {
+            OnDuplicate::Skip => Self::Skip,
+            OnDuplicate::AddFormats => Self::AddFormats,
+            OnDuplicate::AddAnyway => Self::AddAnyway,
+        }
+    }
+}
+
+/// What [`clb_cmd_import_book`] did.
+#[derive(Serialize, specta::Type)]
+#[serde(tag = "outcome", rename_all = "snake_case")]
+pub enum LibraryImportOutcome {
+    Added {
+        book_id: String,
+    },
+    Skipped {
+        duplicates: Vec<LibraryDuplicateBookMatch>,
+    },
+    /// `formats` lists the formats the existing book gained; it is empty
+    /// when the book already had them all.
+    AddedFormats {
+        book_id: String,
+        formats: Vec<String>,
+    },
+}
+
+/// Import a book unless the library already has it, by ISBN, by title and
+/// author, or by identical file content.
+#[tauri::command]
+#[specta::specta]
+pub fn clb_cmd_import_book(
+    state: tauri::State<CitadelState>,
+    md: ImportableBookMetadata,
+    on_duplicate: OnDuplicate,
+) -> Result<LibraryImportOutcome, String> {
+    let outcome = state
+        .with_library(|lib| lib.import_book(md.to_book_add(), on_duplicate.into()))?
+        .map_err(|e| e.to_string())?;
+
+    Ok(match outcome {
+        libcalibre::ImportOutcome::Added(book) => LibraryImportOutcome::Added {
+            book_id: book.id.as_i32().to_string(),
+        },
+        libcalibre::ImportOutcome::Skipped { duplicates } => LibraryImportOutcome::Skipped {
+            duplicates: duplicates
+                .into_iter()
+                .map(LibraryDuplicateBookMatch::from)
+                .collect(),
+        },
+        libcalibre::ImportOutcome::AddedFormats { book_id, formats } => {
+            LibraryImportOutcome::AddedFormats {
+                book_id: book_id.as_i32().to_string(),
+                formats,
+            }
+        }
+    })
+}
+
+/// Which cover merging books leaves the merged book with.
+#[derive(Deserialize, specta::Type, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum MergeCover {
+    /// The target's own cover, or the first source's if it has none.
+    Target,
+    /// The first source's cover, or the target's if no source has one.
+    Source,
+    /// The largest cover file.
+    Largest,
+}
+
+impl From<MergeCover> for libcalibre::MergeCover {
+    fn from(cover: MergeCover) -> Self {
+        match cover {
+            MergeCover::Target => Self::Target,
+            MergeCover::Source => Self::Source,
+            MergeCover::Largest => Self::Largest,
+        }
+    }
+}
+
+/// Merge duplicate books into `target_id`: it gains the metadata and formats
+/// it lacks, and the sources go to the trash. Nothing changes on failure.
+#[tauri::command]
+#[specta::specta]
+pub fn clb_cmd_merge_books(
+    handle: tauri::AppHandle,
+    state: tauri::State<CitadelState>,
+    target_id: String,
+    source_ids: Vec<String>,
+    cover: MergeCover,
+) -> Result<(), String> {
+    let target = target_id.parse::<i32>().map_err(|e| e.to_string())?;
+    let sources = source_ids
+        .iter()
+        .map(|id| id.parse::<i32>().map(libcalibre::BookId::from))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    let library_root = state
+        .get_library_path()
+        .ok_or("No library initialized. Please load a library first.")?;
+
+    state.with_library(|lib| {
+        lib.merge_books(libcalibre::BookId::from(target), &sources, cover.into())
+            .map_err(|e| e.to_string())
+    })??;
+
+    // The merged book's cover may have changed.
+    if let Ok(app_cache_dir) = handle.path().app_cache_dir() {
+        cover_thumbs::invalidate_thumbnail(&app_cache_dir, &library_root, &target_id);
+    }
+
+    Ok(())
+}
+
 #[tauri::command]
 #[specta::specta]
 pub fn clb_cmd_upsert_book_identifier(
@@ -162,6 +446,189 @@ pub async fn clb_cmd_set_book_cover_from_url(
     })?
 }
 
+#[tauri::command]
+#[specta::specta]
+pub fn clb_cmd_remove_book_cover(
+    handle: tauri::AppHandle,
+    state: tauri::State<CitadelState>,
+    book_id: String,
+) -> Result<(), String> {
+    let book_id_int = book_id.parse::<i32>().map_err(|e| e.to_string())?;
+    let library_root = state
+        .get_library_path()
+        .ok_or("No library initialized. Please load a library first.")?;
+
+    state.with_library(|lib| {
+        lib.remove_book_cover(libcalibre::BookId::from(book_id_int))
+            .map_err(|e| e.to_string())
+    })??;
+
+    if let Ok(app_cache_dir) = handle.path().app_cache_dir() {
+        cover_thumbs::invalidate_thumbnail(&app_cache_dir, &library_root, &book_id);
+    }
+
+    Ok(())
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn clb_cmd_restore_book(
+    state: tauri::State<CitadelState>,
+    book_id: String,
+) -> Result<(), String> {
+    state.with_library(|lib| {
+        let book_id_int = book_id.parse::<i32>().map_err(|e| e.to_string())?;
+        lib.restore_book(libcalibre::BookId::from(book_id_int))
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    })?
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn clb_cmd_restore_book_file(
+    state: tauri::State<CitadelState>,
+    book_id: String,
+    format: String,
+) -> Result<(), String> {
+    state.with_library(|lib| {
+        let book_id_int = book_id.parse::<i32>().map_err(|e| e.to_string())?;
+        lib.restore_book_file(libcalibre::BookId::from(book_id_int), &format)
+            .map_err(|e| e.to_string())
+    })?
+}
+
+/// Permanently delete trash entries older than `older_than_days`, or the
+/// whole trash when `None`. Returns the number of entries deleted.
+#[tauri::command]
+#[specta::specta]
+pub fn clb_cmd_empty_trash(
+    state: tauri::State<CitadelState>,
+    older_than_days: Option<u32>,
+) -> Result<u32, String> {
+    let older_than =
+        older_than_days.map(|days| std::time::Duration::from_secs(u64::from(days) * 24 * 60 * 60));
+    state.with_library(|lib| {
+        lib.empty_trash(older_than)
+            .map(|removed| u32::try_from(removed).unwrap_or(u32::MAX))
+            .map_err(|e| e.to_string())
+    })?
+}
+
+/// Rename a tag on every book. Renaming onto another tag's name merges the
+/// two; returns the tag that remains.
+#[tauri::command]
+#[specta::specta]
+pub fn clb_cmd_rename_tag(
+    state: tauri::State<CitadelState>,
+    tag_id: i32,
+    new_name: String,
+) -> Result<LibraryTag, String> {
+    state.with_library(|lib| {
+        lib.rename_tag(tag_id, &new_name)
+            .map(|tag| LibraryTag {
+                id: tag.id,
+                name: tag.name,
+            })
+            .map_err(|e| e.to_string())
+    })?
+}
+
+/// Move every book tagged `from_id` to `into_id` and delete `from_id`.
+#[tauri::command]
+#[specta::specta]
+pub fn clb_cmd_merge_tags(
+    state: tauri::State<CitadelState>,
+    from_id: i32,
+    into_id: i32,
+) -> Result<LibraryTag, String> {
+    state.with_library(|lib| {
+        lib.merge_tags(from_id, into_id)
+            .map(|tag| LibraryTag {
+                id: tag.id,
+                name: tag.name,
+            })
+            .map_err(|e| e.to_string())
+    })?
+}
+
+/// Remove a tag from every book and delete it. Returns how many books had it.
+#[tauri::command]
+#[specta::specta]
+pub fn clb_cmd_delete_tag(state: tauri::State<CitadelState>, tag_id: i32) -> Result<u32, String> {
+    state.with_library(|lib| {
+        lib.delete_tag(tag_id)
+            .map(|books| u32::try_from(books).unwrap_or(u32::MAX))
+            .map_err(|e| e.to_string())
+    })?
+}
+
+/// Delete every tag no book uses. Returns the deleted tags.
+#[tauri::command]
+#[specta::specta]
+pub fn clb_cmd_prune_unused_tags(
+    state: tauri::State<CitadelState>,
+) -> Result<Vec<LibraryTag>, String> {
+    state.with_library(|lib| {
+        lib.prune_unused_tags()
+            .map(|tags| {
+                tags.into_iter()
+                    .map(|tag| LibraryTag {
+                        id: tag.id,
+                        name: tag.name,
+                    })
+                    .collect()
+            })
+            .map_err(|e| e.to_string())
+    })?
+}
+
+/// Save `query` under `name`, replacing any saved search of that name.
+#[tauri::command]
+#[specta::specta]
+pub fn clb_cmd_save_search(
+    state: tauri::State<CitadelState>,
+    name: String,
+    query: String,
+) -> Result<(), String> {
+    state.with_library(|lib| lib.save_search(&name, &query).map_err(|e| e.to_string()))?
+}
+
+/// Delete a saved search. Returns whether it existed.
+#[tauri::command]
+#[specta::specta]
+pub fn clb_cmd_remove_saved_search(
+    state: tauri::State<CitadelState>,
+    name: String,
+) -> Result<bool, String> {
+    state.with_library(|lib| lib.remove_saved_search(&name).map_err(|e| e.to_string()))?
+}
+
+/// Define a virtual library, replacing any of the same name. An empty
+/// `search` is the whole library.
+#[tauri::command]
+#[specta::specta]
+pub fn clb_cmd_save_virtual_library(
+    state: tauri::State<CitadelState>,
+    name: String,
+    search: String,
+) -> Result<(), String> {
+    state.with_library(|lib| {
+        lib.save_virtual_library(&name, &search)
+            .map_err(|e| e.to_string())
+    })?
+}
+
+/// Delete a virtual library. Returns whether it existed.
+#[tauri::command]
+#[specta::specta]
+pub fn clb_cmd_remove_virtual_library(
+    state: tauri::State<CitadelState>,
+    name: String,
+) -> Result<bool, String> {
+    state.with_library(|lib| lib.remove_virtual_library(&name).map_err(|e| e.to_string()))?
+}
+
 #[tauri::command]
 #[specta::specta]
 pub async fn clb_cmd_ensure_cover_thumbnails(
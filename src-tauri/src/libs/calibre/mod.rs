@@ -21,6 +21,48 @@ pub struct ImportableFile {
 #[derive(Serialize, specta::Type)]
 pub struct CalibreClientConfig {
     library_path: String,
+    /// The library database's schema version, and whether it needs an
+    /// upgrade or is newer than citadel understands.
+    schema: LibrarySchemaStatus,
+}
+
+/// What citadel knows about a library's database schema.
+#[derive(Serialize, specta::Type, Debug, PartialEq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum LibrarySchemaStatus {
+    Current {
+        version: i32,
+    },
+    /// An older Calibre created the database. Ask the user before upgrading
+    /// it with `clb_cmd_upgrade_schema`.
+    NeedsUpgrade {
+        from: i32,
+        to: i32,
+    },
+    /// The database was upgraded by `clb_cmd_upgrade_schema`.
+    Upgraded {
+        from: i32,
+        to: i32,
+    },
+    /// A newer Calibre wrote the database. It was left as it is; warn the user
+    /// before they edit anything.
+    Newer {
+        version: i32,
+        supported: i32,
+    },
+}
+
+impl From<libcalibre::SchemaStatus> for LibrarySchemaStatus {
+    fn from(status: libcalibre::SchemaStatus) -> Self {
+        match status {
+            libcalibre::SchemaStatus::Current { version } => Self::Current { version },
+            libcalibre::SchemaStatus::NeedsUpgrade { from, to } => Self::NeedsUpgrade { from, to },
+            libcalibre::SchemaStatus::Upgraded { from, to } => Self::Upgraded { from, to },
+            libcalibre::SchemaStatus::Newer { version, supported } => {
+                Self::Newer { version, supported }
+            }
+        }
+    }
 }
 
 #[tauri::command]
@@ -29,9 +71,10 @@ pub fn init_client(
     state: tauri::State<CitadelState>,
     library_path: String,
 ) -> Result<CalibreClientConfig, String> {
-    state.init_library(library_path.clone())?;
+    let schema_status = state.init_library(library_path.clone())?;
     Ok(CalibreClientConfig {
         library_path: library_path.clone(),
+        schema: schema_status.into(),
     })
 }
 
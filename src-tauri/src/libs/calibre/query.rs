@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::path::Path;
 
 use libcalibre::mime_type::MIMETYPE;
@@ -58,6 +59,23 @@ pub enum BookSortOrder {
     TitleDesc,
     AuthorAsc,
     AuthorDesc,
+    AddedAsc,
+    AddedDesc,
+    ModifiedAsc,
+    ModifiedDesc,
+    PublishedAsc,
+    PublishedDesc,
+    SeriesAsc,
+    SeriesDesc,
+    RatingAsc,
+    RatingDesc,
+    PublisherAsc,
+    PublisherDesc,
+    SizeAsc,
+    SizeDesc,
+    /// Sort by the custom column with this id.
+    CustomColumnAsc(i32),
+    CustomColumnDesc(i32),
 }
 
 impl From<BookSortOrder> for libcalibre::BookSortOrder {
@@ -67,6 +85,22 @@ impl From<BookSortOrder> for libcalibre::BookSortOrder {
             BookSortOrder::TitleDesc => libcalibre::BookSortOrder::TitleDesc,
             BookSortOrder::AuthorAsc => libcalibre::BookSortOrder::AuthorAsc,
             BookSortOrder::AuthorDesc => libcalibre::BookSortOrder::AuthorDesc,
+            BookSortOrder::AddedAsc => libcalibre::BookSortOrder::AddedAsc,
+            BookSortOrder::AddedDesc => libcalibre::BookSortOrder::AddedDesc,
+            BookSortOrder::ModifiedAsc => libcalibre::BookSortOrder::ModifiedAsc,
+            BookSortOrder::ModifiedDesc => libcalibre::BookSortOrder::ModifiedDesc,
+            BookSortOrder::PublishedAsc => libcalibre::BookSortOrder::PublishedAsc,
+            BookSortOrder::PublishedDesc => libcalibre::BookSortOrder::PublishedDesc,
+            BookSortOrder::SeriesAsc => libcalibre::BookSortOrder::SeriesAsc,
+            BookSortOrder::SeriesDesc => libcalibre::BookSortOrder::SeriesDesc,
+            BookSortOrder::RatingAsc => libcalibre::BookSortOrder::RatingAsc,
+            BookSortOrder::RatingDesc => libcalibre::BookSortOrder::RatingDesc,
+            BookSortOrder::PublisherAsc => libcalibre::BookSortOrder::PublisherAsc,
+            BookSortOrder::PublisherDesc => libcalibre::BookSortOrder::PublisherDesc,
+            BookSortOrder::SizeAsc => libcalibre::BookSortOrder::SizeAsc,
+            BookSortOrder::SizeDesc => libcalibre::BookSortOrder::SizeDesc,
+            BookSortOrder::CustomColumnAsc(id) => libcalibre::BookSortOrder::CustomColumnAsc(id),
+            BookSortOrder::CustomColumnDesc(id) => libcalibre::BookSortOrder::CustomColumnDesc(id),
         }
     }
 }
@@ -79,12 +113,52 @@ pub struct LibraryBookQuery {
     pub author_id: Option<String>,
     pub series_id: Option<i32>,
     pub hide_read: bool,
+    /// Only books with at least one of these tag ids.
+    pub tags_any: Vec<i32>,
+    /// Only books with every one of these tag ids.
+    pub tags_all: Vec<i32>,
+    /// Only books in at least one of these languages (`eng`, `fr`, ...).
+    pub language_codes: Vec<String>,
+    /// Only books with a file in at least one of these formats.
+    pub formats: Vec<String>,
+    pub publisher_id: Option<i32>,
+    /// Rating bounds on Calibre's 0–10 scale; unrated books never match.
+    pub min_rating: Option<i32>,
+    pub max_rating: Option<i32>,
+    pub has_cover: Option<bool>,
+    /// Only books with an identifier of each of these types (`isbn`, ...).
+    pub with_identifiers: Vec<String>,
+    /// Only books with no identifier of any of these types.
+    pub without_identifiers: Vec<String>,
+    pub added: LibraryDateRange,
+    pub published: LibraryDateRange,
+    /// Only books in the virtual library with this name.
+    pub virtual_library: Option<String>,
+    /// A query in Calibre's search language (`tag:fantasy and rating:>=4`).
+    /// `None` or empty matches all books.
+    pub search: Option<String>,
     pub sort: BookSortOrder,
     /// Page size. `None` returns all matches.
     pub limit: Option<u32>,
     pub offset: u32,
 }
 
+/// An inclusive range of days; a `None` end is open.
+#[derive(Serialize, Deserialize, specta::Type, Clone, Copy, Debug)]
+pub struct LibraryDateRange {
+    pub from: Option<chrono::NaiveDate>,
+    pub to: Option<chrono::NaiveDate>,
+}
+
+impl From<LibraryDateRange> for libcalibre::DateRange {
+    fn from(range: LibraryDateRange) -> Self {
+        libcalibre::DateRange {
+            from: range.from,
+            to: range.to,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, specta::Type, Clone)]
 pub struct LibraryBookPage {
     pub items: Vec<LibraryBook>,
@@ -92,6 +166,45 @@ pub struct LibraryBookPage {
     pub total: u32,
 }
 
+impl TryFrom<LibraryBookQuery> for libcalibre::BookQuery {
+    type Error = String;
+
+    fn try_from(query: LibraryBookQuery) -> Result<Self, Self::Error> {
+        let author_id = query
+            .author_id
+            .as_deref()
+            .map(|raw| {
+                raw.parse::<libcalibre::AuthorId>()
+                    .map_err(|e| format!("Invalid author id '{raw}': {e}"))
+            })
+            .transpose()?;
+
+        Ok(libcalibre::BookQuery {
+            text: query.text,
+            author_id,
+            series_id: query.series_id,
+            hide_read: query.hide_read,
+            tags_any: query.tags_any,
+            tags_all: query.tags_all,
+            language_codes: query.language_codes,
+            formats: query.formats,
+            publisher_id: query.publisher_id,
+            min_rating: query.min_rating,
+            max_rating: query.max_rating,
+            has_cover: query.has_cover,
+            with_identifiers: query.with_identifiers,
+            without_identifiers: query.without_identifiers,
+            added: query.added.into(),
+            published: query.published.into(),
+            virtual_library: query.virtual_library,
+            search: query.search,
+            sort: query.sort.into(),
+            limit: query.limit.map(i64::from),
+            offset: i64::from(query.offset),
+        })
+    }
+}
+
 #[tauri::command]
 #[specta::specta]
 pub fn clb_query_books(
@@ -101,25 +214,7 @@ pub fn clb_query_books(
     let library_root = state
         .get_library_path()
         .ok_or("No library loaded".to_string())?;
-
-    let author_id = query
-        .author_id
-        .as_deref()
-        .map(|raw| {
-            raw.parse::<libcalibre::AuthorId>()
-                .map_err(|e| format!("Invalid author id '{raw}': {e}"))
-        })
-        .transpose()?;
-
-    let book_query = libcalibre::BookQuery {
-        text: query.text,
-        author_id,
-        series_id: query.series_id,
-        hide_read: query.hide_read,
-        sort: query.sort.into(),
-        limit: query.limit.map(i64::from),
-        offset: i64::from(query.offset),
-    };
+    let book_query = libcalibre::BookQuery::try_from(query)?;
 
     let page = state.with_library(|lib| book::query_page(library_root, lib, book_query))?;
     let (items, total) = page.map_err(|e| e.to_string())?;
@@ -130,6 +225,77 @@ pub fn clb_query_books(
     })
 }
 
+/// One value of a facet category with the number of matching books.
+/// `value` is what the corresponding [`LibraryBookQuery`] filter takes.
+#[derive(Serialize, Deserialize, specta::Type, Clone)]
+pub struct LibraryFacetValue<V> {
+    pub value: V,
+    pub name: String,
+    pub book_count: u32,
+}
+
+impl<V, W: Into<V>> From<libcalibre::FacetCount<W>> for LibraryFacetValue<V> {
+    fn from(count: libcalibre::FacetCount<W>) -> Self {
+        LibraryFacetValue {
+            value: count.value.into(),
+            name: count.name,
+            book_count: u32::try_from(count.book_count).unwrap_or(u32::MAX),
+        }
+    }
+}
+
+/// Book counts per author, series, tag, publisher, language, format and
+/// rating within a query's matches: the tag browser sidebar.
+#[derive(Serialize, Deserialize, specta::Type, Clone)]
+pub struct LibraryFacets {
+    pub total: u32,
+    pub authors: Vec<LibraryFacetValue<String>>,
+    pub series: Vec<LibraryFacetValue<i32>>,
+    pub tags: Vec<LibraryFacetValue<i32>>,
+    pub publishers: Vec<LibraryFacetValue<i32>>,
+    pub languages: Vec<LibraryFacetValue<String>>,
+    pub formats: Vec<LibraryFacetValue<String>>,
+    /// Keyed by rating on Calibre's 0–10 scale, highest first.
+    pub ratings: Vec<LibraryFacetValue<i32>>,
+}
+
+fn facet_values<V, W: Into<V>>(
+    counts: Vec<libcalibre::FacetCount<W>>,
+) -> Vec<LibraryFacetValue<V>> {
+    counts.into_iter().map(LibraryFacetValue::from).collect()
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn clb_query_facets(
+    state: tauri::State<CitadelState>,
+    query: LibraryBookQuery,
+) -> Result<LibraryFacets, String> {
+    let book_query = libcalibre::BookQuery::try_from(query)?;
+    let facets = state
+        .with_library(|lib| lib.facets(&book_query))?
+        .map_err(|e| e.to_string())?;
+
+    Ok(LibraryFacets {
+        total: u32::try_from(facets.total).unwrap_or(u32::MAX),
+        authors: facets
+            .authors
+            .into_iter()
+            .map(|author| LibraryFacetValue {
+                value: author.value.to_string(),
+                name: author.name,
+                book_count: u32::try_from(author.book_count).unwrap_or(u32::MAX),
+            })
+            .collect(),
+        series: facet_values(facets.series),
+        tags: facet_values(facets.tags),
+        publishers: facet_values(facets.publishers),
+        languages: facet_values(facets.languages),
+        formats: facet_values(facets.formats),
+        ratings: facet_values(facets.ratings),
+    })
+}
+
 #[tauri::command]
 #[specta::specta]
 pub fn clb_query_get_book(
@@ -201,6 +367,103 @@ pub fn clb_query_list_tags(state: tauri::State<CitadelState>) -> Result<Vec<Libr
         .collect())
 }
 
+/// A query saved under a name; run it with `search:"name"`.
+#[derive(Serialize, Deserialize, specta::Type, Clone)]
+pub struct LibrarySavedSearch {
+    pub name: String,
+    pub query: String,
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn clb_query_list_saved_searches(
+    state: tauri::State<CitadelState>,
+) -> Result<Vec<LibrarySavedSearch>, String> {
+    let searches = state
+        .with_library(|lib| lib.saved_searches())?
+        .map_err(|e| e.to_string())?;
+
+    Ok(searches
+        .into_iter()
+        .map(|search| LibrarySavedSearch {
+            name: search.name,
+            query: search.query,
+        })
+        .collect())
+}
+
+/// A named view of part of the library; an empty `search` is the whole
+/// library.
+#[derive(Serialize, Deserialize, specta::Type, Clone)]
+pub struct LibraryVirtualLibrary {
+    pub name: String,
+    pub search: String,
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn clb_query_list_virtual_libraries(
+    state: tauri::State<CitadelState>,
+) -> Result<Vec<LibraryVirtualLibrary>, String> {
+    let libraries = state
+        .with_library(|lib| lib.virtual_libraries())?
+        .map_err(|e| e.to_string())?;
+
+    Ok(libraries
+        .into_iter()
+        .map(|library| LibraryVirtualLibrary {
+            name: library.name,
+            search: library.search,
+        })
+        .collect())
+}
+
+/// Whether a trash entry is a whole removed book or formats removed from a
+/// book still in the library.
+#[derive(Serialize, Deserialize, specta::Type, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum TrashEntryKind {
+    Book,
+    Formats,
+}
+
+/// One entry in the library's trash.
+#[derive(Serialize, Deserialize, specta::Type, Clone)]
+pub struct LibraryTrashEntry {
+    pub book_id: String,
+    pub kind: TrashEntryKind,
+    pub title: String,
+    pub authors: Vec<String>,
+    pub formats: Vec<String>,
+    /// When the entry was trashed, in UTC.
+    pub trashed_at: chrono::NaiveDateTime,
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn clb_query_list_trash(
+    state: tauri::State<CitadelState>,
+) -> Result<Vec<LibraryTrashEntry>, String> {
+    let entries = state
+        .with_library(|lib| lib.list_trash())?
+        .map_err(|e| e.to_string())?;
+
+    Ok(entries
+        .into_iter()
+        .map(|entry| LibraryTrashEntry {
+            book_id: entry.book_id.as_i32().to_string(),
+            kind: match entry.kind {
+                libcalibre::TrashEntryKind::Book => TrashEntryKind::Book,
+                libcalibre::TrashEntryKind::Formats => TrashEntryKind::Formats,
+            },
+            title: entry.title,
+            authors: entry.authors,
+            formats: entry.formats,
+            trashed_at: entry.trashed_at,
+        })
+        .collect())
+}
+
 #[tauri::command]
 #[specta::specta]
 pub fn clb_query_list_all_authors(
@@ -219,6 +482,141 @@ pub fn clb_query_list_all_authors(
         .and_then(|result| result.map_err(|e| format!("Failed to list authors: {}", e)))
 }
 
+/// Authors that are probably one person written differently, for the user
+/// to review and merge. `key` is the normalized name they share.
+#[derive(Serialize, specta::Type, Clone)]
+pub struct LibraryDuplicateAuthorGroup {
+    pub key: String,
+    /// Most books first.
+    pub authors: Vec<LibraryAuthor>,
+}
+
+/// Groups of authors whose names differ only in spacing, accents, case,
+/// titles or "Last, First" order.
+#[tauri::command]
+#[specta::specta]
+pub fn clb_query_find_duplicate_authors(
+    state: tauri::State<CitadelState>,
+) -> Result<Vec<LibraryDuplicateAuthorGroup>, String> {
+    let groups = state
+        .with_library(|lib| lib.find_duplicate_authors())?
+        .map_err(|e| format!("Failed to find duplicate authors: {}", e))?;
+
+    Ok(groups
+        .into_iter()
+        .map(|group| {
+            let book_counts: HashMap<libcalibre::AuthorId, i64> = group
+                .authors
+                .iter()
+                .map(|dup| (dup.author.id, dup.book_count))
+                .collect();
+            LibraryDuplicateAuthorGroup {
+                key: group.key,
+                authors: group
+                    .authors
+                    .iter()
+                    .map(|dup| LibraryAuthor::from_author(&dup.author, &book_counts))
+                    .collect(),
+            }
+        })
+        .collect())
+}
+
+/// Why two books are taken to be the same.
+#[derive(Serialize, Deserialize, specta::Type, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicateReason {
+    /// They share an ISBN, counting ISBN-10 and ISBN-13 forms as equal.
+    Isbn,
+    /// Their titles and an author match, ignoring case, accents and
+    /// punctuation.
+    TitleAndAuthor,
+    /// They have a format file with identical content.
+    SameFile,
+}
+
+impl From<libcalibre::DuplicateReason> for DuplicateReason {
+    fn from(reason: libcalibre::DuplicateReason) -> Self {
+        match reason {
+            libcalibre::DuplicateReason::Isbn => Self::Isbn,
+            libcalibre::DuplicateReason::TitleAndAuthor => Self::TitleAndAuthor,
+            libcalibre::DuplicateReason::SameFile => Self::SameFile,
+        }
+    }
+}
+
+/// A book already in the library that an import would duplicate.
+#[derive(Serialize, specta::Type, Clone)]
+pub struct LibraryDuplicateBookMatch {
+    pub book_id: String,
+    pub reasons: Vec<DuplicateReason>,
+}
+
+impl From<libcalibre::DuplicateBookMatch> for LibraryDuplicateBookMatch {
+    fn from(found: libcalibre::DuplicateBookMatch) -> Self {
+        LibraryDuplicateBookMatch {
+            book_id: found.book_id.as_i32().to_string(),
+            reasons: found
+                .reasons
+                .into_iter()
+                .map(DuplicateReason::from)
+                .collect(),
+        }
+    }
+}
+
+/// Books in the library that look like one another.
+#[derive(Serialize, specta::Type, Clone)]
+pub struct LibraryDuplicateBookGroup {
+    pub book_ids: Vec<String>,
+    pub reasons: Vec<DuplicateReason>,
+}
+
+/// Books already in the library that importing `md` would duplicate, the
+/// closest first.
+#[tauri::command]
+#[specta::specta]
+pub fn clb_query_find_duplicates_for_import(
+    state: tauri::State<CitadelState>,
+    md: ImportableBookMetadata,
+) -> Result<Vec<LibraryDuplicateBookMatch>, String> {
+    let found = state
+        .with_library(|lib| lib.find_duplicates_of(&md.to_book_add()))?
+        .map_err(|e| format!("Failed to check for duplicates: {}", e))?;
+
+    Ok(found
+        .into_iter()
+        .map(LibraryDuplicateBookMatch::from)
+        .collect())
+}
+
+/// Every group of books in the library that look like one another.
+#[tauri::command]
+#[specta::specta]
+pub fn clb_query_find_duplicate_books(
+    state: tauri::State<CitadelState>,
+) -> Result<Vec<LibraryDuplicateBookGroup>, String> {
+    let groups = state
+        .with_library(|lib| lib.find_duplicate_books())?
+        .map_err(|e| format!("Failed to find duplicate books: {}", e))?;
+
+    Ok(groups
+        .into_iter()
+        .map(|group| LibraryDuplicateBookGroup {
+            book_ids: group
+                .book_ids
+                .iter()
+                .map(|book_id| book_id.as_i32().to_string())
+                .collect(),
+            reasons: group
+                .reasons
+                .into_iter()
+                .map(DuplicateReason::from)
+                .collect(),
+        })
+        .collect())
+}
+
 #[tauri::command]
 #[specta::specta]
 pub fn clb_query_is_file_importable(path_to_file: String) -> Option<ImportableFile> {
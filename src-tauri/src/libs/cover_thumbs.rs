@@ -248,6 +248,20 @@ pub fn list_thumbnails(app_cache_dir: &Path, library_root: &str) -> Vec<CoverThu
         .collect()
 }
 
+/// Drop a book's thumbnail from the index and delete its file, for when the
+/// cover itself is removed. `ensure_thumbnails` never sees coverless books,
+/// so without this the startup [`list_thumbnails`] pass would keep serving
+/// the old image.
+pub fn invalidate_thumbnail(app_cache_dir: &Path, library_root: &str, book_id: &str) {
+    let cache_dir = thumb_cache_dir(app_cache_dir, library_root);
+    let _guard = INDEX_LOCK.lock().expect("thumb index lock poisoned");
+    let mut index = load_index(&cache_dir);
+    if let Some(meta) = index.remove(book_id) {
+        let _ = fs::remove_file(cache_dir.join(&meta.file_name));
+        store_index(&cache_dir, &index);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -368,4 +382,26 @@ mod tests {
         assert!(thumbs.is_empty());
         let _ = fs::remove_dir_all(&dir);
     }
+
+    #[test]
+    fn invalidate_removes_index_entry_and_file() {
+        let dir = temp_dir("invalidate");
+        let cover = write_test_cover(&dir, "cover.jpg", 600, 900);
+        ensure_thumbnails(
+            &dir,
+            "/library",
+            &[CoverSource {
+                book_id: "9".into(),
+                cover_path: cover,
+            }],
+        );
+        let cache_dir = thumb_cache_dir(&dir, "/library");
+        let file_name = load_index(&cache_dir)["9"].file_name.clone();
+
+        invalidate_thumbnail(&dir, "/library", "9");
+
+        assert!(list_thumbnails(&dir, "/library").is_empty());
+        assert!(!cache_dir.join(file_name).exists());
+        let _ = fs::remove_dir_all(&dir);
+    }
 }
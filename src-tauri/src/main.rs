@@ -24,32 +24,57 @@ fn run_tauri_backend() -> std::io::Result<()> {
         calibre::init_client,
         calibre::query::clb_query_is_path_valid_library,
         calibre::command::clb_cmd_create_library,
+        calibre::command::clb_cmd_upgrade_schema,
         // Book query commands
         calibre::query::clb_query_search_books,
         calibre::query::clb_query_books,
+        calibre::query::clb_query_facets,
         calibre::query::clb_query_get_book,
         calibre::query::clb_query_is_file_importable,
         calibre::query::clb_query_importable_file_metadata,
         calibre::query::clb_query_list_all_filetypes,
         // Series query commands
         calibre::query::clb_query_list_series,
-        // Tag query commands
+        // Tag query and management commands
         calibre::query::clb_query_list_tags,
+        calibre::command::clb_cmd_rename_tag,
+        calibre::command::clb_cmd_merge_tags,
+        calibre::command::clb_cmd_delete_tag,
+        calibre::command::clb_cmd_prune_unused_tags,
         // Book manipulation commands
         calibre::command::clb_cmd_create_book,
+        calibre::command::clb_cmd_import_book,
+        calibre::query::clb_query_find_duplicates_for_import,
+        calibre::query::clb_query_find_duplicate_books,
+        calibre::command::clb_cmd_merge_books,
         calibre::command::clb_cmd_update_book,
+        calibre::command::clb_cmd_bulk_update_books,
         calibre::command::clb_cmd_upsert_book_identifier,
         calibre::command::clb_cmd_delete_book_identifier,
         calibre::command::clb_cmd_set_book_cover_from_url,
+        calibre::command::clb_cmd_remove_book_cover,
         calibre::command::clb_cmd_ensure_cover_thumbnails,
         calibre::command::clb_cmd_warm_cover_thumbnails,
         calibre::query::clb_query_list_cover_thumbnails,
+        // Trash commands
+        calibre::query::clb_query_list_trash,
+        calibre::command::clb_cmd_restore_book,
+        calibre::command::clb_cmd_restore_book_file,
+        calibre::command::clb_cmd_empty_trash,
+        // Saved search and virtual library commands
+        calibre::query::clb_query_list_saved_searches,
+        calibre::query::clb_query_list_virtual_libraries,
+        calibre::command::clb_cmd_save_search,
+        calibre::command::clb_cmd_remove_saved_search,
+        calibre::command::clb_cmd_save_virtual_library,
+        calibre::command::clb_cmd_remove_virtual_library,
         // Custom column commands
         calibre::query::clb_query_list_custom_columns,
         calibre::query::clb_query_get_custom_values_for_book,
         calibre::command::clb_cmd_set_custom_value,
         // Author query and manipulation commands
         calibre::query::clb_query_list_all_authors,
+        calibre::query::clb_query_find_duplicate_authors,
         calibre::command::clb_cmd_create_authors,
         calibre::command::clb_cmd_update_author,
         calibre::command::clb_cmd_delete_author,
@@ -89,42 +89,12 @@ pub struct ProviderStatus {
 }
 
 // ---------------------------------------------------------------------------
-// ISBN normalization & equivalence (the single 10<->13 authority)
+// ISBN normalization & equivalence
 // ---------------------------------------------------------------------------
 
-/// Normalize a raw ISBN string into a compact, validated form (13 digits, or
-/// 10 digits with an optional trailing `X`). Returns `None` if the input is
-/// not a structurally valid ISBN.
-pub fn normalize_isbn(raw: &str) -> Option<String> {
-    let trimmed = raw.trim();
-    if trimmed.is_empty() {
-        return None;
-    }
-    let without_prefix = trimmed
-        .strip_prefix("ISBN:")
-        .or_else(|| trimmed.strip_prefix("isbn:"))
-        .unwrap_or(trimmed)
-        .trim();
-
-    let compact: String = without_prefix
-        .chars()
-        .filter(|c| c.is_ascii_alphanumeric())
-        .collect();
-    if compact.is_empty() {
-        return None;
-    }
-
-    let upper = compact.to_uppercase();
-    let valid = upper.len() == 13 && upper.chars().all(|c| c.is_ascii_digit())
-        || (upper.len() == 10
-            && upper[..9].chars().all(|c| c.is_ascii_digit())
-            && upper[9..].chars().all(|c| c.is_ascii_digit() || c == 'X'));
-    if valid {
-        Some(upper)
-    } else {
-        None
-    }
-}
+// The 10<->13 conversion lives in libcalibre, which also matches duplicate
+// books by ISBN.
+pub use libcalibre::isbn::{isbn_equivalent, normalize_isbn};
 
 /// Choose the best ISBN from a list, preferring the 13-digit form.
 pub fn pick_preferred_isbn(isbns: &[String]) -> Option<String> {
@@ -138,73 +108,3 @@ pub fn pick_preferred_isbn(isbns: &[String]) -> Option<String> {
         .cloned()
         .or_else(|| normalized.into_iter().next())
 }
-
-/// Canonicalize any valid ISBN to its 13-digit form so that the ISBN-10 and
-/// ISBN-13 representations of the same edition compare equal.
-fn canonical_isbn13(raw: &str) -> Option<String> {
-    let n = normalize_isbn(raw)?;
-    match n.len() {
-        13 => Some(n),
-        10 => {
-            let core: String = format!("978{}", &n[..9]);
-            let check = ean13_check_digit(&core)?;
-            Some(format!("{core}{check}"))
-        }
-        _ => None,
-    }
-}
-
-fn ean13_check_digit(twelve: &str) -> Option<char> {
-    if twelve.len() != 12 {
-        return None;
-    }
-    let mut sum = 0u32;
-    for (i, c) in twelve.chars().enumerate() {
-        let d = c.to_digit(10)?;
-        sum += if i % 2 == 0 { d } else { d * 3 };
-    }
-    let check = (10 - (sum % 10)) % 10;
-    char::from_digit(check, 10)
-}
-
-/// True when two ISBN strings denote the same edition, comparing across the
-/// ISBN-10 and ISBN-13 forms. This is the only place 10<->13 conversion lives;
-/// the frontend relies on Rust having verified equivalence.
-pub fn isbn_equivalent(a: &str, b: &str) -> bool {
-    match (canonical_isbn13(a), canonical_isbn13(b)) {
-        (Some(x), Some(y)) => x == y,
-        _ => false,
-    }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn normalizes_and_strips_prefixes() {
-        assert_eq!(
-            normalize_isbn("ISBN: 978-0-553-10354-0").as_deref(),
-            Some("9780553103540")
-        );
-        assert_eq!(normalize_isbn("0553103547").as_deref(), Some("0553103547"));
-        assert_eq!(normalize_isbn("notanisbn"), None);
-    }
-
-    #[test]
-    fn isbn_10_and_13_are_equivalent() {
-        // A Game of Thrones, ISBN-10 vs ISBN-13 for the same edition.
-        assert!(isbn_equivalent("0553103547", "9780553103540"));
-        assert!(isbn_equivalent("9780553103540", "0553103547"));
-        // Same form compares equal.
-        assert!(isbn_equivalent("9780553103540", "978-0-553-10354-0"));
-        // Different editions do not.
-        assert!(!isbn_equivalent("9780553103540", "9780374528379"));
-    }
-
-    #[test]
-    fn equivalence_is_false_for_garbage() {
-        assert!(!isbn_equivalent("", "9780553103540"));
-        assert!(!isbn_equivalent("xyz", "9780553103540"));
-    }
-}
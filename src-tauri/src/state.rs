@@ -1,6 +1,6 @@
 use std::sync::Mutex;
 
-use libcalibre::Library;
+use libcalibre::{Library, SchemaStatus};
 
 pub struct CitadelState {
     library: Mutex<Option<Library>>,
@@ -15,12 +15,14 @@ impl CitadelState {
         }
     }
 
-    /// Initialize or switch to a library
-    pub fn init_library(&self, library_path: String) -> Result<(), String> {
+    /// Initialize or switch to a library, returning what opening it found out
+    /// about its database schema.
+    pub fn init_library(&self, library_path: String) -> Result<SchemaStatus, String> {
         let db_path = libcalibre::util::get_db_path(&library_path)
             .ok_or_else(|| format!("Invalid library path: {}", library_path))?;
 
         let lib = Library::new(db_path).map_err(|e| format!("Failed to open library: {}", e))?;
+        let schema_status = lib.schema_status();
 
         *self.library.lock().expect("Library mutex poisoned") = Some(lib);
         *self
@@ -28,7 +30,7 @@ impl CitadelState {
             .lock()
             .expect("Library path mutex poisoned") = Some(library_path);
 
-        Ok(())
+        Ok(schema_status)
     }
 
     /// Execute a function with mutable access to the library
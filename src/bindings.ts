@@ -24,6 +24,19 @@ async clbCmdCreateLibrary(libraryRoot: string) : Promise<Result<null, string>> {
     else return { status: "error", error: e  as any };
 }
 },
+/**
+ * Upgrade the open library's database to the schema version citadel
+ * understands, after backing it up in the library folder. Run only once the
+ * user has agreed to an upgrade `init_client` reported as needed.
+ */
+async clbCmdUpgradeSchema() : Promise<Result<LibrarySchemaStatus, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("clb_cmd_upgrade_schema") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
 async clbQuerySearchBooks(query: string) : Promise<Result<LibraryBook[], string>> {
     try {
     return { status: "ok", data: await TAURI_INVOKE("clb_query_search_books", { query }) };
@@ -40,6 +53,14 @@ async clbQueryBooks(query: LibraryBookQuery) : Promise<Result<LibraryBookPage, s
     else return { status: "error", error: e  as any };
 }
 },
+async clbQueryFacets(query: LibraryBookQuery) : Promise<Result<LibraryFacets, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("clb_query_facets", { query }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
 async clbQueryGetBook(bookId: string) : Promise<Result<LibraryBook, string>> {
     try {
     return { status: "ok", data: await TAURI_INVOKE("clb_query_get_book", { bookId }) };
@@ -73,6 +94,51 @@ async clbQueryListTags() : Promise<Result<LibraryTag[], string>> {
     else return { status: "error", error: e  as any };
 }
 },
+/**
+ * Rename a tag on every book. Renaming onto another tag's name merges the
+ * two; returns the tag that remains.
+ */
+async clbCmdRenameTag(tagId: number, newName: string) : Promise<Result<LibraryTag, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("clb_cmd_rename_tag", { tagId, newName }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Move every book tagged `from_id` to `into_id` and delete `from_id`.
+ */
+async clbCmdMergeTags(fromId: number, intoId: number) : Promise<Result<LibraryTag, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("clb_cmd_merge_tags", { fromId, intoId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Remove a tag from every book and delete it. Returns how many books had it.
+ */
+async clbCmdDeleteTag(tagId: number) : Promise<Result<number, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("clb_cmd_delete_tag", { tagId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Delete every tag no book uses. Returns the deleted tags.
+ */
+async clbCmdPruneUnusedTags() : Promise<Result<LibraryTag[], string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("clb_cmd_prune_unused_tags") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
 async clbCmdCreateBook(md: ImportableBookMetadata) : Promise<Result<string, string>> {
     try {
     return { status: "ok", data: await TAURI_INVOKE("clb_cmd_create_book", { md }) };
@@ -81,6 +147,53 @@ async clbCmdCreateBook(md: ImportableBookMetadata) : Promise<Result<string, stri
     else return { status: "error", error: e  as any };
 }
 },
+/**
+ * Import a book unless the library already has it, by ISBN, by title and
+ * author, or by identical file content.
+ */
+async clbCmdImportBook(md: ImportableBookMetadata, onDuplicate: OnDuplicate) : Promise<Result<LibraryImportOutcome, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("clb_cmd_import_book", { md, onDuplicate }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Books already in the library that importing `md` would duplicate, the
+ * closest first.
+ */
+async clbQueryFindDuplicatesForImport(md: ImportableBookMetadata) : Promise<Result<LibraryDuplicateBookMatch[], string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("clb_query_find_duplicates_for_import", { md }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Every group of books in the library that look like one another.
+ */
+async clbQueryFindDuplicateBooks() : Promise<Result<LibraryDuplicateBookGroup[], string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("clb_query_find_duplicate_books") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Merge duplicate books into `target_id`: it gains the metadata and formats
+ * it lacks, and the sources go to the trash. Nothing changes on failure.
+ */
+async clbCmdMergeBooks(targetId: string, sourceIds: string[], cover: MergeCover) : Promise<Result<null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("clb_cmd_merge_books", { targetId, sourceIds, cover }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
 async clbCmdUpdateBook(bookId: string, updates: BookUpdate) : Promise<Result<number, string>> {
     try {
     return { status: "ok", data: await TAURI_INVOKE("clb_cmd_update_book", { bookId, updates }) };
@@ -89,6 +202,18 @@ async clbCmdUpdateBook(bookId: string, updates: BookUpdate) : Promise<Result<num
     else return { status: "error", error: e  as any };
 }
 },
+/**
+ * Apply one edit to many books in a single call and transaction. Books the
+ * edit cannot be applied to are reported; the rest are updated.
+ */
+async clbCmdBulkUpdateBooks(bookIds: string[], edit: BulkEdit) : Promise<Result<LibraryBulkUpdateReport, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("clb_cmd_bulk_update_books", { bookIds, edit }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
 async clbCmdUpsertBookIdentifier(bookId: string, label: string, value: string, existingId: number | null) : Promise<Result<null, string>> {
     try {
     return { status: "ok", data: await TAURI_INVOKE("clb_cmd_upsert_book_identifier", { bookId, label, value, existingId }) };
@@ -113,6 +238,14 @@ async clbCmdSetBookCoverFromUrl(bookId: string, imageUrl: string) : Promise<Resu
     else return { status: "error", error: e  as any };
 }
 },
+async clbCmdRemoveBookCover(bookId: string) : Promise<Result<null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("clb_cmd_remove_book_cover", { bookId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
 async clbCmdEnsureCoverThumbnails(bookIds: string[]) : Promise<Result<CoverThumbnail[], string>> {
     try {
     return { status: "ok", data: await TAURI_INVOKE("clb_cmd_ensure_cover_thumbnails", { bookIds }) };
@@ -144,6 +277,103 @@ async clbQueryListCoverThumbnails() : Promise<Result<CoverThumbnail[], string>>
     else return { status: "error", error: e  as any };
 }
 },
+async clbQueryListTrash() : Promise<Result<LibraryTrashEntry[], string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("clb_query_list_trash") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async clbCmdRestoreBook(bookId: string) : Promise<Result<null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("clb_cmd_restore_book", { bookId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async clbCmdRestoreBookFile(bookId: string, format: string) : Promise<Result<null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("clb_cmd_restore_book_file", { bookId, format }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Permanently delete trash entries older than `older_than_days`, or the
+ * whole trash when `None`. Returns the number of entries deleted.
+ */
+async clbCmdEmptyTrash(olderThanDays: number | null) : Promise<Result<number, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("clb_cmd_empty_trash", { olderThanDays }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async clbQueryListSavedSearches() : Promise<Result<LibrarySavedSearch[], string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("clb_query_list_saved_searches") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async clbQueryListVirtualLibraries() : Promise<Result<LibraryVirtualLibrary[], string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("clb_query_list_virtual_libraries") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Save `query` under `name`, replacing any saved search of that name.
+ */
+async clbCmdSaveSearch(name: string, query: string) : Promise<Result<null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("clb_cmd_save_search", { name, query }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Delete a saved search. Returns whether it existed.
+ */
+async clbCmdRemoveSavedSearch(name: string) : Promise<Result<boolean, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("clb_cmd_remove_saved_search", { name }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Define a virtual library, replacing any of the same name. An empty
+ * `search` is the whole library.
+ */
+async clbCmdSaveVirtualLibrary(name: string, search: string) : Promise<Result<null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("clb_cmd_save_virtual_library", { name, search }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Delete a virtual library. Returns whether it existed.
+ */
+async clbCmdRemoveVirtualLibrary(name: string) : Promise<Result<boolean, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("clb_cmd_remove_virtual_library", { name }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
 async clbQueryListCustomColumns() : Promise<Result<CustomColumnDef[], string>> {
     try {
     return { status: "ok", data: await TAURI_INVOKE("clb_query_list_custom_columns") };
@@ -176,6 +406,18 @@ async clbQueryListAllAuthors() : Promise<Result<LibraryAuthor[], string>> {
     else return { status: "error", error: e  as any };
 }
 },
+/**
+ * Groups of authors whose names differ only in spacing, accents, case,
+ * titles or "Last, First" order.
+ */
+async clbQueryFindDuplicateAuthors() : Promise<Result<LibraryDuplicateAuthorGroup[], string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("clb_query_find_duplicate_authors") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
 async clbCmdCreateAuthors(newAuthors: NewAuthor[]) : Promise<Result<LibraryAuthor[], string>> {
     try {
     return { status: "ok", data: await TAURI_INVOKE("clb_cmd_create_authors", { newAuthors }) };
@@ -293,7 +535,11 @@ language_code: string | null;
  * Hardcover deep-link slug; `None` for other providers.
  */
 slug: string | null }
-export type BookSortOrder = "TitleAsc" | "TitleDesc" | "AuthorAsc" | "AuthorDesc"
+export type BookSortOrder = "TitleAsc" | "TitleDesc" | "AuthorAsc" | "AuthorDesc" | "AddedAsc" | "AddedDesc" | "ModifiedAsc" | "ModifiedDesc" | "PublishedAsc" | "PublishedDesc" | "SeriesAsc" | "SeriesDesc" | "RatingAsc" | "RatingDesc" | "PublisherAsc" | "PublisherDesc" | "SizeAsc" | "SizeDesc" | 
+/**
+ * Sort by the custom column with this id.
+ */
+{ CustomColumnAsc: number } | { CustomColumnDesc: number }
 export type BookUpdate = { author_id_list: string[] | null; tag_list: string[] | null; title: string | null; timestamp: string | null; publication_date: string | null; is_read: boolean | null; description: string | null; 
 /**
  * An empty (or whitespace) name unlinks the book from its series;
@@ -306,7 +552,45 @@ series: string | null; series_index: number | null;
  * languages; `None` leaves them unchanged.
  */
 language_list: string[] | null }
-export type CalibreClientConfig = { library_path: string }
+/**
+ * A custom column value to set (or clear, with `null`) on every book.
+ */
+export type BulkCustomValue = { column_id: number; value: CustomValueDto | null }
+/**
+ * Changes applied to every book of a bulk edit. `null` and empty fields
+ * leave that metadata as it is.
+ */
+export type BulkEdit = { add_tags: string[]; remove_tags: string[]; 
+/**
+ * An empty name unlinks the books from their series.
+ */
+series: string | null; series_index: BulkSeriesIndex; publisher: string | null; language_list: string[] | null; 
+/**
+ * Calibre's 0–10 scale; 0 clears the rating.
+ */
+rating: number | null; is_read: boolean | null; custom_values: BulkCustomValue[]; search_replace: SearchReplace | null }
+/**
+ * The index setting a series gives each book in a bulk edit.
+ */
+export type BulkSeriesIndex = 
+/**
+ * Each book keeps the index it has.
+ */
+{ mode: "keep" } | 
+/**
+ * Number the books in the order given, after the series' last book.
+ */
+{ mode: "next" } | 
+/**
+ * Number the books in the order given, from `start`.
+ */
+{ mode: "from"; start: number }
+export type CalibreClientConfig = { library_path: string; 
+/**
+ * The library database's schema version, and whether it needs an
+ * upgrade or is newer than citadel understands.
+ */
+schema: LibrarySchemaStatus }
 /**
  * What the frontend needs to render one grid cover: a small image URL and the
  * thumbnail's pixel dimensions (same aspect ratio as the source cover) to
@@ -349,6 +633,23 @@ export type CustomValueDto = { Bool: boolean } | { Int: number } | { Float: numb
  * RFC3339 datetime string, e.g. `2024-01-15T10:30:00+00:00`.
  */
 { Datetime: string } | { Enumeration: string }
+/**
+ * Why two books are taken to be the same.
+ */
+export type DuplicateReason = 
+/**
+ * They share an ISBN, counting ISBN-10 and ISBN-13 forms as equal.
+ */
+"isbn" | 
+/**
+ * Their titles and an author match, ignoring case, accents and
+ * punctuation.
+ */
+"title_and_author" | 
+/**
+ * They have a format file with identical content.
+ */
+"same_file"
 /**
  * Book identifiers, such as ISBN, DOI, Google Books ID, etc.
  */
@@ -389,7 +690,24 @@ export type LibraryBook = { id: string; uuid: string | null; title: string; auth
  * Canonical Calibre language codes (ISO 639-2/3, e.g. `eng`, `fra`),
  * ordered. Empty when the book has no language metadata.
  */
-language_list: string[] }
+language_list: string[]; publisher: string | null; 
+/**
+ * Calibre's 0–10 scale (two points per displayed star); `None` when
+ * unrated.
+ */
+rating: number | null; 
+/**
+ * Calibre's `pubdate`; `None` when unset.
+ */
+publication_date: string | null; 
+/**
+ * When the book was added to the library (Calibre's `timestamp`).
+ */
+created_at: string; 
+/**
+ * When the book's metadata last changed (Calibre's `last_modified`).
+ */
+updated_at: string }
 export type LibraryBookPage = { items: LibraryBook[]; 
 /**
  * Total number of books matching the filters, ignoring limit/offset.
@@ -400,11 +718,122 @@ export type LibraryBookQuery = {
  * Substring match across title, author names, and series names.
  * `None` or empty text matches all books.
  */
-text: string | null; author_id: string | null; series_id: number | null; hide_read: boolean; sort: BookSortOrder; 
+text: string | null; author_id: string | null; series_id: number | null; hide_read: boolean; 
+/**
+ * Only books with at least one of these tag ids.
+ */
+tags_any: number[]; 
+/**
+ * Only books with every one of these tag ids.
+ */
+tags_all: number[]; 
+/**
+ * Only books in at least one of these languages (`eng`, `fr`, ...).
+ */
+language_codes: string[]; 
+/**
+ * Only books with a file in at least one of these formats.
+ */
+formats: string[]; publisher_id: number | null; 
+/**
+ * Rating bounds on Calibre's 0–10 scale; unrated books never match.
+ */
+min_rating: number | null; max_rating: number | null; has_cover: boolean | null; 
+/**
+ * Only books with an identifier of each of these types (`isbn`, ...).
+ */
+with_identifiers: string[]; 
+/**
+ * Only books with no identifier of any of these types.
+ */
+without_identifiers: string[]; added: LibraryDateRange; published: LibraryDateRange; 
+/**
+ * Only books in the virtual library with this name.
+ */
+virtual_library: string | null; 
+/**
+ * A query in Calibre's search language (`tag:fantasy and rating:>=4`).
+ * `None` or empty matches all books.
+ */
+search: string | null; sort: BookSortOrder; 
 /**
  * Page size. `None` returns all matches.
  */
 limit: number | null; offset: number }
+/**
+ * A book a bulk edit left as it was, and why.
+ */
+export type LibraryBulkUpdateFailure = { book_id: string; reason: string }
+/**
+ * What [`clb_cmd_bulk_update_books`] did.
+ */
+export type LibraryBulkUpdateReport = { updated: string[]; failed: LibraryBulkUpdateFailure[] }
+/**
+ * An inclusive range of days; a `None` end is open.
+ */
+export type LibraryDateRange = { from: string | null; to: string | null }
+/**
+ * Authors that are probably one person written differently, for the user
+ * to review and merge. `key` is the normalized name they share.
+ */
+export type LibraryDuplicateAuthorGroup = { key: string; 
+/**
+ * Most books first.
+ */
+authors: LibraryAuthor[] }
+/**
+ * Books in the library that look like one another.
+ */
+export type LibraryDuplicateBookGroup = { book_ids: string[]; reasons: DuplicateReason[] }
+/**
+ * A book already in the library that an import would duplicate.
+ */
+export type LibraryDuplicateBookMatch = { book_id: string; reasons: DuplicateReason[] }
+/**
+ * One value of a facet category with the number of matching books.
+ * `value` is what the corresponding [`LibraryBookQuery`] filter takes.
+ */
+export type LibraryFacetValue<V> = { value: V; name: string; book_count: number }
+/**
+ * Book counts per author, series, tag, publisher, language, format and
+ * rating within a query's matches: the tag browser sidebar.
+ */
+export type LibraryFacets = { total: number; authors: LibraryFacetValue<string>[]; series: LibraryFacetValue<number>[]; tags: LibraryFacetValue<number>[]; publishers: LibraryFacetValue<number>[]; languages: LibraryFacetValue<string>[]; formats: LibraryFacetValue<string>[]; 
+/**
+ * Keyed by rating on Calibre's 0–10 scale, highest first.
+ */
+ratings: LibraryFacetValue<number>[] }
+/**
+ * What [`clb_cmd_import_book`] did.
+ */
+export type LibraryImportOutcome = { outcome: "added"; book_id: string } | { outcome: "skipped"; duplicates: LibraryDuplicateBookMatch[] } | 
+/**
+ * `formats` lists the formats the existing book gained; it is empty
+ * when the book already had them all.
+ */
+{ outcome: "added_formats"; book_id: string; formats: string[] }
+/**
+ * A query saved under a name; run it with `search:"name"`.
+ */
+export type LibrarySavedSearch = { name: string; query: string }
+/**
+ * What citadel knows about a library's database schema.
+ */
+export type LibrarySchemaStatus = { status: "current"; version: number } | 
+/**
+ * An older Calibre created the database. Ask the user before upgrading
+ * it with `clb_cmd_upgrade_schema`.
+ */
+{ status: "needs_upgrade"; from: number; to: number } | 
+/**
+ * The database was upgraded by `clb_cmd_upgrade_schema`.
+ */
+{ status: "upgraded"; from: number; to: number } | 
+/**
+ * A newer Calibre wrote the database. It was left as it is; warn the user
+ * before they edit anything.
+ */
+{ status: "newer"; version: number; supported: number }
 /**
  * One series in the library. `id` is what [`LibraryBookQuery::series_id`]
  * filters on; the frontend otherwise only ever sees series names.
@@ -414,6 +843,19 @@ export type LibrarySeries = { id: number; name: string; book_count: number }
  * One tag in the library; `name` is what the tag autocomplete suggests.
  */
 export type LibraryTag = { id: number; name: string }
+/**
+ * One entry in the library's trash.
+ */
+export type LibraryTrashEntry = { book_id: string; kind: TrashEntryKind; title: string; authors: string[]; formats: string[]; 
+/**
+ * When the entry was trashed, in UTC.
+ */
+trashed_at: string }
+/**
+ * A named view of part of the library; an empty `search` is the whole
+ * library.
+ */
+export type LibraryVirtualLibrary = { name: string; search: string }
 export type LocalFile = { 
 /**
  * The absolute path to the file, including extension.
@@ -425,6 +867,22 @@ path: string;
 mime_type: string }
 export type LocalOrRemote = "Local" | "Remote"
 export type LocalOrRemoteUrl = { kind: LocalOrRemote; url: string; local_path: string | null }
+/**
+ * Which cover merging books leaves the merged book with.
+ */
+export type MergeCover = 
+/**
+ * The target's own cover, or the first source's if it has none.
+ */
+"target" | 
+/**
+ * The first source's cover, or the target's if no source has one.
+ */
+"source" | 
+/**
+ * The largest cover file.
+ */
+"largest"
 /**
  * The metadata sources Citadel can look books up against. The serialized
  * short form is the single id used across the Rust enum, the TS string union,
@@ -432,8 +890,31 @@ export type LocalOrRemoteUrl = { kind: LocalOrRemote; url: string; local_path: s
  */
 export type MetadataProvider = "hardcover" | "loc" | "dnb" | "k10plus" | "openlibrary"
 export type NewAuthor = { name: string; sortable_name: string | null }
+/**
+ * What importing does with a book the library already has.
+ */
+export type OnDuplicate = 
+/**
+ * Add nothing, and report the duplicates.
+ */
+"skip" | 
+/**
+ * Add the file to the closest duplicate as a new format.
+ */
+"add_formats" | "add_anyway"
 export type ProviderStatus = { provider: MetadataProvider; is_valid: boolean; message: string }
 export type RemoteFile = { url: string }
+/**
+ * A regular expression replaced throughout one field of each book.
+ * `replacement` may refer to capture groups as `$1`.
+ */
+export type SearchReplace = { field: SearchReplaceField; pattern: string; replacement: string; case_sensitive: boolean }
+export type SearchReplaceField = "title" | "tags" | "series" | "publisher" | "comments"
+/**
+ * Whether a trash entry is a whole removed book or formats removed from a
+ * book still in the library.
+ */
+export type TrashEntryKind = "book" | "formats"
 export type UpdateCheckResult = { has_update: boolean; version: string | null }
 
 /** tauri-specta globals **/
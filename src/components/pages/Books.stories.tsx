@@ -43,6 +43,11 @@ const MOCK_BOOK: LibraryBook = {
 	series: "The Bug Chronicles",
 	series_index: 1.5,
 	language_list: ["eng"],
+	publisher: "Bug House",
+	rating: 8,
+	publication_date: "1915-10-01T00:00:00",
+	created_at: "2024-06-20T12:00:00",
+	updated_at: "2024-06-20T12:00:00",
 	identifier_list: [
 		{
 			id: 123,
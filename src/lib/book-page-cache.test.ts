@@ -30,6 +30,11 @@ const book = (id: string, extra: Partial<LibraryBook> = {}): LibraryBook => ({
 	series: null,
 	series_index: null,
 	language_list: [],
+	publisher: null,
+	rating: null,
+	publication_date: null,
+	created_at: "1970-01-01T00:00:00",
+	updated_at: "1970-01-01T00:00:00",
 	...extra,
 });
 
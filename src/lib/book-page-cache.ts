@@ -64,8 +64,8 @@ export const serializeBookFilter = (filter: BookGridFilter): string =>
  * The backend query for one page of a filter.
  *
  * Series filters are special-cased to a single unbounded page: books in a
- * series read in series order, but the backend only sorts by title/author
- * (Calibre sort columns), so the whole series is fetched at once and sorted
+ * series read in series order, but the grid's sort orders are title/author
+ * only (Calibre sort columns), so the whole series is fetched at once and sorted
  * by `series_index` client-side. Series are small (rarely more than a few
  * dozen books), so this stays cheap.
  */
@@ -78,6 +78,20 @@ export const toBookQuery = (
 	author_id: filter.authorId,
 	series_id: filter.seriesId,
 	hide_read: filter.hideRead,
+	tags_any: [],
+	tags_all: [],
+	language_codes: [],
+	formats: [],
+	publisher_id: null,
+	min_rating: null,
+	max_rating: null,
+	has_cover: null,
+	with_identifiers: [],
+	without_identifiers: [],
+	added: { from: null, to: null },
+	published: { from: null, to: null },
+	virtual_library: null,
+	search: null,
 	sort: SORT_ORDER_TO_BACKEND[filter.sortOrder],
 	limit: filter.seriesId !== null ? null : pageSize,
 	offset: filter.seriesId !== null ? 0 : pageIndex * pageSize,
@@ -1,3 +1,4 @@
+import { ask } from "@tauri-apps/plugin-dialog";
 import {
 	commands,
 	type ImportableBookMetadata,
@@ -22,6 +23,21 @@ const genLocalCalibreClient = async (
 		throw new Error(configResult.error);
 	}
 
+	const schema = configResult.data.schema;
+	if (schema.status === "needs_upgrade") {
+		const upgrade = await ask(
+			`This library was created by an older version of Calibre (database version ${schema.from}). Upgrade it to version ${schema.to}? A backup of the database is kept in the library folder.`,
+			{ title: "Upgrade library", kind: "warning", okLabel: "Upgrade" },
+		);
+		if (!upgrade) {
+			throw new Error("The library needs an upgrade before it can be opened.");
+		}
+		const upgradeResult = await commands.clbCmdUpgradeSchema();
+		if (upgradeResult.status === "error") {
+			throw new Error(upgradeResult.error);
+		}
+	}
+
 	const bookCoverCache = new Map<
 		LibraryBook["id"],
 		{